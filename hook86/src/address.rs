@@ -0,0 +1,270 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt;
+use std::sync::{LazyLock, RwLock};
+
+use crate::error::{Hook86Error, Result};
+use crate::mem::{ByteSearcher, IntPtr, ModuleOffset};
+
+/// An absolute virtual address in the current process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Va(pub IntPtr);
+
+impl Va {
+    /// Get the address as a pointer to a `T`
+    pub const fn as_ptr<T>(self) -> *const T {
+        self.0 as usize as *const T
+    }
+
+    /// Get the address as a mutable pointer to a `T`
+    pub const fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as usize as *mut T
+    }
+
+    /// Get the address `offset` bytes away from this one
+    pub const fn offset(self, offset: isize) -> Self {
+        Self(self.0.wrapping_add_signed(offset as i32))
+    }
+}
+
+impl<T> From<*const T> for Va {
+    fn from(ptr: *const T) -> Self {
+        Self(ptr as IntPtr)
+    }
+}
+
+impl<T> From<*mut T> for Va {
+    fn from(ptr: *mut T) -> Self {
+        Self(ptr as IntPtr)
+    }
+}
+
+impl fmt::Display for Va {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", self.0)
+    }
+}
+
+/// An address relative to the base of a module
+///
+/// Games with ASLR enabled load at a different address each run, so hardcoded absolute addresses
+/// break; an `Rva` stays valid and is converted to an absolute address when it's needed. Offsets
+/// are relative to the module base (i.e. what disassemblers show when the image is rebased to 0),
+/// not to the image's preferred base address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rva {
+    /// The lowercase name of the module, e.g. "game.exe"
+    pub module: Cow<'static, str>,
+    /// The offset from the module base
+    pub offset: u32,
+}
+
+impl Rva {
+    /// Create an RVA in the named module
+    ///
+    /// This is a `const fn` so RVAs can be declared as constants alongside other game data.
+    pub const fn new(module: &'static str, offset: u32) -> Self {
+        Self {
+            module: Cow::Borrowed(module),
+            offset,
+        }
+    }
+
+    /// Get the absolute address of this RVA using a searcher's discovered modules
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module hasn't been discovered or the offset lies outside it.
+    pub fn resolve(&self, searcher: &ByteSearcher) -> Result<Va> {
+        let (start, end) = searcher
+            .module_range(&self.module)
+            .ok_or_else(|| Hook86Error::ModuleNotFound { module: self.module.to_string() })?;
+        // compare offsets rather than addresses, which can wrap around the top of memory
+        if self.offset as usize >= end as usize - start as usize {
+            return Err(Hook86Error::RvaOutOfRange {
+                module: self.module.to_string(),
+                offset: self.offset,
+            });
+        }
+
+        Ok(Va::from(start.wrapping_byte_add(self.offset as usize)))
+    }
+}
+
+impl From<ModuleOffset> for Rva {
+    fn from(location: ModuleOffset) -> Self {
+        Self {
+            module: Cow::Owned(location.module),
+            offset: location.offset as u32,
+        }
+    }
+}
+
+impl fmt::Display for Rva {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:X}", self.module, self.offset)
+    }
+}
+
+static GLOBAL: LazyLock<AddressBook> = LazyLock::new(AddressBook::new);
+
+/// A registry of resolved addresses by name
+///
+/// Fill it in during initialization, from signature scans or hardcoded addresses, and any part of
+/// the mod can then look addresses up by name instead of having them passed around. Most mods only
+/// need the one returned by `AddressBook::global`.
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    entries: RwLock<HashMap<String, usize>>,
+}
+
+impl AddressBook {
+    /// Create a new, empty address book
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the process-wide address book
+    pub fn global() -> &'static Self {
+        &GLOBAL
+    }
+
+    /// Record an address under a name, replacing any address already recorded under that name
+    pub fn insert(&self, name: impl Into<String>, address: *const c_void) {
+        self.entries
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.into(), address as usize);
+    }
+
+    /// Record several named addresses at once
+    pub fn extend<S: Into<String>>(&self, entries: impl IntoIterator<Item = (S, *const c_void)>) {
+        let mut book = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        book.extend(entries.into_iter().map(|(name, address)| (name.into(), address as usize)));
+    }
+
+    /// Remove the address recorded under a name, returning it if there was one
+    pub fn remove(&self, name: &str) -> Option<*const c_void> {
+        self.entries
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name)
+            .map(|address| address as *const c_void)
+    }
+
+    /// Look up the address recorded under a name
+    pub fn get(&self, name: &str) -> Option<*const c_void> {
+        self.entries
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .map(|&address| address as *const c_void)
+    }
+
+    /// Check whether an address has been recorded under a name
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Look up the address recorded under a name, failing if there isn't one
+    ///
+    /// # Errors
+    ///
+    /// Returns `Hook86Error::UnknownAddress` if no address was recorded under the name.
+    pub fn require(&self, name: &str) -> Result<*const c_void> {
+        self.get(name).ok_or_else(|| Hook86Error::UnknownAddress { name: name.to_string() })
+    }
+
+    /// Look up the address recorded under a name as a pointer to a `T`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Hook86Error::UnknownAddress` if no address was recorded under the name.
+    pub fn ptr<T>(&self, name: &str) -> Result<*mut T> {
+        self.require(name).map(|address| address as *mut T)
+    }
+
+    /// Look up the address recorded under a name as a function pointer
+    ///
+    /// `T` must be a function pointer type, e.g. `extern "thiscall" fn(*mut Player, u32) -> bool`.
+    ///
+    /// # Safety
+    ///
+    /// The returned function pointer is only as good as the recorded address. It must point to a
+    /// function with exactly the signature and calling convention of `T`, and `T` must be a
+    /// function pointer type; anything else pointer-sized gets an address transmuted into it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Hook86Error::UnknownAddress` if no address was recorded under the name, or
+    /// `Hook86Error::NotFunctionPointer` if `T` isn't the size of a pointer.
+    pub unsafe fn fn_ptr<T: Copy>(&self, name: &str) -> Result<T> {
+        if size_of::<T>() != size_of::<*const c_void>() {
+            return Err(Hook86Error::NotFunctionPointer {
+                name: name.to_string(),
+                type_name: std::any::type_name::<T>(),
+            });
+        }
+
+        let address = self.require(name)?;
+        Ok(unsafe { std::mem::transmute_copy(&address) })
+    }
+
+    /// Get a copy of every recorded name and address
+    pub fn entries(&self) -> Vec<(String, *const c_void)> {
+        self.entries
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, &address)| (name.clone(), address as *const c_void))
+            .collect()
+    }
+
+    /// Remove every recorded address
+    pub fn clear(&self) {
+        self.entries.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::core::w;
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+
+    use super::*;
+
+    #[test]
+    fn va() {
+        let value = 5u32;
+        let va = Va::from(&raw const value);
+        assert_eq!(va.as_ptr::<u32>(), &raw const value);
+        assert_eq!(va.as_mut_ptr::<u32>() as *const u32, &raw const value);
+        assert_eq!(Va(0x1000).offset(0x10), Va(0x1010));
+        assert_eq!(Va(0x1000).offset(-0x10), Va(0xFF0));
+        assert_eq!(Va(0x401000).to_string(), "00401000");
+    }
+
+    #[test]
+    fn rva() {
+        let rva = Rva::new("kernel32.dll", 0x1234);
+        assert_eq!(rva.to_string(), "kernel32.dll+1234");
+        let location = ModuleOffset {
+            module: String::from("game.exe"),
+            offset: 0x5678,
+        };
+        assert_eq!(Rva::from(location), Rva::new("game.exe", 0x5678));
+
+        let mut searcher = ByteSearcher::new();
+        assert!(matches!(rva.resolve(&searcher), Err(Hook86Error::ModuleNotFound { .. })));
+        searcher.discover_modules().unwrap();
+        let base = unsafe { GetModuleHandleW(w!("kernel32.dll")) }.unwrap().0 as *const c_void;
+        assert_eq!(rva.resolve(&searcher).unwrap(), Va::from(base.wrapping_byte_add(0x1234)));
+        // the module's name is matched case-insensitively, like everywhere else
+        assert_eq!(Rva::new("KERNEL32.DLL", 0).resolve(&searcher).unwrap(), Va::from(base));
+        assert!(matches!(
+            Rva::new("kernel32.dll", u32::MAX).resolve(&searcher),
+            Err(Hook86Error::RvaOutOfRange { offset: u32::MAX, .. })
+        ));
+    }
+}
@@ -4,6 +4,47 @@ use thiserror::Error;
 
 /// The opcode of the nop instruction
 pub const NOP: u8 = 0x90;
+/// The opcode of the int3 (breakpoint) instruction
+pub const INT3: u8 = 0xCC;
+
+/// A 32-bit general-purpose register
+///
+/// The discriminant of each variant is the register's number in ModRM and opcode encodings.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Eax = 0,
+    Ecx = 1,
+    Edx = 2,
+    Ebx = 3,
+    Esp = 4,
+    Ebp = 5,
+    Esi = 6,
+    Edi = 7,
+}
+
+impl Reg {
+    /// The register's number in ModRM and opcode encodings
+    pub const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the register with the given number in ModRM and opcode encodings
+    ///
+    /// Only the low 3 bits of `code` are considered.
+    pub const fn from_code(code: u8) -> Self {
+        match code & 7 {
+            0 => Self::Eax,
+            1 => Self::Ecx,
+            2 => Self::Edx,
+            3 => Self::Ebx,
+            4 => Self::Esp,
+            5 => Self::Ebp,
+            6 => Self::Esi,
+            _ => Self::Edi,
+        }
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum UnexpectedOpcodeError {
@@ -13,6 +54,11 @@ pub enum UnexpectedOpcodeError {
     DoubleByteOpcode { ptr: *const c_void, opcode1: u8, opcode2: u8 },
 }
 
+// the pointers only identify where the opcode was found and are never dereferenced, and they can't
+// be stored as integers because the functions that return this error are const
+unsafe impl Send for UnexpectedOpcodeError {}
+unsafe impl Sync for UnexpectedOpcodeError {}
+
 /// Get an absolute address from an instruction containing a 32-bit relative offset
 ///
 /// # Arguments
@@ -21,19 +67,23 @@ pub enum UnexpectedOpcodeError {
 /// * `N` - Size of the instruction in bytes
 pub const unsafe fn get_absolute_from_rel32<const N: isize>(ptr: *const c_void) -> *const c_void {
     unsafe {
-        let original_jump_offset = std::ptr::read_unaligned(ptr.offset(N - 4) as *const isize) + N;
+        let original_jump_offset = std::ptr::read_unaligned(ptr.offset(N - 4) as *const i32) as isize + N;
         ptr.offset(original_jump_offset)
     }
 }
 
 /// Get an absolute address from an instruction containing an 8-bit relative offset
 ///
+/// The instruction is assumed to be 2 bytes long (a one-byte opcode followed by the offset). Like
+/// the CPU, and like `get_absolute_from_rel32`, the offset is taken relative to the end of the
+/// instruction, so `EB FE` (a jump to itself) returns `ptr`.
+///
 /// # Arguments
 ///
 /// * `ptr` - A pointer to the start of the instruction (NOT the relative offset within the instruction)
 pub const unsafe fn get_absolute_from_rel8(ptr: *const c_void) -> *const c_void {
     unsafe {
-        let original_jump_offset = std::ptr::read_unaligned((ptr as *const i8).offset(1)) as isize;
+        let original_jump_offset = std::ptr::read_unaligned((ptr as *const i8).offset(1)) as isize + 2;
         ptr.offset(original_jump_offset)
     }
 }
@@ -75,6 +125,556 @@ pub const unsafe fn get_branch_target(ptr: *const c_void) -> Result<*const c_voi
     }
 }
 
+/// The type of control transfer performed by a branch instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BranchKind {
+    Call,
+    Jump,
+    ConditionalJump,
+}
+
+/// A decoded branch instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchInfo {
+    /// Whether the instruction calls, jumps, or conditionally jumps
+    pub kind: BranchKind,
+    /// The length of the instruction in bytes
+    pub length: usize,
+    /// Whether the destination is read from a register or memory rather than encoded in the instruction
+    pub indirect: bool,
+    /// The absolute address of the destination, if it can be determined without executing the code
+    pub target: Option<*const c_void>,
+}
+
+/// Get the number of bytes in a ModRM operand (the ModRM byte itself plus any SIB byte and
+/// displacement), assuming 32-bit addressing
+const unsafe fn modrm_len(ptr: *const u8) -> usize {
+    let modrm = unsafe { *ptr };
+    let mode = modrm >> 6;
+    let rm = modrm & 7;
+    if mode == 3 {
+        return 1;
+    }
+
+    let mut len = 1;
+    let mut base = rm;
+    if rm == 4 {
+        // SIB byte follows
+        len += 1;
+        base = unsafe { *ptr.add(1) } & 7;
+    }
+
+    len + match mode {
+        0 if base == 5 => 4,
+        0 => 0,
+        1 => 1,
+        _ => 4,
+    }
+}
+
+/// Decode the branch instruction at the given address
+///
+/// In addition to everything supported by `get_branch_target`, this recognizes indirect calls and
+/// jumps through a register or memory operand (FF /2 and FF /4). For the `[disp32]` memory form,
+/// the pointer slot is dereferenced to find the target; for other indirect forms, the target is
+/// `None` because it depends on register values.
+///
+/// # Safety
+///
+/// The whole instruction at `ptr` must be readable, up to 7 bytes for a far branch. For the
+/// `[disp32]` form, the pointer slot it refers to must be readable too.
+///
+/// # Errors
+///
+/// An UnexpectedOpcodeError is returned if the instruction at the provided location is not a
+/// supported branch instruction.
+pub unsafe fn decode_branch(ptr: *const c_void) -> Result<BranchInfo, UnexpectedOpcodeError> {
+    let byte_ptr = ptr as *const u8;
+    unsafe {
+        let opcode = *byte_ptr;
+        let (kind, length) = match opcode {
+            0xE8 => (BranchKind::Call, 5),
+            0xE9 => (BranchKind::Jump, 5),
+            0x9A => (BranchKind::Call, 7),
+            0xEA => (BranchKind::Jump, 7),
+            0xEB => (BranchKind::Jump, 2),
+            // jcc rel8, loop/loope/loopne, and jecxz
+            0x70..=0x7F | 0xE0..=0xE3 => (BranchKind::ConditionalJump, 2),
+            0x0F => {
+                let sub_opcode = *byte_ptr.add(1);
+                if !(0x80..=0x8F).contains(&sub_opcode) {
+                    return Err(UnexpectedOpcodeError::DoubleByteOpcode { ptr, opcode1: opcode, opcode2: sub_opcode });
+                }
+                (BranchKind::ConditionalJump, 6)
+            }
+            0xFF => {
+                let modrm = *byte_ptr.add(1);
+                let kind = match (modrm >> 3) & 7 {
+                    2 => BranchKind::Call,
+                    4 => BranchKind::Jump,
+                    _ => return Err(UnexpectedOpcodeError::DoubleByteOpcode { ptr, opcode1: opcode, opcode2: modrm }),
+                };
+                let length = 1 + modrm_len(byte_ptr.add(1));
+                // only the absolute [disp32] form can be resolved statically
+                let target = if modrm & 0xC7 == 0x05 {
+                    let slot = std::ptr::read_unaligned(byte_ptr.add(2) as *const usize) as *const *const c_void;
+                    Some(std::ptr::read_unaligned(slot))
+                } else {
+                    None
+                };
+                return Ok(BranchInfo { kind, length, indirect: true, target });
+            }
+            _ => return Err(UnexpectedOpcodeError::SingleByteOpcode { ptr, opcode }),
+        };
+
+        let target = match length {
+            2 => get_absolute_from_rel8(ptr),
+            5 => get_absolute_from_rel32::<5>(ptr),
+            6 => get_absolute_from_rel32::<6>(ptr),
+            _ => std::ptr::read_unaligned(byte_ptr.add(1) as *const *const c_void),
+        };
+
+        Ok(BranchInfo { kind, length, indirect: false, target: Some(target) })
+    }
+}
+
+/// How a piece of code refers to an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    /// A call or jump with a 32-bit relative offset to the address
+    Branch(BranchKind),
+    /// A 32-bit value equal to the address, such as an immediate or a memory displacement
+    Absolute,
+}
+
+/// A location in code that refers to an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CodeReference {
+    /// For branches, the address of the branch instruction. For absolute references, the address
+    /// of the 32-bit value, which is somewhere inside the referencing instruction.
+    pub address: usize,
+    /// How the code refers to the address
+    pub kind: ReferenceKind,
+}
+
+/// Find every place in a block of code that refers to the target address
+///
+/// This recognizes `call rel32`, `jmp rel32`, and `jcc rel32` instructions that branch to the
+/// target, and any 32-bit value equal to the target (e.g. `push imm32`, `mov eax, [disp32]`).
+/// The code isn't disassembled, so every byte offset is considered; expect occasional false
+/// positives where the bytes happen to line up in the middle of other instructions or data.
+///
+/// `code_address` is the address that `code` is (or will be) located at.
+pub fn find_references(code: &[u8], code_address: usize, target: usize) -> impl Iterator<Item = CodeReference> + '_ {
+    let read_u32 = move |offset: usize| code.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    (0..code.len()).flat_map(move |offset| {
+        let address = code_address.wrapping_add(offset);
+        let branch = match code[offset] {
+            0xE8 => Some((BranchKind::Call, 1)),
+            0xE9 => Some((BranchKind::Jump, 1)),
+            0x0F if code.get(offset + 1).is_some_and(|&b| b & 0xF0 == 0x80) => Some((BranchKind::ConditionalJump, 2)),
+            _ => None,
+        }
+        .filter(|&(_, opcode_len)| {
+            read_u32(offset + opcode_len).is_some_and(|rel| {
+                let next = address.wrapping_add(opcode_len + 4);
+                next.wrapping_add_signed(rel as i32 as isize) == target
+            })
+        })
+        .map(|(kind, _)| CodeReference { address, kind: ReferenceKind::Branch(kind) });
+
+        let absolute = read_u32(offset)
+            .filter(|&value| value as usize == target)
+            .map(|_| CodeReference { address, kind: ReferenceKind::Absolute });
+
+        branch.into_iter().chain(absolute)
+    })
+}
+
+/// The layout of a decoded instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionInfo {
+    /// The total length of the instruction in bytes
+    pub length: usize,
+    /// The number of prefix bytes
+    pub prefix_len: usize,
+    /// The opcode bytes (up to 3), excluding prefixes
+    pub opcode: [u8; 3],
+    /// The number of opcode bytes
+    pub opcode_len: usize,
+    /// The offset of the ModRM byte from the start of the instruction, if the instruction has one
+    pub modrm_offset: Option<usize>,
+    /// The length of the ModRM operand (ModRM, SIB, and displacement) in bytes
+    pub modrm_len: usize,
+    /// The size of the immediate operand (or relative offset) at the end of the instruction in bytes
+    pub imm_len: usize,
+    /// Whether the instruction uses 16-bit addressing (0x67 prefix)
+    pub addr16: bool,
+}
+
+impl InstructionInfo {
+    /// The offset of the immediate operand from the start of the instruction, if there is one
+    pub const fn imm_offset(&self) -> Option<usize> {
+        if self.imm_len > 0 {
+            Some(self.length - self.imm_len)
+        } else {
+            None
+        }
+    }
+}
+
+/// Get the number of bytes in a ModRM operand using 16-bit addressing
+const unsafe fn modrm_len16(ptr: *const u8) -> usize {
+    let modrm = unsafe { *ptr };
+    match modrm >> 6 {
+        0 if modrm & 7 == 6 => 3,
+        0 | 3 => 1,
+        1 => 2,
+        _ => 3,
+    }
+}
+
+/// Check whether a one-byte opcode is followed by a ModRM byte
+const fn has_modrm(opcode: u8) -> bool {
+    match opcode {
+        0x00..=0x3F => opcode & 0x04 == 0,
+        0x62 | 0x63 | 0x69 | 0x6B | 0x80..=0x8F | 0xC0 | 0xC1 | 0xC4..=0xC7 | 0xD0..=0xD3 | 0xD8..=0xDF
+        | 0xF6 | 0xF7 | 0xFE | 0xFF => true,
+        _ => false,
+    }
+}
+
+/// Check whether a two-byte (0x0F-prefixed) opcode is followed by a ModRM byte
+const fn has_modrm_0f(opcode: u8) -> bool {
+    !matches!(
+        opcode,
+        0x05..=0x09 | 0x0B | 0x0E | 0x30..=0x37 | 0x77 | 0x80..=0x8F | 0xA0..=0xA2 | 0xA8..=0xAA | 0xC8..=0xCF
+    )
+}
+
+/// Decode the layout of the instruction at the given address
+///
+/// This is a length decoder, not a disassembler: it determines where the prefixes, opcode, ModRM
+/// operand, and immediate are located, but it doesn't validate that the opcode is defined. It
+/// understands the general-purpose, x87, MMX, and SSE instructions found in typical 32-bit game
+/// code.
+///
+/// # Safety
+///
+/// The whole instruction at `ptr` must be readable, which can be up to 15 bytes.
+pub unsafe fn decode_instruction(ptr: *const c_void) -> InstructionInfo {
+    let bytes = ptr as *const u8;
+    unsafe {
+        let mut pos = 0;
+        let mut op16 = false;
+        let mut addr16 = false;
+        loop {
+            match *bytes.add(pos) {
+                0x66 => op16 = true,
+                0x67 => addr16 = true,
+                0xF0 | 0xF2 | 0xF3 | 0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65 => (),
+                _ => break,
+            }
+            pos += 1;
+        }
+        let prefix_len = pos;
+        let imm_word = if op16 { 2 } else { 4 };
+        let addr_word = if addr16 { 2 } else { 4 };
+
+        let mut opcode = [*bytes.add(pos), 0, 0];
+        let mut opcode_len = 1;
+        pos += 1;
+
+        let (modrm, imm_len) = if opcode[0] == 0x0F {
+            opcode[1] = *bytes.add(pos);
+            opcode_len = 2;
+            pos += 1;
+            match opcode[1] {
+                0x38 | 0x3A => {
+                    opcode[2] = *bytes.add(pos);
+                    opcode_len = 3;
+                    pos += 1;
+                    (true, if opcode[1] == 0x3A { 1 } else { 0 })
+                }
+                0x80..=0x8F => (false, imm_word),
+                0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 => (true, 1),
+                op => (has_modrm_0f(op), 0),
+            }
+        } else {
+            let op = opcode[0];
+            let imm_len = match op {
+                0x00..=0x3F if op & 0x07 == 0x04 => 1,
+                0x00..=0x3F if op & 0x07 == 0x05 => imm_word,
+                0x68 | 0x69 | 0x81 | 0xA9 | 0xB8..=0xBF | 0xC7 | 0xE8 | 0xE9 => imm_word,
+                0x6A | 0x6B | 0x70..=0x7F | 0x80 | 0x82 | 0x83 | 0xA8 | 0xB0..=0xB7 | 0xC0 | 0xC1 | 0xC6
+                | 0xCD | 0xD4 | 0xD5 | 0xE0..=0xE7 | 0xEB => 1,
+                0xA0..=0xA3 => addr_word,
+                0xC2 | 0xCA => 2,
+                0xC8 => 3,
+                0x9A | 0xEA => imm_word + 2,
+                // test r/m, imm is the only member of the F6/F7 groups with an immediate
+                0xF6 if (*bytes.add(pos) >> 3) & 7 < 2 => 1,
+                0xF7 if (*bytes.add(pos) >> 3) & 7 < 2 => imm_word,
+                _ => 0,
+            };
+            (has_modrm(op), imm_len)
+        };
+
+        let (modrm_offset, modrm_len) = if modrm {
+            let len = if addr16 { modrm_len16(bytes.add(pos)) } else { modrm_len(bytes.add(pos)) };
+            (Some(pos), len)
+        } else {
+            (None, 0)
+        };
+
+        InstructionInfo {
+            length: pos + modrm_len + imm_len,
+            prefix_len,
+            opcode,
+            opcode_len,
+            modrm_offset,
+            modrm_len,
+            imm_len,
+            addr16,
+        }
+    }
+}
+
+/// Measure the whole instructions covering at least `min_len` bytes at the given address, for
+/// copying them somewhere else to re-execute
+///
+/// Returns `None` if the instructions contain a relative branch or call, since a copy would branch
+/// to the wrong address.
+///
+/// # Safety
+///
+/// The instructions at `ptr` must be readable, through the end of the one that reaches `min_len`.
+pub unsafe fn relocatable_length(ptr: *const c_void, min_len: usize) -> Option<usize> {
+    let mut len = 0;
+    while len < min_len {
+        let instruction = ptr.wrapping_byte_add(len);
+        if unsafe { decode_branch(instruction) }.is_ok_and(|branch| !branch.indirect) {
+            return None;
+        }
+        len += unsafe { decode_instruction(instruction) }.length;
+    }
+
+    Some(len)
+}
+
+/// A memory operand of the form `[base + index*scale + disp]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryOperand {
+    pub base: Option<Reg>,
+    pub index: Option<Reg>,
+    pub scale: u8,
+    pub disp: i32,
+}
+
+impl MemoryOperand {
+    /// The absolute address referenced by the operand, if it doesn't depend on any registers
+    pub const fn absolute(&self) -> Option<usize> {
+        if self.base.is_none() && self.index.is_none() {
+            Some(self.disp as u32 as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extract the memory operand of the instruction at the given address
+///
+/// For example, this will return `[0xDEADBEEF]` for `mov eax, [0xDEADBEEF]` and `[esi+0x30]` for
+/// `cmp dword ptr [esi+0x30], 1`. Returns `None` if the instruction has no memory operand or uses
+/// 16-bit addressing.
+///
+/// # Safety
+///
+/// See `decode_instruction`.
+pub unsafe fn memory_operand(ptr: *const c_void) -> Option<MemoryOperand> {
+    let info = unsafe { decode_instruction(ptr) };
+    if info.addr16 {
+        return None;
+    }
+
+    let bytes = ptr as *const u8;
+    let Some(modrm_offset) = info.modrm_offset else {
+        // mov with a moffs32 operand (A0-A3) is the only form of absolute address without a ModRM
+        if info.opcode_len == 1 && matches!(info.opcode[0], 0xA0..=0xA3) {
+            let disp = unsafe { std::ptr::read_unaligned(bytes.add(info.prefix_len + 1) as *const i32) };
+            return Some(MemoryOperand { base: None, index: None, scale: 1, disp });
+        }
+        return None;
+    };
+
+    let modrm = unsafe { *bytes.add(modrm_offset) };
+    let mode = modrm >> 6;
+    if mode == 3 {
+        return None;
+    }
+
+    let mut operand = MemoryOperand { base: Some(Reg::from_code(modrm)), index: None, scale: 1, disp: 0 };
+    let mut disp_offset = modrm_offset + 1;
+    let mut no_base = mode == 0 && modrm & 7 == 5;
+    if modrm & 7 == 4 {
+        let sib = unsafe { *bytes.add(disp_offset) };
+        disp_offset += 1;
+        operand.scale = 1 << (sib >> 6);
+        operand.base = Some(Reg::from_code(sib));
+        // an index of esp means no index
+        if (sib >> 3) & 7 != 4 {
+            operand.index = Some(Reg::from_code(sib >> 3));
+        }
+        no_base = mode == 0 && sib & 7 == 5;
+    }
+
+    if no_base {
+        operand.base = None;
+    }
+
+    operand.disp = unsafe {
+        match mode {
+            1 => *(bytes.add(disp_offset) as *const i8) as i32,
+            2 => std::ptr::read_unaligned(bytes.add(disp_offset) as *const i32),
+            _ if no_base => std::ptr::read_unaligned(bytes.add(disp_offset) as *const i32),
+            _ => 0,
+        }
+    };
+
+    Some(operand)
+}
+
+/// Check whether the bytes at the given address are a standard `push ebp; mov ebp, esp` prologue
+const unsafe fn is_frame_prologue(ptr: *const u8) -> bool {
+    unsafe {
+        *ptr == 0x55 && ((*ptr.add(1) == 0x8B && *ptr.add(2) == 0xEC) || (*ptr.add(1) == 0x89 && *ptr.add(2) == 0xE5))
+    }
+}
+
+/// Find the probable start of the function containing the given address
+///
+/// This walks backwards from `ptr` looking for either a standard `push ebp; mov ebp, esp`
+/// prologue (including a preceding `mov edi, edi` hotpatch point, if present) or a 16-byte
+/// aligned address preceded by int3 or nop padding, whichever comes first. It's a heuristic:
+/// functions that don't set up a frame pointer and aren't padded won't be found, and data that
+/// happens to look like a prologue can produce false positives.
+///
+/// # Arguments
+///
+/// * `ptr` - An address inside the function
+/// * `max_distance` - The maximum number of bytes to search backwards. All memory from
+///   `ptr - max_distance` to `ptr + 2` must be readable.
+///
+/// # Safety
+///
+/// The memory described under `max_distance` must be readable. Nothing is checked.
+pub unsafe fn find_function_start(ptr: *const c_void, max_distance: usize) -> Option<*const c_void> {
+    let start = ptr as *const u8;
+    let lower_bound = (start as usize).saturating_sub(max_distance);
+    let mut addr = start as usize;
+    loop {
+        let p = addr as *const u8;
+        unsafe {
+            if is_frame_prologue(p) {
+                let hotpatch = addr >= lower_bound + 2 && *p.sub(2) == 0x8B && *p.sub(1) == 0xFF;
+                return Some(if hotpatch { p.sub(2) } else { p } as *const c_void);
+            }
+
+            if addr.is_multiple_of(16) && addr > lower_bound && matches!(*p.sub(1), INT3 | NOP) {
+                return Some(p as *const c_void);
+            }
+        }
+
+        if addr == lower_bound {
+            return None;
+        }
+        addr -= 1;
+    }
+}
+
+/// Estimate the length of the function starting at the given address
+///
+/// This decodes instructions forward from `ptr`, keeping track of the furthest forward branch
+/// target seen so far. The function is considered to end after a ret or unconditional jmp that
+/// isn't skipped over by an earlier branch and is followed by int3/nop padding or another
+/// function's prologue. Functions that are immediately followed by other code without padding,
+/// or that contain jump tables, may be misjudged.
+///
+/// # Arguments
+///
+/// * `ptr` - The start of the function
+/// * `max_len` - The maximum length to consider. All memory from `ptr` to `ptr + max_len` must be
+///   readable.
+///
+/// # Return
+///
+/// The estimated length of the function in bytes, or `None` if no end was found within `max_len`
+/// bytes.
+///
+/// # Safety
+///
+/// The memory described under `max_len` must be readable. Nothing is checked, and the last
+/// instruction may be read past `max_len` before its length is known.
+pub unsafe fn estimate_function_length(ptr: *const c_void, max_len: usize) -> Option<usize> {
+    let start = ptr as *const u8;
+    let mut offset = 0;
+    let mut furthest_target = 0;
+    while offset < max_len {
+        let p = unsafe { start.add(offset) };
+        let info = unsafe { decode_instruction(p as *const c_void) };
+        let next = offset + info.length;
+        if next > max_len {
+            return None;
+        }
+
+        let op = info.opcode;
+        let is_rel_branch = match info.opcode_len {
+            1 => matches!(op[0], 0x70..=0x7F | 0xE0..=0xE3 | 0xE9 | 0xEB),
+            2 => matches!(op[1], 0x80..=0x8F),
+            _ => false,
+        };
+        if is_rel_branch {
+            let rel = unsafe {
+                let imm = p.add(info.length - info.imm_len);
+                if info.imm_len == 1 {
+                    *(imm as *const i8) as isize
+                } else {
+                    std::ptr::read_unaligned(imm as *const i32) as isize
+                }
+            };
+            let target = next as isize + rel;
+            if target > offset as isize && (target as usize) < max_len {
+                furthest_target = furthest_target.max(target as usize);
+            }
+        }
+
+        let is_terminator = match info.opcode_len {
+            1 => match op[0] {
+                0xC2 | 0xC3 | 0xCA | 0xCB | 0xE9 | 0xEB => true,
+                0xFF => info.modrm_offset.is_some_and(|m| (unsafe { *p.add(m) } >> 3) & 7 == 4),
+                _ => false,
+            },
+            _ => false,
+        };
+        if is_terminator && next >= furthest_target {
+            if next == max_len {
+                return Some(next);
+            }
+
+            let following = unsafe { start.add(next) };
+            if matches!(unsafe { *following }, INT3 | NOP)
+                || (next + 3 <= max_len && unsafe { is_frame_prologue(following) })
+            {
+                return Some(next);
+            }
+        }
+
+        offset = next;
+    }
+
+    None
+}
+
 /// Get the relative offset between two addresses as a byte array
 const fn addr_offset<const N: usize>(
     from: usize,
@@ -98,6 +698,32 @@ pub const fn jmp(from: usize, to: usize) -> [u8; 5] {
     [0xE9, bytes[0], bytes[1], bytes[2], bytes[3]]
 }
 
+/// Get the bytes of a call instruction that calls the address stored in a pointer slot
+///
+/// The encoded instruction is `call dword ptr [slot]`.
+pub const fn call_indirect(slot: usize) -> [u8; 6] {
+    let bytes = slot.to_le_bytes();
+    [0xFF, 0x15, bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Get the bytes of a jump instruction that jumps to the address stored in a pointer slot
+///
+/// The encoded instruction is `jmp dword ptr [slot]`.
+pub const fn jmp_indirect(slot: usize) -> [u8; 6] {
+    let bytes = slot.to_le_bytes();
+    [0xFF, 0x25, bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Get the bytes of a call instruction that calls the address held in a register
+pub const fn call_reg(reg: Reg) -> [u8; 2] {
+    [0xFF, 0xD0 | reg.code()]
+}
+
+/// Get the bytes of a jump instruction that jumps to the address held in a register
+pub const fn jmp_reg(reg: Reg) -> [u8; 2] {
+    [0xFF, 0xE0 | reg.code()]
+}
+
 const fn cond_jmp(from: usize, to: usize, cond: u8) -> [u8; 6] {
     let bytes = addr_offset::<6>(from, to);
     [0x0F, cond, bytes[0], bytes[1], bytes[2], bytes[3]]
@@ -127,12 +753,181 @@ pub const fn jge(from: usize, to: usize) -> [u8; 6] {
     cond_jmp(from, to, 0x8D)
 }
 
+/// A branch instruction that may have been encoded in either short or near form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoBranch {
+    bytes: [u8; 6],
+    len: usize,
+}
+
+impl AutoBranch {
+    const fn short(opcode: u8, disp: i8) -> Self {
+        Self {
+            bytes: [opcode, disp as u8, 0, 0, 0, 0],
+            len: 2,
+        }
+    }
+
+    /// The length of the encoded instruction in bytes
+    pub const fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the instruction was encoded with an 8-bit offset
+    pub const fn is_short(&self) -> bool {
+        self.len == 2
+    }
+
+    /// The bytes of the encoded instruction
+    pub const fn as_bytes(&self) -> &[u8] {
+        self.bytes.split_at(self.len).0
+    }
+}
+
+/// Get the offset for a 2-byte short branch from one address to another, if it fits in 8 bits
+const fn short_offset(from: usize, to: usize) -> Option<i8> {
+    let disp = to.wrapping_sub(from + 2) as isize;
+    if disp >= i8::MIN as isize && disp <= i8::MAX as isize {
+        Some(disp as i8)
+    } else {
+        None
+    }
+}
+
+/// Get the bytes of an unconditional jump instruction from one address to another, using the
+/// shortest available encoding
+///
+/// The returned instruction is 2 bytes if the displacement fits in an 8-bit offset and 5 bytes
+/// otherwise.
+pub const fn jmp_auto(from: usize, to: usize) -> AutoBranch {
+    match short_offset(from, to) {
+        Some(disp) => AutoBranch::short(0xEB, disp),
+        None => {
+            let b = jmp(from, to);
+            AutoBranch {
+                bytes: [b[0], b[1], b[2], b[3], b[4], 0],
+                len: 5,
+            }
+        }
+    }
+}
+
+const fn cond_jmp_auto(from: usize, to: usize, cond: u8) -> AutoBranch {
+    match short_offset(from, to) {
+        // the short form of each conditional jump is 0x10 less than the second byte of the near form
+        Some(disp) => AutoBranch::short(cond - 0x10, disp),
+        None => AutoBranch {
+            bytes: cond_jmp(from, to, cond),
+            len: 6,
+        },
+    }
+}
+
+/// Get the bytes of a jz instruction from one address to another, using the shortest available
+/// encoding
+///
+/// The returned instruction is 2 bytes if the displacement fits in an 8-bit offset and 6 bytes
+/// otherwise.
+pub const fn jz_auto(from: usize, to: usize) -> AutoBranch {
+    cond_jmp_auto(from, to, 0x84)
+}
+
+/// Get the bytes of a jl instruction from one address to another, using the shortest available
+/// encoding
+///
+/// The returned instruction is 2 bytes if the displacement fits in an 8-bit offset and 6 bytes
+/// otherwise.
+pub const fn jl_auto(from: usize, to: usize) -> AutoBranch {
+    cond_jmp_auto(from, to, 0x8C)
+}
+
+/// Get the bytes of a jge instruction from one address to another, using the shortest available
+/// encoding
+///
+/// The returned instruction is 2 bytes if the displacement fits in an 8-bit offset and 6 bytes
+/// otherwise.
+pub const fn jge_auto(from: usize, to: usize) -> AutoBranch {
+    cond_jmp_auto(from, to, 0x8D)
+}
+
+/// Get the bytes of a ret instruction that pops the given number of bytes of arguments off the stack
+///
+/// This is the return used by stdcall and thiscall functions, which clean up their own arguments.
+pub const fn ret_n(bytes: u16) -> [u8; 3] {
+    let imm = bytes.to_le_bytes();
+    [0xC2, imm[0], imm[1]]
+}
+
+/// Get the bytes of a standard stack frame prologue
+///
+/// The encoded instructions are `push ebp; mov ebp, esp; sub esp, locals`. `push ebp; mov ebp, esp`
+/// uses the same encoding as MSVC (`55 8B EC`) so that debuggers and stack walkers recognize the
+/// frame. Unlike MSVC, which uses `83 EC imm8` for small frames, `sub` always uses a 32-bit
+/// immediate (`81 EC imm32`), so the prologue is always 9 bytes long.
+pub const fn prologue(locals: u32) -> [u8; 9] {
+    let bytes = locals.to_le_bytes();
+    [0x55, 0x8B, 0xEC, 0x81, 0xEC, bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Get the bytes of a standard stack frame epilogue for a function whose caller cleans up the stack
+///
+/// The encoded instructions are `mov esp, ebp; pop ebp; ret`.
+pub const fn epilogue() -> [u8; 4] {
+    [0x8B, 0xE5, 0x5D, 0xC3]
+}
+
+/// Get the bytes of a standard stack frame epilogue for a function that cleans up its own arguments
+///
+/// The encoded instructions are `mov esp, ebp; pop ebp; ret arg_bytes`.
+pub const fn epilogue_n(arg_bytes: u16) -> [u8; 6] {
+    let ret = ret_n(arg_bytes);
+    [0x8B, 0xE5, 0x5D, ret[0], ret[1], ret[2]]
+}
+
 /// Get the bytes of a push instruction that pushes the provided immediate value onto the stack
 pub const fn push(imm: usize) -> [u8; 5] {
     let bytes = imm.to_le_bytes();
     [0x68, bytes[0], bytes[1], bytes[2], bytes[3]]
 }
 
+/// Get the bytes of a push instruction that pushes the provided register onto the stack
+pub const fn push_reg(reg: Reg) -> [u8; 1] {
+    [0x50 + reg.code()]
+}
+
+/// Get the bytes of a pop instruction that pops the top of the stack into the provided register
+pub const fn pop_reg(reg: Reg) -> [u8; 1] {
+    [0x58 + reg.code()]
+}
+
+/// Get the bytes of a mov instruction that loads an immediate value into a register
+pub const fn mov_reg_imm(reg: Reg, imm: usize) -> [u8; 5] {
+    let bytes = imm.to_le_bytes();
+    [0xB8 + reg.code(), bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Get the bytes of a mov instruction that copies one register into another
+pub const fn mov_reg_reg(dst: Reg, src: Reg) -> [u8; 2] {
+    [0x89, 0xC0 | (src.code() << 3) | dst.code()]
+}
+
+/// Get the bytes of a mov instruction that loads a register from `[base+disp]`
+///
+/// The returned instruction always uses a SIB byte and a 32-bit displacement so that its size
+/// doesn't depend on the base register or the magnitude of the displacement.
+pub const fn mov_reg_mem(dst: Reg, base: Reg, disp: i32) -> [u8; 7] {
+    let bytes = disp.to_le_bytes();
+    [0x8B, 0x84 | (dst.code() << 3), 0x20 | base.code(), bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Get the bytes of a mov instruction that stores a register to `[base+disp]`
+///
+/// Like `mov_reg_mem`, the returned instruction always uses a SIB byte and a 32-bit displacement.
+pub const fn mov_mem_reg(base: Reg, disp: i32, src: Reg) -> [u8; 7] {
+    let bytes = disp.to_le_bytes();
+    [0x89, 0x84 | (src.code() << 3), 0x20 | base.code(), bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -146,6 +941,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn references() {
+        let base = 0x401000;
+        let mut code = Vec::new();
+        code.extend_from_slice(&call(base, 0x402000));
+        code.extend_from_slice(&jz(base + 5, 0x402000));
+        code.extend_from_slice(&push(0x402000));
+        code.extend_from_slice(&jmp(base + 16, 0x403000));
+
+        let refs: Vec<_> = find_references(&code, base, 0x402000).collect();
+        assert_eq!(
+            refs,
+            [
+                CodeReference { address: base, kind: ReferenceKind::Branch(BranchKind::Call) },
+                CodeReference { address: base + 5, kind: ReferenceKind::Branch(BranchKind::ConditionalJump) },
+                CodeReference { address: base + 12, kind: ReferenceKind::Absolute },
+            ]
+        );
+    }
+
     #[test]
     fn call_bytes() {
         assert_eq!(call(0x80000000, 0x80000010), [0xE8, 11, 0, 0, 0]);
@@ -165,4 +980,187 @@ mod tests {
     fn jge_bytes() {
         assert_eq!(jge(0x80000000, 0x800000E0), [0x0F, 0x8D, 0xDA, 0, 0, 0]);
     }
+
+    #[test]
+    fn jmp_auto_short() {
+        let branch = jmp_auto(0x80000000, 0x80000010);
+        assert!(branch.is_short());
+        assert_eq!(branch.as_bytes(), [0xEB, 0x0E]);
+        assert_eq!(jmp_auto(0x80000000, 0x7FFFFF82).as_bytes(), [0xEB, 0x80]);
+    }
+
+    #[test]
+    fn jmp_auto_near() {
+        let branch = jmp_auto(0x80000000, 0x80000082);
+        assert_eq!(branch.size(), 5);
+        assert_eq!(branch.as_bytes(), jmp(0x80000000, 0x80000082));
+    }
+
+    #[test]
+    fn cond_jmp_auto_bytes() {
+        assert_eq!(jz_auto(0x80000000, 0x80000010).as_bytes(), [0x74, 0x0E]);
+        assert_eq!(jge_auto(0x80000000, 0x800000E0).as_bytes(), jge(0x80000000, 0x800000E0));
+    }
+
+    #[test]
+    fn mov_bytes() {
+        assert_eq!(mov_reg_imm(Reg::Ecx, 0x12345678), [0xB9, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(mov_reg_reg(Reg::Ebp, Reg::Esp), [0x89, 0xE5]);
+        assert_eq!(mov_reg_mem(Reg::Eax, Reg::Esp, 4), [0x8B, 0x84, 0x24, 4, 0, 0, 0]);
+        assert_eq!(mov_reg_mem(Reg::Edx, Reg::Esi, -0x10), [0x8B, 0x94, 0x26, 0xF0, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(mov_mem_reg(Reg::Ecx, 4, Reg::Eax), [0x89, 0x84, 0x21, 4, 0, 0, 0]);
+    }
+
+    #[test]
+    fn push_pop_reg_bytes() {
+        assert_eq!(push_reg(Reg::Eax), [0x50]);
+        assert_eq!(push_reg(Reg::Edi), [0x57]);
+        assert_eq!(pop_reg(Reg::Ebp), [0x5D]);
+    }
+
+    #[test]
+    fn ret_n_bytes() {
+        assert_eq!(ret_n(8), [0xC2, 8, 0]);
+        assert_eq!(ret_n(0x104), [0xC2, 4, 1]);
+    }
+
+    #[test]
+    fn indirect_bytes() {
+        assert_eq!(call_indirect(0x00401000), [0xFF, 0x15, 0x00, 0x10, 0x40, 0x00]);
+        assert_eq!(jmp_indirect(0x12345678), [0xFF, 0x25, 0x78, 0x56, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn frame_bytes() {
+        assert_eq!(prologue(0x20), [0x55, 0x8B, 0xEC, 0x81, 0xEC, 0x20, 0, 0, 0]);
+        assert_eq!(epilogue(), [0x8B, 0xE5, 0x5D, 0xC3]);
+        assert_eq!(epilogue_n(12), [0x8B, 0xE5, 0x5D, 0xC2, 12, 0]);
+    }
+
+    #[test]
+    fn reg_branch_bytes() {
+        assert_eq!(call_reg(Reg::Eax), [0xFF, 0xD0]);
+        assert_eq!(jmp_reg(Reg::Ecx), [0xFF, 0xE1]);
+    }
+
+    #[test]
+    fn branch_targets() {
+        let jmp_rel32 = [0xE9u8, 0xFB, 0xFF, 0xFF, 0xFF, 0xCC, 0xCC, 0xCC];
+        let target = unsafe { get_branch_target(jmp_rel32.as_ptr() as *const c_void) };
+        assert_eq!(target.unwrap(), jmp_rel32.as_ptr() as *const c_void);
+
+        let jz_rel32 = [0x0Fu8, 0x84, 0x10, 0, 0, 0];
+        let target = unsafe { get_branch_target(jz_rel32.as_ptr() as *const c_void) };
+        assert_eq!(target.unwrap(), jz_rel32.as_ptr().wrapping_add(0x16) as *const c_void);
+
+        let jmp_short = [0xEBu8, 0x05];
+        let target = unsafe { get_branch_target(jmp_short.as_ptr() as *const c_void) };
+        assert_eq!(target.unwrap(), jmp_short.as_ptr().wrapping_add(7) as *const c_void);
+
+        let jz_self = [0x74u8, 0xFE];
+        assert_eq!(unsafe { get_absolute_from_rel8(jz_self.as_ptr() as *const c_void) }, jz_self.as_ptr() as *const c_void);
+    }
+
+    #[test]
+    fn decode_branch_forms() {
+        let call_rel = [0xE8u8, 0x10, 0, 0, 0];
+        let info = unsafe { decode_branch(call_rel.as_ptr() as *const c_void) }.unwrap();
+        assert_eq!(info.kind, BranchKind::Call);
+        assert_eq!(info.length, 5);
+        assert_eq!(info.target, Some(call_rel.as_ptr().wrapping_add(0x15) as *const c_void));
+
+        let jnz_short = [0x75u8, 0xFE];
+        let info = unsafe { decode_branch(jnz_short.as_ptr() as *const c_void) }.unwrap();
+        assert_eq!(info.kind, BranchKind::ConditionalJump);
+        assert_eq!(info.target, Some(jnz_short.as_ptr() as *const c_void));
+
+        let call_eax = call_reg(Reg::Eax);
+        let info = unsafe { decode_branch(call_eax.as_ptr() as *const c_void) }.unwrap();
+        assert_eq!((info.kind, info.length, info.indirect, info.target), (BranchKind::Call, 2, true, None));
+
+        let jmp_mem = [0xFFu8, 0x64, 0x24, 0x08];
+        let info = unsafe { decode_branch(jmp_mem.as_ptr() as *const c_void) }.unwrap();
+        assert_eq!((info.kind, info.length, info.target), (BranchKind::Jump, 4, None));
+    }
+
+    fn decode(bytes: &[u8]) -> InstructionInfo {
+        unsafe { decode_instruction(bytes.as_ptr() as *const c_void) }
+    }
+
+    fn mem_operand(bytes: &[u8]) -> Option<MemoryOperand> {
+        unsafe { memory_operand(bytes.as_ptr() as *const c_void) }
+    }
+
+    #[test]
+    fn instruction_lengths() {
+        assert_eq!(decode(&[0x55]).length, 1); // push ebp
+        assert_eq!(decode(&[0x8B, 0xEC]).length, 2); // mov ebp, esp
+        assert_eq!(decode(&[0x83, 0xEC, 0x10]).length, 3); // sub esp, 0x10
+        assert_eq!(decode(&[0x81, 0xEC, 0, 1, 0, 0]).length, 6); // sub esp, 0x100
+        assert_eq!(decode(&[0xA1, 0xEF, 0xBE, 0xAD, 0xDE]).length, 5); // mov eax, [0xDEADBEEF]
+        assert_eq!(decode(&[0xC7, 0x44, 0x24, 0x04, 1, 0, 0, 0]).length, 8); // mov dword ptr [esp+4], 1
+        assert_eq!(decode(&[0x66, 0xC7, 0x00, 0x34, 0x12]).length, 5); // mov word ptr [eax], 0x1234
+        assert_eq!(decode(&[0x0F, 0x84, 0, 0, 0, 0]).length, 6); // jz rel32
+        assert_eq!(decode(&[0x0F, 0xB6, 0x45, 0x08]).length, 4); // movzx eax, byte ptr [ebp+8]
+        assert_eq!(decode(&[0xF3, 0x0F, 0x10, 0x05, 0, 0, 0, 0]).length, 8); // movss xmm0, [disp32]
+        assert_eq!(decode(&[0xF6, 0xC1, 0x01]).length, 3); // test cl, 1
+        assert_eq!(decode(&[0xF7, 0xD8]).length, 2); // neg eax
+        assert_eq!(decode(&[0xC2, 0x08, 0x00]).length, 3); // ret 8
+    }
+
+    #[test]
+    fn memory_operands() {
+        let absolute = mem_operand(&[0xA1, 0xEF, 0xBE, 0xAD, 0xDE]).unwrap();
+        assert_eq!(absolute.absolute(), Some(0xDEADBEEF));
+
+        let absolute = mem_operand(&[0x8B, 0x0D, 0xEF, 0xBE, 0xAD, 0xDE]).unwrap();
+        assert_eq!(absolute.absolute(), Some(0xDEADBEEF));
+
+        let relative = mem_operand(&[0x83, 0x7E, 0x30, 0x01]).unwrap(); // cmp dword ptr [esi+0x30], 1
+        assert_eq!(relative, MemoryOperand { base: Some(Reg::Esi), index: None, scale: 1, disp: 0x30 });
+
+        let sib = mem_operand(&[0x8B, 0x04, 0x8D, 0x00, 0x10, 0x40, 0x00]).unwrap(); // mov eax, [ecx*4+0x401000]
+        assert_eq!(sib, MemoryOperand { base: None, index: Some(Reg::Ecx), scale: 4, disp: 0x401000 });
+
+        assert_eq!(mem_operand(&[0x8B, 0xEC]), None);
+    }
+
+    #[repr(align(16))]
+    struct AlignedCode([u8; 32]);
+
+    #[test]
+    fn function_start_from_prologue() {
+        let code = [0x90u8, 0x8B, 0xFF, 0x55, 0x8B, 0xEC, 0x83, 0xEC, 0x10, 0x33, 0xC0];
+        let inside = code.as_ptr().wrapping_add(9) as *const c_void;
+        let start = unsafe { find_function_start(inside, 9) };
+        assert_eq!(start, Some(code.as_ptr().wrapping_add(1) as *const c_void));
+        assert_eq!(unsafe { find_function_start(inside, 4) }, None);
+    }
+
+    #[test]
+    fn function_start_from_padding() {
+        let mut code = AlignedCode([INT3; 32]);
+        code.0[16..21].copy_from_slice(&[0x53, 0x56, 0x57, 0x33, 0xC0]);
+        let inside = code.0.as_ptr().wrapping_add(19) as *const c_void;
+        let start = unsafe { find_function_start(inside, 19) };
+        assert_eq!(start, Some(code.0.as_ptr().wrapping_add(16) as *const c_void));
+    }
+
+    #[test]
+    fn function_length() {
+        let code = [
+            0x55u8, 0x8B, 0xEC, // push ebp; mov ebp, esp
+            0x85, 0xC0, // test eax, eax
+            0x74, 0x02, // jz +2
+            0x5D, // pop ebp
+            0xC3, // ret
+            0x33, 0xC0, // xor eax, eax
+            0x5D, // pop ebp
+            0xC3, // ret
+            0xCC, 0xCC, 0xCC,
+        ];
+        let ptr = code.as_ptr() as *const c_void;
+        assert_eq!(unsafe { estimate_function_length(ptr, code.len()) }, Some(13));
+        assert_eq!(unsafe { estimate_function_length(ptr, 12) }, None);
+    }
 }
\ No newline at end of file
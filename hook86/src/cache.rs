@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use crate::error::Result;
+use crate::fingerprint::Fingerprint;
+use crate::loader::{self, ModuleEventKind};
+
+/// Identifies the build of a loaded module that cached addresses belong to
+///
+/// Entries are keyed by the module's fingerprint and stored as offsets from its base, so a module
+/// that is unloaded and later reloaded somewhere else still hits the cache, while a different
+/// build of a module with the same name never matches stale entries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleKey {
+    pub fingerprint: Fingerprint,
+    pub base: usize,
+    pub size: usize,
+}
+
+impl ModuleKey {
+    /// Identify the module loaded at `base` in the current process
+    ///
+    /// The module's fingerprint is read from its headers the first time its base address is seen
+    /// and reused until the module is invalidated. Returns `None` if the headers are invalid.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    pub unsafe fn of(base: *const c_void) -> Option<Self> {
+        let mut fingerprints = FINGERPRINTS.lock().unwrap_or_else(|e| e.into_inner());
+        let fingerprint = match fingerprints.get(&(base as usize)) {
+            Some(&fingerprint) => fingerprint,
+            None => {
+                let fingerprint = unsafe { Fingerprint::from_headers(base) }.ok()?;
+                fingerprints.insert(base as usize, fingerprint);
+                fingerprint
+            }
+        };
+
+        Some(Self {
+            fingerprint,
+            base: base as usize,
+            size: fingerprint.image_size as usize,
+        })
+    }
+
+    const fn contains(&self, addr: usize) -> bool {
+        addr >= self.base && addr < self.base + self.size
+    }
+
+    fn entry(&self, id: &str) -> (Fingerprint, String) {
+        (self.fingerprint, id.to_string())
+    }
+}
+
+/// Resolved addresses saved to disk, stored as offsets from the module base
+struct Persistence {
+    file: File,
+    entries: HashMap<(Fingerprint, String), usize>,
+}
+
+/// Escape the characters that would break a line of the tab-separated cache file
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Undo `escape`, returning `None` for an invalid escape sequence
+fn unescape(field: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+
+    Some(unescaped)
+}
+
+impl Persistence {
+    fn parse_line(line: &str) -> Option<((Fingerprint, String), usize)> {
+        let mut fields = line.splitn(5, '\t');
+        let timestamp = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let checksum = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let image_size = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let offset = usize::from_str_radix(fields.next()?, 16).ok()?;
+        let id = unescape(fields.next()?)?;
+        let fingerprint = Fingerprint {
+            timestamp,
+            checksum,
+            image_size,
+            ..Default::default()
+        };
+        Some(((fingerprint, id), offset))
+    }
+
+    fn format_line(fingerprint: &Fingerprint, id: &str, offset: usize) -> String {
+        format!(
+            "{:08X}\t{:08X}\t{:X}\t{:X}\t{}",
+            fingerprint.timestamp,
+            fingerprint.checksum,
+            fingerprint.image_size,
+            offset,
+            escape(id)
+        )
+    }
+}
+
+/// Resolved offsets from module bases, by module fingerprint and signature id
+static RESOLVED: LazyLock<Mutex<HashMap<(Fingerprint, String), usize>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+/// The fingerprints of the modules the cache has seen, by base address
+static FINGERPRINTS: LazyLock<Mutex<HashMap<usize, Fingerprint>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static PERSISTED: Mutex<Option<Persistence>> = Mutex::new(None);
+static UNLOAD_INVALIDATION: AtomicBool = AtomicBool::new(false);
+
+/// Save resolved addresses to a file so later runs can skip scanning for them
+///
+/// Entries already in the file are loaded, and every address inserted into the cache from now on
+/// is appended to it. Entries are keyed by the module's fingerprint, so an updated game binary
+/// won't pick up stale entries. Because a module can change without its headers changing,
+/// addresses from the file should be verified before use; see `get_persisted`.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be opened or read.
+pub fn enable_persistence(path: impl AsRef<Path>) -> io::Result<()> {
+    let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+    let mut entries = HashMap::new();
+    for line in BufReader::new(&file).lines() {
+        // later entries for the same signature supersede earlier ones
+        if let Some((key, offset)) = Persistence::parse_line(&line?) {
+            entries.insert(key, offset);
+        }
+    }
+
+    *PERSISTED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Persistence { file, entries });
+    Ok(())
+}
+
+/// Look up an address for the given signature saved to disk by a previous run
+///
+/// The address has not been verified; the caller should check that the signature still matches
+/// there before using it. Returns `None` if persistence isn't enabled or there's no saved entry.
+pub fn get_persisted(module: &ModuleKey, id: &str) -> Option<*const c_void> {
+    let persisted = PERSISTED.lock().unwrap_or_else(|e| e.into_inner());
+    let offset = *persisted.as_ref()?.entries.get(&module.entry(id))?;
+    (offset < module.size).then(|| (module.base + offset) as *const c_void)
+}
+
+fn persist(module: &ModuleKey, id: &str, offset: usize) {
+    let mut persisted = PERSISTED.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(persistence) = persisted.as_mut() else {
+        return;
+    };
+
+    let key = module.entry(id);
+    if persistence.entries.get(&key) == Some(&offset) {
+        return;
+    }
+
+    // the cache is only an optimization, so failing to save it isn't worth reporting
+    let _ = writeln!(persistence.file, "{}", Persistence::format_line(&module.fingerprint, id, offset));
+    persistence.entries.insert(key, offset);
+}
+
+/// Look up a previously resolved address for the given signature in the given module
+pub fn get(module: &ModuleKey, id: &str) -> Option<*const c_void> {
+    let resolved = RESOLVED.lock().unwrap_or_else(|e| e.into_inner());
+    let offset = *resolved.get(&module.entry(id))?;
+    (offset < module.size).then(|| (module.base + offset) as *const c_void)
+}
+
+/// Record the resolved address of a signature in the given module
+///
+/// Only addresses inside the module can be cached, since they're stored relative to its base;
+/// anything else is ignored. If persistence is enabled, the address is also saved to disk.
+pub fn insert(module: &ModuleKey, id: &str, addr: *const c_void) {
+    let addr = addr as usize;
+    if !module.contains(addr) {
+        return;
+    }
+
+    let offset = addr - module.base;
+    RESOLVED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(module.entry(id), offset);
+    persist(module, id, offset);
+}
+
+/// Discard all cached addresses belonging to the module loaded at the given base address
+///
+/// The module will be fingerprinted again the next time it's looked up, in case a different build
+/// is loaded at the same address.
+pub fn invalidate_module(base: *const c_void) {
+    let Some(fingerprint) = FINGERPRINTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&(base as usize))
+    else {
+        return;
+    };
+
+    let mut resolved = RESOLVED.lock().unwrap_or_else(|e| e.into_inner());
+    resolved.retain(|(module, _), _| *module != fingerprint);
+}
+
+/// Discard all cached addresses
+pub fn clear() {
+    RESOLVED.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    FINGERPRINTS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Automatically invalidate cached addresses when the module they were resolved in is unloaded
+///
+/// Calling this more than once has no additional effect.
+pub fn enable_unload_invalidation() -> Result<()> {
+    if UNLOAD_INVALIDATION.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+
+    loader::add_listener(|event| {
+        if event.kind == ModuleEventKind::Unloaded {
+            invalidate_module(event.base);
+        }
+    })
+    .inspect_err(|_| UNLOAD_INVALIDATION.store(false, Ordering::Release))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        for id in ["plain", "tab\there", "line\r\nbreak", "back\\slash\\t"] {
+            assert_eq!(unescape(&escape(id)).as_deref(), Some(id));
+            assert!(!escape(id).contains(['\t', '\n', '\r']));
+        }
+        assert_eq!(unescape("bad\\x"), None);
+        assert_eq!(unescape("trailing\\"), None);
+    }
+
+    #[test]
+    fn persisted_lines() {
+        let fingerprint = Fingerprint {
+            timestamp: 0x4A1B2C3D,
+            checksum: 0x5C8A1F,
+            image_size: 0x5C3000,
+            ..Default::default()
+        };
+        let line = Persistence::format_line(&fingerprint, "pattern 8B\t45", 0x1234);
+
+        assert_eq!(line, "4A1B2C3D\t005C8A1F\t5C3000\t1234\tpattern 8B\\t45");
+        assert_eq!(
+            Persistence::parse_line(&line),
+            Some(((fingerprint, String::from("pattern 8B\t45")), 0x1234))
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Run code that was called from game code, returning `None` if it panicked
+///
+/// Every hook that calls into Rust goes through here. A panic can't unwind into game code, which
+/// has no idea how to handle it, so it has to stop at the boundary. The panic hook has already
+/// reported it by the time this returns, so callers only have to decide how the game should carry
+/// on, e.g. by doing whatever it would have done without the hook.
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> R) -> Option<R> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+/// Identifies a callback registered with a `CallbackList` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CallbackHandle(u64);
+
+struct Entry<F: ?Sized> {
+    handle: CallbackHandle,
+    priority: i32,
+    callback: Arc<Mutex<F>>,
+}
+
+/// Callbacks registered for an event that fires inside game code, e.g. once per frame
+///
+/// The list isn't locked while the callbacks run, so a callback can register or remove callbacks
+/// (including itself), and a callback that triggers the event again isn't run recursively. A
+/// callback that panics is removed, since it would most likely panic again the next time.
+pub(crate) struct CallbackList<F: ?Sized> {
+    entries: Mutex<Vec<Entry<F>>>,
+    next_handle: AtomicU64,
+}
+
+impl<F: ?Sized> CallbackList<F> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+            next_handle: AtomicU64::new(0),
+        }
+    }
+
+    /// Register a callback to run after the ones already registered
+    pub fn add(&self, callback: Arc<Mutex<F>>) -> CallbackHandle {
+        self.insert(0, callback)
+    }
+
+    /// Register a callback to run in ascending order of priority
+    ///
+    /// Callbacks with the same priority run in the order they were registered. `add` registers
+    /// callbacks with priority 0.
+    pub fn insert(&self, priority: i32, callback: Arc<Mutex<F>>) -> CallbackHandle {
+        let handle = CallbackHandle(self.next_handle.fetch_add(1, Ordering::Relaxed));
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let index = entries.partition_point(|entry| entry.priority <= priority);
+        entries.insert(index, Entry { handle, priority, callback });
+
+        handle
+    }
+
+    /// Remove a callback
+    ///
+    /// If the callbacks are already running, the removed callback may still run one last time.
+    /// Returns false if the callback was already removed.
+    pub fn remove(&self, handle: CallbackHandle) -> bool {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(index) = entries.iter().position(|entry| entry.handle == handle) else {
+            return false;
+        };
+
+        entries.remove(index);
+        true
+    }
+
+    /// Run every registered callback with `call`
+    pub fn dispatch(&self, mut call: impl FnMut(&mut F)) {
+        // callbacks may register or remove callbacks, so the list can't stay locked while they run
+        let callbacks: Vec<(CallbackHandle, Arc<Mutex<F>>)> = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|entry| (entry.handle, entry.callback.clone()))
+            .collect();
+
+        for (handle, callback) in callbacks {
+            // the callback is already locked if it's what triggered the event
+            let Ok(mut callback) = callback.try_lock() else {
+                continue;
+            };
+            if catch_panic(|| call(&mut *callback)).is_none() {
+                self.remove(handle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Callback = dyn FnMut(&mut Vec<&'static str>) + Send;
+
+    #[test]
+    fn order_and_removal() {
+        let list: CallbackList<Callback> = CallbackList::new();
+        let late = list.insert(10, Arc::new(Mutex::new(|log: &mut Vec<_>| log.push("late"))));
+        list.insert(-5, Arc::new(Mutex::new(|log: &mut Vec<_>| log.push("early"))));
+        list.add(Arc::new(Mutex::new(|log: &mut Vec<_>| log.push("first"))));
+        list.add(Arc::new(Mutex::new(|log: &mut Vec<_>| log.push("second"))));
+        let panicky = list.add(Arc::new(Mutex::new(|_: &mut Vec<_>| panic!("callback failed"))));
+
+        let mut log = Vec::new();
+        list.dispatch(|callback| callback(&mut log));
+        assert_eq!(log, ["early", "first", "second", "late"]);
+        assert!(!list.remove(panicky));
+
+        log.clear();
+        assert!(list.remove(late));
+        assert!(!list.remove(late));
+        list.dispatch(|callback| callback(&mut log));
+        assert_eq!(log, ["early", "first", "second"]);
+    }
+
+    #[test]
+    fn reentrant() {
+        static LIST: CallbackList<dyn FnMut(u32) + Send> = CallbackList::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = calls.clone();
+        LIST.add(Arc::new(Mutex::new(move |depth| {
+            log.lock().unwrap().push(depth);
+            if depth == 0 {
+                // triggering the event again and registering a callback mustn't deadlock
+                LIST.dispatch(|callback| callback(depth + 1));
+                let log = log.clone();
+                LIST.add(Arc::new(Mutex::new(move |depth| log.lock().unwrap().push(depth + 100))));
+            }
+        })));
+
+        LIST.dispatch(|callback| callback(0));
+        assert_eq!(*calls.lock().unwrap(), [0]);
+        LIST.dispatch(|callback| callback(5));
+        assert_eq!(*calls.lock().unwrap(), [0, 5, 105]);
+    }
+}
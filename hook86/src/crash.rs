@@ -1,32 +1,62 @@
 #![cfg(feature = "crash_logging")]
 
-use std::ffi::c_void;
+use std::backtrace::Backtrace;
+use std::cell::UnsafeCell;
+use std::ffi::{c_void, OsString};
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io;
 use std::ops::BitAnd;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::io::{FromRawHandle, IntoRawHandle};
 use std::panic;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use std::sync::Mutex;
 use std::cmp;
 
-use windows::core::PWSTR;
-use windows::Win32::Foundation::{DBG_PRINTEXCEPTION_C, DBG_PRINTEXCEPTION_WIDE_C, HMODULE, MAX_PATH, NTSTATUS};
+use windows::core::{w, HSTRING, PCWSTR};
+use windows::Win32::Foundation::{
+    CloseHandle, DBG_PRINTEXCEPTION_C, DBG_PRINTEXCEPTION_WIDE_C, FALSE, HANDLE, HMODULE, MAX_PATH, NTSTATUS,
+};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, FlushFileBuffers, WriteFile, CREATE_ALWAYS, FILE_APPEND_DATA, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_READ,
+    FILE_GENERIC_WRITE, FILE_SHARE_READ, OPEN_ALWAYS,
+};
 use windows::Win32::System::Diagnostics::Debug::{
-    AddVectoredExceptionHandler, CONTEXT_CONTROL_X86, CONTEXT_DEBUG_REGISTERS_X86,
-    CONTEXT_FLOATING_POINT_X86, CONTEXT_INTEGER_X86, CONTEXT_SEGMENTS_X86, EXCEPTION_POINTERS,
+    AddVectoredExceptionHandler, GetThreadContext, MiniDumpWriteDump, OutputDebugStringW, SetUnhandledExceptionFilter, CONTEXT, CONTEXT_CONTROL_X86, CONTEXT_DEBUG_REGISTERS_X86,
+    CONTEXT_EXTENDED_REGISTERS_X86, CONTEXT_FLOATING_POINT_X86, CONTEXT_INTEGER_X86, CONTEXT_SEGMENTS_X86, EXCEPTION_POINTERS,
+    EXCEPTION_CONTINUE_SEARCH, LPTOP_LEVEL_EXCEPTION_FILTER, MINIDUMP_EXCEPTION_INFORMATION,
+};
+pub use windows::Win32::System::Diagnostics::Debug::MINIDUMP_TYPE;
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
 };
+use windows::Win32::System::EventLog::{DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE};
 use windows::Win32::System::Kernel::ExceptionContinueSearch;
+use windows::Win32::System::LibraryLoader::{
+    GetModuleFileNameW, GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
 use windows::Win32::System::Memory::{
-    VirtualQuery, MEMORY_BASIC_INFORMATION, MEM_COMMIT, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+    VirtualQuery, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_IMAGE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
     PAGE_PROTECTION_FLAGS, PAGE_READONLY, PAGE_READWRITE,
 };
 use windows::Win32::System::ProcessStatus::{
     EnumProcessModules, GetModuleBaseNameW, GetModuleInformation, MODULEINFO,
 };
-use windows::Win32::System::Threading::GetCurrentProcess;
+use windows::Win32::System::Threading::{
+    GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId, OpenThread, ResumeThread, Sleep, SuspendThread,
+    THREAD_GET_CONTEXT, THREAD_QUERY_INFORMATION, THREAD_SUSPEND_RESUME,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    MessageBoxW, MB_ICONERROR, MB_OK, MB_SETFOREGROUND, MB_TOPMOST,
+};
+
+use crate::debug::current_hook_context;
+use crate::hook::HookManager;
 
-const IGNORED_EXCEPTIONS: [NTSTATUS; 2] = [
-    DBG_PRINTEXCEPTION_C,
-    DBG_PRINTEXCEPTION_WIDE_C,
-];
 const STACK_DUMP_WORDS_PER_LINE: usize = 4;
-const STACK_DUMP_LINES: usize = 6;
 const READABLE_PROTECT: [PAGE_PROTECTION_FLAGS; 4] = [
     PAGE_EXECUTE_READ,
     PAGE_EXECUTE_READWRITE,
@@ -34,189 +64,1145 @@ const READABLE_PROTECT: [PAGE_PROTECTION_FLAGS; 4] = [
     PAGE_READONLY,
 ];
 const MAX_MODULES: usize = 1000;
+const MAX_STACK_FRAMES: usize = 64;
+// offsets into the FXSAVE area stored in CONTEXT::ExtendedRegisters
+const FXSAVE_MXCSR_OFFSET: usize = 24;
+const FXSAVE_XMM_OFFSET: usize = 160;
+const NUM_XMM_REGISTERS: usize = 8;
+const THREAD_QUERY_SET_WIN32_START_ADDRESS: u32 = 9;
+const PATCH_PREVIEW_BYTES: usize = 16;
+const REPORT_LINE_SIZE: usize = 1024;
+const MESSAGE_BOX_TEXT_SIZE: usize = 1024;
+// the maximum length of an event log message string
+const EVENT_LOG_TEXT_SIZE: usize = 31839;
+const CRASH_EVENT_ID: u32 = 1000;
+// how long a crashing thread will wait for another thread to finish reporting its own crash
+const HANDLER_LOCK_ATTEMPTS: u32 = 500;
+const HANDLER_LOCK_RETRY_MS: u32 = 10;
+#[cfg(feature = "disassembler")]
+const DISASM_CONTEXT_BYTES: usize = 48;
+#[cfg(feature = "disassembler")]
+const DISASM_INSTRUCTIONS_BEFORE: usize = 6;
+#[cfg(feature = "disassembler")]
+const DISASM_INSTRUCTIONS_AFTER: usize = 4;
+#[cfg(feature = "disassembler")]
+const DISASM_BYTES_COLUMN_WIDTH: usize = 20;
 
-unsafe extern "system" fn exception_handler(exc_info: *mut EXCEPTION_POINTERS) -> i32 {
-    unsafe {
-        let Some(exc_info) = exc_info.as_ref() else {
-            return ExceptionContinueSearch.0;
-        };
+windows::core::link!("ntdll.dll" "system" fn NtQueryInformationThread(thread: HANDLE, information_class: u32, information: *mut c_void, information_length: u32, return_length: *mut u32) -> NTSTATUS);
 
-        let mut had_notable_exception = false;
-
-        // exception details
-        let mut record_ptr = exc_info.ExceptionRecord;
-        while let Some(record) = record_ptr.as_ref() {
-            if !IGNORED_EXCEPTIONS.contains(&record.ExceptionCode) {
-                had_notable_exception = true;
-                log::error!(
-                    "Unhandled exception {:08X} at {:08X}. Parameters: {:?}",
-                    record.ExceptionCode.0,
-                    record.ExceptionAddress as usize,
-                    &record.ExceptionInformation[..record.NumberParameters as usize]
-                );
-            }
-            record_ptr = record.ExceptionRecord;
+static MINIDUMP_CONFIG: Mutex<Option<Minidump>> = Mutex::new(None);
+static CONTEXT_PROVIDERS: Mutex<Vec<ContextProvider>> = Mutex::new(Vec::new());
+static REPORT_FILE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+static HANDLER_OWNER: AtomicU32 = AtomicU32::new(0);
+static HANDLER_STATE: HandlerCell<HandlerState> = HandlerCell(UnsafeCell::new(HandlerState {
+    report: Report {
+        line: [0; REPORT_LINE_SIZE],
+        len: 0,
+        log: false,
+        debug_output: false,
+        event_source: HANDLE(ptr::null_mut()),
+        event_text: WideBuf {
+            buf: [0; EVENT_LOG_TEXT_SIZE],
+            len: 0,
+        },
+    },
+    modules: [HMODULE(ptr::null_mut()); MAX_MODULES],
+    config: None,
+    crashes_logged: 0,
+    last_reported: None,
+    filter_installed: false,
+    previous_filter: None,
+    report_path: None,
+    pending_report_path: None,
+    message: WideBuf {
+        buf: [0; MESSAGE_BOX_TEXT_SIZE],
+        len: 0,
+    },
+    #[cfg(feature = "disassembler")]
+    formatter: None,
+}));
+
+type ContextProvider = Box<dyn Fn(&mut dyn fmt::Write) -> fmt::Result + Send + Sync>;
+
+/// Write a line to a crash report
+///
+/// Writing to a `Report` never fails, so the result is discarded.
+macro_rules! report {
+    ($out:expr) => {{
+        let _ = writeln!($out);
+    }};
+    ($out:expr, $($arg:tt)*) => {{
+        let _ = writeln!($out, $($arg)*);
+    }};
+}
+
+/// Wrapper for state that is only accessed while holding the `HandlerLock`
+struct HandlerCell<T>(UnsafeCell<T>);
+
+unsafe impl<T> Sync for HandlerCell<T> {}
+
+/// Buffers preallocated for the exception handler so that it doesn't need the heap
+struct HandlerState {
+    report: Report,
+    modules: [HMODULE; MAX_MODULES],
+    config: Option<CrashConfig>,
+    crashes_logged: usize,
+    /// The code and address of the last exception reported
+    last_reported: Option<(NTSTATUS, usize)>,
+    filter_installed: bool,
+    previous_filter: LPTOP_LEVEL_EXCEPTION_FILTER,
+    report_path: Option<PathBuf>,
+    /// A null-terminated report file path to open the first time there's something to report
+    pending_report_path: Option<Vec<u16>>,
+    message: WideBuf<MESSAGE_BOX_TEXT_SIZE>,
+    #[cfg(feature = "disassembler")]
+    formatter: Option<iced_x86::IntelFormatter>,
+}
+
+/// Settings controlling which exceptions the OS crash logger reports and how much it logs
+#[derive(Debug, Clone)]
+pub struct CrashConfig {
+    /// Exception codes that are never reported
+    ///
+    /// By default, this contains the exceptions raised by `OutputDebugString`. Add any benign
+    /// exceptions the game throws and handles itself.
+    pub ignored_exceptions: Vec<NTSTATUS>,
+    /// The number of lines of raw stack memory to include in each report
+    pub stack_dump_lines: usize,
+    /// The maximum number of crashes to report per run, or `None` for no limit
+    ///
+    /// Exceptions the game handles itself count toward the limit. An exception that reaches the
+    /// unhandled exception filter is always reported, since it's the one that kills the process.
+    pub max_crashes: Option<usize>,
+    /// Also install a top-level unhandled exception filter
+    ///
+    /// The vectored handler sees every exception first, but a game's own SEH frames may catch an
+    /// exception and rethrow a different one, or the vectored handler may have hit the crash limit.
+    /// The filter runs as the process is about to die and reports anything the vectored handler
+    /// didn't, then passes the exception on to any filter that was previously installed.
+    pub unhandled_exception_filter: bool,
+    /// Show a message box telling the user about the crash after the report has been written
+    ///
+    /// If `unhandled_exception_filter` is set, the message box is only shown for exceptions that
+    /// reach the filter, since those are the ones that will kill the process. Otherwise, it's shown
+    /// for every reported exception.
+    pub message_box: bool,
+    /// Send the report to the log crate
+    ///
+    /// Off by default: loggers format into heap buffers and take locks, so if the crash happened
+    /// inside the allocator or the logger, the handler deadlocks or crashes again instead of
+    /// writing the rest of the report. Only turn this on if the logger is known to cope with that.
+    pub log: bool,
+    /// Send the report to `OutputDebugString` so it can be captured with a debugger or DebugView
+    pub debug_output: bool,
+    /// Also write each report to the Windows Application event log under the given source name
+    pub event_log_source: Option<String>,
+    /// Called when the handler is installed to choose a file to write reports to
+    ///
+    /// The file is only created when the first crash is reported, and reports are written to it
+    /// directly, so this works even if no logger has been set up. Defaults to
+    /// `report_path_next_to_module`. Ignored if `set_report_file` has already been called.
+    pub report_file: Option<fn() -> Option<PathBuf>>,
+    /// Capture a backtrace when logging Rust panics with `install_crash_loggers`
+    pub panic_backtrace: bool,
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        Self {
+            ignored_exceptions: vec![DBG_PRINTEXCEPTION_C, DBG_PRINTEXCEPTION_WIDE_C],
+            stack_dump_lines: 6,
+            max_crashes: Some(10),
+            unhandled_exception_filter: false,
+            message_box: false,
+            log: false,
+            debug_output: false,
+            event_log_source: None,
+            report_file: Some(report_path_next_to_module),
+            panic_backtrace: false,
         }
+    }
+}
+
+/// Exclusive access to the exception handler's preallocated state
+///
+/// This is a spin lock keyed on the thread ID rather than a `Mutex` so that a crash inside the
+/// handler itself can be detected and doesn't deadlock.
+struct HandlerLock;
 
-        if !had_notable_exception {
-            return ExceptionContinueSearch.0;
+impl HandlerLock {
+    /// Acquire the lock, waiting a limited time for any other thread that holds it
+    ///
+    /// Returns `None` if the current thread already holds the lock (i.e. the handler itself
+    /// crashed) or if another thread held it for too long.
+    fn acquire() -> Option<Self> {
+        let thread_id = unsafe { GetCurrentThreadId() };
+        for _ in 0..HANDLER_LOCK_ATTEMPTS {
+            match HANDLER_OWNER.compare_exchange(0, thread_id, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(Self),
+                Err(owner) if owner == thread_id => return None,
+                Err(_) => unsafe { Sleep(HANDLER_LOCK_RETRY_MS) },
+            }
         }
 
-        // registers
-        let mut sp = None;
-        if let Some(context) = exc_info.ContextRecord.as_ref() {
-            if context.ContextFlags.bitand(CONTEXT_INTEGER_X86) == CONTEXT_INTEGER_X86 {
-                log::error!("\tedi = {:08X}\tesi = {:08X}", context.Edi, context.Esi);
-                log::error!("\tebx = {:08X}\tedx = {:08X}", context.Ebx, context.Edx);
-                log::error!("\tecx = {:08X}\teax = {:08X}", context.Ecx, context.Eax);
+        None
+    }
+
+    fn state(&mut self) -> &mut HandlerState {
+        unsafe { &mut *HANDLER_STATE.0.get() }
+    }
+}
+
+impl Drop for HandlerLock {
+    fn drop(&mut self) {
+        HANDLER_OWNER.store(0, Ordering::Release);
+    }
+}
+
+/// A crash report that is written out one line at a time from a fixed-size buffer
+///
+/// Lines longer than the buffer are truncated.
+struct Report {
+    line: [u8; REPORT_LINE_SIZE],
+    len: usize,
+    log: bool,
+    debug_output: bool,
+    event_source: HANDLE,
+    /// The report so far, collected to be written to the event log as a single event
+    event_text: WideBuf<EVENT_LOG_TEXT_SIZE>,
+}
+
+impl Report {
+    /// Write the current line to each of the report's outputs
+    fn emit(&mut self) {
+        // only whole characters are ever copied into the buffer, so it's always valid UTF-8
+        let line = std::str::from_utf8(&self.line[..self.len]).unwrap_or_default();
+
+        let file = REPORT_FILE.load(Ordering::Acquire);
+        if !file.is_null() {
+            let mut bytes_written = 0;
+            unsafe {
+                let _ = WriteFile(HANDLE(file), Some(line.as_bytes()), Some(&mut bytes_written), None);
+                let _ = WriteFile(HANDLE(file), Some(b"\r\n"), Some(&mut bytes_written), None);
             }
+        }
+
+        if self.log {
+            log::error!("{}", line);
+        }
+
+        if self.debug_output {
+            // OutputDebugString raises an exception that we ignore; see `HandlerLock::acquire`
+            let mut wide = WideBuf { buf: [0u16; REPORT_LINE_SIZE + 2], len: 0 };
+            let _ = writeln!(wide, "{}", line);
+            unsafe { OutputDebugStringW(wide.as_pcwstr()) };
+        }
+
+        if !self.event_source.is_invalid() {
+            let _ = write!(self.event_text, "{}\r\n", line);
+        }
 
-            if context.ContextFlags.bitand(CONTEXT_CONTROL_X86) == CONTEXT_CONTROL_X86 {
-                log::error!("\tebp = {:08X}\teip = {:08X}", context.Ebp, context.Eip);
-                log::error!(
-                    "\tesp = {:08X}\teflags = {:08X}",
-                    context.Esp,
-                    context.EFlags
-                );
-                log::error!("\tcs = {:04X}\tss = {:04X}", context.SegCs, context.SegSs);
-                sp = Some(context.Esp as usize);
+        self.len = 0;
+    }
+
+    /// Emit any partial line and flush all outputs
+    fn flush(&mut self) {
+        if self.len > 0 {
+            self.emit();
+        }
+
+        let file = REPORT_FILE.load(Ordering::Acquire);
+        if !file.is_null() {
+            let _ = unsafe { FlushFileBuffers(HANDLE(file)) };
+        }
+
+        // lines the mod logged before the crash may still be buffered, even if the report itself
+        // isn't going to the logger
+        log::logger().flush();
+
+        if !self.event_source.is_invalid() && self.event_text.len > 0 {
+            let text = [self.event_text.as_pcwstr()];
+            let _ = unsafe {
+                ReportEventW(self.event_source, EVENTLOG_ERROR_TYPE, 0, CRASH_EVENT_ID, None, 0, Some(&text), None)
+            };
+            self.event_text.clear();
+        }
+    }
+}
+
+impl fmt::Write for Report {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, part) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.emit();
             }
 
-            if context.ContextFlags.bitand(CONTEXT_SEGMENTS_X86) == CONTEXT_SEGMENTS_X86 {
-                log::error!("\tgs = {:04X}\tfs = {:04X}", context.SegGs, context.SegFs);
-                log::error!("\tes = {:04X}\tds = {:04X}", context.SegEs, context.SegDs);
+            let mut len = cmp::min(part.len(), self.line.len() - self.len);
+            while !part.is_char_boundary(len) {
+                len -= 1;
             }
+            self.line[self.len..self.len + len].copy_from_slice(&part.as_bytes()[..len]);
+            self.len += len;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "disassembler")]
+impl iced_x86::FormatterOutput for Report {
+    fn write(&mut self, text: &str, _kind: iced_x86::FormatterTextKind) {
+        let _ = self.write_str(text);
+    }
+}
+
+/// A fixed-size, null-terminated UTF-16 string for passing text to Win32 without allocating
+///
+/// Text that doesn't fit is truncated.
+struct WideBuf<const N: usize> {
+    buf: [u16; N],
+    len: usize,
+}
+
+impl<const N: usize> WideBuf<N> {
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn as_pcwstr(&mut self) -> PCWSTR {
+        self.buf[self.len] = 0;
+        PCWSTR::from_raw(self.buf.as_ptr())
+    }
+}
 
-            if context.ContextFlags.bitand(CONTEXT_FLOATING_POINT_X86) == CONTEXT_FLOATING_POINT_X86
-            {
-                log::error!("\tfloat: {:?}", context.FloatSave);
+impl<const N: usize> fmt::Write for WideBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut units = [0u16; 2];
+        for c in s.chars() {
+            let encoded = c.encode_utf16(&mut units);
+            // leave room for the null terminator
+            if self.len + encoded.len() >= N {
+                break;
             }
+            self.buf[self.len..self.len + encoded.len()].copy_from_slice(encoded);
+            self.len += encoded.len();
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a UTF-16 string without allocating
+fn write_wide(out: &mut impl fmt::Write, chars: &[u16]) -> fmt::Result {
+    for c in char::decode_utf16(chars.iter().copied()) {
+        out.write_char(c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+    }
+
+    Ok(())
+}
+
+/// Find the module containing the given address and copy its name into the buffer
+///
+/// Returns the module and the length of its name. `GetModuleHandleEx` would take the loader lock,
+/// which the crashing thread may hold, so the module is found from the address's allocation
+/// instead.
+fn find_module(addr: usize, name_buf: &mut [u16]) -> Option<(HMODULE, usize)> {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+    let info_size = size_of::<MEMORY_BASIC_INFORMATION>();
+    if unsafe { VirtualQuery(Some(addr as *const c_void), &mut info, info_size) } < info_size
+        || info.State != MEM_COMMIT
+        || info.Type != MEM_IMAGE
+    {
+        return None;
+    }
+
+    // every section of an image belongs to the allocation made when the image was mapped
+    let module = HMODULE(info.AllocationBase);
+    let num_chars = unsafe { GetModuleBaseNameW(GetCurrentProcess(), Some(module), name_buf) } as usize;
+    (num_chars > 0 && num_chars < name_buf.len()).then_some((module, num_chars))
+}
 
-            if context.ContextFlags.bitand(CONTEXT_DEBUG_REGISTERS_X86)
-                == CONTEXT_DEBUG_REGISTERS_X86
-            {
-                log::error!("\tdr0 = {:08X}\tdr1 = {:08X}", context.Dr0, context.Dr1);
-                log::error!("\tdr2 = {:08X}\tdr3 = {:08X}", context.Dr2, context.Dr3);
-                log::error!("\tdr6 = {:08X}\tdr7 = {:08X}", context.Dr6, context.Dr7);
+/// Displays an address as `module+offset`, or as a plain address if it isn't inside a module
+///
+/// Unlike `debug::locate`, this doesn't allocate.
+struct Located(usize);
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut name_buf = [0u16; MAX_PATH as usize];
+        match find_module(self.0, &mut name_buf) {
+            Some((module, len)) => {
+                write_wide(f, &name_buf[..len])?;
+                write!(f, "+0x{:X}", self.0 - module.0 as usize)
             }
+            None => write!(f, "{:08X}", self.0),
         }
+    }
+}
 
-        // stack dump if it's valid
-        if let Some(mut ptr) = sp {
-            let mut info = MEMORY_BASIC_INFORMATION::default();
-            let info_size = size_of::<MEMORY_BASIC_INFORMATION>();
-            let mut region_end = ptr;
-            log::error!("Stack dump:");
-            for _ in 0..STACK_DUMP_LINES {
-                let mut words = [0usize; STACK_DUMP_WORDS_PER_LINE];
-                let mut exit = false;
-                let line_addr = ptr;
-                for word in &mut words {
-                    let mut word_buf = [0u8; size_of::<usize>()];
-                    let bytes_to_copy = cmp::min(region_end - ptr, word_buf.len());
-                    if bytes_to_copy > 0 {
-                        (ptr as *const u8)
-                            .copy_to_nonoverlapping(word_buf.as_mut_ptr(), bytes_to_copy);
-                    }
-                    ptr += bytes_to_copy;
-                    if bytes_to_copy < word_buf.len() {
-                        // we reached the end of the region; need to query the next region
-                        let bytes_written =
-                            VirtualQuery(Some(ptr as *const c_void), &mut info, info_size);
-                        if bytes_written < info_size {
-                            log::error!("{:08X}: VirtualQuery for stack info failed", ptr);
-                            exit = true;
-                            break;
-                        } else if info.State != MEM_COMMIT
-                            || !READABLE_PROTECT
-                            .iter()
-                            .any(|p| info.Protect.bitand(*p) == *p)
-                        {
-                            log::error!("{:08X}: memory is not readable", ptr);
-                            exit = true;
-                            break;
-                        }
+/// Displays a register value, followed by the module and offset it points into, if any
+struct Annotated(u32);
 
-                        region_end = info.BaseAddress as usize + info.RegionSize;
-                        let remaining_bytes = word_buf.len() - bytes_to_copy;
-                        (ptr as *const u8).copy_to_nonoverlapping(
-                            word_buf[bytes_to_copy..].as_mut_ptr(),
-                            remaining_bytes,
-                        );
-                        ptr += remaining_bytes;
-                    }
+impl fmt::Display for Annotated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut name_buf = [0u16; MAX_PATH as usize];
+        write!(f, "{:08X}", self.0)?;
+        if let Some((module, len)) = find_module(self.0 as usize, &mut name_buf) {
+            f.write_str(" (")?;
+            write_wide(f, &name_buf[..len])?;
+            write!(f, "+0x{:X})", self.0 as usize - module.0 as usize)?;
+        }
 
-                    *word = usize::from_le_bytes(word_buf);
-                }
+        Ok(())
+    }
+}
+
+/// Also write crash reports directly to the given file
+///
+/// The file is opened for appending immediately so that nothing needs to be opened or allocated
+/// when the process crashes. Replaces any previously-set report file.
+pub fn set_report_file(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let old_file = REPORT_FILE.swap(file.into_raw_handle(), Ordering::AcqRel);
+    // if the handler is busy, leak the old handle rather than closing it out from under the handler
+    if let Some(mut lock) = HandlerLock::acquire() {
+        lock.state().report_path = Some(path.to_path_buf());
+        if !old_file.is_null() {
+            drop(unsafe { File::from_raw_handle(old_file) });
+        }
+    }
+
+    Ok(())
+}
+
+/// Get a path for crash reports in the same directory as the module containing hook86
+///
+/// The report is named after the module, e.g. `mymod.crash.log` for `mymod.dll`. Returns `None` if
+/// the module's path can't be determined.
+pub fn report_path_next_to_module() -> Option<PathBuf> {
+    let mut module = HMODULE::default();
+    let mut path_buf = [0u16; MAX_PATH as usize];
+    let num_chars = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR::from_raw(report_path_next_to_module as *const u16),
+            &mut module,
+        )
+        .ok()?;
+        GetModuleFileNameW(Some(module), &mut path_buf) as usize
+    };
+    if num_chars == 0 || num_chars >= path_buf.len() {
+        return None;
+    }
+
+    let module_path = PathBuf::from(OsString::from_wide(&path_buf[..num_chars]));
+    Some(module_path.with_extension("crash.log"))
+}
+
+/// Open the report file chosen by `CrashConfig::report_file` if it hasn't been opened yet
+fn open_pending_report_file(state: &mut HandlerState) {
+    let Some(ref path) = state.pending_report_path else {
+        return;
+    };
+    if !REPORT_FILE.load(Ordering::Acquire).is_null() {
+        return;
+    }
+
+    let file = unsafe {
+        CreateFileW(
+            PCWSTR::from_raw(path.as_ptr()),
+            FILE_APPEND_DATA.0,
+            FILE_SHARE_READ,
+            None,
+            OPEN_ALWAYS,
+            FILE_ATTRIBUTE_NORMAL,
+            None,
+        )
+    };
+    match file {
+        Ok(file) => REPORT_FILE.store(file.0, Ordering::Release),
+        Err(e) => report!(&mut state.report, "Could not open crash report file: error {:08X}", e.code().0),
+    }
+    state.pending_report_path = None;
+}
+
+/// Register a function that adds application-specific state to crash reports
+///
+/// Providers are called in the order they were registered after the rest of the report has been
+/// written. Providers run inside the exception handler, so they should avoid allocating or taking
+/// locks that the crashing code might hold.
+pub fn add_context_provider(provider: impl Fn(&mut dyn fmt::Write) -> fmt::Result + Send + Sync + 'static) {
+    CONTEXT_PROVIDERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Box::new(provider));
+}
+
+fn log_user_context(out: &mut Report) {
+    let providers = match CONTEXT_PROVIDERS.try_lock() {
+        Ok(providers) => providers,
+        Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(std::sync::TryLockError::WouldBlock) => {
+            report!(out, "Context: providers are locked by another thread");
+            return;
+        }
+    };
+    if providers.is_empty() {
+        return;
+    }
+
+    report!(out, "Context:");
+    for provider in providers.iter() {
+        // a panic can't be allowed to unwind out of the exception handler
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| provider(out)));
+        if out.len > 0 {
+            report!(out);
+        }
+        match result {
+            Ok(Ok(())) => (),
+            Ok(Err(_)) => report!(out, "\t<context provider failed>"),
+            Err(_) => report!(out, "\t<context provider panicked>"),
+        }
+    }
+}
+
+/// Settings for writing a minidump when the process crashes
+#[derive(Debug, Clone)]
+pub struct MinidumpConfig {
+    /// The file to write the dump to. If the file already exists, it will be overwritten.
+    pub path: PathBuf,
+    /// The kind of information to include in the dump, e.g. `MiniDumpNormal` or
+    /// `MiniDumpWithFullMemory`
+    pub dump_type: MINIDUMP_TYPE,
+}
+
+/// Minidump settings along with everything the handler needs to write the dump without allocating
+struct Minidump {
+    config: MinidumpConfig,
+    /// `config.path` as a null-terminated UTF-16 string
+    wide_path: Vec<u16>,
+}
+
+/// Write a minidump with the given settings whenever the OS crash logger logs a crash
+///
+/// Replaces any previously-configured minidump settings. The dump is written after the textual
+/// crash report. The handler itself doesn't allocate to create the file, but `MiniDumpWriteDump`
+/// does, so the dump may fail if the heap is corrupted.
+pub fn enable_minidumps(config: MinidumpConfig) {
+    let wide_path = config.path.as_os_str().encode_wide().chain([0]).collect();
+    *MINIDUMP_CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = Some(Minidump { config, wide_path });
+}
+
+/// Stop writing minidumps on crash
+pub fn disable_minidumps() {
+    *MINIDUMP_CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn write_minidump(out: &mut Report, exc_info: &EXCEPTION_POINTERS) {
+    // don't wait on the lock; if another thread is holding it, it may be the one that crashed
+    let minidump = match MINIDUMP_CONFIG.try_lock() {
+        Ok(minidump) => minidump,
+        Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(std::sync::TryLockError::WouldBlock) => {
+            report!(out, "Minidump: settings are locked by another thread");
+            return;
+        }
+    };
+    let Some(ref minidump) = *minidump else {
+        return;
+    };
+    let config = &minidump.config;
+
+    let file = unsafe {
+        CreateFileW(
+            PCWSTR::from_raw(minidump.wide_path.as_ptr()),
+            (FILE_GENERIC_READ | FILE_GENERIC_WRITE).0,
+            FILE_SHARE_READ,
+            None,
+            CREATE_ALWAYS,
+            FILE_ATTRIBUTE_NORMAL,
+            None,
+        )
+    };
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            report!(out, "Minidump: could not create {}: error {:08X}", config.path.display(), e.code().0);
+            return;
+        }
+    };
+
+    let exception = MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: unsafe { GetCurrentThreadId() },
+        ExceptionPointers: exc_info as *const _ as *mut _,
+        ClientPointers: FALSE,
+    };
+    let result = unsafe {
+        MiniDumpWriteDump(
+            GetCurrentProcess(),
+            GetCurrentProcessId(),
+            file,
+            config.dump_type,
+            Some(&exception),
+            None,
+            None,
+        )
+    };
+    let _ = unsafe { CloseHandle(file) };
+    match result {
+        Ok(_) => report!(out, "Minidump written to {}", config.path.display()),
+        Err(e) => report!(out, "Minidump: could not write {}: error {:08X}", config.path.display(), e.code().0),
+    }
+}
+
+/// Log MXCSR and the XMM registers from an FXSAVE area
+fn log_sse_registers(out: &mut Report, fxsave: &[u8; 512]) {
+    let read_u32 = |offset: usize| u32::from_le_bytes(fxsave[offset..offset + 4].try_into().unwrap());
+
+    report!(out, "\tmxcsr = {:08X}", read_u32(FXSAVE_MXCSR_OFFSET));
+    for i in 0..NUM_XMM_REGISTERS {
+        let offset = FXSAVE_XMM_OFFSET + i * 16;
+        let lanes: [u32; 4] = std::array::from_fn(|lane| read_u32(offset + lane * 4));
+        // show the lanes as floats too since that's what most game code keeps in them
+        report!(
+            out,
+            "\txmm{} = {:08X} {:08X} {:08X} {:08X}\t({} {} {} {})",
+            i,
+            lanes[3],
+            lanes[2],
+            lanes[1],
+            lanes[0],
+            f32::from_bits(lanes[3]),
+            f32::from_bits(lanes[2]),
+            f32::from_bits(lanes[1]),
+            f32::from_bits(lanes[0])
+        );
+    }
+}
+
+/// Check whether every byte in the given address range is committed and readable
+fn is_range_readable(start: usize, end: usize) -> bool {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+    let info_size = size_of::<MEMORY_BASIC_INFORMATION>();
+    let mut addr = start;
+    while addr < end {
+        let bytes_written = unsafe { VirtualQuery(Some(addr as *const c_void), &mut info, info_size) };
+        if bytes_written < info_size
+            || info.State != MEM_COMMIT
+            || !READABLE_PROTECT.iter().any(|p| info.Protect.bitand(*p) == *p)
+        {
+            return false;
+        }
+        addr = info.BaseAddress as usize + info.RegionSize;
+    }
+
+    true
+}
+
+/// Log the call stack by following the chain of saved frame pointers
+///
+/// This is best-effort: functions compiled with frame pointer omission don't maintain the chain, so
+/// frames may be skipped or the walk may end early.
+fn log_stack_walk(out: &mut Report, ip: usize, mut frame_ptr: usize) {
+    report!(out, "Stack trace:");
+    report!(out, "\t#0  {:08X}  {}", ip, Located(ip));
+
+    for i in 1..MAX_STACK_FRAMES {
+        if frame_ptr == 0
+            || !frame_ptr.is_multiple_of(size_of::<usize>())
+            || !is_range_readable(frame_ptr, frame_ptr + 2 * size_of::<usize>())
+        {
+            break;
+        }
+
+        // [ebp] holds the caller's ebp and [ebp+4] holds the return address
+        let frame = unsafe { (frame_ptr as *const [usize; 2]).read() };
+        let [next_frame_ptr, return_address] = frame;
+        if return_address == 0 {
+            break;
+        }
+
+        report!(out, "\t#{:<2} {:08X}  {}", i, return_address, Located(return_address));
+
+        // the stack grows down, so each caller's frame must be at a higher address
+        if next_frame_ptr <= frame_ptr {
+            break;
+        }
+        frame_ptr = next_frame_ptr;
+    }
+}
 
-                if exit {
-                    break;
+/// Log the raw contents of the stack starting at the given stack pointer
+fn log_stack_dump(out: &mut Report, mut ptr: usize, num_lines: usize) {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+    let info_size = size_of::<MEMORY_BASIC_INFORMATION>();
+    let mut region_end = ptr;
+    report!(out, "Stack dump:");
+    for _ in 0..num_lines {
+        let mut words = [0usize; STACK_DUMP_WORDS_PER_LINE];
+        let line_addr = ptr;
+        for word in &mut words {
+            let mut word_buf = [0u8; size_of::<usize>()];
+            let bytes_to_copy = cmp::min(region_end - ptr, word_buf.len());
+            if bytes_to_copy > 0 {
+                unsafe { (ptr as *const u8).copy_to_nonoverlapping(word_buf.as_mut_ptr(), bytes_to_copy) };
+            }
+            ptr += bytes_to_copy;
+            if bytes_to_copy < word_buf.len() {
+                // we reached the end of the region; need to query the next region
+                let bytes_written = unsafe { VirtualQuery(Some(ptr as *const c_void), &mut info, info_size) };
+                if bytes_written < info_size {
+                    report!(out, "{:08X}: VirtualQuery for stack info failed", ptr);
+                    return;
+                } else if info.State != MEM_COMMIT
+                    || !READABLE_PROTECT
+                    .iter()
+                    .any(|p| info.Protect.bitand(*p) == *p)
+                {
+                    report!(out, "{:08X}: memory is not readable", ptr);
+                    return;
                 }
 
-                let mut line = format!("\t{:08X}: ", line_addr);
-                for word in words {
-                    line = format!("{} {:08X}", line, word);
+                region_end = info.BaseAddress as usize + info.RegionSize;
+                let remaining_bytes = word_buf.len() - bytes_to_copy;
+                unsafe {
+                    (ptr as *const u8).copy_to_nonoverlapping(
+                        word_buf[bytes_to_copy..].as_mut_ptr(),
+                        remaining_bytes,
+                    )
+                };
+                ptr += remaining_bytes;
+            }
+
+            *word = usize::from_le_bytes(word_buf);
+        }
+
+        let _ = write!(out, "\t{:08X}: ", line_addr);
+        for word in words {
+            let _ = write!(out, " {:08X}", word);
+        }
+        report!(out);
+    }
+}
+
+/// Get the address a thread started executing at
+fn thread_start_address(thread: HANDLE) -> Option<usize> {
+    let mut start_address = 0usize;
+    let status = unsafe {
+        NtQueryInformationThread(
+            thread,
+            THREAD_QUERY_SET_WIN32_START_ADDRESS,
+            &mut start_address as *mut usize as *mut c_void,
+            size_of::<usize>() as u32,
+            ptr::null_mut(),
+        )
+    };
+    status.is_ok().then_some(start_address)
+}
+
+/// Get a thread's current instruction pointer by briefly suspending it
+fn thread_ip(thread: HANDLE) -> Option<usize> {
+    let mut context = CONTEXT {
+        ContextFlags: CONTEXT_CONTROL_X86,
+        ..Default::default()
+    };
+
+    unsafe {
+        if SuspendThread(thread) == u32::MAX {
+            return None;
+        }
+        // nothing can allocate or log while the thread is suspended in case it holds a lock we need
+        let result = GetThreadContext(thread, &mut context);
+        ResumeThread(thread);
+        result.ok().map(|_| context.Eip as usize)
+    }
+}
+
+/// Log every thread in the process along with where it started and where it currently is
+fn log_threads(out: &mut Report) {
+    let snapshot = match unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) } {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            report!(out, "Threads: could not enumerate threads: error {:08X}", e.code().0);
+            return;
+        }
+    };
+
+    let process_id = unsafe { GetCurrentProcessId() };
+    let current_thread_id = unsafe { GetCurrentThreadId() };
+    let mut entry = THREADENTRY32 {
+        dwSize: size_of::<THREADENTRY32>() as u32,
+        ..Default::default()
+    };
+
+    report!(out, "Threads:");
+    let mut result = unsafe { Thread32First(snapshot, &mut entry) };
+    while result.is_ok() {
+        if entry.th32OwnerProcessID == process_id {
+            let thread_id = entry.th32ThreadID;
+            let access = THREAD_GET_CONTEXT | THREAD_QUERY_INFORMATION | THREAD_SUSPEND_RESUME;
+            match unsafe { OpenThread(access, false, thread_id) } {
+                Ok(thread) => {
+                    let _ = write!(out, "\t{:5}\tstart = ", thread_id);
+                    let _ = match thread_start_address(thread) {
+                        Some(start) => write!(out, "{}", Located(start)),
+                        None => out.write_str("<unknown>"),
+                    };
+                    let _ = out.write_str("\teip = ");
+                    // the faulting thread's state was already logged above, and it can't suspend itself
+                    if thread_id == current_thread_id {
+                        report!(out, "<crashed thread>");
+                    } else {
+                        match thread_ip(thread) {
+                            Some(ip) => report!(out, "{}", Located(ip)),
+                            None => report!(out, "<unknown>"),
+                        }
+                    }
+                    let _ = unsafe { CloseHandle(thread) };
                 }
-                log::error!("{}", line);
+                Err(e) => report!(out, "\t{:5}\tcould not open thread: error {:08X}", thread_id, e.code().0),
             }
-        } else {
-            log::error!("Stack dump: stack pointer was not present");
         }
 
-        // module list
-        let mut modules = [HMODULE::default(); MAX_MODULES];
-        let mut size_needed = 0;
-        if !EnumProcessModules(
+        result = unsafe { Thread32Next(snapshot, &mut entry) };
+    }
+
+    let _ = unsafe { CloseHandle(snapshot) };
+}
+
+/// Log every patch applied through the hook manager, flagging any that cover the faulting address
+fn log_applied_patches(out: &mut Report, fault_address: Option<usize>) {
+    let Some(manager) = HookManager::try_global() else {
+        report!(out, "Patches: hook manager is locked by another thread");
+        return;
+    };
+    if manager.patches().is_empty() {
+        return;
+    }
+
+    report!(out, "Patches:");
+    for patch in manager.patches() {
+        let age = patch.applied_at.elapsed().map_or(0.0, |d| d.as_secs_f64());
+        let marker = match fault_address {
+            Some(addr) if patch.overlaps(addr, addr + 1) => "=>",
+            _ => "  ",
+        };
+        let _ = write!(
+            out,
+            "\t{} {} at {} ({} bytes, applied {:.1}s ago):",
+            marker,
+            patch.name,
+            Located(patch.address),
+            patch.patched.len(),
+            age
+        );
+        for byte in patch.patched.iter().take(PATCH_PREVIEW_BYTES) {
+            let _ = write!(out, " {:02X}", byte);
+        }
+        if patch.patched.len() > PATCH_PREVIEW_BYTES {
+            let _ = out.write_str(" ...");
+        }
+        report!(out);
+    }
+}
+
+/// Log the base address, size, and name of every loaded module
+fn log_modules(out: &mut Report, modules: &mut [HMODULE]) {
+    let mut size_needed = 0;
+    let enumerated = unsafe {
+        EnumProcessModules(
             GetCurrentProcess(),
             modules.as_mut_ptr(),
-            size_of::<[HMODULE; MAX_MODULES]>() as u32,
+            size_of_val(modules) as u32,
             &mut size_needed,
         )
-            .is_ok()
-        {
-            log::error!("Modules: could not enumerate modules");
+    };
+    if enumerated.is_err() {
+        report!(out, "Modules: could not enumerate modules");
+        return;
+    }
+
+    report!(out, "Modules:");
+    let num_modules = cmp::min(size_needed as usize / size_of::<HMODULE>(), modules.len());
+    for &module in &modules[..num_modules] {
+        let _ = out.write_char('\t');
+        let mut name_buf = [0u16; MAX_PATH as usize];
+        let chars_copied = unsafe { GetModuleBaseNameW(GetCurrentProcess(), Some(module), &mut name_buf) } as usize;
+        if chars_copied == 0 || chars_copied >= name_buf.len() {
+            let _ = out.write_str("<unknown>");
         } else {
-            log::error!("Modules:");
-            let num_modules = size_needed as usize / size_of::<HMODULE>();
-            for module in modules.into_iter().take(num_modules) {
-                let mut name_buf = [0u16; MAX_PATH as usize];
-                let chars_copied = GetModuleBaseNameW(GetCurrentProcess(), Some(module), &mut name_buf);
-                let module_name = if chars_copied == 0 || chars_copied >= name_buf.len() as u32 {
-                    String::from("<unknown>")
-                } else {
-                    PWSTR::from_raw(name_buf.as_mut_ptr())
-                        .to_string()
-                        .unwrap_or_else(|_| String::from("<invalid>"))
-                };
+            let _ = write_wide(out, &name_buf[..chars_copied]);
+        }
 
-                let mut mod_info = MODULEINFO::default();
-                let address_range = match GetModuleInformation(
-                    GetCurrentProcess(),
-                    module,
-                    &mut mod_info,
-                    size_of::<MODULEINFO>() as u32,
-                ) {
-                    Ok(_) => format!(
-                        "{:08X}-{:08X}",
-                        mod_info.lpBaseOfDll as usize,
-                        mod_info.lpBaseOfDll as usize + mod_info.SizeOfImage as usize
-                    ),
-                    Err(e) => format!("error: {:?}", e),
-                };
+        let mut mod_info = MODULEINFO::default();
+        match unsafe {
+            GetModuleInformation(
+                GetCurrentProcess(),
+                module,
+                &mut mod_info,
+                size_of::<MODULEINFO>() as u32,
+            )
+        } {
+            Ok(_) => report!(
+                out,
+                "\t{:08X}-{:08X}",
+                mod_info.lpBaseOfDll as usize,
+                mod_info.lpBaseOfDll as usize + mod_info.SizeOfImage as usize
+            ),
+            Err(e) => report!(out, "\terror: {:08X}", e.code().0),
+        }
+    }
+}
 
-                log::error!("\t{}\t{}", module_name, address_range);
-            }
+/// Log the instructions surrounding the faulting instruction
+#[cfg(feature = "disassembler")]
+fn log_disassembly(out: &mut Report, formatter: &mut Option<iced_x86::IntelFormatter>, ip: usize) {
+    use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction};
+
+    let Some(formatter) = formatter else {
+        return;
+    };
+
+    // start decoding from a little before the faulting instruction, then shrink the window until
+    // we hit the instruction boundary at ip
+    let mut start = ip.saturating_sub(DISASM_CONTEXT_BYTES);
+    let end = ip + DISASM_CONTEXT_BYTES;
+    while start < ip && !is_range_readable(start, end) {
+        start += 1;
+    }
+    if !is_range_readable(start, end) {
+        report!(out, "Disassembly: code at {:08X} is not readable", ip);
+        return;
+    }
+
+    let code = unsafe { std::slice::from_raw_parts(start as *const u8, end - start) };
+    let decode_from = |offset: usize| {
+        Decoder::with_ip(32, &code[offset..], (start + offset) as u64, DecoderOptions::NONE)
+    };
+    // how many instructions precede ip when decoding from the given offset, if decoding lands on it
+    let instructions_before = |offset: usize| {
+        let mut decoder = decode_from(offset);
+        let mut instruction = Instruction::default();
+        let mut count = 0usize;
+        while decoder.can_decode() && (decoder.ip() as usize) < ip {
+            decoder.decode_out(&mut instruction);
+            count += 1;
         }
+        (decoder.ip() as usize == ip).then_some(count)
+    };
+    let (sync_offset, fault_index) = (0..ip - start)
+        .find_map(|offset| instructions_before(offset).map(|count| (offset, count)))
+        .unwrap_or((ip - start, 0));
+    let skip = fault_index.saturating_sub(DISASM_INSTRUCTIONS_BEFORE);
+    let count = fault_index - skip + DISASM_INSTRUCTIONS_AFTER + 1;
 
-        log::logger().flush();
+    report!(out, "Disassembly:");
+    for instruction in decode_from(sync_offset).into_iter().skip(skip).take(count) {
+        let marker = if instruction.ip() as usize == ip { "=>" } else { "  " };
+        let _ = write!(out, "\t{} {:08X}: ", marker, instruction.ip());
+        let offset = instruction.ip() as usize - start;
+        for byte in &code[offset..offset + instruction.len()] {
+            let _ = write!(out, "{:02X}", byte);
+        }
+        let padding = DISASM_BYTES_COLUMN_WIDTH.saturating_sub(instruction.len() * 2) + 1;
+        let _ = write!(out, "{:1$}", "", padding);
+        formatter.format(&instruction, out);
+        report!(out);
+    }
+}
+
+/// Write the crash report for an exception
+fn write_report(state: &mut HandlerState, config: &CrashConfig, exc_info: &EXCEPTION_POINTERS) {
+    let out = &mut state.report;
+
+    // exception details
+    let mut record_ptr = exc_info.ExceptionRecord;
+    while let Some(record) = unsafe { record_ptr.as_ref() } {
+        if !config.ignored_exceptions.contains(&record.ExceptionCode) {
+            report!(
+                out,
+                "Unhandled exception {:08X} at {:08X}. Parameters: {:?}",
+                record.ExceptionCode.0,
+                record.ExceptionAddress as usize,
+                &record.ExceptionInformation[..record.NumberParameters as usize]
+            );
+        }
+        record_ptr = record.ExceptionRecord;
+    }
+
+    // registers
+    let context = unsafe { exc_info.ContextRecord.as_ref() };
+    let control = context.filter(|c| c.ContextFlags.bitand(CONTEXT_CONTROL_X86) == CONTEXT_CONTROL_X86);
+    if let Some(context) = context {
+        if context.ContextFlags.bitand(CONTEXT_INTEGER_X86) == CONTEXT_INTEGER_X86 {
+            report!(out, "\tedi = {}\tesi = {}", Annotated(context.Edi), Annotated(context.Esi));
+            report!(out, "\tebx = {}\tedx = {}", Annotated(context.Ebx), Annotated(context.Edx));
+            report!(out, "\tecx = {}\teax = {}", Annotated(context.Ecx), Annotated(context.Eax));
+        }
+
+        if let Some(context) = control {
+            report!(out, "\tebp = {}\teip = {}", Annotated(context.Ebp), Annotated(context.Eip));
+            report!(out, "\tesp = {}\teflags = {:08X}", Annotated(context.Esp), context.EFlags);
+            report!(out, "\tcs = {:04X}\tss = {:04X}", context.SegCs, context.SegSs);
+        }
+
+        if context.ContextFlags.bitand(CONTEXT_SEGMENTS_X86) == CONTEXT_SEGMENTS_X86 {
+            report!(out, "\tgs = {:04X}\tfs = {:04X}", context.SegGs, context.SegFs);
+            report!(out, "\tes = {:04X}\tds = {:04X}", context.SegEs, context.SegDs);
+        }
+
+        if context.ContextFlags.bitand(CONTEXT_FLOATING_POINT_X86) == CONTEXT_FLOATING_POINT_X86
+        {
+            report!(out, "\tfloat: {:?}", context.FloatSave);
+        }
+
+        if context.ContextFlags.bitand(CONTEXT_EXTENDED_REGISTERS_X86)
+            == CONTEXT_EXTENDED_REGISTERS_X86
+        {
+            log_sse_registers(out, &context.ExtendedRegisters);
+        }
+
+        if context.ContextFlags.bitand(CONTEXT_DEBUG_REGISTERS_X86)
+            == CONTEXT_DEBUG_REGISTERS_X86
+        {
+            report!(out, "\tdr0 = {:08X}\tdr1 = {:08X}", context.Dr0, context.Dr1);
+            report!(out, "\tdr2 = {:08X}\tdr3 = {:08X}", context.Dr2, context.Dr3);
+            report!(out, "\tdr6 = {:08X}\tdr7 = {:08X}", context.Dr6, context.Dr7);
+        }
+    }
+
+    if let Some(context) = control {
+        #[cfg(feature = "disassembler")]
+        log_disassembly(out, &mut state.formatter, context.Eip as usize);
+
+        log_stack_walk(out, context.Eip as usize, context.Ebp as usize);
+
+        // stack dump if it's valid
+        log_stack_dump(out, context.Esp as usize, config.stack_dump_lines);
+    } else {
+        report!(out, "Stack dump: stack pointer was not present");
+    }
+
+    log_threads(out);
+
+    let fault_address = unsafe { exc_info.ExceptionRecord.as_ref() }.map(|r| r.ExceptionAddress as usize);
+    log_applied_patches(out, fault_address);
+
+    log_modules(out, &mut state.modules);
+
+    log_user_context(out);
+
+    write_minidump(out, exc_info);
+
+    out.flush();
+}
+
+/// Tell the user that the process crashed and where to find the report
+fn show_crash_message(state: &mut HandlerState, exception: Option<(NTSTATUS, usize)>) {
+    let message = &mut state.message;
+    message.clear();
+    let _ = match exception {
+        Some((code, address)) => write!(
+            message,
+            "The program crashed with exception {:08X} at {}.",
+            code.0,
+            Located(address)
+        ),
+        None => message.write_str("The program crashed."),
+    };
+    let _ = match state.report_path {
+        Some(ref path) => write!(message, "\n\nA crash report was written to:\n{}", path.display()),
+        None => message.write_str("\n\nDetails were written to the log."),
+    };
+
+    unsafe {
+        MessageBoxW(
+            None,
+            message.as_pcwstr(),
+            w!("Crash"),
+            MB_OK | MB_ICONERROR | MB_SETFOREGROUND | MB_TOPMOST,
+        );
+    }
+}
+
+/// Report an exception if it's notable and we haven't hit the crash limit
+///
+/// An exception that reaches the unhandled exception filter is about to kill the process, so it's
+/// reported even past the limit; otherwise first-chance exceptions the game handles itself could
+/// use up the limit and the fatal crash would go unreported.
+///
+/// Returns the unhandled exception filter that was installed before ours, if any.
+fn report_exception(exc_info: &EXCEPTION_POINTERS, from_filter: bool) -> LPTOP_LEVEL_EXCEPTION_FILTER {
+    // if this thread crashed while writing a report, let the exception go rather than recursing
+    let mut lock = HandlerLock::acquire()?;
+    let state = lock.state();
+    // take the config out so the rest of the state can be borrowed mutably while we use it
+    let config = state.config.take()?;
+
+    let mut had_notable_exception = false;
+    let mut record_ptr = exc_info.ExceptionRecord;
+    while let Some(record) = unsafe { record_ptr.as_ref() } {
+        had_notable_exception |= !config.ignored_exceptions.contains(&record.ExceptionCode);
+        record_ptr = record.ExceptionRecord;
+    }
 
-        ExceptionContinueSearch.0
+    let exception = unsafe { exc_info.ExceptionRecord.as_ref() }.map(|r| (r.ExceptionCode, r.ExceptionAddress as usize));
+    let under_limit = config.max_crashes.is_none_or(|max| state.crashes_logged < max);
+    let mut reported = true;
+    if from_filter && exception.is_some() && exception == state.last_reported {
+        // the vectored handler already reported this one; just note that it was fatal
+        report!(&mut state.report, "Exception reached the unhandled exception filter; the process will terminate");
+        state.report.flush();
+    } else if had_notable_exception && (from_filter || under_limit) {
+        if from_filter {
+            report!(&mut state.report, "Exception reached the unhandled exception filter");
+        }
+        state.crashes_logged += 1;
+        state.last_reported = exception;
+        open_pending_report_file(state);
+        write_report(state, &config, exc_info);
+        if config.max_crashes == Some(state.crashes_logged) {
+            report!(&mut state.report, "Crash limit reached; further crashes will not be logged");
+            state.report.flush();
+        }
+    } else {
+        reported = false;
+    }
+
+    if reported && config.message_box && (from_filter || !config.unhandled_exception_filter) {
+        show_crash_message(state, exception);
+    }
+
+    state.config = Some(config);
+    state.previous_filter
+}
+
+unsafe extern "system" fn exception_handler(exc_info: *mut EXCEPTION_POINTERS) -> i32 {
+    if let Some(exc_info) = unsafe { exc_info.as_ref() } {
+        report_exception(exc_info, false);
+    }
+
+    ExceptionContinueSearch.0
+}
+
+unsafe extern "system" fn unhandled_exception_filter(exc_info: *const EXCEPTION_POINTERS) -> i32 {
+    let Some(exc_info) = (unsafe { exc_info.as_ref() }) else {
+        return EXCEPTION_CONTINUE_SEARCH;
+    };
+
+    match report_exception(exc_info, true) {
+        Some(previous_filter) => unsafe { previous_filter(exc_info) },
+        None => EXCEPTION_CONTINUE_SEARCH,
     }
 }
 
 /// Install a panic handler that logs Rust panics with the log crate
-pub fn install_panic_logger() {
-    panic::set_hook(Box::new(|info| {
+///
+/// The log includes the panicking thread's name and ID. If `capture_backtrace` is true, a backtrace
+/// is captured and logged as well, regardless of the `RUST_BACKTRACE` environment variable.
+pub fn install_panic_logger(capture_backtrace: bool) {
+    panic::set_hook(Box::new(move |info| {
         let msg = if let Some(msg) = info.payload().downcast_ref::<&str>() {
             *msg
         } else if let Some(msg) = info.payload().downcast_ref::<String>() {
@@ -227,20 +1213,86 @@ pub fn install_panic_logger() {
         let (file, line) = info
             .location()
             .map_or(("unknown", 0), |l| (l.file(), l.line()));
-        log::error!("Panic in {} on line {}: {}", file, line, msg);
+        let thread = std::thread::current();
+        log::error!(
+            "Panic in {} on line {} on thread {} ({}): {}",
+            file,
+            line,
+            thread.name().unwrap_or("<unnamed>"),
+            unsafe { GetCurrentThreadId() },
+            msg
+        );
+        if let Some(context) = current_hook_context() {
+            log::error!("\tinside hook {} (target {:08X})", context.name, context.target);
+            if let Some(registers) = context.registers {
+                log::error!("\t{}", registers);
+            }
+        }
+        if capture_backtrace {
+            let backtrace = Backtrace::force_capture();
+            log::error!("Backtrace:");
+            for line in backtrace.to_string().lines() {
+                log::error!("\t{}", line);
+            }
+        }
         log::logger().flush();
     }));
 }
 
-/// Install a Windows vectored exception handler that logs process crashes with the log crate
-pub fn install_os_crash_logger() {
-    unsafe {
-        AddVectoredExceptionHandler(0, Some(exception_handler));
+/// Install a Windows vectored exception handler that reports process crashes
+///
+/// The handler writes its report from preallocated buffers and doesn't take the loader lock, so
+/// it still works if the process crashed inside the allocator or while loading a module. Reports
+/// go to the outputs chosen in `config`; sending them to the log crate puts the handler at the
+/// mercy of the installed logger, which is why `CrashConfig::log` is off by default.
+///
+/// If the handler was already installed, this only replaces its configuration.
+pub fn install_os_crash_logger(config: CrashConfig) {
+    let Some(mut lock) = HandlerLock::acquire() else {
+        return;
+    };
+    let state = lock.state();
+    let already_installed = state.config.is_some();
+    let old_source = state.config.as_ref().and_then(|c| c.event_log_source.as_deref());
+    if old_source != config.event_log_source.as_deref() {
+        let report = &mut state.report;
+        if !report.event_source.is_invalid() {
+            let _ = unsafe { DeregisterEventSource(report.event_source) };
+        }
+        report.event_source = config
+            .event_log_source
+            .as_deref()
+            .and_then(|name| unsafe { RegisterEventSourceW(None, &HSTRING::from(name)) }.ok())
+            .unwrap_or_default();
+    }
+    state.report.log = config.log;
+    state.report.debug_output = config.debug_output;
+    if state.report_path.is_none()
+        && let Some(path) = config.report_file.and_then(|report_file| report_file())
+    {
+        state.pending_report_path = Some(path.as_os_str().encode_wide().chain([0]).collect());
+        state.report_path = Some(path);
+    }
+    if config.unhandled_exception_filter && !state.filter_installed {
+        state.previous_filter = unsafe { SetUnhandledExceptionFilter(Some(unhandled_exception_filter)) };
+        state.filter_installed = true;
+    }
+    state.config = Some(config);
+    #[cfg(feature = "disassembler")]
+    if state.formatter.is_none() {
+        state.formatter = Some(iced_x86::IntelFormatter::new());
+    }
+    drop(lock);
+
+    if !already_installed {
+        unsafe {
+            AddVectoredExceptionHandler(0, Some(exception_handler));
+        }
     }
 }
 
 /// Install handlers that log crashes with the log crate, whether the crash originates in Rust code or not
-pub fn install_crash_loggers() {
-    install_panic_logger();
-    install_os_crash_logger();
+pub fn install_crash_loggers(config: CrashConfig) {
+    install_panic_logger(config.panic_backtrace);
+    install_os_crash_logger(config);
 }
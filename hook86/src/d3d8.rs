@@ -0,0 +1,320 @@
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::core::{w, BOOL, HRESULT};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::System::LibraryLoader::LoadLibraryW;
+use windows::Win32::UI::WindowsAndMessaging::{CreateWindowExW, DestroyWindow, WINDOW_EX_STYLE, WS_OVERLAPPEDWINDOW};
+
+use crate::callbacks::{CallbackHandle, CallbackList};
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+use crate::pe;
+
+/// The index of `CreateDevice` in the `IDirect3D8` vtable
+pub const CREATE_DEVICE_INDEX: usize = 15;
+/// The index of `Reset` in the `IDirect3DDevice8` vtable
+pub const RESET_INDEX: usize = 14;
+/// The index of `Present` in the `IDirect3DDevice8` vtable
+pub const PRESENT_INDEX: usize = 15;
+/// The index of `EndScene` in the `IDirect3DDevice8` vtable
+pub const END_SCENE_INDEX: usize = 35;
+
+const RELEASE_INDEX: usize = 2;
+const GET_ADAPTER_DISPLAY_MODE_INDEX: usize = 8;
+const D3D_SDK_VERSION: u32 = 220;
+const D3DADAPTER_DEFAULT: u32 = 0;
+const D3DDEVTYPE_HAL: u32 = 1;
+const D3DSWAPEFFECT_DISCARD: u32 = 1;
+const D3DCREATE_SOFTWARE_VERTEXPROCESSING: u32 = 0x20;
+
+type Direct3DCreate8Fn = unsafe extern "system" fn(u32) -> *mut c_void;
+type ReleaseFn = unsafe extern "system" fn(*mut c_void) -> u32;
+type GetAdapterDisplayModeFn = unsafe extern "system" fn(*mut c_void, u32, *mut DisplayMode) -> HRESULT;
+type CreateDeviceFn =
+    unsafe extern "system" fn(*mut c_void, u32, u32, HWND, u32, *mut PresentParameters, *mut *mut c_void) -> HRESULT;
+
+/// Direct3D 8's `D3DDISPLAYMODE`
+#[repr(C)]
+#[derive(Debug, Default)]
+struct DisplayMode {
+    width: u32,
+    height: u32,
+    refresh_rate: u32,
+    format: u32,
+}
+
+/// Direct3D 8's `D3DPRESENT_PARAMETERS`, which differs from Direct3D 9's
+#[repr(C)]
+#[derive(Debug, Default)]
+struct PresentParameters {
+    back_buffer_width: u32,
+    back_buffer_height: u32,
+    back_buffer_format: u32,
+    back_buffer_count: u32,
+    multi_sample_type: u32,
+    swap_effect: u32,
+    device_window: HWND,
+    windowed: BOOL,
+    enable_auto_depth_stencil: BOOL,
+    auto_depth_stencil_format: u32,
+    flags: u32,
+    full_screen_refresh_rate: u32,
+    full_screen_presentation_interval: u32,
+}
+
+static ORIGINAL_CREATE_DEVICE: AtomicUsize = AtomicUsize::new(0);
+static DEVICE_CALLBACKS: CallbackList<dyn FnMut(*mut c_void) + Send> = CallbackList::new();
+
+thread_local! {
+    /// Set while hook86 creates its own temporary device, so device callbacks don't see it
+    static CREATING_TEMPORARY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Identifies a callback registered with `on_device_created` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceHandle(CallbackHandle);
+
+/// Get a method from a COM object's vtable
+unsafe fn method<F: Copy>(object: *mut c_void, index: usize) -> F {
+    let vtable = unsafe { *(object as *const *const usize) };
+    unsafe { std::mem::transmute_copy(&*vtable.add(index)) }
+}
+
+unsafe fn release(object: *mut c_void) {
+    let release: ReleaseFn = unsafe { method(object, RELEASE_INDEX) };
+    unsafe { release(object) };
+}
+
+/// Register a callback to run whenever the game creates a Direct3D 8 device
+///
+/// The callback receives the new `IDirect3DDevice8`. Its vtable (the first pointer-sized value in
+/// the object) is where `Present`, `EndScene`, and `Reset` hooks go; see the `*_INDEX` constants.
+/// The device isn't `AddRef`ed, so it's only valid as long as the game keeps it. Nothing runs until
+/// `install` or `hook_create_device` has been called, and the temporary device `find_device_vtable`
+/// creates is never reported. A callback that panics is removed.
+pub fn on_device_created(callback: impl FnMut(*mut c_void) + Send + 'static) -> DeviceHandle {
+    DeviceHandle(DEVICE_CALLBACKS.add(Arc::new(Mutex::new(callback))))
+}
+
+/// Remove a callback registered with `on_device_created`
+///
+/// Returns false if the callback was already removed.
+pub fn remove_device_created(handle: DeviceHandle) -> bool {
+    DEVICE_CALLBACKS.remove(handle.0)
+}
+
+unsafe extern "system" fn create_device(
+    direct3d: *mut c_void,
+    adapter: u32,
+    device_type: u32,
+    focus_window: HWND,
+    behavior_flags: u32,
+    parameters: *mut PresentParameters,
+    device: *mut *mut c_void,
+) -> HRESULT {
+    let original: CreateDeviceFn = unsafe { std::mem::transmute(ORIGINAL_CREATE_DEVICE.load(Ordering::Acquire)) };
+    let result = unsafe { original(direct3d, adapter, device_type, focus_window, behavior_flags, parameters, device) };
+    if result.is_err() || device.is_null() || CREATING_TEMPORARY.get() {
+        return result;
+    }
+
+    let device = unsafe { *device };
+    DEVICE_CALLBACKS.dispatch(|callback| callback(device));
+
+    result
+}
+
+/// Hook `CreateDevice` for every `IDirect3D8` object that shares the given vtable
+///
+/// `install` finds the vtable by itself, but this can be used with a vtable found by a pattern scan
+/// or read from the game's own object. Hooking the same vtable twice does nothing.
+///
+/// # Safety
+///
+/// `vtable` must point to the `IDirect3D8` vtable, which must stay loaded.
+///
+/// # Errors
+///
+/// Returns an error if a different vtable is already hooked or the vtable couldn't be patched.
+pub unsafe fn hook_create_device(vtable: *const usize) -> Result<()> {
+    let slot = vtable.wrapping_add(CREATE_DEVICE_INDEX);
+    let detour = create_device as CreateDeviceFn as usize;
+    let current = unsafe { *slot };
+    if current == detour {
+        return Ok(());
+    }
+
+    // claim the slot atomically so two threads hooking different vtables can't both take it
+    if ORIGINAL_CREATE_DEVICE
+        .compare_exchange(0, current, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return Err(Hook86Error::InvalidHook {
+            name: String::from("d3d8 CreateDevice"),
+            reason: "a different IDirect3D8 vtable is already hooked",
+        });
+    }
+
+    let name = "hook86 d3d8 CreateDevice";
+    if let Err(e) = unsafe { HookManager::global().apply(name, slot as *const c_void, &detour.to_le_bytes()) } {
+        ORIGINAL_CREATE_DEVICE.store(0, Ordering::Release);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Create a temporary `IDirect3D8` object, loading d3d8.dll if necessary
+///
+/// The caller is responsible for releasing it.
+unsafe fn create_direct3d() -> Result<*mut c_void> {
+    unsafe { LoadLibraryW(w!("d3d8.dll")) }?;
+    let direct3d_create: Direct3DCreate8Fn =
+        unsafe { std::mem::transmute(pe::get_export("d3d8.dll", "Direct3DCreate8")?) };
+    let direct3d = unsafe { direct3d_create(D3D_SDK_VERSION) };
+    if direct3d.is_null() {
+        return Err(Hook86Error::InvalidHook {
+            name: String::from("d3d8 device"),
+            reason: "Direct3D 8 isn't available",
+        });
+    }
+
+    Ok(direct3d)
+}
+
+/// Find the vtable shared by `IDirect3D8` objects by creating a temporary one
+///
+/// # Safety
+///
+/// This loads d3d8.dll, so it must not be called while holding the loader lock (e.g. from
+/// `DllMain`).
+///
+/// # Errors
+///
+/// Returns an error if Direct3D 8 isn't available.
+pub unsafe fn find_direct3d_vtable() -> Result<*const usize> {
+    let direct3d = unsafe { create_direct3d() }?;
+    let vtable = unsafe { *(direct3d as *const *const usize) };
+    unsafe { release(direct3d) };
+
+    Ok(vtable)
+}
+
+/// Find the vtable shared by Direct3D 8 devices by creating a temporary device
+///
+/// The device is created on a hidden window and released before this returns. Devices created
+/// with `D3DCREATE_PUREDEVICE` may use a different vtable; use `on_device_created` to get the
+/// game's own device in that case.
+///
+/// # Safety
+///
+/// See `find_direct3d_vtable`. The window is created on the calling thread, which needs to pump
+/// messages if Direct3D sends any while the device exists.
+///
+/// # Errors
+///
+/// Returns an error if Direct3D 8 isn't available or the device couldn't be created.
+pub unsafe fn find_device_vtable() -> Result<*const usize> {
+    let direct3d = unsafe { create_direct3d() }?;
+    // CreateDevice may already be hooked
+    let was_creating = CREATING_TEMPORARY.replace(true);
+    let result = unsafe { temporary_device_vtable(direct3d) };
+    CREATING_TEMPORARY.set(was_creating);
+    unsafe { release(direct3d) };
+
+    result
+}
+
+unsafe fn temporary_device_vtable(direct3d: *mut c_void) -> Result<*const usize> {
+    // windowed devices in Direct3D 8 need a back buffer in the same format as the desktop
+    let mut mode = DisplayMode::default();
+    let get_adapter_display_mode: GetAdapterDisplayModeFn = unsafe { method(direct3d, GET_ADAPTER_DISPLAY_MODE_INDEX) };
+    unsafe { get_adapter_display_mode(direct3d, D3DADAPTER_DEFAULT, &mut mode) }.ok()?;
+
+    let window = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("STATIC"),
+            w!("hook86 d3d8"),
+            WS_OVERLAPPEDWINDOW,
+            0,
+            0,
+            64,
+            64,
+            None,
+            None,
+            None,
+            None,
+        )
+    }?;
+
+    let mut parameters = PresentParameters {
+        back_buffer_format: mode.format,
+        swap_effect: D3DSWAPEFFECT_DISCARD,
+        device_window: window,
+        windowed: true.into(),
+        ..Default::default()
+    };
+    let mut device = std::ptr::null_mut();
+    let create_device: CreateDeviceFn = unsafe { method(direct3d, CREATE_DEVICE_INDEX) };
+    let result = unsafe {
+        create_device(
+            direct3d,
+            D3DADAPTER_DEFAULT,
+            D3DDEVTYPE_HAL,
+            window,
+            D3DCREATE_SOFTWARE_VERTEXPROCESSING,
+            &mut parameters,
+            &mut device,
+        )
+    };
+
+    // the vtable is all we need, so the device doesn't have to outlive its window
+    let vtable = (!device.is_null()).then(|| unsafe { *(device as *const *const usize) });
+    if !device.is_null() {
+        unsafe { release(device) };
+    }
+    let _ = unsafe { DestroyWindow(window) };
+    result.ok()?;
+
+    vtable.ok_or_else(|| Hook86Error::InvalidHook {
+        name: String::from("d3d8 device"),
+        reason: "CreateDevice didn't return a device",
+    })
+}
+
+/// Hook `IDirect3D8::CreateDevice` so device callbacks see every device the game creates
+///
+/// A temporary `IDirect3D8` object is created to find the vtable; the game's objects share it, so
+/// they're hooked no matter when the game creates them.
+///
+/// # Safety
+///
+/// See `find_direct3d_vtable`. The vtable is patched while other threads may be using it.
+///
+/// # Errors
+///
+/// Returns an error if Direct3D 8 isn't available or the vtable couldn't be patched.
+pub unsafe fn install() -> Result<()> {
+    let vtable = unsafe { find_direct3d_vtable() }?;
+    unsafe { hook_create_device(vtable) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use super::*;
+
+    #[test]
+    fn present_parameters_layout() {
+        // D3DPRESENT_PARAMETERS from the Direct3D 8 headers
+        assert_eq!(size_of::<PresentParameters>(), 52);
+        assert_eq!(offset_of!(PresentParameters, device_window), 24);
+        assert_eq!(offset_of!(PresentParameters, full_screen_presentation_interval), 48);
+        assert_eq!(size_of::<DisplayMode>(), 16);
+    }
+}
@@ -0,0 +1,274 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::core::{w, Interface, HRESULT};
+use windows::Win32::Foundation::{HWND, RECT};
+use windows::Win32::Graphics::Direct3D9::{
+    Direct3DCreate9, IDirect3DDevice9, D3DADAPTER_DEFAULT, D3DCREATE_DISABLE_DRIVER_MANAGEMENT,
+    D3DCREATE_SOFTWARE_VERTEXPROCESSING, D3DDEVTYPE_HAL, D3DPRESENT_PARAMETERS, D3DSWAPEFFECT_DISCARD, D3D_SDK_VERSION,
+};
+use windows::Win32::Graphics::Gdi::RGNDATA;
+use windows::Win32::UI::WindowsAndMessaging::{CreateWindowExW, DestroyWindow, WINDOW_EX_STYLE, WS_OVERLAPPEDWINDOW};
+
+use crate::callbacks::{CallbackHandle, CallbackList};
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+use crate::loader;
+
+/// The index of `Reset` in the `IDirect3DDevice9` vtable
+pub const RESET_INDEX: usize = 16;
+/// The index of `Present` in the `IDirect3DDevice9` vtable
+pub const PRESENT_INDEX: usize = 17;
+/// The index of `EndScene` in the `IDirect3DDevice9` vtable
+pub const END_SCENE_INDEX: usize = 42;
+
+type ResetFn = unsafe extern "system" fn(*mut c_void, *mut D3DPRESENT_PARAMETERS) -> HRESULT;
+type PresentFn = unsafe extern "system" fn(*mut c_void, *const RECT, *const RECT, HWND, *const RGNDATA) -> HRESULT;
+type EndSceneFn = unsafe extern "system" fn(*mut c_void) -> HRESULT;
+
+/// The point in a frame a render callback is being run at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderEvent {
+    /// Before `EndScene`, while the game's scene is still open, which is the usual place to draw
+    /// overlays. Some games call `EndScene` more than once per frame.
+    EndScene,
+    /// Before `Present`, once per frame
+    Present,
+    /// Before `Reset`, e.g. when the resolution changes or the device is lost. Resources created
+    /// in `D3DPOOL_DEFAULT` must be released here or the reset fails.
+    BeforeReset,
+    /// After a successful `Reset`, where resources released in `BeforeReset` can be recreated
+    AfterReset,
+}
+
+/// Identifies a callback registered with `on_render` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderHandle(CallbackHandle);
+
+type RenderCallback = dyn FnMut(RenderEvent, &IDirect3DDevice9) + Send;
+
+static RENDER_CALLBACKS: CallbackList<RenderCallback> = CallbackList::new();
+
+/// The vtable the hooks are installed in, or 0 if they haven't been installed
+static HOOKED_VTABLE: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_RESET: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_PRESENT: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_END_SCENE: AtomicUsize = AtomicUsize::new(0);
+
+/// Register a callback to run on the render thread at each `RenderEvent`
+///
+/// Callbacks run in the order they were registered and receive the game's device. Nothing runs
+/// until `install`, `hook_device`, or `hook_vtable` has been called. A callback that panics is
+/// removed, but the others still run. A callback isn't run again for device calls it makes
+/// itself, so calling `Reset` from a callback doesn't recurse into it.
+pub fn on_render(callback: impl FnMut(RenderEvent, &IDirect3DDevice9) + Send + 'static) -> RenderHandle {
+    RenderHandle(RENDER_CALLBACKS.add(Arc::new(Mutex::new(callback))))
+}
+
+/// Remove a callback registered with `on_render`
+///
+/// If the callbacks for the current event are already running, the removed callback may still
+/// run one last time. Returns false if the callback was already removed.
+pub fn remove_render(handle: RenderHandle) -> bool {
+    RENDER_CALLBACKS.remove(handle.0)
+}
+
+fn dispatch(event: RenderEvent, device: *mut c_void) {
+    let Some(device) = (unsafe { IDirect3DDevice9::from_raw_borrowed(&device) }) else {
+        return;
+    };
+
+    // a callback isn't run again for device calls it makes itself
+    RENDER_CALLBACKS.dispatch(|callback| callback(event, device));
+}
+
+unsafe extern "system" fn reset(device: *mut c_void, parameters: *mut D3DPRESENT_PARAMETERS) -> HRESULT {
+    dispatch(RenderEvent::BeforeReset, device);
+    let original: ResetFn = unsafe { std::mem::transmute(ORIGINAL_RESET.load(Ordering::Acquire)) };
+    let result = unsafe { original(device, parameters) };
+    if result.is_ok() {
+        dispatch(RenderEvent::AfterReset, device);
+    }
+
+    result
+}
+
+unsafe extern "system" fn present(
+    device: *mut c_void,
+    source: *const RECT,
+    destination: *const RECT,
+    window: HWND,
+    dirty_region: *const RGNDATA,
+) -> HRESULT {
+    dispatch(RenderEvent::Present, device);
+    let original: PresentFn = unsafe { std::mem::transmute(ORIGINAL_PRESENT.load(Ordering::Acquire)) };
+    unsafe { original(device, source, destination, window, dirty_region) }
+}
+
+unsafe extern "system" fn end_scene(device: *mut c_void) -> HRESULT {
+    dispatch(RenderEvent::EndScene, device);
+    let original: EndSceneFn = unsafe { std::mem::transmute(ORIGINAL_END_SCENE.load(Ordering::Acquire)) };
+    unsafe { original(device) }
+}
+
+/// Find the vtable shared by Direct3D 9 devices by creating a temporary device
+///
+/// The device is created on a hidden window and released before this returns.
+///
+/// # Safety
+///
+/// Creating a device loads the graphics driver's DLLs, which deadlocks if the calling thread holds
+/// the loader lock, so this must not be called from `DllMain` or a `dll_main!` teardown; the
+/// `dll_main!` init function runs on its own thread and is fine. The window is created on the
+/// calling thread.
+///
+/// # Errors
+///
+/// Returns an error if the calling thread holds the loader lock, Direct3D 9 isn't available, or
+/// the device couldn't be created.
+pub unsafe fn find_vtable() -> Result<*const usize> {
+    if loader::holds_loader_lock() {
+        return Err(Hook86Error::InvalidHook {
+            name: String::from("d3d9 device"),
+            reason: "a device can't be created while holding the loader lock",
+        });
+    }
+
+    let direct3d = unsafe { Direct3DCreate9(D3D_SDK_VERSION) }.ok_or_else(|| Hook86Error::InvalidHook {
+        name: String::from("d3d9 device"),
+        reason: "Direct3D 9 isn't available",
+    })?;
+
+    let window = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("STATIC"),
+            w!("hook86 d3d9"),
+            WS_OVERLAPPEDWINDOW,
+            0,
+            0,
+            64,
+            64,
+            None,
+            None,
+            None,
+            None,
+        )
+    }?;
+
+    let mut parameters = D3DPRESENT_PARAMETERS {
+        Windowed: true.into(),
+        SwapEffect: D3DSWAPEFFECT_DISCARD,
+        hDeviceWindow: window,
+        ..Default::default()
+    };
+    let mut device: Option<IDirect3DDevice9> = None;
+    let result = unsafe {
+        direct3d.CreateDevice(
+            D3DADAPTER_DEFAULT,
+            D3DDEVTYPE_HAL,
+            window,
+            (D3DCREATE_SOFTWARE_VERTEXPROCESSING | D3DCREATE_DISABLE_DRIVER_MANAGEMENT) as u32,
+            &mut parameters,
+            &mut device,
+        )
+    };
+    let vtable = device.as_ref().map(|device| unsafe { *(device.as_raw() as *const *const usize) });
+
+    // the device has to go before its window does
+    drop(device);
+    let _ = unsafe { DestroyWindow(window) };
+    result?;
+
+    vtable.ok_or_else(|| Hook86Error::InvalidHook {
+        name: String::from("d3d9 device"),
+        reason: "CreateDevice didn't return a device",
+    })
+}
+
+/// Hook `Reset`, `Present`, and `EndScene` in a Direct3D 9 device vtable
+///
+/// Use this with a vtable address found by a pattern scan if creating a temporary device with
+/// `install` isn't an option. The hooks are registered with the `HookManager`. Hooking the same
+/// vtable twice does nothing.
+///
+/// # Safety
+///
+/// `vtable` must point to an `IDirect3DDevice9` vtable that stays loaded. It's patched while the
+/// game may be using it.
+///
+/// # Errors
+///
+/// Returns an error if a different vtable is already hooked or the vtable couldn't be patched.
+pub unsafe fn hook_vtable(vtable: *const usize) -> Result<()> {
+    // claim the hooks atomically so two threads can't hook different vtables at once
+    match HOOKED_VTABLE.compare_exchange(0, vtable as usize, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => (),
+        Err(hooked) if hooked == vtable as usize => return Ok(()),
+        Err(_) => {
+            return Err(Hook86Error::InvalidHook {
+                name: String::from("d3d9 device"),
+                reason: "a different device vtable is already hooked",
+            });
+        }
+    }
+
+    let hooks: [(&str, usize, &AtomicUsize, usize); 3] = [
+        ("Reset", RESET_INDEX, &ORIGINAL_RESET, reset as ResetFn as usize),
+        ("Present", PRESENT_INDEX, &ORIGINAL_PRESENT, present as PresentFn as usize),
+        ("EndScene", END_SCENE_INDEX, &ORIGINAL_END_SCENE, end_scene as EndSceneFn as usize),
+    ];
+
+    let mut manager = HookManager::global();
+    for (i, &(function, index, original, detour)) in hooks.iter().enumerate() {
+        let slot = vtable.wrapping_add(index);
+        // the original has to be in place before the game can call the detour
+        original.store(unsafe { *slot }, Ordering::Release);
+        let name = format!("hook86 d3d9 {}", function);
+        if let Err(e) = unsafe { manager.apply(&name, slot as *const c_void, &detour.to_le_bytes()) } {
+            for &(function, ..) in &hooks[..i] {
+                let _ = unsafe { manager.revert(&format!("hook86 d3d9 {}", function)) };
+            }
+            HOOKED_VTABLE.store(0, Ordering::Release);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Hook `Reset`, `Present`, and `EndScene` for every device that shares the given device's vtable
+///
+/// Pass the game's own device here if it was created in a way that gives it a different vtable
+/// than the temporary device `install` creates, e.g. with `D3DCREATE_PUREDEVICE`.
+///
+/// # Safety
+///
+/// `device` must point to a live `IDirect3DDevice9`. See also `hook_vtable`.
+///
+/// # Errors
+///
+/// Returns an error if a different vtable is already hooked or the vtable couldn't be patched.
+pub unsafe fn hook_device(device: *mut c_void) -> Result<()> {
+    unsafe { hook_vtable(*(device as *const *const usize)) }
+}
+
+/// Hook `Reset`, `Present`, and `EndScene` for Direct3D 9 devices and start running render
+/// callbacks
+///
+/// A temporary device is created to find the vtable; the game's device shares it, so it's hooked
+/// no matter when the game creates it.
+///
+/// # Safety
+///
+/// See `find_vtable` and `hook_vtable`. In particular, this can't be called under the loader lock.
+///
+/// # Errors
+///
+/// Returns an error if the temporary device couldn't be created or the vtable couldn't be
+/// patched.
+pub unsafe fn install() -> Result<()> {
+    let vtable = unsafe { find_vtable() }?;
+    unsafe { hook_vtable(vtable) }
+}
@@ -0,0 +1,254 @@
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::mem::offset_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::core::{Interface, HRESULT};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::DirectDraw::{
+    DirectDrawCreateEx, IDirectDraw, IDirectDraw2, IDirectDraw4, IDirectDraw7, IDirectDrawSurface7, DDSCAPS_OFFSCREENPLAIN,
+    DDSCAPS_SYSTEMMEMORY, DDSCL_NORMAL, DDSD_CAPS, DDSD_HEIGHT, DDSD_WIDTH, DDSURFACEDESC2,
+};
+
+use crate::callbacks::{CallbackHandle, CallbackList};
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+
+/// The index of `CreateSurface` in the vtable of every version of `IDirectDraw`
+pub const CREATE_SURFACE_INDEX: usize = 6;
+/// The index of `Blt` in the vtable of every version of `IDirectDrawSurface`
+pub const BLT_INDEX: usize = 5;
+/// The index of `Flip` in the vtable of every version of `IDirectDrawSurface`
+pub const FLIP_INDEX: usize = 11;
+/// The offset of `dwFlags` in both `DDSURFACEDESC` and `DDSURFACEDESC2`
+const FLAGS_OFFSET: usize = offset_of!(DDSURFACEDESC2, dwFlags);
+/// The offset of `ddsCaps.dwCaps` in both `DDSURFACEDESC` and `DDSURFACEDESC2`
+const CAPS_OFFSET: usize = offset_of!(DDSURFACEDESC2, ddsCaps.dwCaps);
+/// The number of distinct `IDirectDraw` vtables that can be hooked, one for each interface version
+const MAX_VTABLES: usize = 4;
+
+/// `CreateSurface` takes a `DDSURFACEDESC` or a `DDSURFACEDESC2` depending on the interface version
+type CreateSurfaceFn = unsafe extern "system" fn(*mut c_void, *mut c_void, *mut *mut c_void, *mut c_void) -> HRESULT;
+
+/// The original `CreateSurface` of each hooked vtable, indexed the same as `DETOURS`
+static ORIGINALS: [AtomicUsize; MAX_VTABLES] = [const { AtomicUsize::new(0) }; MAX_VTABLES];
+const DETOURS: [CreateSurfaceFn; MAX_VTABLES] =
+    [create_surface::<0>, create_surface::<1>, create_surface::<2>, create_surface::<3>];
+static SURFACE_CALLBACKS: CallbackList<dyn FnMut(*mut c_void, u32) + Send> = CallbackList::new();
+
+thread_local! {
+    /// Set while hook86 creates its own temporary objects, so surface callbacks don't see them
+    static CREATING_TEMPORARY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Identifies a callback registered with `on_surface_created` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SurfaceHandle(CallbackHandle);
+
+/// Register a callback to run whenever the game creates a DirectDraw surface
+///
+/// The callback receives the new surface, which implements the `IDirectDrawSurface` version that
+/// matches the `IDirectDraw` version it was created with, and its `DDSCAPS_*` flags. Check for
+/// `DDSCAPS_PRIMARYSURFACE` to find the surface the game presents frames on, whose vtable's `Flip`
+/// (or `Blt`, for games that don't flip) is where per-frame hooks go. The flags are 0 if the game
+/// didn't specify any. The surface isn't `AddRef`ed, so it's only valid as long as the game keeps
+/// it. Nothing runs until `install` or `hook_create_surface` has been called, and the temporary
+/// surfaces hook86 creates to find vtables are never reported. A callback that panics is removed.
+pub fn on_surface_created(callback: impl FnMut(*mut c_void, u32) + Send + 'static) -> SurfaceHandle {
+    SurfaceHandle(SURFACE_CALLBACKS.add(Arc::new(Mutex::new(callback))))
+}
+
+/// Remove a callback registered with `on_surface_created`
+///
+/// Returns false if the callback was already removed.
+pub fn remove_surface_created(handle: SurfaceHandle) -> bool {
+    SURFACE_CALLBACKS.remove(handle.0)
+}
+
+unsafe extern "system" fn create_surface<const N: usize>(
+    direct_draw: *mut c_void,
+    description: *mut c_void,
+    surface: *mut *mut c_void,
+    outer: *mut c_void,
+) -> HRESULT {
+    let original: CreateSurfaceFn = unsafe { std::mem::transmute(ORIGINALS[N].load(Ordering::Acquire)) };
+    let result = unsafe { original(direct_draw, description, surface, outer) };
+    if result.is_err() || description.is_null() || surface.is_null() || CREATING_TEMPORARY.get() {
+        return result;
+    }
+
+    // ddsCaps is only meaningful if the game said it filled it in
+    let flags = unsafe { *(description.byte_add(FLAGS_OFFSET) as *const u32) };
+    let caps = if flags & DDSD_CAPS as u32 != 0 {
+        unsafe { *(description.byte_add(CAPS_OFFSET) as *const u32) }
+    } else {
+        0
+    };
+    let surface = unsafe { *surface };
+    SURFACE_CALLBACKS.dispatch(|callback| callback(surface, caps));
+
+    result
+}
+
+/// Hook `CreateSurface` for every DirectDraw object that shares the given vtable
+///
+/// Each version of the `IDirectDraw` interface has its own vtable; `install` hooks them all, but
+/// this can be used with a vtable found by a pattern scan or read from the game's own object.
+/// Hooking the same vtable twice does nothing.
+///
+/// # Safety
+///
+/// `vtable` must point to an `IDirectDraw` vtable (of any version) that stays loaded.
+///
+/// # Errors
+///
+/// Returns an error if too many different vtables have been hooked or the vtable couldn't be
+/// patched.
+pub unsafe fn hook_create_surface(vtable: *const usize) -> Result<()> {
+    let slot = vtable.wrapping_add(CREATE_SURFACE_INDEX);
+    let current = unsafe { *slot };
+    if DETOURS.iter().any(|&detour| detour as usize == current) {
+        return Ok(());
+    }
+
+    // claim a free slot atomically so two threads hooking different vtables can't both take it
+    let Some(index) = ORIGINALS.iter().position(|original| {
+        original.compare_exchange(0, current, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }) else {
+        return Err(Hook86Error::InvalidHook {
+            name: String::from("ddraw CreateSurface"),
+            reason: "too many DirectDraw vtables are hooked",
+        });
+    };
+
+    let name = format!("hook86 ddraw CreateSurface {}", index);
+    let detour = (DETOURS[index] as usize).to_le_bytes();
+    if let Err(e) = unsafe { HookManager::global().apply(&name, slot as *const c_void, &detour) } {
+        ORIGINALS[index].store(0, Ordering::Release);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+fn vtable_of(object: &impl Interface) -> *const usize {
+    unsafe { *(object.as_raw() as *const *const usize) }
+}
+
+/// Create a temporary DirectDraw object and an offscreen surface and pass them to `f`
+///
+/// Both are released when this returns.
+unsafe fn with_temporary_objects<T>(f: impl FnOnce(&IDirectDraw7, &IDirectDrawSurface7) -> Result<T>) -> Result<T> {
+    // CreateSurface may already be hooked
+    let was_creating = CREATING_TEMPORARY.replace(true);
+    let objects = unsafe { create_temporary_objects() };
+    CREATING_TEMPORARY.set(was_creating);
+    let (direct_draw, surface) = objects?;
+
+    f(&direct_draw, &surface)
+}
+
+unsafe fn create_temporary_objects() -> Result<(IDirectDraw7, IDirectDrawSurface7)> {
+    let mut direct_draw = std::ptr::null_mut();
+    unsafe { DirectDrawCreateEx(std::ptr::null_mut(), &mut direct_draw, &IDirectDraw7::IID, None) }?;
+    let direct_draw = unsafe { IDirectDraw7::from_raw(direct_draw) };
+    unsafe { direct_draw.SetCooperativeLevel(HWND::default(), DDSCL_NORMAL as u32) }?;
+
+    let mut description = DDSURFACEDESC2 {
+        dwSize: size_of::<DDSURFACEDESC2>() as u32,
+        dwFlags: (DDSD_CAPS | DDSD_WIDTH | DDSD_HEIGHT) as u32,
+        dwWidth: 1,
+        dwHeight: 1,
+        ..Default::default()
+    };
+    description.ddsCaps.dwCaps = (DDSCAPS_OFFSCREENPLAIN | DDSCAPS_SYSTEMMEMORY) as u32;
+    let mut surface = None;
+    unsafe { direct_draw.CreateSurface(&mut description, &mut surface, None) }?;
+    let surface = surface.ok_or_else(|| Hook86Error::InvalidHook {
+        name: String::from("ddraw surface"),
+        reason: "CreateSurface didn't return a surface",
+    })?;
+
+    Ok((direct_draw, surface))
+}
+
+/// Find the vtable of the given `IDirectDraw` interface version (e.g. `IDirectDraw2`)
+///
+/// A temporary DirectDraw object is created and released to find it.
+///
+/// # Safety
+///
+/// This creates COM objects, so COM must be usable on the calling thread, and it must not be
+/// called while holding the loader lock (e.g. from `DllMain`).
+///
+/// # Errors
+///
+/// Returns an error if DirectDraw isn't available or doesn't support the interface.
+pub unsafe fn find_direct_draw_vtable<T: Interface>() -> Result<*const usize> {
+    unsafe { with_temporary_objects(|direct_draw, _| Ok(vtable_of(&direct_draw.cast::<T>()?))) }
+}
+
+/// Find the vtable of the given `IDirectDrawSurface` interface version (e.g. `IDirectDrawSurface7`)
+///
+/// A temporary surface is created and released to find it. Surfaces of every kind (primary,
+/// back buffer, offscreen) normally share the vtable for their interface version, but
+/// `on_surface_created` can confirm it for the game's own primary surface.
+///
+/// # Safety
+///
+/// See `find_direct_draw_vtable`.
+///
+/// # Errors
+///
+/// Returns an error if DirectDraw isn't available or doesn't support the interface.
+pub unsafe fn find_surface_vtable<T: Interface>() -> Result<*const usize> {
+    unsafe { with_temporary_objects(|_, surface| Ok(vtable_of(&surface.cast::<T>()?))) }
+}
+
+/// Hook `CreateSurface` for every version of the `IDirectDraw` interface, so surface callbacks see
+/// every surface the game creates
+///
+/// Temporary objects are created to find the vtables; the game's objects share them, so they're
+/// hooked no matter when the game creates them.
+///
+/// # Safety
+///
+/// See `find_direct_draw_vtable`. The vtables are patched while other threads may be using them.
+///
+/// # Errors
+///
+/// Returns an error if DirectDraw isn't available or a vtable couldn't be patched.
+pub unsafe fn install() -> Result<()> {
+    let vtables = unsafe {
+        with_temporary_objects(|direct_draw, _| {
+            Ok([
+                vtable_of(&direct_draw.cast::<IDirectDraw>()?),
+                vtable_of(&direct_draw.cast::<IDirectDraw2>()?),
+                vtable_of(&direct_draw.cast::<IDirectDraw4>()?),
+                vtable_of(direct_draw),
+            ])
+        })
+    }?;
+
+    for vtable in vtables {
+        unsafe { hook_create_surface(vtable) }?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Graphics::DirectDraw::DDSURFACEDESC;
+
+    use super::*;
+
+    #[test]
+    fn description_layout() {
+        // the hook reads both versions of the description through the same offsets
+        assert_eq!(offset_of!(DDSURFACEDESC, dwFlags), FLAGS_OFFSET);
+        assert_eq!(offset_of!(DDSURFACEDESC, ddsCaps.dwCaps), CAPS_OFFSET);
+        assert_eq!(CAPS_OFFSET, 104);
+    }
+}
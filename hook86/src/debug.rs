@@ -0,0 +1,301 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::fmt;
+
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{HMODULE, MAX_PATH};
+use windows::Win32::System::Diagnostics::Debug::RtlCaptureStackBackTrace;
+use windows::Win32::System::LibraryLoader::{
+    GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+use windows::Win32::System::ProcessStatus::GetModuleBaseNameW;
+use windows::Win32::System::Threading::GetCurrentProcess;
+
+use crate::asm::{self, Reg};
+use crate::callbacks;
+use crate::error::Result;
+use crate::hook::{CaveHook, HookSite, Prelude};
+use crate::pe::{self, ExportTarget};
+
+/// The nearest exported function at or before a code address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the export
+    pub name: String,
+    /// The distance from the start of the export to the address
+    pub offset: usize,
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+0x{:X}", self.name, self.offset)
+    }
+}
+
+/// A code address attributed to the module it resides in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeLocation {
+    /// The absolute address
+    pub address: usize,
+    /// The name of the module containing the address, if any
+    pub module: Option<String>,
+    /// The offset of the address from the module base, or the absolute address if there is no module
+    pub offset: usize,
+    /// The nearest named export of the module at or before the address, if any
+    ///
+    /// Game executables rarely export anything, so this is mostly useful for addresses in system
+    /// and middleware DLLs. An address far past the export may belong to an unexported function.
+    pub symbol: Option<Symbol>,
+}
+
+impl fmt::Display for CodeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.module {
+            Some(ref module) => write!(f, "{}+0x{:X}", module, self.offset)?,
+            None => write!(f, "{:08X}", self.address)?,
+        }
+
+        match self.symbol {
+            Some(ref symbol) => write!(f, " ({})", symbol),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Find the named export of a module closest to and not after `address`
+fn nearest_export(module: HMODULE, address: usize) -> Option<Symbol> {
+    let exports = unsafe { pe::exports(module.0 as *const c_void) }.ok()?;
+    exports
+        .into_iter()
+        .filter_map(|export| match export.target {
+            ExportTarget::Address(start) if start as usize <= address => Some((export.name?, start as usize)),
+            _ => None,
+        })
+        .max_by_key(|&(_, start)| start)
+        .map(|(name, start)| Symbol {
+            name,
+            offset: address - start,
+        })
+}
+
+/// Determine which module, and which exported function of that module, the given address belongs to
+///
+/// If the address isn't inside any loaded module (e.g. it's in a heap-allocated stub or JIT
+/// code), the returned location will have no module name or symbol and its offset will be the
+/// absolute address.
+pub fn locate(addr: *const c_void) -> CodeLocation {
+    let address = addr as usize;
+    let mut module = HMODULE::default();
+    let found = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR::from_raw(addr as *const u16),
+            &mut module,
+        )
+    }
+    .is_ok();
+
+    if !found {
+        return CodeLocation {
+            address,
+            module: None,
+            offset: address,
+            symbol: None,
+        };
+    }
+
+    let mut name_buf = [0u16; MAX_PATH as usize];
+    let module_name = unsafe {
+        let num_chars = GetModuleBaseNameW(GetCurrentProcess(), Some(module), &mut name_buf) as usize;
+        if num_chars == 0 || num_chars >= name_buf.len() {
+            None
+        } else {
+            PWSTR::from_raw(name_buf.as_mut_ptr()).to_string().ok()
+        }
+    };
+
+    CodeLocation {
+        address,
+        module: module_name,
+        offset: address - module.0 as usize,
+        symbol: nearest_export(module, address),
+    }
+}
+
+/// Identify the code that called the function calling `caller`
+///
+/// This is meant to be called at the top of a detour to answer "who called this?". The return
+/// address is recovered by walking the stack with `RtlCaptureStackBackTrace`, which is a heuristic:
+/// game code compiled with frame pointer omission can confuse the walk, in which case this returns
+/// `None` or a misattributed address. When exact results are required, use `trace_calls`, whose
+/// stub captures `[esp]` on entry to the hooked function.
+#[inline(never)]
+pub fn caller() -> Option<CodeLocation> {
+    let mut frames = [std::ptr::null_mut(); 1];
+    // skip our own frame and the detour's frame to land on the detour's return address
+    let num_frames = unsafe { RtlCaptureStackBackTrace(2, &mut frames, None) };
+    if num_frames == 0 {
+        return None;
+    }
+
+    Some(locate(frames[0]))
+}
+
+/// A snapshot of the general-purpose registers
+///
+/// The fields are in the order `pushad` leaves them on the stack, so a stub that executes
+/// `pushad; push esp` can pass a pointer to this struct to a handler.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub edi: u32,
+    pub esi: u32,
+    pub ebp: u32,
+    pub esp: u32,
+    pub ebx: u32,
+    pub edx: u32,
+    pub ecx: u32,
+    pub eax: u32,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "eax = {:08X}\tecx = {:08X}\tedx = {:08X}\tebx = {:08X}\tesp = {:08X}\tebp = {:08X}\tesi = {:08X}\tedi = {:08X}",
+            self.eax, self.ecx, self.edx, self.ebx, self.esp, self.ebp, self.esi, self.edi
+        )
+    }
+}
+
+/// Information identifying the detour that is currently executing
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    /// A human-readable name for the hook
+    pub name: &'static str,
+    /// The address that was hooked
+    pub target: usize,
+    /// The registers on entry to the detour, if they were captured
+    pub registers: Option<Registers>,
+}
+
+thread_local! {
+    static HOOK_CONTEXT: RefCell<Vec<HookContext>> = const { RefCell::new(Vec::new()) };
+}
+
+struct HookContextGuard;
+
+impl Drop for HookContextGuard {
+    fn drop(&mut self) {
+        let _ = HOOK_CONTEXT.try_with(|stack| stack.borrow_mut().pop());
+    }
+}
+
+/// Run a detour body with the given context attached
+///
+/// If the body panics, the panic logger will report the hook's name, target address, and
+/// registers along with the panic message. Contexts nest, so a detour that calls into another
+/// hooked function reports the innermost hook.
+pub fn with_hook_context<R>(context: HookContext, body: impl FnOnce() -> R) -> R {
+    HOOK_CONTEXT.with(|stack| stack.borrow_mut().push(context));
+    let _guard = HookContextGuard;
+    body()
+}
+
+/// Get the context of the innermost detour executing on the current thread, if any
+pub fn current_hook_context() -> Option<HookContext> {
+    HOOK_CONTEXT
+        .try_with(|stack| stack.try_borrow().ok().and_then(|stack| stack.last().cloned()))
+        .ok()
+        .flatten()
+}
+
+/// A call to a function hooked with `trace_calls`
+#[derive(Debug)]
+pub struct TracedCall<'a> {
+    /// The name the hook was installed under
+    pub name: &'a str,
+    /// The address of the hooked function
+    pub target: usize,
+    /// Where the function was called from, i.e. the return address it will return to
+    pub caller: CodeLocation,
+    /// The registers on entry to the function. `esp` points at the return address, so the
+    /// function's stack arguments start at `esp + 4`.
+    pub registers: &'a Registers,
+}
+
+struct TraceHook {
+    name: &'static str,
+    target: usize,
+    callback: Box<dyn Fn(&TracedCall) + Send + Sync>,
+}
+
+unsafe extern "system" fn trace_dispatch(hook: *const TraceHook, registers: *const Registers, return_address: usize) {
+    let hook = unsafe { &*hook };
+    let registers = unsafe { &*registers };
+    let context = HookContext {
+        name: hook.name,
+        target: hook.target,
+        registers: Some(*registers),
+    };
+
+    callbacks::catch_panic(|| {
+        with_hook_context(context, || {
+            (hook.callback)(&TracedCall {
+                name: hook.name,
+                target: hook.target,
+                caller: locate(return_address as *const c_void),
+                registers,
+            })
+        })
+    });
+}
+
+/// Hook the start of a function to report every call to it along with its caller
+///
+/// The generated stub saves the registers, reads the return address from `[esp]` before the
+/// function has touched the stack, and passes both to `callback`, which runs inside a hook
+/// context so a panic in it is reported with the hook's name. The function then runs normally.
+/// Unlike `caller`, this doesn't depend on stack walking, so it works for code compiled without
+/// frame pointers. The hook is registered with the `HookManager` under `name`, so it can be
+/// reverted through it.
+///
+/// # Safety
+///
+/// `target` must be the first instruction of a function, and no thread may be executing the first
+/// 5 bytes of it while the hook is installed. `callback` runs on whichever thread calls the
+/// function, possibly with game locks held.
+///
+/// # Errors
+///
+/// Returns an error if the start of the function contains a relative branch, which can't be
+/// re-executed from the stub, or if the hook couldn't be installed.
+pub unsafe fn trace_calls(
+    name: &str,
+    target: *const c_void,
+    callback: impl Fn(&TracedCall) + Send + Sync + 'static,
+) -> Result<HookSite> {
+    let prelude = || {
+        // the stub and its context live as long as the hook, which is never freed
+        let trace: &'static TraceHook = Box::leak(Box::new(TraceHook {
+            name: name.to_string().leak(),
+            target: target as usize,
+            callback: Box::new(callback),
+        }));
+
+        // pushad; mov eax, esp; pushfd; mov ecx, [eax+32]; push ecx; push eax; push trace;
+        // call trace_dispatch; popfd; popad
+        Prelude::new()
+            .code(&[0x60])
+            .code(&asm::mov_reg_reg(Reg::Eax, Reg::Esp))
+            .code(&[0x9C])
+            .code(&asm::mov_reg_mem(Reg::Ecx, Reg::Eax, size_of::<Registers>() as i32))
+            .code(&asm::push_reg(Reg::Ecx))
+            .code(&asm::push_reg(Reg::Eax))
+            .code(&asm::push(trace as *const TraceHook as usize))
+            .call(trace_dispatch as unsafe extern "system" fn(_, _, _) as usize)
+            .code(&[0x9D, 0x61])
+    };
+
+    unsafe { CaveHook::install_with_prelude(name, target, prelude) }
+}
@@ -0,0 +1,284 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use windows::core::{Interface, GUID, HRESULT};
+use windows::Win32::Devices::HumanInterfaceDevice::{
+    DirectInput8Create, IDirectInput8A, IDirectInput8W, IDirectInputDevice8A, IDirectInputDevice8W, DIMOUSESTATE,
+    DIMOUSESTATE2, DIRECTINPUT_VERSION, GUID_SysKeyboard, GUID_SysMouse,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+
+/// The index of `GetDeviceState` in the vtable of every version of `IDirectInputDevice`
+const GET_DEVICE_STATE_INDEX: usize = 9;
+/// The number of distinct device vtables that can be hooked
+const MAX_VTABLES: usize = 4;
+const KEYBOARD_STATE_SIZE: usize = 256;
+const MAX_MOUSE_BUTTONS: usize = 8;
+
+type GetDeviceStateFn = unsafe extern "system" fn(*mut c_void, u32, *mut c_void) -> HRESULT;
+
+/// The original `GetDeviceState` of each hooked vtable, indexed the same as `DETOURS`
+static ORIGINALS: [AtomicUsize; MAX_VTABLES] = [const { AtomicUsize::new(0) }; MAX_VTABLES];
+const DETOURS: [GetDeviceStateFn; MAX_VTABLES] =
+    [get_device_state::<0>, get_device_state::<1>, get_device_state::<2>, get_device_state::<3>];
+
+/// The input the game has read most recently
+#[derive(Debug)]
+struct ObservedState {
+    keys: [u8; KEYBOARD_STATE_SIZE],
+    buttons: [u8; MAX_MOUSE_BUTTONS],
+    /// Relative mouse movement (x, y, wheel) accumulated since the last `DirectInput::update`
+    movement: (i32, i32, i32),
+}
+
+static OBSERVED: Mutex<ObservedState> = Mutex::new(ObservedState {
+    keys: [0; KEYBOARD_STATE_SIZE],
+    buttons: [0; MAX_MOUSE_BUTTONS],
+    movement: (0, 0, 0),
+});
+
+/// Record device state the game just read
+///
+/// Keyboard and mouse devices share a vtable, so the kind of device is told apart by the size of
+/// the state structure the game asked for.
+unsafe fn observe(size: usize, data: *const c_void) {
+    let mut observed = OBSERVED.lock().unwrap_or_else(|e| e.into_inner());
+    match size {
+        KEYBOARD_STATE_SIZE => {
+            observed.keys = unsafe { *(data as *const [u8; KEYBOARD_STATE_SIZE]) };
+        }
+        _ if size == size_of::<DIMOUSESTATE>() || size == size_of::<DIMOUSESTATE2>() => {
+            // the two structures have the same layout apart from the number of buttons
+            let state = unsafe { &*(data as *const DIMOUSESTATE2) };
+            let num_buttons = if size == size_of::<DIMOUSESTATE>() { 4 } else { MAX_MOUSE_BUTTONS };
+            observed.buttons[..num_buttons].copy_from_slice(&state.rgbButtons[..num_buttons]);
+            observed.movement.0 += state.lX;
+            observed.movement.1 += state.lY;
+            observed.movement.2 += state.lZ;
+        }
+        // joysticks and custom data formats
+        _ => (),
+    }
+}
+
+unsafe extern "system" fn get_device_state<const N: usize>(device: *mut c_void, size: u32, data: *mut c_void) -> HRESULT {
+    let original: GetDeviceStateFn = unsafe { std::mem::transmute(ORIGINALS[N].load(Ordering::Acquire)) };
+    let result = unsafe { original(device, size, data) };
+    if result.is_ok() && !data.is_null() {
+        unsafe { observe(size as usize, data) };
+    }
+
+    result
+}
+
+/// Hook `GetDeviceState` for every DirectInput device that shares the given device's vtable
+///
+/// `install` only covers DirectInput 8. Games that use DirectInput 7 or older read their devices
+/// through `IDirectInputDevice`, `IDirectInputDevice2`, or `IDirectInputDevice7`, which hook86
+/// can't create temporary instances of; pass one of the game's own devices (e.g. captured from
+/// a `CreateDevice` hook) here instead. `GetDeviceState` is at the same index in every version, so
+/// any of them works. Hooking the same vtable twice does nothing.
+///
+/// # Safety
+///
+/// `device` must point to a live `IDirectInputDevice` of any version. Its vtable is patched while
+/// the game may be using it.
+///
+/// # Errors
+///
+/// Returns an error if too many different vtables have been hooked or the vtable couldn't be
+/// patched.
+pub unsafe fn hook_device(device: *mut c_void) -> Result<()> {
+    let vtable = unsafe { *(device as *const *const usize) };
+    let slot = vtable.wrapping_add(GET_DEVICE_STATE_INDEX);
+    let current = unsafe { *slot };
+    if DETOURS.iter().any(|&detour| detour as usize == current) {
+        return Ok(());
+    }
+
+    // claim a free slot atomically so two threads hooking different vtables can't both take it
+    let Some(index) = ORIGINALS.iter().position(|original| {
+        original.compare_exchange(0, current, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }) else {
+        return Err(Hook86Error::InvalidHook {
+            name: String::from("dinput GetDeviceState"),
+            reason: "too many DirectInput device vtables are hooked",
+        });
+    };
+
+    let name = format!("hook86 dinput GetDeviceState {}", index);
+    let detour = (DETOURS[index] as usize).to_le_bytes();
+    if let Err(e) = unsafe { HookManager::global().apply(&name, slot as *const c_void, &detour) } {
+        ORIGINALS[index].store(0, Ordering::Release);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Hook `GetDeviceState` for DirectInput 8 keyboard and mouse devices
+///
+/// Temporary devices are created to find the vtables; the game's own devices share them, so
+/// they're hooked no matter when the game creates them. Games that read input with
+/// `GetDeviceData` (buffered input) instead of `GetDeviceState` aren't observed, and neither are
+/// games using DirectInput 7 or older; see `hook_device` for those.
+///
+/// # Safety
+///
+/// This loads dinput8.dll, so it must not be called while holding the loader lock (e.g. from
+/// `DllMain`). The vtables are patched while the game may be using them.
+///
+/// # Errors
+///
+/// Returns an error if DirectInput 8 isn't available or a vtable couldn't be patched.
+pub unsafe fn install() -> Result<()> {
+    let instance = unsafe { GetModuleHandleW(None) }?;
+    for device_guid in [GUID_SysKeyboard, GUID_SysMouse] {
+        let direct_input: IDirectInput8W = unsafe { create_direct_input(instance.into(), &IDirectInput8W::IID) }?;
+        let mut device: Option<IDirectInputDevice8W> = None;
+        unsafe { direct_input.CreateDevice(&device_guid, &mut device, None) }?;
+        if let Some(device) = device {
+            unsafe { hook_device(device.as_raw()) }?;
+        }
+
+        // ANSI games get a different vtable
+        let direct_input: IDirectInput8A = unsafe { create_direct_input(instance.into(), &IDirectInput8A::IID) }?;
+        let mut device: Option<IDirectInputDevice8A> = None;
+        unsafe { direct_input.CreateDevice(&device_guid, &mut device, None) }?;
+        if let Some(device) = device {
+            unsafe { hook_device(device.as_raw()) }?;
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn create_direct_input<T: Interface>(
+    instance: windows::Win32::Foundation::HINSTANCE,
+    iid: &GUID,
+) -> windows::core::Result<T> {
+    let mut direct_input = std::ptr::null_mut();
+    unsafe { DirectInput8Create(instance, DIRECTINPUT_VERSION, iid, &mut direct_input, None) }?;
+    Ok(unsafe { T::from_raw(direct_input) })
+}
+
+/// Keyboard and mouse state as the game read it through DirectInput, updated once per frame like
+/// `Keyboard`
+///
+/// Keys are identified by DirectInput scan codes (`DIK_*`). Nothing is reported until `install` or
+/// `hook_device` has been called and the game has read its devices.
+#[derive(Debug)]
+pub struct DirectInput {
+    old_keys: [u8; KEYBOARD_STATE_SIZE],
+    new_keys: [u8; KEYBOARD_STATE_SIZE],
+    old_buttons: [u8; MAX_MOUSE_BUTTONS],
+    new_buttons: [u8; MAX_MOUSE_BUTTONS],
+    movement: (i32, i32, i32),
+}
+
+impl DirectInput {
+    pub const fn new() -> Self {
+        Self {
+            old_keys: [0; KEYBOARD_STATE_SIZE],
+            new_keys: [0; KEYBOARD_STATE_SIZE],
+            old_buttons: [0; MAX_MOUSE_BUTTONS],
+            new_buttons: [0; MAX_MOUSE_BUTTONS],
+            movement: (0, 0, 0),
+        }
+    }
+
+    pub fn update(&mut self) {
+        let mut observed = OBSERVED.lock().unwrap_or_else(|e| e.into_inner());
+        self.old_keys = self.new_keys;
+        self.new_keys = observed.keys;
+        self.old_buttons = self.new_buttons;
+        self.new_buttons = observed.buttons;
+        self.movement = std::mem::take(&mut observed.movement);
+    }
+
+    pub const fn is_key_down(&self, key: u32) -> bool {
+        self.new_keys[key as usize & 0xFF] & 0x80 != 0
+    }
+
+    pub const fn is_key_down_once(&self, key: u32) -> bool {
+        self.is_key_down(key) && self.old_keys[key as usize & 0xFF] & 0x80 == 0
+    }
+
+    pub const fn is_key_up_once(&self, key: u32) -> bool {
+        !self.is_key_down(key) && self.old_keys[key as usize & 0xFF] & 0x80 != 0
+    }
+
+    pub fn is_any_key_down_once(&self, keys: &[u32]) -> bool {
+        keys.iter().any(|&key| self.is_key_down_once(key))
+    }
+
+    /// Whether a mouse button is down, where 0 is the left button, 1 is the right, and 2 is the middle
+    pub const fn is_button_down(&self, button: usize) -> bool {
+        button < MAX_MOUSE_BUTTONS && self.new_buttons[button] & 0x80 != 0
+    }
+
+    pub const fn is_button_down_once(&self, button: usize) -> bool {
+        self.is_button_down(button) && self.old_buttons[button] & 0x80 == 0
+    }
+
+    /// The relative mouse movement the game read between the last two updates, as (x, y, wheel)
+    pub const fn mouse_movement(&self) -> (i32, i32, i32) {
+        self.movement
+    }
+}
+
+impl Default for DirectInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observed_state() {
+        let mut keys = [0u8; KEYBOARD_STATE_SIZE];
+        keys[0x1E] = 0x80;
+        let mouse = DIMOUSESTATE {
+            lX: 5,
+            lY: -3,
+            lZ: 120,
+            rgbButtons: [0x80, 0, 0, 0x80],
+        };
+        let mouse2 = DIMOUSESTATE2 {
+            lX: 1,
+            rgbButtons: [0, 0, 0, 0, 0, 0, 0, 0x80],
+            ..Default::default()
+        };
+
+        let mut input = DirectInput::new();
+        unsafe {
+            observe(size_of_val(&keys), keys.as_ptr() as *const c_void);
+            observe(size_of::<DIMOUSESTATE>(), &mouse as *const _ as *const c_void);
+        }
+        input.update();
+        assert!(input.is_key_down_once(0x1E));
+        assert!(!input.is_key_down(0x1F));
+        assert!(input.is_button_down_once(0) && input.is_button_down(3));
+        assert_eq!(input.mouse_movement(), (5, -3, 120));
+
+        // DIMOUSESTATE only has 4 buttons, so the rest keep their last state
+        unsafe { observe(size_of::<DIMOUSESTATE2>(), &mouse2 as *const _ as *const c_void) };
+        input.update();
+        assert!(input.is_key_down(0x1E) && !input.is_key_down_once(0x1E));
+        assert!(!input.is_button_down(0) && input.is_button_down_once(7));
+        assert_eq!(input.mouse_movement(), (1, 0, 0));
+
+        // data the hook doesn't understand is ignored
+        unsafe { observe(4, keys.as_ptr() as *const c_void) };
+        input.update();
+        assert_eq!(input.mouse_movement(), (0, 0, 0));
+        assert!(input.is_key_down(0x1E));
+    }
+}
@@ -0,0 +1,178 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::thread;
+
+use windows::Win32::Foundation::HMODULE;
+use windows::Win32::System::LibraryLoader::DisableThreadLibraryCalls;
+use windows::Win32::System::SystemServices::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
+
+use crate::error::Result;
+use crate::hook::HookManager;
+
+#[cfg(feature = "crash_logging")]
+pub use crate::crash::install_crash_loggers;
+
+static MODULE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// The functions a `dll_main!` entry point calls
+///
+/// This is filled in by the macro; there's normally no reason to build one by hand.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    /// Runs on the init thread before `init`, e.g. to install crash loggers
+    pub setup: fn(),
+    /// The mod's initialization
+    pub init: fn() -> Result<()>,
+    /// The mod's cleanup when the DLL is unloaded, which runs under the loader lock
+    pub teardown: fn(),
+}
+
+/// The base address of the DLL whose entry point was generated by `dll_main!`
+///
+/// This is null until the DLL has been attached.
+pub fn module() -> *const c_void {
+    MODULE.load(Ordering::Acquire)
+}
+
+fn report_init_failure(error: &crate::Hook86Error) {
+    #[cfg(feature = "log")]
+    log::error!("Initialization failed: {}", error);
+    #[cfg(not(feature = "log"))]
+    {
+        let message = windows::core::HSTRING::from(format!("Initialization failed: {}\n", error));
+        unsafe { windows::Win32::System::Diagnostics::Debug::OutputDebugStringW(&message) };
+    }
+}
+
+/// The implementation of the `DllMain` generated by `dll_main!`
+///
+/// On attach, this disables thread notifications and starts a thread that runs `setup` and then
+/// `init`. Doing the real work on a separate thread means it doesn't run under the loader lock,
+/// where loading another DLL or waiting on a thread can deadlock the process.
+///
+/// On detach, `teardown` is called and every patch registered with the `HookManager` is reverted,
+/// but only if the DLL is being unloaded with `FreeLibrary`. If the process is exiting, the other
+/// threads have already been killed, possibly while holding locks, so nothing is done.
+///
+/// `teardown` runs on the thread that called `FreeLibrary`, while it holds the loader lock. It must
+/// not load or free DLLs, create threads or wait for them (including joining the init thread),
+/// wait on locks or events another thread might need the loader lock to release, or create or
+/// release COM and graphics objects, any of which can deadlock the process. Unhooking, freeing
+/// memory, and flushing files are fine.
+///
+/// # Safety
+///
+/// This must only be called from a DLL's entry point with the arguments the loader passed to it.
+pub unsafe fn dll_main(module: *mut c_void, reason: u32, reserved: *mut c_void, entry: &Entry) -> i32 {
+    match reason {
+        DLL_PROCESS_ATTACH => {
+            MODULE.store(module, Ordering::Release);
+            let _ = unsafe { DisableThreadLibraryCalls(HMODULE(module)) };
+
+            let entry = *entry;
+            let spawned = thread::Builder::new().name(String::from("hook86 init")).spawn(move || {
+                (entry.setup)();
+                if let Err(e) = (entry.init)() {
+                    report_init_failure(&e);
+                }
+            });
+            if spawned.is_err() {
+                return 0;
+            }
+        }
+        DLL_PROCESS_DETACH if reserved.is_null() => {
+            (entry.teardown)();
+            // don't wait for the lock; whoever holds it may never release it while we hold the
+            // loader lock
+            if let Some(mut manager) = HookManager::try_global() {
+                let _ = unsafe { manager.revert_all() };
+            }
+        }
+        _ => (),
+    }
+
+    1
+}
+
+/// Generate a `DllMain` for a mod
+///
+/// The generated entry point does the work a mod's `DllMain` always needs to do and is easy to get
+/// wrong; see `entry::dll_main` for the details. `init` is the path of a `fn() -> hook86::Result<()>`
+/// that's run on a new thread after the DLL is loaded. The optional `teardown` is the path of a
+/// `fn()` that's run when the DLL is unloaded, before the registered patches are reverted. It runs
+/// under the loader lock, so it's limited in what it can safely do; see `entry::dll_main`. With the
+/// `crash_logging` feature, `crash` takes a `CrashConfig` to install the crash loggers with before
+/// `init` runs, e.g. `crash: hook86::crash::CrashConfig::default()`. The keys must be given in this
+/// order.
+///
+/// ```no_run
+/// fn init() -> hook86::Result<()> {
+///     // scan for signatures and install hooks
+///     Ok(())
+/// }
+///
+/// fn teardown() {
+///     // runs under the loader lock: no loading DLLs, joining threads, or waiting on locks
+/// }
+///
+/// hook86::dll_main! {
+///     init: init,
+///     teardown: teardown,
+/// }
+/// ```
+#[macro_export]
+macro_rules! dll_main {
+    (init: $init:path $(, teardown: $teardown:path)? $(, crash: $crash:expr)? $(,)?) => {
+        #[unsafe(no_mangle)]
+        pub unsafe extern "system" fn DllMain(
+            module: *mut ::core::ffi::c_void,
+            reason: u32,
+            reserved: *mut ::core::ffi::c_void,
+        ) -> i32 {
+            // closures rather than nested fns, which would shadow a user function with the same
+            // name and make it call itself
+            let entry = $crate::entry::Entry {
+                setup: || {
+                    $($crate::entry::install_crash_loggers($crash);)?
+                },
+                init: $init,
+                teardown: || {
+                    $($teardown();)?
+                },
+            };
+            unsafe { $crate::entry::dll_main(module, reason, reserved, &entry) }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use windows::Win32::System::SystemServices::DLL_PROCESS_DETACH;
+
+    static TEARDOWNS: AtomicU32 = AtomicU32::new(0);
+
+    fn init() -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn teardown() {
+        TEARDOWNS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    crate::dll_main! {
+        init: init,
+        teardown: teardown,
+    }
+
+    #[test]
+    fn detach() {
+        // a non-null reserved pointer means the process is exiting
+        assert_eq!(unsafe { DllMain(std::ptr::null_mut(), DLL_PROCESS_DETACH, std::ptr::dangling_mut()) }, 1);
+        assert_eq!(TEARDOWNS.load(Ordering::Relaxed), 0);
+
+        assert_eq!(unsafe { DllMain(std::ptr::null_mut(), DLL_PROCESS_DETACH, std::ptr::null_mut()) }, 1);
+        assert_eq!(TEARDOWNS.load(Ordering::Relaxed), 1);
+    }
+}
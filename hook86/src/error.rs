@@ -0,0 +1,66 @@
+use thiserror::Error;
+
+use crate::asm::UnexpectedOpcodeError;
+use crate::input::KeyParseError;
+use crate::pattern::PatternParseError;
+
+/// The error type for all fallible hook86 operations
+#[derive(Error, Debug)]
+pub enum Hook86Error {
+    #[error(transparent)]
+    Windows(#[from] windows::core::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    UnexpectedOpcode(#[from] UnexpectedOpcodeError),
+    #[error(transparent)]
+    PatternParse(#[from] PatternParseError),
+    #[error(transparent)]
+    KeyParse(#[from] KeyParseError),
+    #[error("Pattern {name} was not found")]
+    PatternNotFound { name: String },
+    #[error("Module {module} has not been discovered")]
+    ModuleNotFound { module: String },
+    #[error("Offset {offset:X} is outside of module {module}")]
+    RvaOutOfRange { module: String, offset: u32 },
+    #[error("Module {module} has no export {export}")]
+    ExportNotFound { module: String, export: String },
+    #[error("Invalid patch file on line {line}: {reason}")]
+    PatchFileParse { line: usize, reason: &'static str },
+    #[error("Invalid patch set: {reason}")]
+    PatchSet { reason: String },
+    #[error("Unrecognized module build: {fingerprint}")]
+    UnknownBuild { fingerprint: String },
+    #[error("No address named {name} has been recorded")]
+    UnknownAddress { name: String },
+    #[error("Address {name} can't be read as a {type_name}, which isn't pointer-sized")]
+    NotFunctionPointer { name: String, type_name: &'static str },
+    #[error("Failed to resolve signatures: {}", .failures.join("; "))]
+    SignaturesUnresolved { failures: Vec<String> },
+    #[error("Displacement from {from:08X} to {to:08X} does not fit in a {bits}-bit offset")]
+    DisplacementOutOfRange { from: usize, to: usize, bits: u32 },
+    #[error("Unexpected bytes at {ptr:08X}: expected {expected:02X?}, found {actual:02X?}")]
+    VerificationMismatch { ptr: usize, expected: Vec<u8>, actual: Vec<u8> },
+    #[error("Memory at {ptr:08X} ({size} bytes) is not readable")]
+    UnreadableMemory { ptr: usize, size: usize },
+    #[error("Pointer chain broken at hop {hop} (pointer at {address:08X}): {reason}")]
+    BrokenPointerChain { hop: usize, address: usize, reason: &'static str },
+    #[error("Invalid module image at {base:08X}: {reason}")]
+    InvalidImage { base: usize, reason: &'static str },
+    #[error("{operation} is not supported when searching another process")]
+    RemoteUnsupported { operation: &'static str },
+    #[error("A logger has already been installed")]
+    LoggerAlreadySet,
+    #[error("Injection failed: {reason}")]
+    InjectionFailed { reason: &'static str },
+    #[error("Invalid hook {name}: {reason}")]
+    InvalidHook { name: String, reason: &'static str },
+}
+
+pub type Result<T> = std::result::Result<T, Hook86Error>;
+
+// errors have to be able to cross threads, e.g. out of a `dll_main!` init thread or into `anyhow`
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync + 'static>() {}
+    assert_send_sync::<Hook86Error>();
+};
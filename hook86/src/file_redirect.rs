@@ -0,0 +1,244 @@
+use std::cell::Cell;
+use std::ffi::{c_void, OsString};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+use windows::core::{PCSTR, PCWSTR};
+use windows::Win32::Foundation::{HANDLE, HMODULE};
+use windows::Win32::System::LibraryLoader::{
+    GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+
+use crate::callbacks;
+use crate::error::Result;
+use crate::hook::HookManager;
+use crate::mem::ByteSearcher;
+use crate::pe;
+
+type CreateFileWFn = unsafe extern "system" fn(PCWSTR, u32, u32, *const c_void, u32, u32, HANDLE) -> HANDLE;
+type CreateFileAFn = unsafe extern "system" fn(PCSTR, u32, u32, *const c_void, u32, u32, HANDLE) -> HANDLE;
+type Redirect = Box<dyn Fn(&Path) -> Option<PathBuf> + Send + Sync>;
+
+static REDIRECT: RwLock<Option<Redirect>> = RwLock::new(None);
+static ORIGINAL_CREATE_FILE_W: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_CREATE_FILE_A: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Set while the redirect callback runs, so files it opens itself aren't redirected
+    static REDIRECTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set the callback that decides where files are really opened from
+///
+/// The callback receives the path the game passed to `CreateFile` (which may be relative to the
+/// current directory) and returns the path to open instead, or `None` to open the original. It
+/// runs on whichever thread opened the file, so it should be quick. Replaces any previous
+/// callback.
+pub fn set_redirect(redirect: impl Fn(&Path) -> Option<PathBuf> + Send + Sync + 'static) {
+    *REDIRECT.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(redirect));
+}
+
+/// Remove the redirect callback, so every file is opened from its original path
+pub fn clear_redirect() {
+    *REDIRECT.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Strip `prefix` from the start of `path`, comparing components case-insensitively like Windows
+fn strip_prefix_ignore_case<'a>(path: &'a Path, prefix: &Path) -> Option<&'a Path> {
+    let mut components = path.components();
+    for prefix_component in prefix.components() {
+        let component = components.next()?;
+        if !component.as_os_str().eq_ignore_ascii_case(prefix_component.as_os_str()) {
+            return None;
+        }
+    }
+
+    Some(components.as_path())
+}
+
+/// Build a redirect callback for an asset override folder
+///
+/// Files under `original` are opened from the same relative path under `overrides` if a file
+/// exists there, e.g. `overrides\textures\hero.dds` replaces `original\textures\hero.dds`. Files
+/// without an override are opened as usual.
+pub fn override_directory(
+    original: impl Into<PathBuf>,
+    overrides: impl Into<PathBuf>,
+) -> impl Fn(&Path) -> Option<PathBuf> + Send + Sync + 'static {
+    let original = original.into();
+    let overrides = overrides.into();
+    move |path| {
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            std::env::current_dir().ok()?.join(path)
+        };
+
+        let relative = strip_prefix_ignore_case(&absolute, &original)?;
+        // don't let a path climb back out of the override folder
+        if relative.as_os_str().is_empty() || relative.components().any(|component| component == Component::ParentDir) {
+            return None;
+        }
+
+        let candidate = overrides.join(relative);
+        candidate.is_file().then_some(candidate)
+    }
+}
+
+/// Ask the redirect callback for a new path, returning it null-terminated for `CreateFileW`
+fn redirected(path: impl FnOnce() -> Option<PathBuf>) -> Option<Vec<u16>> {
+    if REDIRECTING.get() {
+        return None;
+    }
+
+    REDIRECTING.set(true);
+    // a callback that panics leaves the path alone
+    let new_path = callbacks::catch_panic(|| {
+        let redirect = REDIRECT.read().unwrap_or_else(|e| e.into_inner());
+        redirect.as_ref()?(&path()?)
+    })
+    .flatten();
+    REDIRECTING.set(false);
+
+    new_path.map(|new_path| new_path.as_os_str().encode_wide().chain(std::iter::once(0)).collect())
+}
+
+unsafe extern "system" fn create_file_w(
+    name: PCWSTR,
+    access: u32,
+    share_mode: u32,
+    security_attributes: *const c_void,
+    disposition: u32,
+    flags: u32,
+    template: HANDLE,
+) -> HANDLE {
+    let original: CreateFileWFn = unsafe { std::mem::transmute(ORIGINAL_CREATE_FILE_W.load(Ordering::Acquire)) };
+    let new_name = redirected(|| (!name.is_null()).then(|| PathBuf::from(OsString::from_wide(unsafe { name.as_wide() }))));
+    let name = new_name.as_ref().map_or(name, |new_name| PCWSTR::from_raw(new_name.as_ptr()));
+    unsafe { original(name, access, share_mode, security_attributes, disposition, flags, template) }
+}
+
+unsafe extern "system" fn create_file_a(
+    name: PCSTR,
+    access: u32,
+    share_mode: u32,
+    security_attributes: *const c_void,
+    disposition: u32,
+    flags: u32,
+    template: HANDLE,
+) -> HANDLE {
+    // paths that aren't valid UTF-8 (i.e. non-ASCII characters in the ANSI code page) aren't
+    // offered to the callback
+    let new_name = redirected(|| {
+        let name = unsafe { name.to_string() }.ok()?;
+        Some(PathBuf::from(name))
+    });
+
+    match new_name {
+        // the new path may not be representable in the ANSI code page, so open it with CreateFileW
+        Some(new_name) => {
+            let create_file_w: CreateFileWFn = unsafe { std::mem::transmute(ORIGINAL_CREATE_FILE_W.load(Ordering::Acquire)) };
+            unsafe {
+                create_file_w(
+                    PCWSTR::from_raw(new_name.as_ptr()),
+                    access,
+                    share_mode,
+                    security_attributes,
+                    disposition,
+                    flags,
+                    template,
+                )
+            }
+        }
+        None => {
+            let original: CreateFileAFn = unsafe { std::mem::transmute(ORIGINAL_CREATE_FILE_A.load(Ordering::Acquire)) };
+            unsafe { original(name, access, share_mode, security_attributes, disposition, flags, template) }
+        }
+    }
+}
+
+/// Hook the `CreateFileW` and `CreateFileA` imports of every module the searcher has discovered
+///
+/// Calls are passed through the callback set with `set_redirect`, and the hooks are registered
+/// with the `HookManager`. The module containing hook86 is skipped, so the callback can use
+/// `std::fs` freely. Modules loaded later aren't hooked unless this is called again, and files
+/// opened through other functions (like `OpenFile` or `NtCreateFile`) aren't redirected. Returns
+/// the number of import slots that were hooked.
+///
+/// # Safety
+///
+/// Every module the searcher has discovered must still be loaded. Their import slots are patched
+/// while the game may be calling through them.
+///
+/// # Errors
+///
+/// Returns an error if a module's import table can't be read or a slot couldn't be patched.
+pub unsafe fn install(searcher: &ByteSearcher) -> Result<usize> {
+    let mut own_module = HMODULE::default();
+    unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR::from_raw(install as *const u16),
+            &mut own_module,
+        )
+    }?;
+
+    // redirected CreateFileA calls go to CreateFileW even if nothing imports it
+    if ORIGINAL_CREATE_FILE_W.load(Ordering::Acquire) == 0 {
+        ORIGINAL_CREATE_FILE_W.store(pe::get_export("kernel32.dll", "CreateFileW")? as usize, Ordering::Release);
+    }
+
+    let detours: [(&str, &AtomicUsize, usize); 2] = [
+        ("CreateFileW", &ORIGINAL_CREATE_FILE_W, create_file_w as CreateFileWFn as usize),
+        ("CreateFileA", &ORIGINAL_CREATE_FILE_A, create_file_a as CreateFileAFn as usize),
+    ];
+
+    let mut num_hooked = 0;
+    for (function, original, detour) in detours {
+        for import in searcher.find_imports(function)? {
+            if std::ptr::eq(import.importer, own_module.0 as *const c_void) || import.value as usize == detour {
+                continue;
+            }
+
+            if original.load(Ordering::Acquire) == 0 {
+                original.store(import.value as usize, Ordering::Release);
+            }
+            let name = format!("hook86 file redirect {} {:p}", function, import.slot);
+            unsafe { HookManager::global().apply(&name, import.slot as *const c_void, &detour.to_le_bytes()) }?;
+            num_hooked += 1;
+        }
+    }
+
+    Ok(num_hooked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        let path = Path::new("C:/Games/Game/Data/tex.dds");
+        assert_eq!(strip_prefix_ignore_case(path, Path::new("c:/games/GAME")), Some(Path::new("Data/tex.dds")));
+        assert_eq!(strip_prefix_ignore_case(path, Path::new("C:/Games/Other")), None);
+        assert_eq!(strip_prefix_ignore_case(Path::new("C:/Games"), Path::new("C:/Games/Game")), None);
+    }
+
+    #[test]
+    fn overrides() {
+        let root = std::env::temp_dir().join("hook86_file_redirect_test");
+        let (game, mods) = (root.join("game"), root.join("mods"));
+        std::fs::create_dir_all(mods.join("data")).unwrap();
+        std::fs::write(mods.join("data").join("hero.dds"), b"").unwrap();
+
+        let redirect = override_directory(&game, &mods);
+        assert_eq!(redirect(&game.join("data").join("hero.dds")), Some(mods.join("data").join("hero.dds")));
+        assert_eq!(redirect(&game.join("data").join("villain.dds")), None);
+        assert_eq!(redirect(&game.join("..").join("mods").join("data").join("hero.dds")), None);
+        assert_eq!(redirect(&root.join("elsewhere.dds")), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+use std::ffi::c_void;
+use std::fmt;
+
+use windows::core::{w, HSTRING};
+use windows::Win32::Storage::FileSystem::{GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW, VS_FIXEDFILEINFO};
+
+use crate::error::{Hook86Error, Result};
+use crate::pe;
+
+/// A file version from a module's VERSIONINFO resource, e.g. 1.0.2.3
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub build: u16,
+    pub revision: u16,
+}
+
+impl FileVersion {
+    pub const fn new(major: u16, minor: u16, build: u16, revision: u16) -> Self {
+        Self {
+            major,
+            minor,
+            build,
+            revision,
+        }
+    }
+}
+
+impl fmt::Display for FileVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.build, self.revision)
+    }
+}
+
+/// Identifying details of a build of a module
+///
+/// The timestamp, checksum, and size come from the PE headers and are always available. The
+/// content hash and file version are optional: the hash requires reading the whole file, and many
+/// games don't have a version resource. The `Display` output is meant to be logged by a developer
+/// and pasted into a `BuildTable` registration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// The link timestamp from the file header
+    pub timestamp: u32,
+    /// The checksum from the optional header (zero for most executables that aren't drivers)
+    pub checksum: u32,
+    /// The size of the image in memory
+    pub image_size: u32,
+    /// A hash of the module's file
+    pub content_hash: Option<u64>,
+    /// The file version from the module's version resource
+    pub file_version: Option<FileVersion>,
+}
+
+/// Hash data with 64-bit FNV-1a
+///
+/// This isn't cryptographic; it only needs to tell builds of the same game apart.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0xCBF29CE484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001B3))
+}
+
+/// Read the fixed file version from a module's version resource, if it has one
+fn file_version(base: *const c_void) -> Result<Option<FileVersion>> {
+    let path = HSTRING::from(pe::module_path(base)?.as_os_str());
+    let size = unsafe { GetFileVersionInfoSizeW(&path, None) };
+    if size == 0 {
+        return Ok(None);
+    }
+
+    let mut data = vec![0u8; size as usize];
+    unsafe { GetFileVersionInfoW(&path, None, size, data.as_mut_ptr() as *mut c_void) }?;
+
+    let mut info: *mut c_void = std::ptr::null_mut();
+    let mut info_size = 0;
+    let found = unsafe { VerQueryValueW(data.as_ptr() as *const c_void, w!("\\"), &mut info, &mut info_size) };
+    if !found.as_bool() || info.is_null() || (info_size as usize) < size_of::<VS_FIXEDFILEINFO>() {
+        return Ok(None);
+    }
+
+    let info = unsafe { &*(info as *const VS_FIXEDFILEINFO) };
+    Ok(Some(FileVersion::new(
+        (info.dwFileVersionMS >> 16) as u16,
+        info.dwFileVersionMS as u16,
+        (info.dwFileVersionLS >> 16) as u16,
+        info.dwFileVersionLS as u16,
+    )))
+}
+
+impl Fingerprint {
+    /// Fingerprint the module loaded at `base` from its headers and version resource
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    pub unsafe fn of(base: *const c_void) -> Result<Self> {
+        Ok(Self {
+            file_version: file_version(base)?,
+            ..unsafe { Self::from_headers(base) }?
+        })
+    }
+
+    /// Fingerprint the module loaded at `base` from its headers alone
+    ///
+    /// Unlike `of`, this doesn't touch the module's file, so it's cheap enough to call whenever a
+    /// module needs to be identified.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    pub unsafe fn from_headers(base: *const c_void) -> Result<Self> {
+        let nt_headers = unsafe { &*pe::nt_headers(base)? };
+        Ok(Self {
+            timestamp: nt_headers.FileHeader.TimeDateStamp,
+            checksum: nt_headers.OptionalHeader.CheckSum,
+            image_size: nt_headers.OptionalHeader.SizeOfImage,
+            content_hash: None,
+            file_version: None,
+        })
+    }
+
+    /// Fingerprint the module loaded at `base`, including a hash of its file
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    pub unsafe fn with_content_hash(base: *const c_void) -> Result<Self> {
+        let file = std::fs::read(pe::module_path(base)?)?;
+        Ok(Self {
+            content_hash: Some(fnv1a(&file)),
+            ..unsafe { Self::of(base) }?
+        })
+    }
+
+    /// Check whether this fingerprint matches a known one
+    ///
+    /// A header field that is zero in `known` matches anything, so known builds can be registered
+    /// with only the fields that tell them apart (e.g. with `..Default::default()`); the others
+    /// must be equal. The content hash and file version are only compared when both fingerprints
+    /// have them.
+    pub fn matches(&self, known: &Fingerprint) -> bool {
+        fn optional_eq<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+            a.zip(b).is_none_or(|(a, b)| a == b)
+        }
+        fn header_eq(actual: u32, known: u32) -> bool {
+            known == 0 || actual == known
+        }
+
+        header_eq(self.timestamp, known.timestamp)
+            && header_eq(self.checksum, known.checksum)
+            && header_eq(self.image_size, known.image_size)
+            && optional_eq(self.content_hash, known.content_hash)
+            && optional_eq(self.file_version, known.file_version)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timestamp={:08X} checksum={:08X} image_size={:08X}",
+            self.timestamp, self.checksum, self.image_size
+        )?;
+        if let Some(hash) = self.content_hash {
+            write!(f, " content_hash={:016X}", hash)?;
+        }
+        if let Some(version) = self.file_version {
+            write!(f, " file_version={}", version)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A table of known builds of a module, for telling which one is loaded
+///
+/// `V` is typically an enum of the game versions the mod supports:
+/// ```ignore
+/// let builds = BuildTable::new()
+///     .with(Fingerprint { timestamp: 0x4A1B2C3D, image_size: 0x5C3000, ..Default::default() }, GameVersion::Retail)
+///     .with(Fingerprint { timestamp: 0x4B0F1E2D, image_size: 0x5C5000, ..Default::default() }, GameVersion::Patch1);
+/// let version = unsafe { builds.require(game_base) }?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildTable<V> {
+    builds: Vec<(Fingerprint, V)>,
+}
+
+impl<V> Default for BuildTable<V> {
+    fn default() -> Self {
+        Self { builds: Vec::new() }
+    }
+}
+
+impl<V: Copy> BuildTable<V> {
+    /// Create a new, empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a known build
+    pub fn with(mut self, fingerprint: Fingerprint, version: V) -> Self {
+        self.insert(fingerprint, version);
+        self
+    }
+
+    /// Register a known build
+    pub fn insert(&mut self, fingerprint: Fingerprint, version: V) {
+        self.builds.push((fingerprint, version));
+    }
+
+    /// Find the version a fingerprint belongs to
+    ///
+    /// If several registered builds match, the first one registered wins.
+    pub fn identify(&self, fingerprint: &Fingerprint) -> Option<V> {
+        self.builds
+            .iter()
+            .find(|(known, _)| fingerprint.matches(known))
+            .map(|&(_, version)| version)
+    }
+
+    /// Fingerprint the module loaded at `base` and find its version
+    ///
+    /// The module's file is only hashed if a registered build has a content hash.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    pub unsafe fn detect(&self, base: *const c_void) -> Result<Option<V>> {
+        Ok(self.identify(&unsafe { self.fingerprint(base) }?))
+    }
+
+    /// Fingerprint the module loaded at `base` and find its version, failing if it's unknown
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Hook86Error::UnknownBuild` with the module's fingerprint if no registered build
+    /// matches, so the mod can refuse to load on unsupported versions.
+    pub unsafe fn require(&self, base: *const c_void) -> Result<V> {
+        let fingerprint = unsafe { self.fingerprint(base) }?;
+        self.identify(&fingerprint).ok_or_else(|| Hook86Error::UnknownBuild {
+            fingerprint: fingerprint.to_string(),
+        })
+    }
+
+    unsafe fn fingerprint(&self, base: *const c_void) -> Result<Fingerprint> {
+        if self.builds.iter().any(|(known, _)| known.content_hash.is_some()) {
+            unsafe { Fingerprint::with_content_hash(base) }
+        } else {
+            unsafe { Fingerprint::of(base) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let known = Fingerprint {
+            timestamp: 0x4A1B2C3D,
+            image_size: 0x5C3000,
+            ..Default::default()
+        };
+        let actual = Fingerprint {
+            content_hash: Some(fnv1a(b"game")),
+            file_version: Some(FileVersion::new(1, 2, 0, 0)),
+            ..known
+        };
+
+        assert!(actual.matches(&known));
+        assert!(!actual.matches(&Fingerprint { timestamp: 0x4A1B2C3E, ..known }));
+        assert!(!actual.matches(&Fingerprint { content_hash: Some(fnv1a(b"gamf")), ..known }));
+
+        let builds = BuildTable::new().with(Fingerprint { image_size: 0x5C4000, ..known }, 1).with(known, 2);
+        assert_eq!(builds.identify(&actual), Some(2));
+        assert_eq!(builds.identify(&Fingerprint::default()), None);
+    }
+
+    #[test]
+    fn zero_header_fields_match_anything() {
+        let actual = Fingerprint {
+            timestamp: 0x4A1B2C3D,
+            checksum: 0x005C8A1F,
+            image_size: 0x5C3000,
+            ..Default::default()
+        };
+        let known = Fingerprint {
+            timestamp: 0x4A1B2C3D,
+            ..Default::default()
+        };
+
+        assert!(actual.matches(&known));
+        assert!(actual.matches(&Fingerprint { checksum: 0x005C8A1F, ..known }));
+        assert!(!actual.matches(&Fingerprint { checksum: 0x005C8A20, ..known }));
+        assert!(!Fingerprint::default().matches(&known));
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(fnv1a(b""), 0xCBF29CE484222325);
+        assert_eq!(fnv1a(b"a"), 0xAF63DC4C8601EC8C);
+    }
+}
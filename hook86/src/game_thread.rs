@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use windows::Win32::System::Threading::GetCurrentThreadId;
+
+use crate::callbacks::{self, CallbackHandle, CallbackList};
+use crate::error::Result;
+use crate::hook::{CaveHook, Prelude};
+
+/// The name the pump hook is registered under in the `HookManager`
+pub const PUMP_HOOK_NAME: &str = "hook86 game thread pump";
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Identifies a callback registered with `on_tick` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TickHandle(CallbackHandle);
+
+static TASKS: Mutex<VecDeque<Task>> = Mutex::new(VecDeque::new());
+static TICK_CALLBACKS: CallbackList<dyn FnMut() + Send> = CallbackList::new();
+/// The ID of the thread the pump last ran on, or 0 if it hasn't run yet
+static GAME_THREAD: AtomicU32 = AtomicU32::new(0);
+
+/// Run every queued task, called from the pump hook on the game thread
+///
+/// Tasks queued by the tasks themselves wait for the next call, so a task that requeues itself
+/// can't stall the game.
+fn drain_tasks() {
+    let tasks = std::mem::take(&mut *TASKS.lock().unwrap_or_else(|e| e.into_inner()));
+    for task in tasks {
+        // one broken task shouldn't stop the others
+        callbacks::catch_panic(task);
+    }
+}
+
+/// Run every tick callback once, called from the pump hook on the game thread
+fn run_tick_callbacks() {
+    TICK_CALLBACKS.dispatch(|callback| callback());
+}
+
+extern "C" fn pump() {
+    GAME_THREAD.store(unsafe { GetCurrentThreadId() }, Ordering::Relaxed);
+    drain_tasks();
+    run_tick_callbacks();
+}
+
+/// Designate a frequently-called game function as the game thread's pump
+///
+/// The instructions at `target` are hooked to call into hook86 each time they run, and closures
+/// passed to `run_on_game_thread` are run there. Pick something that runs every frame on the
+/// thread that owns the game state, like the main loop or a per-frame update function. The hook
+/// preserves all general-purpose registers, flags, and x87, MMX, and SSE state, so it can be placed
+/// at any instruction boundary as long as the first 5 bytes of instructions don't contain a
+/// relative branch.
+///
+/// # Safety
+///
+/// `target` must be an instruction boundary in code that stays loaded, and no thread may be
+/// executing the first 5 bytes of instructions there while they're overwritten.
+///
+/// # Errors
+///
+/// Returns an error if the hooked instructions contain a relative branch or call, or if the pump
+/// has already been installed.
+pub unsafe fn install_pump(target: *const c_void) -> Result<()> {
+    // the pump may be in the middle of a function, where the game can have values in x87 and SSE
+    // registers that Rust code is free to clobber. fxsave needs a 16-byte aligned area, so it's
+    // carved out of the stack. fninit empties the x87 register stack, which Rust code assumes.
+    //
+    // pushfd; pushad; mov ebp, esp; sub esp, 512; and esp, -16; fxsave [esp]; fninit; call pump;
+    // fxrstor [esp]; mov esp, ebp; popad; popfd
+    let prelude = || {
+        Prelude::new()
+            .code(&[0x9C, 0x60, 0x8B, 0xEC, 0x81, 0xEC, 0x00, 0x02, 0x00, 0x00, 0x83, 0xE4, 0xF0])
+            .code(&[0x0F, 0xAE, 0x04, 0x24, 0xDB, 0xE3])
+            .call(pump as extern "C" fn() as usize)
+            .code(&[0x0F, 0xAE, 0x0C, 0x24, 0x8B, 0xE5, 0x61, 0x9D])
+    };
+    unsafe { CaveHook::install_with_prelude(PUMP_HOOK_NAME, target, prelude) }?;
+
+    Ok(())
+}
+
+/// Check whether the calling thread is the one the pump runs on
+///
+/// This is false on every thread until the pump has run at least once.
+pub fn is_game_thread() -> bool {
+    GAME_THREAD.load(Ordering::Relaxed) == unsafe { GetCurrentThreadId() }
+}
+
+/// Queue a closure to run on the game thread the next time the pump runs
+///
+/// This returns immediately. Nothing runs until `install_pump` has been called.
+pub fn run_on_game_thread(task: impl FnOnce() + Send + 'static) {
+    TASKS.lock().unwrap_or_else(|e| e.into_inner()).push_back(Box::new(task));
+}
+
+/// Register a callback to run on the game thread every time the pump runs
+///
+/// Callbacks run in ascending order of priority after any tasks queued with `run_on_game_thread`.
+/// Callbacks with the same priority run in the order they were registered. A callback that
+/// panics is removed, but the others still run.
+pub fn on_tick(priority: i32, callback: impl FnMut() + Send + 'static) -> TickHandle {
+    TickHandle(TICK_CALLBACKS.insert(priority, Arc::new(Mutex::new(callback))))
+}
+
+/// Remove a callback registered with `on_tick`
+///
+/// If the callbacks for the current tick are already running, the removed callback may still run
+/// one last time. Returns false if the callback was already removed.
+pub fn remove_tick(handle: TickHandle) -> bool {
+    TICK_CALLBACKS.remove(handle.0)
+}
+
+/// Run a closure on the game thread and wait for its result
+///
+/// If this is called on the game thread, the closure is run immediately instead, since waiting
+/// for the pump there would deadlock.
+///
+/// # Panics
+///
+/// Panics if the closure panics.
+pub fn call_on_game_thread<R: Send + 'static>(task: impl FnOnce() -> R + Send + 'static) -> R {
+    if is_game_thread() {
+        return task();
+    }
+
+    let (sender, receiver) = mpsc::sync_channel(1);
+    run_on_game_thread(move || {
+        let _ = sender.send(task());
+    });
+    receiver.recv().expect("task panicked on the game thread")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            let log = log.clone();
+            run_on_game_thread(move || {
+                if i == 1 {
+                    panic!("task 1 failed");
+                }
+                log.lock().unwrap().push(i);
+            });
+        }
+        let requeue_log = log.clone();
+        run_on_game_thread(move || {
+            run_on_game_thread(move || requeue_log.lock().unwrap().push(3));
+        });
+
+        drain_tasks();
+        assert_eq!(*log.lock().unwrap(), [0, 2]);
+        drain_tasks();
+        assert_eq!(*log.lock().unwrap(), [0, 2, 3]);
+    }
+
+    #[test]
+    fn ticks() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (priority, name) in [(10, "late"), (-5, "early"), (0, "first"), (0, "second")] {
+            let log = log.clone();
+            handles.push(on_tick(priority, move || log.lock().unwrap().push(name)));
+        }
+        let panicky = on_tick(1, || panic!("tick failed"));
+
+        run_tick_callbacks();
+        assert_eq!(*log.lock().unwrap(), ["early", "first", "second", "late"]);
+        assert!(!remove_tick(panicky));
+
+        log.lock().unwrap().clear();
+        assert!(remove_tick(handles[2]));
+        run_tick_callbacks();
+        assert_eq!(*log.lock().unwrap(), ["early", "second", "late"]);
+
+        for handle in handles {
+            remove_tick(handle);
+        }
+    }
+}
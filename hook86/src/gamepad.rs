@@ -0,0 +1,203 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use windows::core::{s, w, PCWSTR};
+use windows::Win32::Foundation::{ERROR_DEVICE_NOT_CONNECTED, ERROR_SUCCESS};
+use windows::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW};
+use windows::Win32::UI::Input::XboxController::*;
+
+/// The number of controllers XInput supports
+pub const MAX_GAMEPADS: u32 = XUSER_MAX_COUNT;
+
+/// How long to wait between polls of a disconnected controller
+///
+/// Polling an empty slot is slow enough to show up in frame times, so controllers that aren't
+/// connected are only checked occasionally to see if one has been plugged in.
+pub const DISCONNECTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// XInput DLLs to try, newest first. xinput1_4 ships with Windows 8 and later, xinput1_3 with the
+/// DirectX runtime, and xinput9_1_0 with Vista and 7.
+const XINPUT_DLLS: [PCWSTR; 3] = [w!("xinput1_4.dll"), w!("xinput1_3.dll"), w!("xinput9_1_0.dll")];
+
+type XInputGetStateFn = unsafe extern "system" fn(u32, *mut XINPUT_STATE) -> u32;
+
+/// Load whichever version of XInput is available
+///
+/// Linking to XInput directly would tie us to one version, and a missing DLL would stop the whole
+/// mod from loading.
+fn xinput_get_state() -> Option<XInputGetStateFn> {
+    static GET_STATE: OnceLock<Option<XInputGetStateFn>> = OnceLock::new();
+    *GET_STATE.get_or_init(|| {
+        XINPUT_DLLS.iter().find_map(|&dll| {
+            let module = unsafe { LoadLibraryW(dll) }.ok()?;
+            let get_state = unsafe { GetProcAddress(module, s!("XInputGetState")) }?;
+            Some(unsafe { std::mem::transmute::<unsafe extern "system" fn() -> isize, XInputGetStateFn>(get_state) })
+        })
+    })
+}
+
+/// The state of a disconnected controller
+const NEUTRAL: XINPUT_GAMEPAD = XINPUT_GAMEPAD {
+    wButtons: XINPUT_GAMEPAD_BUTTON_FLAGS(0),
+    bLeftTrigger: 0,
+    bRightTrigger: 0,
+    sThumbLX: 0,
+    sThumbLY: 0,
+    sThumbRX: 0,
+    sThumbRY: 0,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+/// Scale a thumbstick position to -1.0..=1.0 on each axis, treating anything inside the dead zone
+/// as centered
+///
+/// The dead zone is circular, and the magnitude outside it is rescaled so that the output still
+/// starts at zero at the edge of the dead zone.
+fn normalize_stick(x: i16, y: i16, dead_zone: u16) -> (f32, f32) {
+    let (x, y) = (x as f32, y as f32);
+    let magnitude = (x * x + y * y).sqrt();
+    let dead_zone = dead_zone as f32;
+    if magnitude <= dead_zone {
+        return (0.0, 0.0);
+    }
+
+    let scaled = ((magnitude - dead_zone) / (i16::MAX as f32 - dead_zone)).min(1.0);
+    (x / magnitude * scaled, y / magnitude * scaled)
+}
+
+/// XInput controller state, updated once per frame like `Keyboard`
+#[derive(Debug)]
+pub struct Gamepad {
+    user_index: u32,
+    connected: bool,
+    old_state: XINPUT_GAMEPAD,
+    new_state: XINPUT_GAMEPAD,
+    /// When the controller was last polled while disconnected
+    last_disconnected_poll: Option<Instant>,
+}
+
+impl Gamepad {
+    /// Track the controller in the given slot (0 to `MAX_GAMEPADS - 1`)
+    pub const fn new(user_index: u32) -> Self {
+        Self {
+            user_index,
+            connected: false,
+            old_state: NEUTRAL,
+            new_state: NEUTRAL,
+            last_disconnected_poll: None,
+        }
+    }
+
+    /// Find the first connected controller
+    pub fn first_connected() -> Option<Self> {
+        (0..MAX_GAMEPADS).map(Self::new).find_map(|mut gamepad| gamepad.update().then_some(gamepad))
+    }
+
+    pub const fn user_index(&self) -> u32 {
+        self.user_index
+    }
+
+    /// Poll the controller, returning whether it's connected
+    ///
+    /// While the controller is disconnected, it reports no buttons pressed and centered sticks, and
+    /// it's only polled once every `DISCONNECTED_POLL_INTERVAL`. If no version of XInput is
+    /// installed, every controller is disconnected.
+    pub fn update(&mut self) -> bool {
+        let now = Instant::now();
+        self.old_state = self.new_state;
+        let recently_polled = self
+            .last_disconnected_poll
+            .is_some_and(|last_poll| now.duration_since(last_poll) < DISCONNECTED_POLL_INTERVAL);
+        if !self.connected && recently_polled {
+            return false;
+        }
+
+        let mut state = XINPUT_STATE::default();
+        let result = match xinput_get_state() {
+            Some(get_state) => unsafe { get_state(self.user_index, &mut state) },
+            None => ERROR_DEVICE_NOT_CONNECTED.0,
+        };
+        self.connected = result == ERROR_SUCCESS.0;
+        self.new_state = if self.connected { state.Gamepad } else { NEUTRAL };
+        self.last_disconnected_poll = (!self.connected).then_some(now);
+        self.connected
+    }
+
+    pub const fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub const fn is_button_down(&self, button: XINPUT_GAMEPAD_BUTTON_FLAGS) -> bool {
+        self.new_state.wButtons.0 & button.0 != 0
+    }
+
+    pub const fn is_button_down_once(&self, button: XINPUT_GAMEPAD_BUTTON_FLAGS) -> bool {
+        self.is_button_down(button) && self.old_state.wButtons.0 & button.0 == 0
+    }
+
+    pub const fn is_button_up_once(&self, button: XINPUT_GAMEPAD_BUTTON_FLAGS) -> bool {
+        !self.is_button_down(button) && self.old_state.wButtons.0 & button.0 != 0
+    }
+
+    pub fn is_any_button_down_once(&self, buttons: &[XINPUT_GAMEPAD_BUTTON_FLAGS]) -> bool {
+        buttons.iter().any(|&button| self.is_button_down_once(button))
+    }
+
+    /// How far a trigger is pressed, from 0.0 to 1.0
+    pub fn trigger(&self, trigger: Trigger) -> f32 {
+        Self::trigger_value(&self.new_state, trigger) as f32 / u8::MAX as f32
+    }
+
+    const fn trigger_value(state: &XINPUT_GAMEPAD, trigger: Trigger) -> u8 {
+        match trigger {
+            Trigger::Left => state.bLeftTrigger,
+            Trigger::Right => state.bRightTrigger,
+        }
+    }
+
+    /// Whether a trigger is pressed past XInput's recommended threshold, for treating it as a button
+    pub const fn is_trigger_down(&self, trigger: Trigger) -> bool {
+        Self::trigger_value(&self.new_state, trigger) as u16 > XINPUT_GAMEPAD_TRIGGER_THRESHOLD.0
+    }
+
+    pub const fn is_trigger_down_once(&self, trigger: Trigger) -> bool {
+        self.is_trigger_down(trigger) && Self::trigger_value(&self.old_state, trigger) as u16 <= XINPUT_GAMEPAD_TRIGGER_THRESHOLD.0
+    }
+
+    /// The position of a thumbstick from -1.0 to 1.0 on each axis, with positive y being up
+    ///
+    /// XInput's recommended dead zone for the stick is applied.
+    pub fn stick(&self, stick: Stick) -> (f32, f32) {
+        match stick {
+            Stick::Left => normalize_stick(self.new_state.sThumbLX, self.new_state.sThumbLY, XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE.0),
+            Stick::Right => normalize_stick(self.new_state.sThumbRX, self.new_state.sThumbRY, XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stick_dead_zone() {
+        assert_eq!(normalize_stick(1000, -1000, 7849), (0.0, 0.0));
+        assert_eq!(normalize_stick(i16::MAX, 0, 7849), (1.0, 0.0));
+        assert_eq!(normalize_stick(0, i16::MIN, 7849), (0.0, -1.0));
+
+        let (x, y) = normalize_stick(20000, 20000, 7849);
+        assert!((x - y).abs() < f32::EPSILON);
+        assert!((x * x + y * y).sqrt() <= 1.0);
+    }
+}
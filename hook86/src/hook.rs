@@ -0,0 +1,477 @@
+use std::ffi::c_void;
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use windows::Win32::System::Memory::{VirtualAlloc, MEM_COMMIT, MEM_RESERVE, PAGE_EXECUTE_READWRITE};
+
+use crate::asm::{self, jmp, NOP};
+use crate::error::{Hook86Error, Result};
+use crate::mem::{self, ByteSearcher};
+
+/// How much executable memory to reserve at a time for stubs
+const STUB_BLOCK_SIZE: usize = 0x10000;
+/// The alignment of each stub, which keeps stubs from sharing cache lines with each other
+const STUB_ALIGNMENT: usize = 16;
+
+/// A patch that has been written to memory and can be reverted
+#[derive(Debug, Clone)]
+pub struct AppliedPatch {
+    /// The name the patch was registered under
+    pub name: String,
+    /// The address the patch was written to
+    pub address: usize,
+    /// The bytes that were at the address before the patch was applied
+    pub original: Vec<u8>,
+    /// The bytes that were written
+    pub patched: Vec<u8>,
+    /// When the patch was applied
+    pub applied_at: SystemTime,
+}
+
+impl AppliedPatch {
+    /// Check whether this patch overlaps the given address range
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.address < end && start < self.address + self.patched.len()
+    }
+}
+
+/// A registry of all patches applied to the process
+///
+/// Applying patches through the `HookManager` records the original bytes so that they can be
+/// reverted later (e.g. when the mod is unloaded) and gives diagnostic code a list of everything
+/// that has been modified.
+#[derive(Debug)]
+pub struct HookManager {
+    patches: Vec<AppliedPatch>,
+}
+
+static HOOK_MANAGER: Mutex<HookManager> = Mutex::new(HookManager::new());
+
+impl HookManager {
+    const fn new() -> Self {
+        Self { patches: Vec::new() }
+    }
+
+    /// Get exclusive access to the process-wide hook manager
+    pub fn global() -> MutexGuard<'static, Self> {
+        HOOK_MANAGER.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get exclusive access to the process-wide hook manager if no other thread is using it
+    ///
+    /// This is meant for code that can't afford to block, such as crash handlers.
+    pub fn try_global() -> Option<MutexGuard<'static, Self>> {
+        match HOOK_MANAGER.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(std::sync::TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(std::sync::TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Write the given bytes to the specified address and register the patch under the given name
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the memory in a way that conflicts with the write, e.g. executing
+    /// code that's partially overwritten.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a patch with the same name is already applied or if the memory couldn't
+    /// be patched.
+    pub unsafe fn apply(&mut self, name: &str, addr: *const c_void, data: &[u8]) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("apply patch", name, address = ?addr, bytes = data.len()).entered();
+        if self.get(name).is_some() {
+            return Err(Hook86Error::InvalidHook {
+                name: name.to_string(),
+                reason: "a patch with this name is already applied",
+            });
+        }
+
+        let original = unsafe { std::slice::from_raw_parts(addr as *const u8, data.len()) }.to_vec();
+        unsafe { mem::patch(addr, data) }?;
+        self.patches.push(AppliedPatch {
+            name: name.to_string(),
+            address: addr as usize,
+            original,
+            patched: data.to_vec(),
+            applied_at: SystemTime::now(),
+        });
+
+        #[cfg(feature = "tracing")]
+        tracing::info!("patch applied");
+        Ok(())
+    }
+
+    /// Look up an applied patch by name
+    pub fn get(&self, name: &str) -> Option<&AppliedPatch> {
+        self.patches.iter().find(|p| p.name == name)
+    }
+
+    /// All currently-applied patches, in the order they were applied
+    pub fn patches(&self) -> &[AppliedPatch] {
+        &self.patches
+    }
+
+    /// Restore the original bytes of the named patch
+    ///
+    /// Returns false if no patch with the given name is applied.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may still depend on the patch, e.g. a thread executing a stub that the patch jumps
+    /// to, and the restored bytes must be safe to execute or use as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a patch applied after this one overlaps it, since restoring this
+    /// patch's original bytes would also undo part of the later one. Revert the later patch
+    /// first, or use `revert_all`.
+    pub unsafe fn revert(&mut self, name: &str) -> Result<bool> {
+        let Some(index) = self.patches.iter().position(|p| p.name == name) else {
+            return Ok(false);
+        };
+
+        let patch = &self.patches[index];
+        let end = patch.address + patch.patched.len();
+        if self.patches[index + 1..].iter().any(|later| later.overlaps(patch.address, end)) {
+            return Err(Hook86Error::InvalidHook {
+                name: name.to_string(),
+                reason: "a later patch overlaps this one",
+            });
+        }
+
+        unsafe { mem::patch(patch.address as *const c_void, &patch.original) }?;
+        self.patches.remove(index);
+        #[cfg(feature = "tracing")]
+        tracing::info!(name, "patch reverted");
+
+        Ok(true)
+    }
+
+    /// Restore the original bytes of every applied patch, most recent first
+    ///
+    /// Overlapping patches are restored correctly as long as they were all applied through the
+    /// hook manager. If a patch fails to revert, it and all earlier patches are left applied.
+    ///
+    /// # Safety
+    ///
+    /// See `revert`.
+    pub unsafe fn revert_all(&mut self) -> Result<()> {
+        while let Some(patch) = self.patches.last() {
+            unsafe { mem::patch(patch.address as *const c_void, &patch.original) }?;
+            #[cfg(feature = "tracing")]
+            tracing::info!(name = patch.name, "patch reverted");
+            self.patches.pop();
+        }
+
+        Ok(())
+    }
+}
+
+/// The location of an installed cave hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookSite {
+    /// The address of the hooked code
+    pub target: *const c_void,
+    /// The address immediately after the overwritten instructions, where the stub should return
+    pub return_address: *const c_void,
+}
+
+enum HookTarget<'a> {
+    Address(*const c_void),
+    Signature {
+        searcher: &'a ByteSearcher,
+        module: &'a str,
+        pattern: &'a [u8],
+        offset: isize,
+    },
+}
+
+type StubBinder<'a> = Box<dyn FnOnce(HookSite) -> Result<*const u8> + 'a>;
+
+/// Executable memory that stubs are carved out of
+///
+/// Stubs live for the rest of the process, so memory is only ever handed out, never freed.
+struct StubAllocator {
+    next: usize,
+    end: usize,
+}
+
+static STUB_ALLOCATOR: Mutex<StubAllocator> = Mutex::new(StubAllocator { next: 0, end: 0 });
+
+impl StubAllocator {
+    fn alloc(&mut self, len: usize) -> Result<&'static mut [u8]> {
+        let start = self.next.next_multiple_of(STUB_ALIGNMENT);
+        if self.next == 0 || start + len > self.end {
+            let size = len.next_multiple_of(STUB_BLOCK_SIZE).max(STUB_BLOCK_SIZE);
+            let block = unsafe { VirtualAlloc(None, size, MEM_COMMIT | MEM_RESERVE, PAGE_EXECUTE_READWRITE) };
+            if block.is_null() {
+                return Err(windows::core::Error::from_thread().into());
+            }
+
+            // whatever was left of the previous block is abandoned
+            self.next = block as usize;
+            self.end = block as usize + size;
+            return self.alloc(len);
+        }
+
+        self.next = start + len;
+        // the range is freshly committed and never handed out again
+        Ok(unsafe { std::slice::from_raw_parts_mut(start as *mut u8, len) })
+    }
+}
+
+/// A hook that redirects a piece of game code into a stub defined with `patch!`
+///
+/// The jump to the stub overwrites at least 5 bytes of the target. The stub is responsible for
+/// re-executing the overwritten instructions (if needed) and jumping back to
+/// `HookSite::return_address`.
+pub struct CaveHook;
+
+impl CaveHook {
+    /// Allocate executable memory for a hand-assembled stub
+    ///
+    /// The memory is readable, writable, and executable, and it's never freed, so it's suitable
+    /// for stubs that are built at runtime rather than with `patch!`. Use it from a `stub` binder
+    /// so that the memory is only allocated once the target has been found and verified. The
+    /// memory is zeroed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no more memory could be allocated.
+    pub fn alloc_stub(len: usize) -> Result<&'static mut [u8]> {
+        STUB_ALLOCATOR.lock().unwrap_or_else(|e| e.into_inner()).alloc(len)
+    }
+
+    /// Hook the instructions at `target` with a stub that runs `prelude` before them
+    ///
+    /// The stub runs the prelude, re-executes the instructions the hook overwrote, and jumps back.
+    /// At least 5 bytes of whole instructions are overwritten, and since they run from the stub,
+    /// they can't contain a relative branch or call. `prelude` is only called once the target has
+    /// been checked, so anything it leaks for the stub isn't wasted if the hook can't be installed.
+    /// The hook is registered with the `HookManager` under `name`.
+    ///
+    /// # Safety
+    ///
+    /// `target` must be an instruction boundary in code that stays loaded, no thread may be
+    /// executing the overwritten instructions while they're replaced, and the prelude must preserve
+    /// whatever state the game code relies on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the overwritten instructions contain a relative branch or call, or if the
+    /// hook couldn't be installed.
+    pub(crate) unsafe fn install_with_prelude(
+        name: &str,
+        target: *const c_void,
+        prelude: impl FnOnce() -> Prelude,
+    ) -> Result<HookSite> {
+        let overwrite_len = unsafe { asm::relocatable_length(target, 5) }.ok_or_else(|| Hook86Error::InvalidHook {
+            name: name.to_string(),
+            reason: "the hooked instructions contain a relative branch",
+        })?;
+        let original = unsafe { std::slice::from_raw_parts(target as *const u8, overwrite_len) };
+
+        let hook = Self::builder(name).address(target).overwrite(overwrite_len).stub(|site| {
+            // <prelude>; <original instructions>; jmp return_address
+            let Prelude { code, fixups } = prelude();
+            let relocated = code.len();
+            let jmp_offset = relocated + overwrite_len;
+            let stub = Self::alloc_stub(jmp_offset + 5)?;
+            let base = stub.as_ptr() as usize;
+            stub[..relocated].copy_from_slice(&code);
+            stub[relocated..jmp_offset].copy_from_slice(original);
+            stub[jmp_offset..].copy_from_slice(&jmp(base + jmp_offset, site.return_address as usize));
+
+            // the relative calls and addresses can only be encoded once the stub is at its final address
+            for (offset, fixup) in fixups {
+                let bytes = match fixup {
+                    Fixup::Call(function) => asm::call(base + offset, function),
+                    Fixup::PushRelocated => asm::push(base + relocated),
+                };
+                stub[offset..offset + bytes.len()].copy_from_slice(&bytes);
+            }
+
+            Ok(stub.as_ptr())
+        });
+
+        unsafe { hook.install() }
+    }
+
+    /// Start building a cave hook with the given name
+    ///
+    /// The name is used to register the hook with the `HookManager` and to cache the resolved
+    /// target address.
+    pub fn builder(name: &str) -> CaveHookBuilder<'_> {
+        CaveHookBuilder {
+            name,
+            target: None,
+            expected: None,
+            overwrite_len: None,
+            binder: None,
+        }
+    }
+}
+
+enum Fixup {
+    Call(usize),
+    PushRelocated,
+}
+
+/// Hand-assembled code for a stub built by `CaveHook::install_with_prelude`
+///
+/// Relative calls and the address of the relocated instructions depend on where the stub ends up,
+/// so they're left as placeholders until the stub has been allocated.
+pub(crate) struct Prelude {
+    code: Vec<u8>,
+    fixups: Vec<(usize, Fixup)>,
+}
+
+impl Prelude {
+    pub fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    /// Append instructions
+    pub fn code(mut self, bytes: &[u8]) -> Self {
+        self.code.extend_from_slice(bytes);
+        self
+    }
+
+    /// Append a call to `function`
+    pub fn call(mut self, function: usize) -> Self {
+        self.fixups.push((self.code.len(), Fixup::Call(function)));
+        self.code(&[NOP; 5])
+    }
+
+    /// Append a push of the address of the relocated instructions, which run the hooked code as if
+    /// it wasn't hooked
+    pub fn push_relocated(mut self) -> Self {
+        self.fixups.push((self.code.len(), Fixup::PushRelocated));
+        self.code(&[NOP; 5])
+    }
+}
+
+/// Configuration for a `CaveHook`
+///
+/// At minimum, a target (`address` or `signature`) and a stub must be provided.
+pub struct CaveHookBuilder<'a> {
+    name: &'a str,
+    target: Option<HookTarget<'a>>,
+    expected: Option<&'a [u8]>,
+    overwrite_len: Option<usize>,
+    binder: Option<StubBinder<'a>>,
+}
+
+impl<'a> CaveHookBuilder<'a> {
+    /// Hook the code at a known address
+    pub fn address(mut self, addr: *const c_void) -> Self {
+        self.target = Some(HookTarget::Address(addr));
+        self
+    }
+
+    /// Hook the code found by searching for a byte string in a module
+    ///
+    /// `offset` is added to the address where the pattern was found to get the hook target.
+    pub fn signature(mut self, searcher: &'a ByteSearcher, module: &'a str, pattern: &'a [u8], offset: isize) -> Self {
+        self.target = Some(HookTarget::Signature { searcher, module, pattern, offset });
+        self
+    }
+
+    /// Verify that the target starts with the given bytes before hooking it
+    ///
+    /// Unless `overwrite` is also called, the length of `original` is the number of bytes that will
+    /// be overwritten.
+    pub fn expect(mut self, original: &'a [u8]) -> Self {
+        self.expected = Some(original);
+        self
+    }
+
+    /// Set the number of bytes of the target to overwrite
+    ///
+    /// This must be at least 5 and should cover whole instructions. Bytes after the jump to the
+    /// stub are filled with nops.
+    pub fn overwrite(mut self, len: usize) -> Self {
+        self.overwrite_len = Some(len);
+        self
+    }
+
+    /// Set the function that binds the stub
+    ///
+    /// The function receives the resolved hook site and should call the stub's `bind` method,
+    /// returning the pointer to the stub's bytes. The stub must be in static memory.
+    pub fn stub(mut self, binder: impl FnOnce(HookSite) -> Result<*const u8> + 'a) -> Self {
+        self.binder = Some(Box::new(binder));
+        self
+    }
+
+    fn resolve_target(&self) -> Result<*const c_void> {
+        match self.target {
+            Some(HookTarget::Address(addr)) => Ok(addr),
+            Some(HookTarget::Signature { searcher, module, pattern, offset }) => searcher
+                .resolve(self.name, pattern, None, module)
+                .map(|addr| addr.wrapping_byte_offset(offset))
+                .ok_or_else(|| Hook86Error::PatternNotFound { name: self.name.to_string() }),
+            None => Err(self.invalid("no target was provided")),
+        }
+    }
+
+    fn invalid(&self, reason: &'static str) -> Hook86Error {
+        Hook86Error::InvalidHook {
+            name: self.name.to_string(),
+            reason,
+        }
+    }
+
+    /// Resolve the target, verify it, bind the stub, and write the jump to the stub
+    ///
+    /// The hook is registered with the global `HookManager` under the builder's name.
+    ///
+    /// # Safety
+    ///
+    /// The overwritten bytes must cover whole instructions, no thread may be executing them while
+    /// the jump is written, and the stub must be valid code that preserves whatever state the
+    /// surrounding game code relies on.
+    pub unsafe fn install(mut self) -> Result<HookSite> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("install hook", name = self.name, target = tracing::field::Empty).entered();
+        let overwrite_len = self
+            .overwrite_len
+            .or(self.expected.map(<[u8]>::len))
+            .ok_or_else(|| self.invalid("the number of bytes to overwrite is unknown"))?;
+        if overwrite_len < 5 {
+            return Err(self.invalid("a hook must overwrite at least 5 bytes"));
+        }
+
+        let target = self.resolve_target()?;
+        #[cfg(feature = "tracing")]
+        _span.record("target", tracing::field::debug(target));
+        if let Some(expected) = self.expected {
+            unsafe { mem::verify(target, expected) }?;
+        }
+
+        let site = HookSite {
+            target,
+            return_address: target.wrapping_byte_add(overwrite_len),
+        };
+        // check this before binding, since the stub's memory can't be given back
+        if HookManager::global().get(self.name).is_some() {
+            return Err(self.invalid("a patch with this name is already applied"));
+        }
+        let binder = self.binder.take().ok_or_else(|| self.invalid("no stub was provided"))?;
+        let stub = binder(site)?;
+
+        let mut bytes = vec![NOP; overwrite_len];
+        bytes[..5].copy_from_slice(&jmp(target as usize, stub as usize));
+        unsafe { HookManager::global().apply(self.name, target, &bytes) }?;
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(stub = ?stub, overwritten = overwrite_len, "hook installed");
+        Ok(site)
+    }
+}
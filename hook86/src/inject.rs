@@ -0,0 +1,636 @@
+use std::ffi::c_void;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT};
+use windows::Win32::System::Diagnostics::Debug::{
+    FlushInstructionCache, GetThreadContext, ReadProcessMemory, SetThreadContext, WriteProcessMemory, CONTEXT,
+    CONTEXT_ALL_X86,
+};
+use windows::Win32::System::Memory::{
+    VirtualAllocEx, VirtualFreeEx, VirtualProtectEx, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PAGE_EXECUTE_READWRITE,
+    PAGE_PROTECTION_FLAGS, PAGE_READWRITE,
+};
+use windows::Win32::System::Threading::{
+    CreateRemoteThread, GetExitCodeThread, OpenProcess, OpenThread, ResumeThread, SuspendThread, WaitForSingleObject,
+    INFINITE, LPTHREAD_START_ROUTINE, PROCESS_CREATE_THREAD, PROCESS_QUERY_INFORMATION, PROCESS_VM_OPERATION,
+    PROCESS_VM_READ, PROCESS_VM_WRITE, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT, THREAD_SUSPEND_RESUME,
+};
+
+use crate::asm::{self, Reg};
+use crate::error::{Hook86Error, Result};
+use crate::hook::AppliedPatch;
+use crate::mem::{ByteSearcher, IntPtr, PAGE_SIZE};
+use crate::pe;
+
+/// The offset of the return value slot in the memory allocated by `RemoteProcess::hijack_thread`
+const HIJACK_RESULT: usize = 0;
+/// The offset of the completion flag in the memory allocated by `RemoteProcess::hijack_thread`
+const HIJACK_DONE: usize = 4;
+/// The offset of the flag that lets a hijacked thread return to its original code by itself
+const HIJACK_RELEASE: usize = 8;
+/// The offset of the hijacked thread's original ESP
+const HIJACK_SAVED_ESP: usize = 12;
+/// The offset of the hijacked thread's original EIP
+const HIJACK_SAVED_EIP: usize = 16;
+/// The offset of the code in the memory allocated by `RemoteProcess::hijack_thread`
+const HIJACK_CODE: usize = 20;
+/// How far below the hijacked thread's stack pointer the shellcode's stack starts
+const HIJACK_STACK_GAP: u32 = 0x100;
+/// How often to check whether a hijacked thread has finished its call
+const HIJACK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+fn injection_failed(reason: &'static str) -> Hook86Error {
+    Hook86Error::InjectionFailed { reason }
+}
+
+fn load_library() -> Result<*const c_void> {
+    // system DLLs are loaded at the same address in every process, so our LoadLibraryW is also the
+    // target's
+    pe::get_export("kernel32.dll", "LoadLibraryW")
+}
+
+/// Build the code that a hijacked thread runs
+///
+/// The code calls `function(argument)`, stores the return value and sets the completion flag in
+/// the data at `data`, then spins until the thread's original context is restored. Saving ESP in
+/// EBX lets the function be either cdecl or stdcall.
+///
+/// If the caller gives up waiting, it sets the release flag instead, and once the call finishes
+/// the code restores the general-purpose registers and flags it saved on entry and jumps back to
+/// the original EIP with the original ESP, both of which the caller stores in the data beforehand.
+fn hijack_shellcode(data: usize, function: usize, argument: usize) -> Vec<u8> {
+    let code = data + HIJACK_CODE;
+    let mut shellcode = Vec::with_capacity(64);
+    // pushad; pushfd
+    shellcode.extend_from_slice(&[0x60, 0x9C]);
+    shellcode.extend_from_slice(&asm::mov_reg_reg(Reg::Ebx, Reg::Esp));
+    shellcode.extend_from_slice(&asm::push(argument));
+    shellcode.extend_from_slice(&asm::mov_reg_imm(Reg::Eax, function));
+    shellcode.extend_from_slice(&asm::call_reg(Reg::Eax));
+    shellcode.extend_from_slice(&asm::mov_reg_reg(Reg::Esp, Reg::Ebx));
+    shellcode.extend_from_slice(&asm::mov_reg_imm(Reg::Ecx, data));
+    shellcode.extend_from_slice(&asm::mov_mem_reg(Reg::Ecx, HIJACK_RESULT as i32, Reg::Eax));
+    shellcode.extend_from_slice(&asm::mov_reg_imm(Reg::Edx, 1));
+    shellcode.extend_from_slice(&asm::mov_mem_reg(Reg::Ecx, HIJACK_DONE as i32, Reg::Edx));
+    let spin = code + shellcode.len();
+    // cmp dword [ecx+HIJACK_RELEASE], 0
+    shellcode.extend_from_slice(&[0x83, 0x79, HIJACK_RELEASE as u8, 0x00]);
+    let jz = code + shellcode.len();
+    shellcode.extend_from_slice(asm::jz_auto(jz, spin).as_bytes());
+    // popfd; popad; mov esp, [data+HIJACK_SAVED_ESP]
+    shellcode.extend_from_slice(&[0x9D, 0x61, 0x8B, 0x25]);
+    shellcode.extend_from_slice(&((data + HIJACK_SAVED_ESP) as u32).to_le_bytes());
+    shellcode.extend_from_slice(&asm::jmp_indirect(data + HIJACK_SAVED_EIP));
+    shellcode
+}
+
+struct HijackedThread {
+    handle: HANDLE,
+    /// The thread's suspend count before we suspended it
+    suspend_count: u32,
+    context: CONTEXT,
+}
+
+impl HijackedThread {
+    fn suspend(thread_id: u32) -> Result<Self> {
+        let access = THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT | THREAD_SET_CONTEXT;
+        let handle = unsafe { OpenThread(access, false, thread_id) }?;
+        let suspend_count = unsafe { SuspendThread(handle) };
+        if suspend_count == u32::MAX {
+            let error = windows::core::Error::from_thread();
+            let _ = unsafe { CloseHandle(handle) };
+            return Err(error.into());
+        }
+
+        let mut thread = Self {
+            handle,
+            suspend_count,
+            context: CONTEXT {
+                ContextFlags: CONTEXT_ALL_X86,
+                ..Default::default()
+            },
+        };
+        if let Err(e) = unsafe { GetThreadContext(handle, &mut thread.context) } {
+            thread.resume();
+            return Err(e.into());
+        }
+
+        Ok(thread)
+    }
+
+    /// Undo our suspension of the thread without changing anything
+    fn resume(&self) {
+        unsafe { ResumeThread(self.handle) };
+    }
+
+    /// Point the thread at new code and let it run
+    fn redirect(&self, eip: u32) -> Result<()> {
+        let context = CONTEXT {
+            Eip: eip,
+            Esp: self.context.Esp.wrapping_sub(HIJACK_STACK_GAP) & !0xF,
+            ..self.context
+        };
+        unsafe { SetThreadContext(self.handle, &context) }?;
+        // the thread might have been suspended by someone else too, e.g. a launcher that created
+        // the process suspended, so resume it all the way
+        for _ in 0..=self.suspend_count {
+            unsafe { ResumeThread(self.handle) };
+        }
+
+        Ok(())
+    }
+
+    /// Put the thread back the way we found it
+    ///
+    /// If this fails, the thread is left running where it was.
+    fn restore(&self) -> Result<()> {
+        unsafe { SuspendThread(self.handle) };
+        if let Err(e) = unsafe { SetThreadContext(self.handle, &self.context) } {
+            unsafe { ResumeThread(self.handle) };
+            return Err(e.into());
+        }
+        for _ in 0..self.suspend_count {
+            unsafe { SuspendThread(self.handle) };
+        }
+        unsafe { ResumeThread(self.handle) };
+
+        Ok(())
+    }
+}
+
+impl Drop for HijackedThread {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.handle) };
+    }
+}
+
+/// Another process that a launcher or external tool operates on
+///
+/// Everything else in hook86 works on the process it's loaded into; this is for the code that gets
+/// it loaded there in the first place. The target must be a 32-bit process, like the one running
+/// this code.
+#[derive(Debug)]
+pub struct RemoteProcess {
+    handle: HANDLE,
+    owned: bool,
+}
+
+// a process handle can be used from any thread
+unsafe impl Send for RemoteProcess {}
+unsafe impl Sync for RemoteProcess {}
+
+impl RemoteProcess {
+    /// Open a process by ID with the access rights needed for injection and patching
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process doesn't exist or we don't have permission to open it.
+    pub fn open(process_id: u32) -> Result<Self> {
+        let access = PROCESS_CREATE_THREAD | PROCESS_QUERY_INFORMATION | PROCESS_VM_OPERATION | PROCESS_VM_READ | PROCESS_VM_WRITE;
+        let handle = unsafe { OpenProcess(access, false, process_id) }?;
+        Ok(Self { handle, owned: true })
+    }
+
+    /// Operate on a process through a handle the caller already has, e.g. from `CreateProcessW`
+    ///
+    /// The handle isn't closed when the `RemoteProcess` is dropped.
+    ///
+    /// # Safety
+    ///
+    /// The handle must stay open for as long as the `RemoteProcess` is used.
+    pub unsafe fn from_handle(handle: HANDLE) -> Self {
+        Self { handle, owned: false }
+    }
+
+    /// The process handle
+    pub fn handle(&self) -> HANDLE {
+        self.handle
+    }
+
+    /// Create a `ByteSearcher` for the process's memory
+    pub fn searcher(&self) -> ByteSearcher {
+        ByteSearcher::for_process(self.handle)
+    }
+
+    /// Allocate memory in the process
+    pub fn alloc(&self, size: usize, protection: PAGE_PROTECTION_FLAGS) -> Result<*mut c_void> {
+        let address = unsafe { VirtualAllocEx(self.handle, None, size, MEM_COMMIT | MEM_RESERVE, protection) };
+        if address.is_null() {
+            return Err(windows::core::Error::from_thread().into());
+        }
+
+        Ok(address)
+    }
+
+    /// Free memory allocated with `alloc`
+    ///
+    /// # Safety
+    ///
+    /// Nothing in the target process may still be using the memory.
+    pub unsafe fn free(&self, address: *mut c_void) -> Result<()> {
+        unsafe { VirtualFreeEx(self.handle, address, 0, MEM_RELEASE) }?;
+        Ok(())
+    }
+
+    /// Allocate executable memory in the process and write code into it
+    ///
+    /// `bind` receives the address of the allocation and returns the code to write there. This is
+    /// meant for `patch!` patches, whose relative placeholders depend on where the code runs:
+    /// ```ignore
+    /// let mut patch = MyPatch::new();
+    /// let code = process.write_code(patch.buf().len(), |base| patch.bind_at(base, target).to_vec())?;
+    /// ```
+    ///
+    /// # Return
+    ///
+    /// The address of the code in the process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memory couldn't be allocated or written, or if `bind` returned more
+    /// than `size` bytes. The memory is freed in that case.
+    pub fn write_code(&self, size: usize, bind: impl FnOnce(IntPtr) -> Vec<u8>) -> Result<*const c_void> {
+        let address = self.alloc(size, PAGE_EXECUTE_READWRITE)?;
+        let code = bind(address as IntPtr);
+        let written = if code.len() > size {
+            Err(injection_failed("the bound code is larger than the allocation"))
+        } else {
+            // nothing in the process knows about the memory yet
+            unsafe { self.write(address, &code) }
+                .and_then(|_| Ok(unsafe { FlushInstructionCache(self.handle, Some(address), code.len()) }?))
+        };
+
+        if let Err(e) = written {
+            let _ = unsafe { self.free(address) };
+            return Err(e);
+        }
+
+        Ok(address)
+    }
+
+    /// Copy data into the process's memory
+    ///
+    /// The memory must already be writable; see `patch` for writing to protected memory.
+    ///
+    /// # Safety
+    ///
+    /// Overwriting the memory must not break the target process.
+    pub unsafe fn write(&self, address: *const c_void, data: &[u8]) -> Result<()> {
+        unsafe { WriteProcessMemory(self.handle, address, data.as_ptr() as *const c_void, data.len(), None) }?;
+        Ok(())
+    }
+
+    /// Copy `buffer.len()` bytes from the process's memory, returning whether the whole region
+    /// was readable
+    fn read_into(&self, address: *const c_void, buffer: &mut [u8]) -> bool {
+        unsafe { ReadProcessMemory(self.handle, address, buffer.as_mut_ptr() as *mut c_void, buffer.len(), None) }.is_ok()
+    }
+
+    /// Read bytes from the process's memory
+    ///
+    /// # Errors
+    ///
+    /// An `UnreadableMemory` error is returned if any part of the region can't be read.
+    pub fn read(&self, address: *const c_void, len: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; len];
+        if !self.read_into(address, &mut buffer) {
+            return Err(Hook86Error::UnreadableMemory { ptr: address as usize, size: len });
+        }
+
+        Ok(buffer)
+    }
+
+    /// Check that the bytes at the specified address in the process match what we expect
+    ///
+    /// This is the remote equivalent of `mem::verify`.
+    ///
+    /// # Errors
+    ///
+    /// A `VerificationMismatch` error is returned if the bytes at `address` differ from `expected`.
+    pub fn verify(&self, address: *const c_void, expected: &[u8]) -> Result<()> {
+        let actual = self.read(address, expected.len())?;
+        if actual != expected {
+            return Err(Hook86Error::VerificationMismatch {
+                ptr: address as usize,
+                expected: expected.to_vec(),
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Make a memory region in the process readable, writable, and executable
+    ///
+    /// This is the remote equivalent of `mem::unprotect`.
+    ///
+    /// # Safety
+    ///
+    /// The target process must not depend on the region's protection, e.g. for guard pages.
+    pub unsafe fn unprotect(&self, address: *const c_void, size: usize) -> Result<PAGE_PROTECTION_FLAGS> {
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+        unsafe { VirtualProtectEx(self.handle, address, size, PAGE_EXECUTE_READWRITE, &mut old_protect) }?;
+
+        Ok(old_protect)
+    }
+
+    /// Set the memory protection on a memory region in the process
+    ///
+    /// This is the remote equivalent of `mem::protect`.
+    ///
+    /// # Safety
+    ///
+    /// The new protection must not stop the target process from accessing memory it needs.
+    pub unsafe fn protect(&self, address: *const c_void, size: usize, protection: PAGE_PROTECTION_FLAGS) -> Result<()> {
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+        unsafe { VirtualProtectEx(self.handle, address, size, protection, &mut old_protect) }?;
+
+        Ok(())
+    }
+
+    /// Write the given data to the specified address within a protected memory region of the
+    /// process
+    ///
+    /// This is the remote equivalent of `mem::patch`. The region is unprotected for the write and
+    /// its original protection restored afterwards. It's meant for launchers that start the game
+    /// suspended and patch its startup code (e.g. to disable an integrity check) before the entry
+    /// point runs.
+    ///
+    /// # Safety
+    ///
+    /// No thread in the target process may be executing the patched code while it's written.
+    pub unsafe fn patch(&self, address: *const c_void, data: &[u8]) -> Result<()> {
+        let old_protect = unsafe { self.unprotect(address, data.len()) }?;
+        let result = unsafe { self.write(address, data) };
+        unsafe { self.protect(address, data.len(), old_protect) }?;
+        result?;
+
+        unsafe { FlushInstructionCache(self.handle, Some(address), data.len()) }?;
+        Ok(())
+    }
+
+    /// Patch the process and record the original bytes so the patch can be reverted
+    ///
+    /// This is the remote equivalent of `HookManager::apply`, except that the caller keeps the
+    /// record instead of a registry.
+    ///
+    /// # Safety
+    ///
+    /// See `patch`.
+    pub unsafe fn apply(&self, name: &str, address: *const c_void, data: &[u8]) -> Result<AppliedPatch> {
+        let original = self.read(address, data.len())?;
+        unsafe { self.patch(address, data) }?;
+
+        Ok(AppliedPatch {
+            name: name.to_string(),
+            address: address as usize,
+            original,
+            patched: data.to_vec(),
+            applied_at: SystemTime::now(),
+        })
+    }
+
+    /// Restore the original bytes of a patch applied with `apply`
+    ///
+    /// # Safety
+    ///
+    /// See `patch`.
+    pub unsafe fn revert(&self, patch: &AppliedPatch) -> Result<()> {
+        unsafe { self.patch(patch.address as *const c_void, &patch.original) }
+    }
+
+    /// Copy a null-terminated UTF-16 path into newly-allocated memory in the process
+    fn write_path(&self, path: &Path) -> Result<*mut c_void> {
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+        let path_bytes = unsafe { std::slice::from_raw_parts(path.as_ptr() as *const u8, path.len() * 2) };
+        let remote_path = self.alloc(path_bytes.len(), PAGE_READWRITE)?;
+        if let Err(e) = unsafe { self.write(remote_path, path_bytes) } {
+            let _ = unsafe { self.free(remote_path) };
+            return Err(e);
+        }
+
+        Ok(remote_path)
+    }
+
+    /// Load a DLL into the process by running `LoadLibraryW` on a new thread in it
+    ///
+    /// The DLL's path is copied into the process, so a relative path is resolved relative to the
+    /// *target's* current directory; pass an absolute path to avoid surprises. If `wait` is `None`,
+    /// this returns as soon as the thread is started. Otherwise it waits up to that long (or
+    /// forever, with `Duration::MAX`) for the DLL to finish loading.
+    ///
+    /// # Return
+    ///
+    /// The base address of the DLL in the target process if we waited for it to load.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be started, the wait timed out, or `LoadLibraryW`
+    /// failed in the target (e.g. because the DLL or one of its dependencies wasn't found or its
+    /// `DllMain` returned false).
+    pub fn inject(&self, dll: impl AsRef<Path>, wait: Option<Duration>) -> Result<Option<*const c_void>> {
+        let load_library = load_library()?;
+        type ThreadProc = unsafe extern "system" fn(*mut c_void) -> u32;
+        let start_routine: LPTHREAD_START_ROUTINE = Some(unsafe { std::mem::transmute::<*const c_void, ThreadProc>(load_library) });
+
+        let remote_path = self.write_path(dll.as_ref())?;
+
+        let thread = match unsafe { CreateRemoteThread(self.handle, None, 0, start_routine, Some(remote_path), 0, None) } {
+            Ok(thread) => thread,
+            Err(e) => {
+                let _ = unsafe { self.free(remote_path) };
+                return Err(e.into());
+            }
+        };
+
+        let result = match wait {
+            // the thread still needs the path, so it has to be leaked
+            None => Ok(None),
+            // if the thread didn't exit, it might still be about to read the path
+            Some(timeout) => self.wait_for_load(thread, timeout).and_then(|module| {
+                let _ = unsafe { self.free(remote_path) };
+                module
+            }),
+        };
+
+        let _ = unsafe { CloseHandle(thread) };
+        result
+    }
+
+    /// Call a function in the process on one of its existing threads
+    ///
+    /// This is an alternative to `CreateRemoteThread` for processes that block or monitor remote
+    /// thread creation. The thread is suspended and its context saved, then it's pointed at a
+    /// small piece of injected code that calls `function(argument)`. Once the call returns, the
+    /// thread is put back exactly where it was. `function` may be cdecl or stdcall.
+    ///
+    /// The call only happens when the thread next runs user-mode code, so pick a thread that's
+    /// busy, like the game's main thread, rather than one sleeping in a wait. If the thread is the
+    /// primary thread of a process created suspended, keep in mind that the loader hasn't
+    /// initialized the process yet.
+    ///
+    /// # Return
+    ///
+    /// The function's return value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be opened or its context changed, or if the call
+    /// didn't finish within `timeout`. Once the thread has been redirected, any failure leaves the
+    /// call to finish in the background, after which the thread restores its general-purpose
+    /// registers and flags and carries on from where it was interrupted. The injected code is leaked, and any other state
+    /// the function clobbered (e.g. SSE registers) isn't restored.
+    ///
+    /// # Safety
+    ///
+    /// `function` must be the address of a function in the process that takes one pointer-sized
+    /// argument, and it must be safe to call at whatever point the thread was interrupted (in
+    /// particular, the thread must not be holding a lock the function needs).
+    pub unsafe fn hijack_thread(&self, thread_id: u32, function: *const c_void, argument: usize, timeout: Duration) -> Result<u32> {
+        let data = self.alloc(PAGE_SIZE, PAGE_EXECUTE_READWRITE)?;
+        let shellcode = hijack_shellcode(data as usize, function as usize, argument);
+        let code = data.wrapping_byte_add(HIJACK_CODE);
+        let prepared = unsafe { self.write(code, &shellcode) }
+            .and_then(|_| Ok(unsafe { FlushInstructionCache(self.handle, Some(code), shellcode.len()) }?))
+            .and_then(|_| HijackedThread::suspend(thread_id));
+        let thread = match prepared {
+            Ok(thread) => thread,
+            Err(e) => {
+                let _ = unsafe { self.free(data) };
+                return Err(e);
+            }
+        };
+
+        // the shellcode needs these to find its way back by itself if we stop waiting for it
+        let mut saved = [0u8; 8];
+        saved[..4].copy_from_slice(&thread.context.Esp.to_le_bytes());
+        saved[4..].copy_from_slice(&thread.context.Eip.to_le_bytes());
+        let redirected = unsafe { self.write(data.wrapping_byte_add(HIJACK_SAVED_ESP), &saved) }
+            .and_then(|_| thread.redirect(code as u32));
+        if let Err(e) = redirected {
+            thread.resume();
+            let _ = unsafe { self.free(data) };
+            return Err(e);
+        }
+
+        let start = Instant::now();
+        let done = data.wrapping_byte_add(HIJACK_DONE);
+        let wait = || {
+            while self.read(done, 1)?[0] == 0 {
+                if start.elapsed() >= timeout {
+                    return Err(injection_failed("timed out waiting for the hijacked thread"));
+                }
+                thread::sleep(HIJACK_POLL_INTERVAL);
+            }
+
+            Ok(())
+        };
+        if let Err(e) = wait().and_then(|_| thread.restore()) {
+            // the thread spins in the shellcode until it's put back or released, so let it go back
+            // to what it was doing by itself once the call finishes. it may still be running the
+            // shellcode, so that has to be leaked. if this write fails, there's nothing more we can
+            // do for it.
+            let _ = unsafe { self.write(data.wrapping_byte_add(HIJACK_RELEASE), &[1]) };
+            return Err(e);
+        }
+
+        let result = self.read(data.wrapping_byte_add(HIJACK_RESULT), 4);
+        let _ = unsafe { self.free(data) };
+        let result = result?;
+        Ok(u32::from_le_bytes([result[0], result[1], result[2], result[3]]))
+    }
+
+    /// Load a DLL into the process by hijacking one of its threads to call `LoadLibraryW`
+    ///
+    /// See `hijack_thread` for how the thread is hijacked and `inject` for how the path is
+    /// interpreted.
+    ///
+    /// # Return
+    ///
+    /// The base address of the DLL in the target process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be hijacked, the call timed out, or `LoadLibraryW`
+    /// failed in the target.
+    ///
+    /// # Safety
+    ///
+    /// It must be safe to load a DLL at whatever point the thread was interrupted.
+    pub unsafe fn inject_with_thread(&self, thread_id: u32, dll: impl AsRef<Path>, timeout: Duration) -> Result<*const c_void> {
+        let load_library = load_library()?;
+        let remote_path = self.write_path(dll.as_ref())?;
+        // if this fails, the thread might still be about to read the path, so it has to be leaked
+        let module = unsafe { self.hijack_thread(thread_id, load_library, remote_path as usize, timeout) }?;
+
+        let _ = unsafe { self.free(remote_path) };
+        if module == 0 {
+            return Err(injection_failed("LoadLibraryW failed in the target process"));
+        }
+
+        Ok(module as usize as *const c_void)
+    }
+
+    /// Wait for a `LoadLibraryW` thread to exit and get the module handle it returned
+    ///
+    /// If this fails, the thread may still be running, so the outer `Result` is whether it exited.
+    fn wait_for_load(&self, thread: HANDLE, timeout: Duration) -> Result<Result<Option<*const c_void>>> {
+        let milliseconds = u32::try_from(timeout.as_millis()).unwrap_or(INFINITE);
+        match unsafe { WaitForSingleObject(thread, milliseconds) } {
+            WAIT_OBJECT_0 => (),
+            WAIT_TIMEOUT => return Err(injection_failed("timed out waiting for the DLL to load")),
+            _ => return Err(windows::core::Error::from_thread().into()),
+        }
+
+        // the thread's exit code is the return value of LoadLibraryW, which on 32-bit targets is
+        // the whole module handle
+        let mut exit_code = 0;
+        unsafe { GetExitCodeThread(thread, &mut exit_code) }?;
+        if exit_code == 0 {
+            return Ok(Err(injection_failed("LoadLibraryW failed in the target process")));
+        }
+
+        Ok(Ok(Some(exit_code as usize as *const c_void)))
+    }
+}
+
+impl Drop for RemoteProcess {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = unsafe { CloseHandle(self.handle) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hijack_shellcode_layout() {
+        let data = 0x10000;
+        let shellcode = hijack_shellcode(data, 0x7C801D7B, 0x20000);
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0x60, 0x9C,                               // pushad; pushfd
+            0x89, 0xE3,                               // mov ebx, esp
+            0x68, 0x00, 0x00, 0x02, 0x00,             // push 0x20000
+            0xB8, 0x7B, 0x1D, 0x80, 0x7C,             // mov eax, function
+            0xFF, 0xD0,                               // call eax
+            0x89, 0xDC,                               // mov esp, ebx
+            0xB9, 0x00, 0x00, 0x01, 0x00,             // mov ecx, data
+            0x89, 0x84, 0x21, 0x00, 0x00, 0x00, 0x00, // mov [ecx+0], eax
+            0xBA, 0x01, 0x00, 0x00, 0x00,             // mov edx, 1
+            0x89, 0x94, 0x21, 0x04, 0x00, 0x00, 0x00, // mov [ecx+4], edx
+            0x83, 0x79, 0x08, 0x00,                   // spin: cmp dword [ecx+8], 0
+            0x74, 0xFA,                               // jz spin
+            0x9D, 0x61,                               // popfd; popad
+            0x8B, 0x25, 0x0C, 0x00, 0x01, 0x00,       // mov esp, [data+12]
+            0xFF, 0x25, 0x10, 0x00, 0x01, 0x00,       // jmp [data+16]
+        ];
+        assert_eq!(shellcode, expected);
+    }
+}
@@ -1,10 +1,90 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use windows::Win32::Foundation::{HWND, LPARAM, POINT, WPARAM};
+use windows::Win32::Graphics::Gdi::ScreenToClient;
+use windows::Win32::System::Threading::GetCurrentProcessId;
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetAncestor, GetCursorPos, GetForegroundWindow, GetSystemMetrics, GetWindowThreadProcessId, GA_ROOT, SM_SWAPBUTTON, WHEEL_DELTA, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDBLCLK, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDBLCLK,
+    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEWHEEL, WM_RBUTTONDBLCLK, WM_RBUTTONDOWN, WM_RBUTTONUP,
+    WM_KILLFOCUS, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_XBUTTONDBLCLK, WM_XBUTTONDOWN, WM_XBUTTONUP, XBUTTON1,
+};
+
+/// Check whether a window, or the top-level window containing it, is the foreground window
+pub fn is_window_focused(window: HWND) -> bool {
+    let foreground = unsafe { GetForegroundWindow() };
+    !foreground.is_invalid() && (foreground == window || unsafe { GetAncestor(window, GA_ROOT) } == foreground)
+}
+
+/// Check whether the foreground window belongs to the current process
+pub fn is_process_focused() -> bool {
+    let foreground = unsafe { GetForegroundWindow() };
+    let mut process_id = 0;
+    !foreground.is_invalid()
+        && unsafe { GetWindowThreadProcessId(foreground, Some(&mut process_id)) } != 0
+        && process_id == unsafe { GetCurrentProcessId() }
+}
+
+/// A window handle that can be shared between threads
+///
+/// `HWND` wraps a pointer, so storing one directly would make the input trackers neither `Send` nor
+/// `Sync`, and they couldn't be kept in a static. The handle itself is just an identifier that any
+/// thread can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WindowHandle(isize);
+
+impl WindowHandle {
+    fn new(window: HWND) -> Self {
+        Self(window.0 as isize)
+    }
+
+    fn hwnd(self) -> HWND {
+        HWND(self.0 as *mut c_void)
+    }
 
+    fn is_focused(self) -> bool {
+        is_window_focused(self.hwnd())
+    }
+}
+
+// the trackers are kept in statics and captured by `input_block::on_message` listeners
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Keyboard>();
+    assert_send_sync::<Mouse>();
+};
+
+/// Keyboard state, updated once per frame
+///
+/// By default, `update` polls `GetKeyboardState`. A keyboard created with `with_messages` instead
+/// builds its state from window messages passed to `handle_message`, so a key that's pressed and
+/// released between two updates still shows as down for one frame rather than being missed.
+///
+/// Keys are reported no matter which application has focus unless `set_focus_window` is used.
 #[derive(Debug)]
 pub struct Keyboard {
     old_keys: [u8; 256],
     new_keys: [u8; 256],
     async_keys: [bool; 256],
+    /// When each key was last seen going down
+    pressed_at: [Option<Instant>; 256],
+    updated_at: Option<Instant>,
+    from_messages: bool,
+    /// The key state according to the messages seen so far
+    message_keys: [u8; 256],
+    /// Keys that went down in a message since the last update
+    tapped: [bool; 256],
+    focus_window: Option<WindowHandle>,
+    was_focused: bool,
 }
 
 impl Keyboard {
@@ -13,21 +93,164 @@ impl Keyboard {
             old_keys: [0; 256],
             new_keys: [0; 256],
             async_keys: [false; 256],
+            pressed_at: [None; 256],
+            updated_at: None,
+            from_messages: false,
+            message_keys: [0; 256],
+            tapped: [false; 256],
+            focus_window: None,
+            was_focused: true,
         }
     }
 
+    /// Create a keyboard whose state comes from window messages instead of `GetKeyboardState`
+    ///
+    /// Pass the game window's messages to `handle_message`, e.g. from an
+    /// `input_block::on_message` listener. Lock key toggle states are still read from Windows.
+    pub const fn with_messages() -> Self {
+        let mut keyboard = Self::new();
+        keyboard.from_messages = true;
+        keyboard
+    }
+
     pub fn update(&mut self) -> windows_result::Result<()> {
-        self.old_keys = self.new_keys;
-        unsafe {
-            if let Err(err) = GetKeyboardState(&mut self.new_keys) {
-                log::error!("GetKeyboardState failed: {err}");
-                return Err(err);
+        let keys = if self.from_messages {
+            let mut keys = self.take_message_keys();
+            for lock_key in [VK_CAPITAL, VK_NUMLOCK, VK_SCROLL] {
+                keys[lock_key.0 as usize] |= unsafe { GetKeyState(lock_key.0 as i32) } as u8 & 1;
             }
-        }
+            keys
+        } else {
+            let mut keys = [0; 256];
+            unsafe {
+                if let Err(err) = GetKeyboardState(&mut keys) {
+                    log::error!("GetKeyboardState failed: {err}");
+                    return Err(err);
+                }
+            }
+            keys
+        };
 
+        let is_focused = self.focus_window.is_none_or(WindowHandle::is_focused);
+        self.apply_update(keys, Instant::now(), is_focused);
         Ok(())
     }
 
+    /// Only report keys as down while `window` (or the top-level window containing it) is the
+    /// foreground window, or report them regardless of focus with `None`
+    ///
+    /// This keeps hotkeys from firing while the user is typing in another application. Keys that
+    /// are already held when the window regains focus, like the Alt from Alt+Tab, don't count as
+    /// pressed until they're released and pressed again. The async key functions are gated too.
+    pub fn set_focus_window(&mut self, window: Option<HWND>) {
+        self.focus_window = window.map(WindowHandle::new);
+    }
+
+    fn apply_update(&mut self, mut keys: [u8; 256], now: Instant, is_focused: bool) {
+        if !is_focused {
+            // keep the toggle bits
+            for key_state in &mut keys {
+                *key_state &= 1;
+            }
+        }
+
+        self.set_keys(keys, now);
+        if is_focused && !self.was_focused {
+            // make the keys that are already held look like they were held last frame too
+            self.set_keys(keys, now);
+        }
+        self.was_focused = is_focused;
+    }
+
+    /// Get the key state built from messages, with keys tapped since the last update held down
+    fn take_message_keys(&mut self) -> [u8; 256] {
+        let mut keys = self.message_keys;
+        for (key_state, tapped) in keys.iter_mut().zip(&mut self.tapped) {
+            if std::mem::take(tapped) {
+                *key_state |= 0x80;
+            }
+        }
+
+        keys
+    }
+
+    /// Record a key going up or down from a message
+    fn record_key(&mut self, key: VIRTUAL_KEY, is_down: bool) {
+        let index = key.0 as usize & 0xFF;
+        if is_down {
+            self.tapped[index] |= self.message_keys[index] & 0x80 == 0;
+            self.message_keys[index] |= 0x80;
+        } else {
+            self.message_keys[index] &= !0x80;
+        }
+    }
+
+    /// Update the state of the keyboard from a window message
+    ///
+    /// Handles key and mouse button messages, recording both the generic and left/right variants
+    /// of modifier keys, and releases every key on `WM_KILLFOCUS`. Returns true if the message was
+    /// one of those. This only affects keyboards created with `with_messages`.
+    pub fn handle_message(&mut self, message: u32, wparam: WPARAM, lparam: LPARAM) -> bool {
+        let (key, is_down) = match message {
+            WM_KEYDOWN | WM_SYSKEYDOWN => (VIRTUAL_KEY(wparam.0 as u16), true),
+            WM_KEYUP | WM_SYSKEYUP => (VIRTUAL_KEY(wparam.0 as u16), false),
+            WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => (VK_LBUTTON, true),
+            WM_LBUTTONUP => (VK_LBUTTON, false),
+            WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => (VK_RBUTTON, true),
+            WM_RBUTTONUP => (VK_RBUTTON, false),
+            WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => (VK_MBUTTON, true),
+            WM_MBUTTONUP => (VK_MBUTTON, false),
+            WM_KILLFOCUS => {
+                // keys released while the window doesn't have focus never get a key up message
+                for key_state in &mut self.message_keys {
+                    *key_state &= !0x80;
+                }
+                return true;
+            }
+            WM_XBUTTONDOWN | WM_XBUTTONDBLCLK | WM_XBUTTONUP => {
+                // the high word of wparam says which X button it was
+                let key = if (wparam.0 >> 16) as u16 == XBUTTON1 { VK_XBUTTON1 } else { VK_XBUTTON2 };
+                (key, message != WM_XBUTTONUP)
+            }
+            _ => return false,
+        };
+
+        // key messages only report the generic modifier, so work out which side it was from the
+        // scan code (for shift) or the extended key flag (for ctrl and alt)
+        let scan_code = (lparam.0 >> 16) & 0xFF;
+        let is_extended = lparam.0 & (1 << 24) != 0;
+        let sides = match key {
+            VK_SHIFT => Some((VK_LSHIFT, VK_RSHIFT, scan_code == 0x36)),
+            VK_CONTROL => Some((VK_LCONTROL, VK_RCONTROL, is_extended)),
+            VK_MENU => Some((VK_LMENU, VK_RMENU, is_extended)),
+            _ => None,
+        };
+
+        match sides {
+            Some((left, right, is_right)) => {
+                self.record_key(if is_right { right } else { left }, is_down);
+                // the generic key stays down until both sides are released
+                let either_down = (self.message_keys[left.0 as usize] | self.message_keys[right.0 as usize]) & 0x80 != 0;
+                self.record_key(key, either_down);
+            }
+            None => self.record_key(key, is_down),
+        }
+
+        true
+    }
+
+    /// Make `keys` the current state as of `now`, keeping the previous state for edge detection
+    fn set_keys(&mut self, keys: [u8; 256], now: Instant) {
+        self.old_keys = self.new_keys;
+        self.new_keys = keys;
+        self.updated_at = Some(now);
+        for code in 0..=u8::MAX {
+            if self.is_key_down_once(VIRTUAL_KEY(code as u16)) {
+                self.pressed_at[code as usize] = Some(now);
+            }
+        }
+    }
+
     pub const fn is_key_down(&self, key: VIRTUAL_KEY) -> bool {
         self.new_keys[key.0 as usize] & 0x80 != 0
     }
@@ -36,6 +259,25 @@ impl Keyboard {
         self.is_key_down(key) && self.old_keys[key.0 as usize] & 0x80 == 0
     }
 
+    pub const fn is_key_up_once(&self, key: VIRTUAL_KEY) -> bool {
+        !self.is_key_down(key) && self.old_keys[key.0 as usize] & 0x80 != 0
+    }
+
+    /// How long a key has been held, as of the last update
+    ///
+    /// On the frame the key is released (when `is_key_up_once` is true), this is how long it was
+    /// held before the release, for charge-up style actions that trigger on release. Returns `None`
+    /// otherwise. Durations are measured between updates, so they're only as precise as the
+    /// update rate.
+    pub fn key_held_duration(&self, key: VIRTUAL_KEY) -> Option<Duration> {
+        if !self.is_key_down(key) && !self.is_key_up_once(key) {
+            return None;
+        }
+
+        let pressed_at = self.pressed_at[key.0 as usize]?;
+        Some(self.updated_at?.duration_since(pressed_at))
+    }
+
     pub fn is_any_key_down_once(&self, keys: &[VIRTUAL_KEY]) -> bool {
         for key in keys {
             if self.is_key_down_once(*key) {
@@ -51,7 +293,7 @@ impl Keyboard {
     }
 
     pub fn is_key_down_async(&self, key: VIRTUAL_KEY) -> bool {
-        unsafe { GetAsyncKeyState(key.0 as i32) < 0 }
+        self.focus_window.is_none_or(WindowHandle::is_focused) && unsafe { GetAsyncKeyState(key.0 as i32) < 0 }
     }
     
     pub fn track_key_down_async_once(&mut self, key: VIRTUAL_KEY) -> bool {
@@ -60,4 +302,1052 @@ impl Keyboard {
         self.async_keys[key.0 as usize] = is_down;
         is_down_once
     }
-}
\ No newline at end of file
+
+    /// Whether a chord's key is down with exactly its modifiers held
+    pub const fn is_chord_down(&self, chord: Chord) -> bool {
+        self.is_key_down(chord.key) && Self::modifiers_match(Modifiers::held(self), chord.modifiers)
+    }
+
+    /// Whether a chord's key was pressed this frame with exactly its modifiers held
+    pub const fn is_chord_down_once(&self, chord: Chord) -> bool {
+        self.is_key_down_once(chord.key) && Self::modifiers_match(Modifiers::held(self), chord.modifiers)
+    }
+
+    const fn modifiers_match(held: Modifiers, expected: Modifiers) -> bool {
+        held.ctrl == expected.ctrl && held.shift == expected.shift && held.alt == expected.alt
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum KeyParseError {
+    #[error("Key name is empty")]
+    Empty,
+    #[error("Unknown key `{name}`")]
+    UnknownKey { name: String },
+    #[error("Unknown modifier `{name}`")]
+    UnknownModifier { name: String },
+}
+
+/// The canonical name of each named key, in the order of their codes
+///
+/// Letters and digits aren't listed; they're named by the character itself. Punctuation keys are
+/// named after what they produce on a US layout, since their codes are the same on every layout
+/// but their labels aren't.
+const KEY_NAMES: &[(VIRTUAL_KEY, &str)] = &[
+    (VK_LBUTTON, "LMouse"),
+    (VK_RBUTTON, "RMouse"),
+    (VK_MBUTTON, "MMouse"),
+    (VK_XBUTTON1, "Mouse4"),
+    (VK_XBUTTON2, "Mouse5"),
+    (VK_BACK, "Backspace"),
+    (VK_TAB, "Tab"),
+    (VK_RETURN, "Enter"),
+    (VK_SHIFT, "Shift"),
+    (VK_CONTROL, "Ctrl"),
+    (VK_MENU, "Alt"),
+    (VK_PAUSE, "Pause"),
+    (VK_CAPITAL, "CapsLock"),
+    (VK_ESCAPE, "Escape"),
+    (VK_SPACE, "Space"),
+    (VK_PRIOR, "PageUp"),
+    (VK_NEXT, "PageDown"),
+    (VK_END, "End"),
+    (VK_HOME, "Home"),
+    (VK_LEFT, "Left"),
+    (VK_UP, "Up"),
+    (VK_RIGHT, "Right"),
+    (VK_DOWN, "Down"),
+    (VK_SNAPSHOT, "PrintScreen"),
+    (VK_INSERT, "Insert"),
+    (VK_DELETE, "Delete"),
+    (VK_LWIN, "LWin"),
+    (VK_RWIN, "RWin"),
+    (VK_APPS, "Apps"),
+    (VK_NUMPAD0, "Numpad0"),
+    (VK_NUMPAD1, "Numpad1"),
+    (VK_NUMPAD2, "Numpad2"),
+    (VK_NUMPAD3, "Numpad3"),
+    (VK_NUMPAD4, "Numpad4"),
+    (VK_NUMPAD5, "Numpad5"),
+    (VK_NUMPAD6, "Numpad6"),
+    (VK_NUMPAD7, "Numpad7"),
+    (VK_NUMPAD8, "Numpad8"),
+    (VK_NUMPAD9, "Numpad9"),
+    (VK_MULTIPLY, "NumpadMultiply"),
+    (VK_ADD, "NumpadAdd"),
+    (VK_SUBTRACT, "NumpadSubtract"),
+    (VK_DECIMAL, "NumpadDecimal"),
+    (VK_DIVIDE, "NumpadDivide"),
+    (VK_F1, "F1"),
+    (VK_F2, "F2"),
+    (VK_F3, "F3"),
+    (VK_F4, "F4"),
+    (VK_F5, "F5"),
+    (VK_F6, "F6"),
+    (VK_F7, "F7"),
+    (VK_F8, "F8"),
+    (VK_F9, "F9"),
+    (VK_F10, "F10"),
+    (VK_F11, "F11"),
+    (VK_F12, "F12"),
+    (VK_F13, "F13"),
+    (VK_F14, "F14"),
+    (VK_F15, "F15"),
+    (VK_F16, "F16"),
+    (VK_F17, "F17"),
+    (VK_F18, "F18"),
+    (VK_F19, "F19"),
+    (VK_F20, "F20"),
+    (VK_F21, "F21"),
+    (VK_F22, "F22"),
+    (VK_F23, "F23"),
+    (VK_F24, "F24"),
+    (VK_NUMLOCK, "NumLock"),
+    (VK_SCROLL, "ScrollLock"),
+    (VK_LSHIFT, "LShift"),
+    (VK_RSHIFT, "RShift"),
+    (VK_LCONTROL, "LCtrl"),
+    (VK_RCONTROL, "RCtrl"),
+    (VK_LMENU, "LAlt"),
+    (VK_RMENU, "RAlt"),
+    (VK_OEM_1, "Semicolon"),
+    (VK_OEM_PLUS, "Equals"),
+    (VK_OEM_COMMA, "Comma"),
+    (VK_OEM_MINUS, "Minus"),
+    (VK_OEM_PERIOD, "Period"),
+    (VK_OEM_2, "Slash"),
+    (VK_OEM_3, "Backtick"),
+    (VK_OEM_4, "LBracket"),
+    (VK_OEM_5, "Backslash"),
+    (VK_OEM_6, "RBracket"),
+    (VK_OEM_7, "Quote"),
+];
+
+/// Other names accepted by `parse_key`
+const KEY_ALIASES: &[(&str, VIRTUAL_KEY)] = &[
+    ("Back", VK_BACK),
+    ("Return", VK_RETURN),
+    ("Control", VK_CONTROL),
+    ("Esc", VK_ESCAPE),
+    ("Spacebar", VK_SPACE),
+    ("PgUp", VK_PRIOR),
+    ("PgDn", VK_NEXT),
+    ("Ins", VK_INSERT),
+    ("Del", VK_DELETE),
+    ("LControl", VK_LCONTROL),
+    ("RControl", VK_RCONTROL),
+    ("Grave", VK_OEM_3),
+    ("Tilde", VK_OEM_3),
+];
+
+/// Letters and digits, which are named by their character
+const ALPHANUMERIC: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Get the human-readable name of a key, like "F5", "Numpad3", or "LCtrl"
+///
+/// Returns `None` for keys without a name, such as IME and media keys.
+pub fn key_name(key: VIRTUAL_KEY) -> Option<&'static str> {
+    let code = key.0 as u8 as char;
+    if key.0 <= u8::MAX as u16
+        && let Some(index) = ALPHANUMERIC.find(code)
+    {
+        return Some(&ALPHANUMERIC[index..index + 1]);
+    }
+
+    KEY_NAMES.iter().find(|(named_key, _)| *named_key == key).map(|(_, name)| *name)
+}
+
+/// Parse a key name, ignoring case
+///
+/// Accepts the names returned by `key_name`, some common alternatives (e.g. "Esc" and "PgUp"),
+/// "Num3" for "Numpad3", and hexadecimal codes like "0xAD" for keys that have no name.
+///
+/// # Errors
+///
+/// Returns an error if the name is empty or isn't a known key.
+pub fn parse_key(name: &str) -> Result<VIRTUAL_KEY, KeyParseError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(KeyParseError::Empty);
+    }
+
+    if let [code] = name.as_bytes()
+        && ALPHANUMERIC.contains(code.to_ascii_uppercase() as char)
+    {
+        return Ok(VIRTUAL_KEY(code.to_ascii_uppercase() as u16));
+    }
+
+    if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X"))
+        && let Ok(code) = u8::from_str_radix(hex, 16)
+    {
+        return Ok(VIRTUAL_KEY(code as u16));
+    }
+
+    let named = KEY_NAMES.iter().map(|&(key, name)| (name, key));
+    if let Some((_, key)) = named
+        .chain(KEY_ALIASES.iter().copied())
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+    {
+        return Ok(key);
+    }
+
+    if let Some(digit) = name.get(..3).filter(|prefix| prefix.eq_ignore_ascii_case("num")).and(name.get(3..))
+        && let Ok(digit @ 0..=9) = digit.parse::<u16>()
+    {
+        return Ok(VIRTUAL_KEY(VK_NUMPAD0.0 + digit));
+    }
+
+    Err(KeyParseError::UnknownKey { name: name.to_string() })
+}
+
+/// Modifier keys that can be part of a `Chord`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Self = Self {
+        ctrl: false,
+        shift: false,
+        alt: false,
+    };
+
+    /// The modifiers currently held on a keyboard
+    pub const fn held(keyboard: &Keyboard) -> Self {
+        Self {
+            ctrl: keyboard.is_key_down(VK_CONTROL),
+            shift: keyboard.is_key_down(VK_SHIFT),
+            alt: keyboard.is_key_down(VK_MENU),
+        }
+    }
+}
+
+/// A key combined with modifiers, like Ctrl+Shift+F5
+///
+/// The chord only matches when exactly its modifiers are held, so Ctrl+F5 doesn't also fire when
+/// the user presses Ctrl+Shift+F5.
+///
+/// Chords convert to and from strings like "Ctrl+Shift+F5", with key names as in `key_name`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub key: VIRTUAL_KEY,
+    pub modifiers: Modifiers,
+}
+
+impl Chord {
+    pub const fn new(key: VIRTUAL_KEY) -> Self {
+        Self {
+            key,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    pub const fn ctrl(mut self) -> Self {
+        self.modifiers.ctrl = true;
+        self
+    }
+
+    pub const fn shift(mut self) -> Self {
+        self.modifiers.shift = true;
+        self
+    }
+
+    pub const fn alt(mut self) -> Self {
+        self.modifiers.alt = true;
+        self
+    }
+
+    /// The chord the user pressed this frame, if any, for capturing a new binding
+    ///
+    /// Modifier keys on their own don't count; the chord is the first other key pressed along with
+    /// whatever modifiers are held.
+    pub fn pressed(keyboard: &Keyboard) -> Option<Self> {
+        (0..=u8::MAX as u16)
+            .map(VIRTUAL_KEY)
+            .find(|&key| !is_modifier(key) && keyboard.is_key_down_once(key))
+            .map(|key| Self {
+                key,
+                modifiers: Modifiers::held(keyboard),
+            })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [(self.modifiers.ctrl, "Ctrl"), (self.modifiers.shift, "Shift"), (self.modifiers.alt, "Alt")] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        match key_name(self.key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "0x{:02X}", self.key.0),
+        }
+    }
+}
+
+impl FromStr for Chord {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key_name) = match s.rsplit_once('+') {
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", s),
+        };
+
+        let mut chord = Self::new(parse_key(key_name)?);
+        for name in modifier_names.split('+').map(str::trim).filter(|name| !name.is_empty()) {
+            match parse_key(name) {
+                Ok(VK_CONTROL) => chord.modifiers.ctrl = true,
+                Ok(VK_SHIFT) => chord.modifiers.shift = true,
+                Ok(VK_MENU) => chord.modifiers.alt = true,
+                _ => return Err(KeyParseError::UnknownModifier { name: name.to_string() }),
+            }
+        }
+
+        Ok(chord)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Chord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Chord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Whether a key is a modifier, which doesn't interrupt a `KeySequence` it isn't part of
+const fn is_modifier(key: VIRTUAL_KEY) -> bool {
+    matches!(
+        key,
+        VK_SHIFT | VK_CONTROL | VK_MENU | VK_LSHIFT | VK_RSHIFT | VK_LCONTROL | VK_RCONTROL | VK_LMENU | VK_RMENU
+    )
+}
+
+/// A short series of key presses, like up, up, down, that must be entered in order
+///
+/// Call `update` once per frame after updating the `Keyboard`. Pressing a key that isn't next in
+/// the sequence starts it over, as does waiting longer than the timeout between presses. A wrong
+/// key that continues a shorter attempt still counts towards it, so up, up, up, down completes
+/// up, up, down. Mouse buttons and modifier keys that aren't part of the sequence are ignored.
+///
+/// When several keys are pressed in the same frame, their order is unknown, so they're taken in
+/// whatever order advances the sequence furthest.
+#[derive(Debug, Clone)]
+pub struct KeySequence {
+    keys: Vec<VIRTUAL_KEY>,
+    /// For each prefix of `keys`, the length of its longest proper suffix that's also a prefix,
+    /// i.e. how much progress survives a wrong key
+    fallback: Vec<usize>,
+    timeout: Duration,
+    progress: usize,
+    last_press: Option<Instant>,
+}
+
+impl KeySequence {
+    /// The default maximum time between presses
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new(keys: impl Into<Vec<VIRTUAL_KEY>>) -> Self {
+        Self::with_timeout(keys, Self::DEFAULT_TIMEOUT)
+    }
+
+    /// Create a sequence that starts over if more than `timeout` passes between presses
+    pub fn with_timeout(keys: impl Into<Vec<VIRTUAL_KEY>>, timeout: Duration) -> Self {
+        let keys = keys.into();
+        let mut fallback = vec![0; keys.len()];
+        let mut matched = 0;
+        for i in 1..keys.len() {
+            while matched > 0 && keys[i] != keys[matched] {
+                matched = fallback[matched - 1];
+            }
+            if keys[i] == keys[matched] {
+                matched += 1;
+            }
+            fallback[i] = matched;
+        }
+
+        Self {
+            keys,
+            fallback,
+            timeout,
+            progress: 0,
+            last_press: None,
+        }
+    }
+
+    pub fn keys(&self) -> &[VIRTUAL_KEY] {
+        &self.keys
+    }
+
+    /// Start the sequence over
+    pub fn reset(&mut self) {
+        self.progress = 0;
+        self.last_press = None;
+    }
+
+    /// Check this frame's key presses, returning true on the frame the sequence is completed
+    pub fn update(&mut self, keyboard: &Keyboard) -> bool {
+        self.update_at(keyboard, Instant::now())
+    }
+
+    fn update_at(&mut self, keyboard: &Keyboard, now: Instant) -> bool {
+        if self.keys.is_empty() {
+            return false;
+        }
+
+        if self.last_press.is_some_and(|last_press| now.duration_since(last_press) > self.timeout) {
+            self.reset();
+        }
+
+        // codes below VK_BACK are mouse buttons and VK_CANCEL
+        let mut pressed: Vec<_> = (VK_BACK.0..=u8::MAX as u16)
+            .map(VIRTUAL_KEY)
+            .filter(|&key| keyboard.is_key_down_once(key) && (!is_modifier(key) || self.keys.contains(&key)))
+            .collect();
+
+        while !pressed.is_empty() {
+            self.last_press = Some(now);
+            // prefer the key that's next in the sequence, since we don't know which came first
+            let index = pressed.iter().position(|&key| key == self.keys[self.progress]).unwrap_or(0);
+            let key = pressed.swap_remove(index);
+            self.advance(key);
+
+            if self.progress == self.keys.len() {
+                self.reset();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn advance(&mut self, key: VIRTUAL_KEY) {
+        // the wrong key might continue a shorter attempt that overlaps the current one
+        while self.progress > 0 && key != self.keys[self.progress] {
+            self.progress = self.fallback[self.progress - 1];
+        }
+        if key == self.keys[self.progress] {
+            self.progress += 1;
+        }
+    }
+}
+
+/// Maps actions, like opening a mod's menu, to the chords that trigger them
+///
+/// Each action can have any number of bindings. Mouse buttons can be bound too, since
+/// `GetKeyboardState` reports them under `VK_LBUTTON` and friends. With the `serde` feature, the
+/// map can be saved to and loaded from a config file so users can rebind controls. It's stored as a
+/// map from each action to a list of chords in the same format as `Chord`'s `Display`, e.g.
+/// `{"Menu": ["F1", "Ctrl+M"]}` in JSON.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ActionMap<A: Eq + Hash> {
+    bindings: HashMap<A, Vec<Chord>>,
+}
+
+impl<A: Eq + Hash> ActionMap<A> {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Add a binding for an action, keeping any existing ones
+    pub fn bind(&mut self, action: A, chord: Chord) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&chord) {
+            bindings.push(chord);
+        }
+    }
+
+    /// Replace all bindings for an action
+    pub fn set_bindings(&mut self, action: A, chords: impl Into<Vec<Chord>>) {
+        self.bindings.insert(action, chords.into());
+    }
+
+    /// Remove one binding from an action, returning whether it was bound
+    pub fn unbind(&mut self, action: &A, chord: Chord) -> bool {
+        let Some(bindings) = self.bindings.get_mut(action) else {
+            return false;
+        };
+
+        let len = bindings.len();
+        bindings.retain(|&binding| binding != chord);
+        bindings.len() != len
+    }
+
+    /// Remove all bindings from an action
+    pub fn clear(&mut self, action: &A) {
+        self.bindings.remove(action);
+    }
+
+    pub fn bindings(&self, action: &A) -> &[Chord] {
+        self.bindings.get(action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Find the actions a chord is bound to, for warning about conflicts when rebinding
+    pub fn actions_bound_to(&self, chord: Chord) -> impl Iterator<Item = &A> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&chord))
+            .map(|(action, _)| action)
+    }
+
+    /// Whether any of the action's bindings were pressed this frame
+    pub fn is_action_pressed(&self, action: &A, keyboard: &Keyboard) -> bool {
+        self.bindings(action).iter().any(|&chord| keyboard.is_chord_down_once(chord))
+    }
+
+    /// Whether any of the action's bindings are held
+    pub fn is_action_down(&self, action: &A, keyboard: &Keyboard) -> bool {
+        self.bindings(action).iter().any(|&chord| keyboard.is_chord_down(chord))
+    }
+}
+
+impl<A: Eq + Hash> Default for ActionMap<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    X1,
+    X2,
+}
+
+impl MouseButton {
+    pub const ALL: [MouseButton; 5] = [Self::Left, Self::Right, Self::Middle, Self::X1, Self::X2];
+
+    pub const fn virtual_key(self) -> VIRTUAL_KEY {
+        match self {
+            Self::Left => VK_LBUTTON,
+            Self::Right => VK_RBUTTON,
+            Self::Middle => VK_MBUTTON,
+            Self::X1 => VK_XBUTTON1,
+            Self::X2 => VK_XBUTTON2,
+        }
+    }
+
+    /// The virtual key `GetAsyncKeyState` reports this button under
+    ///
+    /// `GetAsyncKeyState` reads the physical buttons, so when the user has swapped the primary and
+    /// secondary buttons, the primary (`Left`) button is the physical right button.
+    const fn physical_key(self, buttons_swapped: bool) -> VIRTUAL_KEY {
+        match self {
+            Self::Left if buttons_swapped => VK_RBUTTON,
+            Self::Right if buttons_swapped => VK_LBUTTON,
+            _ => self.virtual_key(),
+        }
+    }
+}
+
+/// Mouse state, updated once per frame like `Keyboard`
+///
+/// Button states and the cursor position are polled in `update`. `MouseButton::Left` and `Right`
+/// are the primary and secondary buttons, so they follow the user's choice of swapping the mouse
+/// buttons. The wheel can't be polled, so wheel messages have to be fed in with `handle_message`
+/// (or `add_wheel_delta`) from a window procedure hook; they're accumulated until the next
+/// `update`.
+#[derive(Debug)]
+pub struct Mouse {
+    old_buttons: [bool; 5],
+    new_buttons: [bool; 5],
+    window: Option<WindowHandle>,
+    old_screen_position: (i32, i32),
+    screen_position: (i32, i32),
+    client_position: Option<(i32, i32)>,
+    pending_wheel: (i32, i32),
+    wheel: (i32, i32),
+    /// Vertical wheel movement that didn't add up to a whole notch yet
+    wheel_remainder: i32,
+    wheel_notches: i32,
+    require_focus: bool,
+}
+
+impl Mouse {
+    pub const fn new() -> Self {
+        Self {
+            old_buttons: [false; 5],
+            new_buttons: [false; 5],
+            window: None,
+            old_screen_position: (0, 0),
+            screen_position: (0, 0),
+            client_position: None,
+            pending_wheel: (0, 0),
+            wheel: (0, 0),
+            wheel_remainder: 0,
+            wheel_notches: 0,
+            require_focus: false,
+        }
+    }
+
+    /// Set the window that client-relative positions are measured from, usually the game's main window
+    pub fn set_window(&mut self, window: HWND) {
+        self.window = Some(WindowHandle::new(window));
+    }
+
+    /// Only report buttons as down while the game has focus
+    ///
+    /// If a window has been set with `set_window`, it (or the top-level window containing it) must
+    /// be the foreground window. Otherwise any window belonging to the current process counts.
+    pub fn set_require_focus(&mut self, require_focus: bool) {
+        self.require_focus = require_focus;
+    }
+
+    pub fn update(&mut self) -> windows_result::Result<()> {
+        let is_focused = !self.require_focus || self.window.map_or_else(is_process_focused, WindowHandle::is_focused);
+        let buttons_swapped = unsafe { GetSystemMetrics(SM_SWAPBUTTON) } != 0;
+        self.old_buttons = self.new_buttons;
+        for button in MouseButton::ALL {
+            let key = button.physical_key(buttons_swapped);
+            self.new_buttons[button as usize] = is_focused && unsafe { GetAsyncKeyState(key.0 as i32) } < 0;
+        }
+
+        self.take_wheel();
+
+        let mut point = POINT::default();
+        unsafe { GetCursorPos(&mut point) }?;
+        self.old_screen_position = self.screen_position;
+        self.screen_position = (point.x, point.y);
+        self.client_position = self
+            .window
+            .filter(|window| unsafe { ScreenToClient(window.hwnd(), &mut point) }.as_bool())
+            .map(|_| (point.x, point.y));
+
+        Ok(())
+    }
+
+    pub const fn is_button_down(&self, button: MouseButton) -> bool {
+        self.new_buttons[button as usize]
+    }
+
+    pub const fn is_button_down_once(&self, button: MouseButton) -> bool {
+        self.is_button_down(button) && !self.old_buttons[button as usize]
+    }
+
+    pub const fn is_button_up_once(&self, button: MouseButton) -> bool {
+        !self.is_button_down(button) && self.old_buttons[button as usize]
+    }
+
+    /// The cursor position in screen coordinates
+    pub const fn position(&self) -> (i32, i32) {
+        self.screen_position
+    }
+
+    /// The cursor position relative to the client area of the window set with `set_window`
+    pub const fn client_position(&self) -> Option<(i32, i32)> {
+        self.client_position
+    }
+
+    /// The cursor movement between the last two updates
+    pub const fn movement(&self) -> (i32, i32) {
+        (
+            self.screen_position.0 - self.old_screen_position.0,
+            self.screen_position.1 - self.old_screen_position.1,
+        )
+    }
+
+    /// The vertical wheel movement between the last two updates in notches, positive away from the user
+    ///
+    /// High-resolution wheels report fractions of a notch, which are carried over until they add up
+    /// to a whole one; use `wheel_delta_raw` to see them as they come in.
+    pub const fn wheel_delta(&self) -> i32 {
+        self.wheel_notches
+    }
+
+    /// The vertical and horizontal wheel movement between the last two updates in raw units
+    pub const fn wheel_delta_raw(&self) -> (i32, i32) {
+        self.wheel
+    }
+
+    /// Make the wheel movement accumulated since the last update current
+    fn take_wheel(&mut self) {
+        self.wheel = std::mem::take(&mut self.pending_wheel);
+        let total = self.wheel_remainder + self.wheel.0;
+        self.wheel_notches = total / WHEEL_DELTA as i32;
+        self.wheel_remainder = total % WHEEL_DELTA as i32;
+    }
+
+    pub fn add_wheel_delta(&mut self, vertical: i32, horizontal: i32) {
+        self.pending_wheel.0 += vertical;
+        self.pending_wheel.1 += horizontal;
+    }
+
+    /// Record the wheel movement from a window message
+    ///
+    /// Returns true if the message was a wheel message.
+    pub fn handle_message(&mut self, message: u32, wparam: WPARAM) -> bool {
+        // the delta is the signed high word of wparam
+        let delta = (wparam.0 >> 16) as u16 as i16 as i32;
+        match message {
+            WM_MOUSEWHEEL => self.add_wheel_delta(delta, 0),
+            WM_MOUSEHWHEEL => self.add_wheel_delta(0, delta),
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press_at(keyboard: &mut Keyboard, keys: &[VIRTUAL_KEY], now: Instant) {
+        let mut state = [0; 256];
+        for key in keys {
+            state[key.0 as usize] = 0x80;
+        }
+        keyboard.set_keys(state, now);
+    }
+
+    fn press(keyboard: &mut Keyboard, keys: &[VIRTUAL_KEY]) {
+        press_at(keyboard, keys, Instant::now());
+    }
+
+    #[test]
+    fn messages() {
+        let mut keyboard = Keyboard::with_messages();
+        let key_down = |keyboard: &mut Keyboard, key: VIRTUAL_KEY, lparam| {
+            assert!(keyboard.handle_message(WM_KEYDOWN, WPARAM(key.0 as usize), LPARAM(lparam)));
+        };
+        let key_up = |keyboard: &mut Keyboard, key: VIRTUAL_KEY, lparam| {
+            assert!(keyboard.handle_message(WM_KEYUP, WPARAM(key.0 as usize), LPARAM(lparam)));
+        };
+        let update = |keyboard: &mut Keyboard| {
+            let keys = keyboard.take_message_keys();
+            keyboard.set_keys(keys, Instant::now());
+        };
+
+        // tapped between updates
+        key_down(&mut keyboard, VK_F1, 0);
+        key_up(&mut keyboard, VK_F1, 0);
+        update(&mut keyboard);
+        assert!(keyboard.is_key_down_once(VK_F1));
+        update(&mut keyboard);
+        assert!(keyboard.is_key_up_once(VK_F1));
+
+        // held across updates, with key repeat
+        key_down(&mut keyboard, VK_A, 0);
+        update(&mut keyboard);
+        key_down(&mut keyboard, VK_A, 1 << 30);
+        update(&mut keyboard);
+        assert!(keyboard.is_key_down(VK_A) && !keyboard.is_key_down_once(VK_A));
+
+        // right shift (scan code 0x36), then left shift, then releasing right shift
+        key_down(&mut keyboard, VK_SHIFT, 0x36 << 16);
+        update(&mut keyboard);
+        assert!(keyboard.is_key_down_once(VK_SHIFT) && keyboard.is_key_down(VK_RSHIFT) && !keyboard.is_key_down(VK_LSHIFT));
+        key_down(&mut keyboard, VK_SHIFT, 0x2A << 16);
+        key_up(&mut keyboard, VK_SHIFT, 0x36 << 16);
+        update(&mut keyboard);
+        assert!(keyboard.is_key_down(VK_SHIFT) && keyboard.is_key_down(VK_LSHIFT) && keyboard.is_key_up_once(VK_RSHIFT));
+
+        // right ctrl is an extended key
+        key_down(&mut keyboard, VK_CONTROL, 1 << 24);
+        update(&mut keyboard);
+        assert!(keyboard.is_key_down(VK_RCONTROL) && keyboard.is_key_down(VK_CONTROL));
+
+        assert!(keyboard.handle_message(WM_XBUTTONDOWN, WPARAM(2 << 16), LPARAM(0)));
+        assert!(!keyboard.handle_message(WM_MOUSEWHEEL, WPARAM(0), LPARAM(0)));
+        update(&mut keyboard);
+        assert!(keyboard.is_key_down_once(VK_XBUTTON2));
+    }
+
+    #[test]
+    fn focus() {
+        let mut keyboard = Keyboard::new();
+        let now = Instant::now();
+        let mut keys = [0; 256];
+        keys[VK_F1.0 as usize] = 0x80;
+        keys[VK_CAPITAL.0 as usize] = 0x01;
+
+        keyboard.apply_update(keys, now, false);
+        assert!(!keyboard.is_key_down(VK_F1));
+        assert!(keyboard.is_key_toggled(VK_CAPITAL));
+
+        keys[VK_MENU.0 as usize] = 0x80;
+        keyboard.apply_update(keys, now, true);
+        assert!(keyboard.is_key_down(VK_F1) && keyboard.is_key_down(VK_MENU));
+        assert!(!keyboard.is_key_down_once(VK_F1) && !keyboard.is_key_down_once(VK_MENU));
+
+        keys[VK_MENU.0 as usize] = 0;
+        keyboard.apply_update(keys, now, true);
+        assert!(keyboard.is_key_up_once(VK_MENU));
+        keys[VK_MENU.0 as usize] = 0x80;
+        keyboard.apply_update(keys, now, true);
+        assert!(keyboard.is_key_down_once(VK_MENU));
+
+        let mut keyboard = Keyboard::with_messages();
+        keyboard.handle_message(WM_KEYDOWN, WPARAM(VK_F1.0 as usize), LPARAM(0));
+        assert!(keyboard.handle_message(WM_KILLFOCUS, WPARAM(0), LPARAM(0)));
+        keyboard.take_message_keys();
+        assert_eq!(keyboard.take_message_keys()[VK_F1.0 as usize], 0);
+    }
+
+    #[test]
+    fn hold_duration() {
+        let mut keyboard = Keyboard::new();
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        press_at(&mut keyboard, &[], at(0));
+        assert_eq!(keyboard.key_held_duration(VK_SPACE), None);
+        press_at(&mut keyboard, &[VK_SPACE], at(100));
+        assert_eq!(keyboard.key_held_duration(VK_SPACE), Some(Duration::ZERO));
+        press_at(&mut keyboard, &[VK_SPACE, VK_F1], at(350));
+        assert_eq!(keyboard.key_held_duration(VK_SPACE), Some(Duration::from_millis(250)));
+        assert_eq!(keyboard.key_held_duration(VK_F1), Some(Duration::ZERO));
+        assert!(!keyboard.is_key_up_once(VK_SPACE));
+
+        press_at(&mut keyboard, &[VK_F1], at(400));
+        assert!(keyboard.is_key_up_once(VK_SPACE));
+        assert!(!keyboard.is_key_up_once(VK_F1));
+        assert_eq!(keyboard.key_held_duration(VK_SPACE), Some(Duration::from_millis(300)));
+        press_at(&mut keyboard, &[], at(500));
+        assert!(!keyboard.is_key_up_once(VK_SPACE));
+        assert_eq!(keyboard.key_held_duration(VK_SPACE), None);
+        assert_eq!(keyboard.key_held_duration(VK_F1), Some(Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn key_names() {
+        for code in 0..=u8::MAX as u16 {
+            if let Some(name) = key_name(VIRTUAL_KEY(code)) {
+                assert_eq!(parse_key(name), Ok(VIRTUAL_KEY(code)), "{}", name);
+                assert_eq!(parse_key(&name.to_lowercase()), Ok(VIRTUAL_KEY(code)), "{}", name);
+            }
+        }
+
+        assert_eq!(key_name(VK_F5), Some("F5"));
+        assert_eq!(key_name(VK_NUMPAD3), Some("Numpad3"));
+        assert_eq!(key_name(VK_LCONTROL), Some("LCtrl"));
+        assert_eq!(key_name(VIRTUAL_KEY(b'Q' as u16)), Some("Q"));
+        assert_eq!(key_name(VK_VOLUME_MUTE), None);
+        assert_eq!(parse_key("esc"), Ok(VK_ESCAPE));
+        assert_eq!(parse_key("Num3"), Ok(VK_NUMPAD3));
+        assert_eq!(parse_key("0xAD"), Ok(VK_VOLUME_MUTE));
+        assert_eq!(parse_key(" "), Err(KeyParseError::Empty));
+        assert_eq!(parse_key("Num10"), Err(KeyParseError::UnknownKey { name: String::from("Num10") }));
+    }
+
+    #[test]
+    fn chord_strings() {
+        let chord = Chord::new(VK_F5).ctrl().shift();
+        assert_eq!(chord.to_string(), "Ctrl+Shift+F5");
+        assert_eq!("ctrl + shift + f5".parse(), Ok(chord));
+        assert_eq!("Shift+Control+F5".parse(), Ok(chord));
+        assert_eq!("Alt+0xAD".parse(), Ok(Chord::new(VK_VOLUME_MUTE).alt()));
+        assert_eq!(Chord::new(VK_VOLUME_MUTE).to_string(), "0xAD");
+        assert_eq!("Q+F5".parse::<Chord>(), Err(KeyParseError::UnknownModifier { name: String::from("Q") }));
+        assert_eq!("Ctrl+".parse::<Chord>(), Err(KeyParseError::Empty));
+    }
+
+    #[test]
+    fn chords() {
+        let chord = Chord::new(VK_F5).ctrl().shift();
+        let mut keyboard = Keyboard::new();
+
+        press(&mut keyboard, &[VK_CONTROL, VK_SHIFT]);
+        assert!(!keyboard.is_chord_down(chord));
+        press(&mut keyboard, &[VK_CONTROL, VK_SHIFT, VK_F5]);
+        assert!(keyboard.is_chord_down_once(chord));
+        assert!(!keyboard.is_chord_down_once(Chord::new(VK_F5).ctrl()));
+        press(&mut keyboard, &[VK_CONTROL, VK_SHIFT, VK_F5]);
+        assert!(keyboard.is_chord_down(chord));
+        assert!(!keyboard.is_chord_down_once(chord));
+        press(&mut keyboard, &[VK_CONTROL, VK_SHIFT, VK_MENU, VK_F5]);
+        assert!(!keyboard.is_chord_down(chord));
+    }
+
+    #[test]
+    fn actions() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        enum Action {
+            Menu,
+            Boost,
+        }
+
+        let mut actions = ActionMap::new();
+        actions.bind(Action::Menu, Chord::new(VK_F1));
+        actions.bind(Action::Menu, Chord::new(VK_M).ctrl());
+        actions.bind(Action::Boost, Chord::new(VK_LBUTTON));
+        let mut keyboard = Keyboard::new();
+
+        press(&mut keyboard, &[VK_CONTROL, VK_M, VK_LBUTTON]);
+        assert!(actions.is_action_pressed(&Action::Menu, &keyboard));
+        // bindings need exactly their modifiers
+        assert!(!actions.is_action_pressed(&Action::Boost, &keyboard));
+        press(&mut keyboard, &[VK_CONTROL, VK_M]);
+        assert!(!actions.is_action_pressed(&Action::Menu, &keyboard));
+        assert!(actions.is_action_down(&Action::Menu, &keyboard));
+        press(&mut keyboard, &[VK_LBUTTON]);
+        assert!(actions.is_action_pressed(&Action::Boost, &keyboard));
+
+        assert_eq!(actions.actions_bound_to(Chord::new(VK_F1)).collect::<Vec<_>>(), [&Action::Menu]);
+        assert!(actions.unbind(&Action::Menu, Chord::new(VK_F1)));
+        assert!(!actions.unbind(&Action::Menu, Chord::new(VK_F1)));
+        assert_eq!(actions.bindings(&Action::Menu), [Chord::new(VK_M).ctrl()]);
+
+        press(&mut keyboard, &[]);
+        press(&mut keyboard, &[VK_SHIFT, VK_F7]);
+        let chord = Chord::pressed(&keyboard).unwrap();
+        assert_eq!(chord, Chord::new(VK_F7).shift());
+        actions.set_bindings(Action::Boost, [chord]);
+        assert!(actions.is_action_pressed(&Action::Boost, &keyboard));
+        actions.clear(&Action::Boost);
+        assert!(actions.bindings(&Action::Boost).is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_bindings() {
+        let mut actions = ActionMap::new();
+        actions.set_bindings(String::from("Menu"), [Chord::new(VK_F1), Chord::new(VK_M).ctrl()]);
+        let json = serde_json::to_string(&actions).unwrap();
+        assert_eq!(json, r#"{"Menu":["F1","Ctrl+M"]}"#);
+
+        let actions: ActionMap<String> = serde_json::from_str(r#"{"Boost": ["shift + f7", "LMouse"]}"#).unwrap();
+        assert_eq!(
+            actions.bindings(&String::from("Boost")),
+            [Chord::new(VK_F7).shift(), Chord::new(VK_LBUTTON)]
+        );
+        assert!(serde_json::from_str::<ActionMap<String>>(r#"{"Boost": [112]}"#).is_err());
+    }
+
+    #[test]
+    fn sequences() {
+        let mut sequence = KeySequence::new([VK_UP, VK_UP, VK_DOWN]);
+        let mut keyboard = Keyboard::new();
+        let start = Instant::now();
+        let mut step = |keyboard: &mut Keyboard, keys: &[VIRTUAL_KEY], millis| {
+            press(keyboard, keys);
+            sequence.update_at(keyboard, start + Duration::from_millis(millis))
+        };
+
+        assert!(!step(&mut keyboard, &[VK_UP], 0));
+        assert!(!step(&mut keyboard, &[], 100));
+        assert!(!step(&mut keyboard, &[VK_UP], 200));
+        // holding a key doesn't count as pressing it again, and unrelated modifiers are ignored
+        assert!(!step(&mut keyboard, &[VK_UP, VK_SHIFT], 300));
+        assert!(step(&mut keyboard, &[VK_DOWN], 400));
+
+        // a wrong key starts over, but counts if it's the first key
+        assert!(!step(&mut keyboard, &[VK_UP], 500));
+        assert!(!step(&mut keyboard, &[VK_LEFT], 600));
+        assert!(!step(&mut keyboard, &[VK_DOWN], 700));
+        assert!(!step(&mut keyboard, &[VK_UP], 800));
+        assert!(!step(&mut keyboard, &[VK_RIGHT], 900));
+        assert!(!step(&mut keyboard, &[VK_UP], 1000));
+        assert!(!step(&mut keyboard, &[], 1100));
+        assert!(!step(&mut keyboard, &[VK_UP], 1150));
+        assert!(step(&mut keyboard, &[VK_DOWN], 1200));
+
+        // too slow
+        assert!(!step(&mut keyboard, &[VK_UP], 2000));
+        assert!(!step(&mut keyboard, &[], 2100));
+        assert!(!step(&mut keyboard, &[VK_UP], 2200));
+        assert!(!step(&mut keyboard, &[], 2300));
+        assert!(!step(&mut keyboard, &[VK_DOWN], 3500));
+    }
+
+    #[test]
+    fn sequence_overlapping_prefix() {
+        let mut sequence = KeySequence::new([VK_UP, VK_UP, VK_DOWN]);
+        let mut keyboard = Keyboard::new();
+        let start = Instant::now();
+        let mut step = |keyboard: &mut Keyboard, keys: &[VIRTUAL_KEY], millis| {
+            press(keyboard, keys);
+            sequence.update_at(keyboard, start + Duration::from_millis(millis))
+        };
+
+        // the third up doesn't match, but the last two ups still start the sequence
+        for millis in [0, 200, 400] {
+            assert!(!step(&mut keyboard, &[VK_UP], millis));
+            assert!(!step(&mut keyboard, &[], millis + 100));
+        }
+        assert!(step(&mut keyboard, &[VK_DOWN], 600));
+
+        let mut sequence = KeySequence::new([VK_A, VK_B, VK_A, VK_C]);
+        for key in [VK_A, VK_B, VK_A, VK_B, VK_A] {
+            press(&mut keyboard, &[key]);
+            assert!(!sequence.update_at(&keyboard, start));
+        }
+        press(&mut keyboard, &[VK_C]);
+        assert!(sequence.update_at(&keyboard, start));
+    }
+
+    #[test]
+    fn sequence_same_frame() {
+        // VK_DOWN has a lower code than VK_UP, so going by key code alone would get these wrong
+        let mut sequence = KeySequence::new([VK_LEFT, VK_UP, VK_DOWN]);
+        let mut keyboard = Keyboard::new();
+        let start = Instant::now();
+        press(&mut keyboard, &[VK_LEFT]);
+        assert!(!sequence.update_at(&keyboard, start));
+        press(&mut keyboard, &[VK_UP, VK_DOWN]);
+        assert!(sequence.update_at(&keyboard, start));
+
+        press(&mut keyboard, &[]);
+        press(&mut keyboard, &[VK_DOWN, VK_LEFT, VK_UP]);
+        assert!(sequence.update_at(&keyboard, start));
+    }
+
+    #[test]
+    fn mouse_wheel() {
+        let mut mouse = Mouse::new();
+        assert!(mouse.handle_message(WM_MOUSEWHEEL, WPARAM(((-120i16 as u16 as usize) << 16) | 0x8)));
+        assert!(!mouse.handle_message(WM_LBUTTONDOWN, WPARAM(0)));
+        mouse.take_wheel();
+        assert_eq!((mouse.wheel_delta(), mouse.wheel_delta_raw()), (-1, (-120, 0)));
+
+        // fractions of a notch from a high-resolution wheel add up across updates
+        mouse.add_wheel_delta(80, 30);
+        mouse.take_wheel();
+        assert_eq!((mouse.wheel_delta(), mouse.wheel_delta_raw()), (0, (80, 30)));
+        mouse.add_wheel_delta(80, 0);
+        mouse.take_wheel();
+        assert_eq!(mouse.wheel_delta(), 1);
+        mouse.take_wheel();
+        assert_eq!((mouse.wheel_delta(), mouse.wheel_delta_raw()), (0, (0, 0)));
+        mouse.add_wheel_delta(-160, 0);
+        mouse.take_wheel();
+        assert_eq!(mouse.wheel_delta(), -1);
+    }
+
+    #[test]
+    fn swapped_buttons() {
+        assert_eq!(MouseButton::Left.physical_key(false), VK_LBUTTON);
+        assert_eq!(MouseButton::Left.physical_key(true), VK_RBUTTON);
+        assert_eq!(MouseButton::Right.physical_key(true), VK_LBUTTON);
+        assert_eq!(MouseButton::Middle.physical_key(true), VK_MBUTTON);
+    }
+}
@@ -0,0 +1,363 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::core::BOOL;
+use windows::Win32::Foundation::{GetLastError, SetLastError, HWND, LPARAM, LRESULT, WIN32_ERROR, WPARAM};
+use windows::Win32::UI::Input::KeyboardAndMouse::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::callbacks::{CallbackHandle, CallbackList};
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+use crate::pe;
+
+type GetKeyboardStateFn = unsafe extern "system" fn(*mut u8) -> BOOL;
+type GetKeyStateFn = unsafe extern "system" fn(i32) -> i16;
+
+static BLOCKED_KEYS: [AtomicBool; 256] = [const { AtomicBool::new(false) }; 256];
+static BLOCK_ALL: AtomicBool = AtomicBool::new(false);
+/// Whether the last key press was blocked, in which case the characters it produces are too
+static CHARS_BLOCKED: AtomicBool = AtomicBool::new(false);
+static MESSAGE_LISTENERS: CallbackList<dyn FnMut(u32, WPARAM, LPARAM) + Send> = CallbackList::new();
+
+/// The name used in errors about the message filter
+const FILTER_NAME: &str = "hook86 input block window filter";
+
+/// The game's original window procedure while the message filter is installed
+static ORIGINAL_WNDPROC: AtomicUsize = AtomicUsize::new(0);
+/// Whether the filtered window is a Unicode window
+static FILTER_UNICODE: AtomicBool = AtomicBool::new(true);
+static ORIGINAL_GET_KEYBOARD_STATE: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_GET_ASYNC_KEY_STATE: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_GET_KEY_STATE: AtomicUsize = AtomicUsize::new(0);
+
+/// Mark a key as consumed by the mod so the game doesn't see it pressed
+///
+/// Blocking only has an effect once `install_window_filter` and/or `install_import_hooks` have
+/// been called, depending on how the game reads input. hook86's own `Keyboard` and `Mouse` still
+/// see blocked keys. Blocking a left or right variant (e.g. `VK_LSHIFT`) doesn't block the
+/// generic key (`VK_SHIFT`) or vice versa.
+pub fn block_key(key: VIRTUAL_KEY) {
+    if let Some(blocked) = BLOCKED_KEYS.get(key.0 as usize) {
+        blocked.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn unblock_key(key: VIRTUAL_KEY) {
+    if let Some(blocked) = BLOCKED_KEYS.get(key.0 as usize) {
+        blocked.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Unblock every key blocked with `block_key`
+pub fn unblock_all_keys() {
+    for blocked in &BLOCKED_KEYS {
+        blocked.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Block (or stop blocking) all keyboard and mouse button input, e.g. while a mod menu is open
+///
+/// This also swallows character messages, so the game doesn't receive text typed into the menu.
+pub fn set_block_all(block: bool) {
+    BLOCK_ALL.store(block, Ordering::Relaxed);
+}
+
+pub fn is_key_blocked(key: VIRTUAL_KEY) -> bool {
+    BLOCK_ALL.load(Ordering::Relaxed) || BLOCKED_KEYS.get(key.0 as usize).is_some_and(|blocked| blocked.load(Ordering::Relaxed))
+}
+
+/// Check whether a window message is input the game shouldn't receive
+///
+/// Key and button releases are never blocked, so the game doesn't think a key is stuck down if
+/// it was blocked while held. Use this from your own window procedure hook if the game's window
+/// procedure is already hooked some other way.
+///
+/// Character messages carry a character rather than a key, so they're blocked if the key press
+/// they were translated from was. That means this needs to see every key press message sent to
+/// the window, in order, for character messages to be blocked correctly.
+pub fn should_block_message(message: u32, wparam: WPARAM) -> bool {
+    let key = match message {
+        WM_KEYDOWN | WM_SYSKEYDOWN => {
+            let blocked = is_key_blocked(VIRTUAL_KEY(wparam.0 as u16));
+            // TranslateMessage posts the key's characters right after the key press
+            CHARS_BLOCKED.store(blocked, Ordering::Relaxed);
+            return blocked;
+        }
+        WM_CHAR | WM_SYSCHAR | WM_DEADCHAR | WM_SYSDEADCHAR => {
+            return BLOCK_ALL.load(Ordering::Relaxed) || CHARS_BLOCKED.load(Ordering::Relaxed);
+        }
+        WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => VK_LBUTTON,
+        WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => VK_RBUTTON,
+        WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => VK_MBUTTON,
+        // the high word of wparam says which X button it was
+        WM_XBUTTONDOWN | WM_XBUTTONDBLCLK if (wparam.0 >> 16) as u16 == XBUTTON1 => VK_XBUTTON1,
+        WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => VK_XBUTTON2,
+        _ => return false,
+    };
+
+    is_key_blocked(key)
+}
+
+/// Clear the down bit of every blocked key in a `GetKeyboardState` buffer, keeping toggle states
+fn clear_blocked_keys(state: &mut [u8; 256]) {
+    for (code, key_state) in state.iter_mut().enumerate() {
+        if is_key_blocked(VIRTUAL_KEY(code as u16)) {
+            *key_state &= !0x80;
+        }
+    }
+}
+
+/// Identifies a listener registered with `on_message` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageHandle(CallbackHandle);
+
+/// Register a callback that sees every message sent to the window filtered by
+/// `install_window_filter`, including blocked input
+///
+/// This is the place to feed a `Keyboard::with_messages` or a `Mouse`. Listeners run on the
+/// window's thread before the game's window procedure, in the order they were registered. A
+/// listener that causes a message to be sent to the window won't see that message, but the other
+/// listeners still do. A listener that panics is removed, and the panic doesn't reach the game.
+pub fn on_message(listener: impl FnMut(u32, WPARAM, LPARAM) + Send + 'static) -> MessageHandle {
+    MessageHandle(MESSAGE_LISTENERS.add(Arc::new(Mutex::new(listener))))
+}
+
+/// Remove a listener registered with `on_message`
+///
+/// If the listeners are already running for the current message, the removed listener may still
+/// see it. Returns false if the listener was already removed.
+pub fn remove_message(handle: MessageHandle) -> bool {
+    MESSAGE_LISTENERS.remove(handle.0)
+}
+
+unsafe extern "system" fn filter_wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    // a listener may remove the filter, so find out where the message goes before running them
+    let original = ORIGINAL_WNDPROC.load(Ordering::Acquire);
+    let unicode = FILTER_UNICODE.load(Ordering::Acquire);
+    MESSAGE_LISTENERS.dispatch(|listener| listener(message, wparam, lparam));
+
+    if should_block_message(message, wparam) {
+        return LRESULT(0);
+    }
+
+    if original == 0 {
+        return unsafe { DefWindowProcW(window, message, wparam, lparam) };
+    }
+
+    let original: WNDPROC = unsafe { std::mem::transmute(original) };
+    if unicode {
+        unsafe { CallWindowProcW(original, window, message, wparam, lparam) }
+    } else {
+        unsafe { CallWindowProcA(original, window, message, wparam, lparam) }
+    }
+}
+
+fn filter_address() -> usize {
+    filter_wndproc as unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> LRESULT as usize
+}
+
+/// Replace a window's window procedure, returning the previous one
+///
+/// The ANSI or Unicode variant is used to match the window, so Windows doesn't convert text in
+/// the messages passing through.
+unsafe fn set_wndproc(window: HWND, wndproc: usize, unicode: bool) -> Result<usize> {
+    // 0 is also what a failure returns, so only the last error can tell them apart
+    unsafe { SetLastError(WIN32_ERROR(0)) };
+    let previous = if unicode {
+        unsafe { SetWindowLongPtrW(window, GWL_WNDPROC, wndproc as _) }
+    } else {
+        unsafe { SetWindowLongPtrA(window, GWL_WNDPROC, wndproc as _) }
+    };
+    if previous == 0 {
+        let error = unsafe { GetLastError() };
+        if error != WIN32_ERROR(0) {
+            return Err(windows::core::Error::from(error).into());
+        }
+    }
+
+    Ok(previous as u32 as usize)
+}
+
+/// Replace a window's window procedure with one that drops messages for blocked keys
+///
+/// Every other message is passed to the original window procedure after any `on_message`
+/// listeners have seen it. Only one window can be filtered at a time; installing the filter again
+/// on the same window does nothing.
+///
+/// # Safety
+///
+/// `window` must belong to the current process, and no other window may currently be filtered.
+///
+/// # Errors
+///
+/// Returns an error if the window procedure couldn't be replaced.
+pub unsafe fn install_window_filter(window: HWND) -> Result<()> {
+    let filter = filter_address();
+    let unicode = unsafe { IsWindowUnicode(window) }.as_bool();
+    FILTER_UNICODE.store(unicode, Ordering::Release);
+    let original = unsafe { set_wndproc(window, filter, unicode) }?;
+    if original != filter {
+        ORIGINAL_WNDPROC.store(original, Ordering::Release);
+    }
+
+    Ok(())
+}
+
+/// Put back the window procedure replaced by `install_window_filter`
+///
+/// Does nothing if the filter isn't installed.
+///
+/// # Safety
+///
+/// `window` must be the window passed to `install_window_filter`. This should be called on the
+/// window's thread, so the window procedure isn't swapped out while it's running.
+///
+/// # Errors
+///
+/// Returns an error if something else has replaced the window procedure since the filter was
+/// installed, since putting the original back would also remove theirs, or if the window
+/// procedure couldn't be restored. The filter stays installed in either case.
+pub unsafe fn remove_window_filter(window: HWND) -> Result<()> {
+    let original = ORIGINAL_WNDPROC.load(Ordering::Acquire);
+    if original == 0 {
+        return Ok(());
+    }
+
+    let unicode = FILTER_UNICODE.load(Ordering::Acquire);
+    let current = if unicode {
+        unsafe { GetWindowLongPtrW(window, GWL_WNDPROC) }
+    } else {
+        unsafe { GetWindowLongPtrA(window, GWL_WNDPROC) }
+    };
+    if current as u32 as usize != filter_address() {
+        return Err(Hook86Error::InvalidHook {
+            name: FILTER_NAME.to_string(),
+            reason: "the window procedure was replaced again after the filter was installed",
+        });
+    }
+
+    unsafe { set_wndproc(window, original, unicode) }?;
+    ORIGINAL_WNDPROC.store(0, Ordering::Release);
+
+    Ok(())
+}
+
+unsafe extern "system" fn get_keyboard_state(state: *mut u8) -> BOOL {
+    let original: GetKeyboardStateFn = unsafe { std::mem::transmute(ORIGINAL_GET_KEYBOARD_STATE.load(Ordering::Acquire)) };
+    let result = unsafe { original(state) };
+    if result.as_bool() && !state.is_null() {
+        clear_blocked_keys(unsafe { &mut *(state as *mut [u8; 256]) });
+    }
+
+    result
+}
+
+unsafe extern "system" fn get_async_key_state(key: i32) -> i16 {
+    if is_key_blocked(VIRTUAL_KEY(key as u16)) {
+        return 0;
+    }
+
+    let original: GetKeyStateFn = unsafe { std::mem::transmute(ORIGINAL_GET_ASYNC_KEY_STATE.load(Ordering::Acquire)) };
+    unsafe { original(key) }
+}
+
+unsafe extern "system" fn get_key_state(key: i32) -> i16 {
+    let original: GetKeyStateFn = unsafe { std::mem::transmute(ORIGINAL_GET_KEY_STATE.load(Ordering::Acquire)) };
+    let state = unsafe { original(key) };
+    if is_key_blocked(VIRTUAL_KEY(key as u16)) {
+        // keep the toggle bit so caps lock and friends still read correctly
+        return state & 1;
+    }
+
+    state
+}
+
+/// Hook a module's imports of `GetKeyboardState`, `GetAsyncKeyState`, and `GetKeyState` so
+/// blocked keys read as released
+///
+/// `module` is the base address of the module whose calls should be filtered, usually the game's
+/// executable. The hooks are registered with the `HookManager`. Returns the number of import
+/// slots that were hooked, which is zero if the module doesn't import any of the functions
+/// directly (e.g. if it resolves them with `GetProcAddress`).
+///
+/// # Safety
+///
+/// `module` must point to the start of a module mapped by the loader (i.e. an `HMODULE`). Its
+/// import slots are patched while the game may be calling through them.
+///
+/// # Errors
+///
+/// Returns an error if the module's import table can't be read or a slot couldn't be patched.
+pub unsafe fn install_import_hooks(module: *const c_void) -> Result<usize> {
+    let detours: [(&str, &AtomicUsize, usize); 3] = [
+        (
+            "GetKeyboardState",
+            &ORIGINAL_GET_KEYBOARD_STATE,
+            get_keyboard_state as GetKeyboardStateFn as usize,
+        ),
+        (
+            "GetAsyncKeyState",
+            &ORIGINAL_GET_ASYNC_KEY_STATE,
+            get_async_key_state as GetKeyStateFn as usize,
+        ),
+        ("GetKeyState", &ORIGINAL_GET_KEY_STATE, get_key_state as GetKeyStateFn as usize),
+    ];
+
+    let mut num_hooked = 0;
+    for import in unsafe { pe::imports(module) }? {
+        let Some(&(function, original, detour)) = detours.iter().find(|(function, _, _)| import.is_named(function)) else {
+            continue;
+        };
+        if import.value as usize == detour {
+            continue;
+        }
+
+        // every module's import resolves to the same function in user32
+        original.store(import.value as usize, Ordering::Release);
+        let name = format!("hook86 input block {} {:p}", function, import.slot);
+        unsafe { HookManager::global().apply(&name, import.slot as *const c_void, &detour.to_le_bytes()) }?;
+        num_hooked += 1;
+    }
+
+    Ok(num_hooked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocking() {
+        block_key(VK_F1);
+        block_key(VK_LBUTTON);
+        assert!(should_block_message(WM_KEYDOWN, WPARAM(VK_F1.0 as usize)));
+        assert!(!should_block_message(WM_KEYUP, WPARAM(VK_F1.0 as usize)));
+        assert!(!should_block_message(WM_KEYDOWN, WPARAM(VK_F2.0 as usize)));
+        assert!(should_block_message(WM_LBUTTONDBLCLK, WPARAM(0)));
+        assert!(!should_block_message(WM_CHAR, WPARAM(b'a' as usize)));
+
+        // characters follow the key press they came from
+        block_key(VK_A);
+        assert!(should_block_message(WM_KEYDOWN, WPARAM(VK_A.0 as usize)));
+        assert!(should_block_message(WM_CHAR, WPARAM(b'a' as usize)));
+        assert!(!should_block_message(WM_KEYUP, WPARAM(VK_A.0 as usize)));
+        assert!(!should_block_message(WM_KEYDOWN, WPARAM(VK_B.0 as usize)));
+        assert!(!should_block_message(WM_CHAR, WPARAM(b'b' as usize)));
+
+        let mut state = [0u8; 256];
+        state[VK_F1.0 as usize] = 0x81;
+        state[VK_F2.0 as usize] = 0x80;
+        clear_blocked_keys(&mut state);
+        assert_eq!((state[VK_F1.0 as usize], state[VK_F2.0 as usize]), (0x01, 0x80));
+
+        set_block_all(true);
+        assert!(should_block_message(WM_CHAR, WPARAM(b'a' as usize)));
+        assert!(should_block_message(WM_XBUTTONDOWN, WPARAM((XBUTTON2 as usize) << 16)));
+        set_block_all(false);
+
+        unblock_all_keys();
+        assert!(!should_block_message(WM_KEYDOWN, WPARAM(VK_F1.0 as usize)));
+    }
+}
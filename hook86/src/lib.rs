@@ -1,6 +1,50 @@
+pub mod address;
 pub mod asm;
+pub mod cache;
+mod callbacks;
+pub mod debug;
+pub mod entry;
+pub mod error;
+pub mod file_redirect;
+pub mod fingerprint;
+pub mod game_thread;
+pub mod hook;
+pub mod inject;
 pub mod input;
+pub mod input_block;
+pub mod loader;
 pub mod mem;
 pub mod patch;
+pub mod patch_file;
+pub mod pattern;
+pub mod pe;
+pub mod scan;
+pub mod signature;
+pub mod snapshot;
+pub mod string_patch;
+pub mod timeline;
+pub mod variadic;
 #[cfg(feature = "crash_logging")]
 pub mod crash;
+#[cfg(feature = "d3d8")]
+pub mod d3d8;
+#[cfg(feature = "d3d9")]
+pub mod d3d9;
+#[cfg(feature = "ddraw")]
+pub mod ddraw;
+#[cfg(feature = "dinput")]
+pub mod dinput;
+#[cfg(feature = "xinput")]
+pub mod gamepad;
+#[cfg(feature = "file_logging")]
+pub mod logger;
+#[cfg(feature = "opengl")]
+pub mod opengl;
+#[cfg(feature = "patch_files")]
+pub mod patch_set;
+
+pub use error::{Hook86Error, Result};
+
+// lets the proc macros' `hook86::` paths resolve in this crate's own tests
+#[cfg(test)]
+extern crate self as hook86;
@@ -0,0 +1,144 @@
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex, RwLock};
+
+use windows::Win32::Foundation::{NTSTATUS, UNICODE_STRING};
+use windows::Win32::System::Threading::{GetCurrentThreadId, CRITICAL_SECTION};
+
+use crate::error::Result;
+
+const LDR_DLL_NOTIFICATION_REASON_LOADED: u32 = 1;
+const LDR_DLL_NOTIFICATION_REASON_UNLOADED: u32 = 2;
+/// The offset of `LoaderLock` in the 32-bit PEB
+const PEB_LOADER_LOCK_OFFSET: usize = 0xA0;
+
+#[repr(C)]
+struct LdrDllNotificationData {
+    flags: u32,
+    full_dll_name: *const UNICODE_STRING,
+    base_dll_name: *const UNICODE_STRING,
+    dll_base: *const c_void,
+    size_of_image: u32,
+}
+
+type LdrDllNotificationFunction = unsafe extern "system" fn(u32, *const LdrDllNotificationData, *const c_void);
+
+windows::core::link!("ntdll.dll" "system" fn LdrRegisterDllNotification(flags: u32, notification_function: LdrDllNotificationFunction, context: *const c_void, cookie: *mut *mut c_void) -> NTSTATUS);
+
+/// Whether a module was loaded or unloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleEventKind {
+    Loaded,
+    Unloaded,
+}
+
+/// A module being loaded into or unloaded from the process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleEvent {
+    pub kind: ModuleEventKind,
+    /// The file name of the module, e.g. "d3d9.dll"
+    pub name: String,
+    /// The full path of the module
+    pub path: String,
+    /// The address the module is loaded at
+    pub base: *const c_void,
+    /// The size of the module image in memory
+    pub size: usize,
+}
+
+type Listener = Arc<dyn Fn(&ModuleEvent) + Send + Sync>;
+
+static LISTENERS: RwLock<Vec<Listener>> = RwLock::new(Vec::new());
+/// The cookie for our loader notification, or `None` if it hasn't been registered yet
+static NOTIFICATION_COOKIE: Mutex<Option<usize>> = Mutex::new(None);
+
+fn unicode_string_to_string(string: *const UNICODE_STRING) -> String {
+    let Some(string) = (unsafe { string.as_ref() }) else {
+        return String::new();
+    };
+
+    if string.Buffer.is_null() {
+        return String::new();
+    }
+
+    let chars = unsafe { std::slice::from_raw_parts(string.Buffer.0, string.Length as usize / 2) };
+    String::from_utf16_lossy(chars)
+}
+
+unsafe extern "system" fn dll_notification(reason: u32, data: *const LdrDllNotificationData, _context: *const c_void) {
+    let kind = match reason {
+        LDR_DLL_NOTIFICATION_REASON_LOADED => ModuleEventKind::Loaded,
+        LDR_DLL_NOTIFICATION_REASON_UNLOADED => ModuleEventKind::Unloaded,
+        _ => return,
+    };
+    let Some(data) = (unsafe { data.as_ref() }) else {
+        return;
+    };
+
+    let event = ModuleEvent {
+        kind,
+        name: unicode_string_to_string(data.base_dll_name),
+        path: unicode_string_to_string(data.full_dll_name),
+        base: data.dll_base,
+        size: data.size_of_image as usize,
+    };
+
+    // copy the list so that listeners can register other listeners without deadlocking
+    let listeners = LISTENERS.read().unwrap_or_else(|e| e.into_inner()).clone();
+    for listener in listeners {
+        listener(&event);
+    }
+}
+
+/// Check whether the current thread holds the loader lock, e.g. because it's running `DllMain` or
+/// a module listener
+///
+/// A thread started while the lock is held can't run until the lock is released, so a thread
+/// holding it must not wait for another thread to start or finish.
+pub fn holds_loader_lock() -> bool {
+    let peb: *const u8;
+    // the PEB's address is always at fs:[0x30] on 32-bit Windows
+    unsafe { std::arch::asm!("mov {}, dword ptr fs:[0x30]", out(reg) peb, options(nostack, readonly, preserves_flags)) };
+    let lock = unsafe { *(peb.add(PEB_LOADER_LOCK_OFFSET) as *const *const CRITICAL_SECTION) };
+    // a critical section's owner is recorded by thread ID
+    let owner = unsafe { std::ptr::addr_of!((*lock).OwningThread).read_volatile() };
+    owner.0 as usize == unsafe { GetCurrentThreadId() } as usize
+}
+
+/// Call a function whenever a module is loaded or unloaded
+///
+/// Listeners are called in the order they were added. They're called by the loader while it holds
+/// the loader lock, so they must not load libraries, wait on other threads, or do anything else
+/// that could need the lock. Scanning the newly-loaded module's memory is fine.
+///
+/// # Errors
+///
+/// Returns an error if the loader notification couldn't be registered.
+pub fn add_listener(listener: impl Fn(&ModuleEvent) + Send + Sync + 'static) -> Result<()> {
+    {
+        // hold the lock while registering so that two threads adding the first listener don't
+        // both register a notification and deliver every event twice
+        let mut registered = NOTIFICATION_COOKIE.lock().unwrap_or_else(|e| e.into_inner());
+        if registered.is_none() {
+            let mut cookie = std::ptr::null_mut();
+            unsafe { LdrRegisterDllNotification(0, dll_notification, std::ptr::null(), &mut cookie) }.ok()?;
+            *registered = Some(cookie as usize);
+        }
+    }
+
+    LISTENERS.write().unwrap_or_else(|e| e.into_inner()).push(Arc::new(listener));
+    Ok(())
+}
+
+/// Call a function whenever the named module is loaded
+///
+/// The name is compared case-insensitively against the module's file name. This is meant for
+/// deferring work, such as signature scans, until a module the mod depends on appears; the same
+/// restrictions apply as for `add_listener`.
+pub fn on_load(module: &str, callback: impl Fn(&ModuleEvent) + Send + Sync + 'static) -> Result<()> {
+    let module = module.to_lowercase();
+    add_listener(move |event| {
+        if event.kind == ModuleEventKind::Loaded && event.name.to_lowercase() == module {
+            callback(event);
+        }
+    })
+}
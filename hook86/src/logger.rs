@@ -0,0 +1,264 @@
+use std::ffi::c_void;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HMODULE;
+use windows::Win32::System::LibraryLoader::{
+    GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+use windows::Win32::System::SystemInformation::GetLocalTime;
+use windows::Win32::System::Threading::GetCurrentThreadId;
+
+use crate::error::{Hook86Error, Result};
+use crate::pe;
+
+/// Settings for the file logger
+#[derive(Debug, Clone)]
+pub struct FileLoggerConfig {
+    /// The file to log to
+    ///
+    /// If `None`, the log is written next to the module containing hook86 and named after it, e.g.
+    /// `mymod.log` for `mymod.dll`.
+    pub path: Option<PathBuf>,
+    /// The most verbose level that's logged
+    pub level: LevelFilter,
+    /// Records at this level or more severe are flushed to disk immediately
+    ///
+    /// Everything else is buffered until the buffer fills, a severe record is logged, or the
+    /// logger is flushed (which the crash loggers do before the process dies).
+    pub flush_level: Level,
+    /// The size in bytes at which the log file is rotated
+    pub max_size: u64,
+    /// The number of rotated files to keep, e.g. `mymod.1.log` through `mymod.3.log`
+    ///
+    /// With zero, the log is truncated when it reaches `max_size`.
+    pub max_files: usize,
+}
+
+impl Default for FileLoggerConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            level: LevelFilter::Info,
+            flush_level: Level::Warn,
+            max_size: 5 * 1024 * 1024,
+            max_files: 3,
+        }
+    }
+}
+
+/// Get the path of the `index`th rotated copy of a log file, e.g. `mymod.2.log` for `mymod.log`
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", index));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    path.with_file_name(name)
+}
+
+/// Get a log path in the same directory as the module containing hook86
+fn path_next_to_module() -> Result<PathBuf> {
+    let mut module = HMODULE::default();
+    unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR::from_raw(path_next_to_module as *const u16),
+            &mut module,
+        )
+    }?;
+
+    Ok(pe::module_path(module.0 as *const c_void)?.with_extension("log"))
+}
+
+/// The open log file and its rotation state
+struct LogFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    /// Move the current log to the first rotated slot, shifting older ones down, and start a new one
+    ///
+    /// If the current log can't be moved (e.g. another program has it open without
+    /// `FILE_SHARE_DELETE`), nothing is changed, so this can simply be tried again later.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.max_files > 0 {
+            // move the current log out of the way first, so a failure doesn't shift older logs
+            // along on every retry until they fall off the end. std opens files with
+            // FILE_SHARE_DELETE, so this works even though our handle is open.
+            let rotating = rotated_path(&self.path, 0);
+            fs::rename(&self.path, &rotating)?;
+            // missing files are expected until the log has rotated max_files times
+            for index in (1..self.max_files).rev() {
+                let _ = fs::rename(rotated_path(&self.path, index), rotated_path(&self.path, index + 1));
+            }
+            fs::rename(&rotating, rotated_path(&self.path, 1))?;
+        }
+
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            // losing log lines is worse than an oversized log, so if rotating fails, keep writing
+            // to the current file and try again on the next line
+            let _ = self.rotate();
+        }
+
+        self.writer.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// A `log` implementation that writes to a size-rotated file
+///
+/// Records are written as `2026-01-02 03:04:05.678 [INFO] [1234] target: message`, where 1234 is
+/// the ID of the logging thread.
+pub struct FileLogger {
+    level: LevelFilter,
+    flush_level: Level,
+    file: Mutex<LogFile>,
+}
+
+impl FileLogger {
+    /// Open the log file and create a logger for it without installing it
+    pub fn new(config: FileLoggerConfig) -> Result<Self> {
+        let path = match config.path {
+            Some(path) => path,
+            None => path_next_to_module()?,
+        };
+
+        Ok(Self {
+            level: config.level,
+            flush_level: config.flush_level,
+            file: Mutex::new(LogFile::open(path, config.max_size, config.max_files)?),
+        })
+    }
+
+    /// The path of the current log file
+    pub fn path(&self) -> PathBuf {
+        self.file.lock().unwrap_or_else(|e| e.into_inner()).path.clone()
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let time = unsafe { GetLocalTime() };
+        let line = format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} [{}] [{}] {}: {}\n",
+            time.wYear,
+            time.wMonth,
+            time.wDay,
+            time.wHour,
+            time.wMinute,
+            time.wSecond,
+            time.wMilliseconds,
+            record.level(),
+            unsafe { GetCurrentThreadId() },
+            record.target(),
+            record.args()
+        );
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        // there's nowhere to report a failure to log
+        let _ = file.write_line(&line);
+        if record.level() <= self.flush_level {
+            let _ = file.writer.flush();
+        }
+    }
+
+    fn flush(&self) {
+        // this is called from the crash loggers, possibly on a thread that crashed while holding the
+        // lock, so don't wait for it
+        let mut file = match self.file.try_lock() {
+            Ok(file) => file,
+            Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return,
+        };
+        let _ = file.writer.flush();
+    }
+}
+
+/// Install a `FileLogger` as the global logger
+///
+/// # Errors
+///
+/// Returns an error if the log file can't be opened or another logger has already been installed.
+pub fn init(config: FileLoggerConfig) -> Result<()> {
+    let level = config.level;
+    let logger = FileLogger::new(config)?;
+    log::set_logger(Box::leak(Box::new(logger))).map_err(|_| Hook86Error::LoggerAlreadySet)?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_names() {
+        assert_eq!(rotated_path(Path::new(r"C:\Games\mymod.log"), 1), Path::new(r"C:\Games\mymod.1.log"));
+        assert_eq!(rotated_path(Path::new("mymod.dll.log"), 3), Path::new("mymod.dll.3.log"));
+        assert_eq!(rotated_path(Path::new("mymod"), 2), Path::new("mymod.2"));
+    }
+
+    #[test]
+    fn rotation() -> io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("hook86-logger-test-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("mymod.log");
+        let read = |index| fs::read_to_string(if index == 0 { path.clone() } else { rotated_path(&path, index) });
+
+        let mut log = LogFile::open(path.clone(), 8, 2)?;
+        for line in ["one\n", "two\n", "three\n", "four\n"] {
+            log.write_line(line)?;
+        }
+        log.writer.flush()?;
+        drop(log);
+
+        assert_eq!(read(0)?, "four\n");
+        assert_eq!(read(1)?, "three\n");
+        assert_eq!(read(2)?, "one\ntwo\n");
+        assert!(!rotated_path(&path, 0).exists() && !rotated_path(&path, 3).exists());
+
+        fs::remove_dir_all(&dir)
+    }
+}
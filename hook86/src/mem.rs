@@ -1,27 +1,52 @@
 use std::ffi::c_void;
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write as _};
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use memchr::memmem;
-use windows::core::{PWSTR, Result};
-use windows::Win32::Foundation::{HMODULE, MAX_PATH};
-use windows::Win32::System::Memory::{VirtualProtect, VirtualQuery, MEMORY_BASIC_INFORMATION, MEM_COMMIT, PAGE_PROTECTION_FLAGS,
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{HANDLE, HMODULE, MAX_PATH};
+use windows::Win32::System::Diagnostics::Debug::{ReadProcessMemory, IMAGE_NT_HEADERS32, IMAGE_SECTION_HEADER};
+use windows::Win32::System::Memory::{VirtualProtect, VirtualQuery, VirtualQueryEx, MEMORY_BASIC_INFORMATION, MEM_COMMIT, PAGE_GUARD, PAGE_NOACCESS, PAGE_PROTECTION_FLAGS,
                                      PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY,
                                      PAGE_READWRITE, PAGE_WRITECOPY, PAGE_READONLY};
 use windows::Win32::System::ProcessStatus::{
-    EnumProcessModules, GetModuleBaseNameW, GetModuleInformation, MODULEINFO,
+    EnumProcessModulesEx, GetModuleBaseNameW, GetModuleInformation, LIST_MODULES_ALL, MODULEINFO,
 };
+use windows::Win32::System::SystemServices::IMAGE_DOS_HEADER;
 use windows::Win32::System::Threading::GetCurrentProcess;
 
+use crate::asm::{self, CodeReference, INT3};
+use crate::cache::{self, ModuleKey};
+use crate::error::{Hook86Error, Result};
+use crate::loader::{self, ModuleEventKind};
+use crate::pattern::{Pattern, PatternSet};
+use crate::pe::{self, Import};
+use crate::signature::{self, Signature};
+use crate::timeline;
+
+pub use hook86_macro::game_struct;
+
 // currently we only support 32-bit x86, but I'd like to keep the flexibility to support x64 in the
 // future, so we'll use this type alias and maybe change it to a usize once we're ready to support
 // both architectures.
 pub type IntPtr = u32;
 pub const PTR_SIZE: usize = size_of::<IntPtr>();
+/// The size of a memory page
+pub const PAGE_SIZE: usize = 0x1000;
 
 /// The set of all protection flags that allow reading from the protected memory
 pub const READABLE_PROTECTION: PAGE_PROTECTION_FLAGS =
     PAGE_PROTECTION_FLAGS(PAGE_EXECUTE_READ.0 | PAGE_READONLY.0 | PAGE_READWRITE.0 | PAGE_WRITECOPY.0 | PAGE_EXECUTE_WRITECOPY.0 | PAGE_EXECUTE_READWRITE.0);
 
+/// The set of all protection flags that allow writing to the protected memory
+pub const WRITABLE_PROTECTION: PAGE_PROTECTION_FLAGS =
+    PAGE_PROTECTION_FLAGS(PAGE_READWRITE.0 | PAGE_WRITECOPY.0 | PAGE_EXECUTE_WRITECOPY.0 | PAGE_EXECUTE_READWRITE.0);
+
 /// Make a memory region readable, writable, and executable
 pub fn unprotect(ptr: *const c_void, size: usize) -> Result<PAGE_PROTECTION_FLAGS> {
     let mut old_protect = PAGE_PROTECTION_FLAGS::default();
@@ -33,7 +58,9 @@ pub fn unprotect(ptr: *const c_void, size: usize) -> Result<PAGE_PROTECTION_FLAG
 /// Set the memory protection on a memory region
 pub fn protect(ptr: *const c_void, size: usize, protection: PAGE_PROTECTION_FLAGS) -> Result<()> {
     let mut old_protect = PAGE_PROTECTION_FLAGS::default();
-    unsafe { VirtualProtect(ptr, size, protection, &mut old_protect) }
+    unsafe { VirtualProtect(ptr, size, protection, &mut old_protect) }?;
+
+    Ok(())
 }
 
 /// Write the given data to the specified address within a protected memory region
@@ -41,254 +68,1943 @@ pub fn protect(ptr: *const c_void, size: usize, protection: PAGE_PROTECTION_FLAG
 /// The region containing the address will be unprotected prior to the write. After writing, the
 /// original protection will be restored.
 pub unsafe fn patch(addr: *const c_void, data: &[u8]) -> Result<()> {
+    let _phase = timeline::phase("patch");
+    #[cfg(feature = "tracing")]
+    tracing::debug!(address = ?addr, bytes = data.len(), "patching memory");
     let old_protect = unprotect(addr, data.len())?;
     unsafe { std::slice::from_raw_parts_mut(addr as *mut u8, data.len()).copy_from_slice(data) };
     protect(addr, data.len(), old_protect)
 }
 
+/// Fill a range of code with int3 instructions
+///
+/// This is useful for disabling dead code: unlike a nop sled, any accidental execution of the
+/// range will immediately raise a breakpoint exception.
+///
+/// # Safety
+///
+/// No thread may be executing the range or about to return into it, unless the point is for it to
+/// hit the breakpoint.
+pub unsafe fn int3_range(addr: *const c_void, len: usize) -> Result<()> {
+    unsafe { patch(addr, &vec![INT3; len]) }
+}
+
+/// Check that the bytes at the specified address match what we expect
+///
+/// This is intended to be called before patching to confirm that the game version is the one the
+/// patch was written for.
+///
+/// # Safety
+///
+/// `expected.len()` bytes starting at `addr` must be readable.
+///
+/// # Errors
+///
+/// A `VerificationMismatch` error is returned if the bytes at `addr` differ from `expected`.
+pub unsafe fn verify(addr: *const c_void, expected: &[u8]) -> Result<()> {
+    let actual = unsafe { std::slice::from_raw_parts(addr as *const u8, expected.len()) };
+    if actual != expected {
+        return Err(Hook86Error::VerificationMismatch {
+            ptr: addr as usize,
+            expected: expected.to_vec(),
+            actual: actual.to_vec(),
+        });
+    }
+
+    Ok(())
+}
+
+/// A batch of writes to protected memory that unprotects each page only once
+///
+/// Applying many small patches with `patch` changes the protection of the same pages over and over.
+/// A `ProtectSession` instead unprotects each page the first time it's written to and restores the
+/// original protection of every touched page when the session is finished (or dropped).
+#[derive(Debug, Default)]
+pub struct ProtectSession {
+    pages: BTreeMap<usize, PAGE_PROTECTION_FLAGS>,
+}
+
+impl ProtectSession {
+    /// Create a new, empty session
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the pages containing the given memory region writable for the rest of the session
+    pub fn unprotect(&mut self, ptr: *const c_void, size: usize) -> Result<()> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        let first_page = ptr as usize & !(PAGE_SIZE - 1);
+        let last_page = (ptr as usize + size - 1) & !(PAGE_SIZE - 1);
+        for page in (first_page..=last_page).step_by(PAGE_SIZE) {
+            if self.pages.contains_key(&page) {
+                continue;
+            }
+
+            let old_protect = unprotect(page as *const c_void, PAGE_SIZE)?;
+            self.pages.insert(page, old_protect);
+        }
+
+        Ok(())
+    }
+
+    /// Write the given data to the specified address within a protected memory region
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the memory in a way that conflicts with the write, e.g. executing
+    /// code that's partially overwritten. The pages stay writable until the session ends.
+    pub unsafe fn patch(&mut self, addr: *const c_void, data: &[u8]) -> Result<()> {
+        self.unprotect(addr, data.len())?;
+        unsafe { std::slice::from_raw_parts_mut(addr as *mut u8, data.len()).copy_from_slice(data) };
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        // restore runs of adjacent pages that had the same protection with a single call
+        let mut run: Option<(usize, usize, PAGE_PROTECTION_FLAGS)> = None;
+        for (page, protection) in std::mem::take(&mut self.pages) {
+            match run {
+                Some((start, end, run_protection)) if end == page && run_protection == protection => {
+                    run = Some((start, end + PAGE_SIZE, protection));
+                    continue;
+                }
+                Some((start, end, run_protection)) => {
+                    let restored = protect(start as *const c_void, end - start, run_protection);
+                    result = result.and(restored);
+                }
+                None => (),
+            }
+
+            run = Some((page, page + PAGE_SIZE, protection));
+        }
+
+        if let Some((start, end, run_protection)) = run {
+            result = result.and(protect(start as *const c_void, end - start, run_protection));
+        }
+
+        result
+    }
+
+    /// Restore the original protection of every page touched during the session
+    ///
+    /// Dropping the session has the same effect, but any errors are discarded.
+    pub fn finish(mut self) -> Result<()> {
+        self.restore()
+    }
+}
+
+impl Drop for ProtectSession {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+/// The sections searched for string literals
+const DATA_SECTIONS: [&str; 2] = [".rdata", ".data"];
+
+/// The number of bytes to scan between checks that a region is still readable
+pub(crate) const SCAN_CHUNK_SIZE: usize = 0x10000;
+
+/// Check whether a memory region can be scanned under the given protection filter
+///
+/// Guard pages and no-access pages are never scannable, even if the filter includes those flags:
+/// reading a guard page raises an exception and clears the guard, which would break the stack
+/// growth or any other guard-based mechanism that relies on it.
+fn is_scannable(memory_info: &MEMORY_BASIC_INFORMATION, protection: PAGE_PROTECTION_FLAGS) -> bool {
+    memory_info.State == MEM_COMMIT
+        && (memory_info.Protect & (PAGE_GUARD | PAGE_NOACCESS)).0 == 0
+        && protection.contains(memory_info.Protect)
+}
+
+/// Check that a memory region is committed and has one of the given protections in its entirety
+fn has_protection(ptr: *const c_void, size: usize, protection: PAGE_PROTECTION_FLAGS) -> bool {
+    let mut memory_info = MEMORY_BASIC_INFORMATION::default();
+    let result = unsafe { VirtualQuery(Some(ptr), &mut memory_info, size_of_val(&memory_info)) };
+    if result == 0 || !is_scannable(&memory_info, protection) {
+        return false;
+    }
+
+    let region_end = memory_info.BaseAddress as usize + memory_info.RegionSize;
+    ptr as usize + size <= region_end
+}
+
+/// Check that a memory region is still committed and readable in its entirety
+pub(crate) fn is_readable(ptr: *const c_void, size: usize) -> bool {
+    has_protection(ptr, size, READABLE_PROTECTION)
+}
+
+/// Read a value of type `T` from the specified address
+///
+/// The address doesn't need to be aligned for `T`. The memory is checked before reading, so a bad
+/// address produces an error rather than an access violation.
+///
+/// # Safety
+///
+/// The bytes at the address must be a valid `T`. This is always the case for integers and floats,
+/// but not for types like `bool`, enums, or references.
+///
+/// # Errors
+///
+/// Returns `Hook86Error::UnreadableMemory` if any part of the value isn't readable.
+pub unsafe fn read<T: Copy>(addr: *const c_void) -> Result<T> {
+    let size = size_of::<T>();
+    if !is_readable(addr, size) {
+        return Err(Hook86Error::UnreadableMemory { ptr: addr as usize, size });
+    }
+
+    Ok(unsafe { (addr as *const T).read_unaligned() })
+}
+
+/// Follow a chain of pointers starting from a base address
+///
+/// For each offset, the pointer at the current address is read and the offset is added to it, so
+/// `follow(base, &[0x10, 0x8])` is the address `[[base] + 0x10] + 0x8`. Every pointer is checked
+/// before it's used, which makes this safe to call on chains that are only valid some of the
+/// time, such as ones leading into an object that doesn't exist until a level is loaded.
+///
+/// # Errors
+///
+/// Returns `Hook86Error::BrokenPointerChain` identifying the first hop whose pointer was unreadable
+/// or null.
+pub fn follow(base: *const c_void, offsets: &[usize]) -> Result<*const c_void> {
+    let mut address = base;
+    for (hop, &offset) in offsets.iter().enumerate() {
+        // IntPtr is valid for any bit pattern
+        let Ok(pointer) = (unsafe { read::<IntPtr>(address) }) else {
+            return Err(Hook86Error::BrokenPointerChain {
+                hop,
+                address: address as usize,
+                reason: "pointer is not readable",
+            });
+        };
+        if pointer == 0 {
+            return Err(Hook86Error::BrokenPointerChain {
+                hop,
+                address: address as usize,
+                reason: "pointer is null",
+            });
+        }
+
+        address = (pointer as usize as *const c_void).wrapping_byte_add(offset);
+    }
+
+    Ok(address)
+}
+
+/// Write a value of type `T` to the specified address
+///
+/// The address doesn't need to be aligned for `T`. If the memory isn't writable, it's unprotected
+/// for the duration of the write and its original protection is restored afterwards, so this
+/// works for code and read-only data as well as for game state.
+///
+/// # Safety
+///
+/// Nothing else may be using the memory in a way that conflicts with the write, e.g. executing
+/// code that's partially overwritten.
+///
+/// # Errors
+///
+/// Returns `Hook86Error::UnreadableMemory` if the memory isn't committed, or a Windows error if
+/// the memory's protection couldn't be changed.
+pub unsafe fn write<T: Copy>(addr: *const c_void, value: T) -> Result<()> {
+    let size = size_of::<T>();
+    if has_protection(addr, size, WRITABLE_PROTECTION) {
+        unsafe { (addr as *mut T).write_unaligned(value) };
+        return Ok(());
+    }
+
+    if !is_readable(addr, size) {
+        return Err(Hook86Error::UnreadableMemory { ptr: addr as usize, size });
+    }
+
+    let old_protect = unprotect(addr, size)?;
+    unsafe { (addr as *mut T).write_unaligned(value) };
+    protect(addr, size, old_protect)
+}
+
+/// How a scan reads the memory it searches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ScanMode {
+    /// Search memory in place. This is the fastest mode, but if another thread decommits or
+    /// protects a region between the readability check and the search, the scan will crash.
+    #[default]
+    Direct,
+    /// Copy each chunk into a scratch buffer with `ReadProcessMemory` before searching it. A chunk
+    /// that becomes unreadable mid-scan results in a short or failed copy instead of an access
+    /// violation, at the cost of an extra copy of everything scanned.
+    Copy,
+}
+
+static COPY_SCANNING: AtomicBool = AtomicBool::new(false);
+
+/// Set how subsequent scans read memory
+///
+/// This applies to every scan in the process. Use `ScanMode::Copy` when scanning regions that other
+/// threads may be freeing, such as heaps, while the game is running.
+pub fn set_scan_mode(mode: ScanMode) {
+    COPY_SCANNING.store(mode == ScanMode::Copy, Ordering::Relaxed);
+}
+
+/// Get the current scan mode
+pub fn scan_mode() -> ScanMode {
+    if COPY_SCANNING.load(Ordering::Relaxed) {
+        ScanMode::Copy
+    } else {
+        ScanMode::Direct
+    }
+}
+
+/// The process whose memory is being scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Process(HANDLE);
+
+// a process handle can be used from any thread
+unsafe impl Send for Process {}
+unsafe impl Sync for Process {}
+
+impl Process {
+    fn current() -> Self {
+        Self(unsafe { GetCurrentProcess() })
+    }
+
+    fn is_current(&self) -> bool {
+        *self == Self::current()
+    }
+
+    /// Get information about the memory region containing `ptr`
+    fn query(&self, ptr: *const c_void) -> Option<MEMORY_BASIC_INFORMATION> {
+        let mut memory_info = MEMORY_BASIC_INFORMATION::default();
+        let result = unsafe { VirtualQueryEx(self.0, Some(ptr), &mut memory_info, size_of_val(&memory_info)) };
+        (result != 0).then_some(memory_info)
+    }
+
+    /// Copy as much of a memory region as possible into `buffer`, returning the number of bytes
+    /// copied
+    ///
+    /// The copy stops at the first byte that can't be read, so the result is less than the buffer
+    /// length if part of the region was decommitted or protected.
+    fn read(&self, ptr: *const u8, buffer: &mut [u8]) -> usize {
+        let mut bytes_read = 0;
+        let result = unsafe {
+            ReadProcessMemory(
+                self.0,
+                ptr as *const c_void,
+                buffer.as_mut_ptr() as *mut c_void,
+                buffer.len(),
+                Some(&mut bytes_read),
+            )
+        };
+
+        // on a partial copy, ReadProcessMemory fails but still reports how much it copied
+        match result {
+            Ok(()) => buffer.len(),
+            Err(_) => cmp::min(bytes_read, buffer.len()),
+        }
+    }
+}
+
+/// A callback that receives the running totals of a scan
+type ProgressCallback = dyn Fn(&ScanStats) + Send + Sync;
+
+/// Everything a scan needs to know besides what to search for and where
+#[derive(Clone)]
+struct ScanContext {
+    process: Process,
+    progress: Option<Arc<ProgressCallback>>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl ScanContext {
+    fn new(process: Process) -> Self {
+        Self {
+            process,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    fn current() -> Self {
+        Self::new(Process::current())
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    fn report(&self, stats: &ScanStats) {
+        if let Some(ref progress) = self.progress {
+            progress(stats);
+        }
+    }
+}
+
+impl fmt::Debug for ScanContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanContext")
+            .field("process", &self.process)
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+/// Call `chunk_func` on successive chunks of a memory region, checking that each chunk is still
+/// readable before handing it out
+///
+/// Consecutive chunks overlap by `overlap` bytes so that matches straddling a chunk boundary
+/// aren't missed. `chunk_func` receives the chunk, the address of the chunk in memory, and a flag
+/// indicating whether it's the final chunk, and returns true to stop early. Returns true if
+/// `chunk_func` asked to stop. In `ScanMode::Copy`, or when scanning another process, the chunk is
+/// a copy, so `chunk_func` must use the address it's given rather than the address of the chunk
+/// slice. If the scan is cancelled, this stops before the next chunk and returns true.
+fn for_each_chunk(
+    context: &ScanContext,
+    search_base: *const u8,
+    region_size: usize,
+    overlap: usize,
+    stats: &mut ScanStats,
+    mut chunk_func: impl FnMut(&[u8], *const u8, bool) -> bool,
+) -> bool {
+    // another process's memory can only be read by copying it
+    let mut scratch = if scan_mode() == ScanMode::Copy || !context.process.is_current() {
+        vec![0u8; cmp::min(SCAN_CHUNK_SIZE + overlap, region_size)]
+    } else {
+        Vec::new()
+    };
+
+    let mut offset = 0;
+    while offset < region_size {
+        if context.is_cancelled() {
+            stats.cancelled = true;
+            return true;
+        }
+
+        let chunk_base = unsafe { search_base.add(offset) };
+        let chunk_size = cmp::min(SCAN_CHUNK_SIZE + overlap, region_size - offset);
+        let chunk = if scratch.is_empty() {
+            if !is_readable(chunk_base as *const c_void, chunk_size) {
+                stats.regions_skipped += 1;
+                stats.bytes_skipped += region_size - offset;
+                return false;
+            }
+
+            unsafe { std::slice::from_raw_parts(chunk_base, chunk_size) }
+        } else {
+            let copied = context.process.read(chunk_base, &mut scratch[..chunk_size]);
+            if copied < chunk_size {
+                // search what we managed to copy, then give up on the rest of the region
+                stats.partial_reads += 1;
+                stats.regions_skipped += 1;
+                let scanned = cmp::min(SCAN_CHUNK_SIZE, copied);
+                stats.bytes_scanned += scanned;
+                stats.bytes_skipped += region_size - offset - scanned;
+                return copied > 0 && chunk_func(&scratch[..copied], chunk_base, true);
+            }
+
+            &scratch[..chunk_size]
+        };
+
+        let is_last = region_size - offset <= SCAN_CHUNK_SIZE;
+        stats.bytes_scanned += cmp::min(SCAN_CHUNK_SIZE, region_size - offset);
+        if chunk_func(chunk, chunk_base, is_last) {
+            return true;
+        }
+
+        offset += SCAN_CHUNK_SIZE;
+    }
+
+    stats.regions_scanned += 1;
+    false
+}
+
+/// The longest pattern length minus one, i.e. how far chunks need to overlap to find every match
+fn chunk_overlap(patterns: &PatternSet) -> usize {
+    patterns.max_len().saturating_sub(1)
+}
+
+/// Describe a pattern and protection filter for the resolved address cache
+///
+/// Pattern searches aren't named by their callers, so they're cached under a description of
+/// everything that affects where they match, e.g. `pattern E8 ?? ?? ?? ?? align 1`.
+fn pattern_cache_id(pattern: &Pattern, protection: Option<PAGE_PROTECTION_FLAGS>) -> String {
+    let mut id = String::from("pattern");
+    for (i, &byte) in pattern.bytes().iter().enumerate() {
+        let _ = match pattern.mask().map_or(0xFF, |mask| mask[i]) {
+            0xFF => write!(id, " {:02X}", byte),
+            0x00 => write!(id, " ??"),
+            mask => write!(id, " {:02X}&{:02X}", byte & mask, mask),
+        };
+    }
+    let _ = write!(id, " align {}", pattern.alignment());
+    if let Some(protection) = protection {
+        let _ = write!(id, " protect {:X}", protection.0);
+    }
+
+    id
+}
+
+/// Statistics about the memory regions examined during a scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// The number of regions that were scanned in full
+    pub regions_scanned: usize,
+    /// The number of regions that became unreadable partway through the scan and were skipped
+    pub regions_skipped: usize,
+    /// The number of bytes that were scanned
+    pub bytes_scanned: usize,
+    /// The number of bytes that were skipped because their region became unreadable
+    pub bytes_skipped: usize,
+    /// The number of chunks that could only be partly copied in `ScanMode::Copy`
+    ///
+    /// The part that was copied is still searched; the rest of the region is counted as skipped.
+    pub partial_reads: usize,
+    /// Whether the scan was cancelled before it finished
+    pub cancelled: bool,
+}
+
+impl ScanStats {
+    /// Emit a tracing event summarizing a finished scan
+    #[cfg(feature = "tracing")]
+    fn trace(&self, start: Instant) {
+        tracing::debug!(
+            regions_scanned = self.regions_scanned,
+            regions_skipped = self.regions_skipped,
+            bytes_scanned = self.bytes_scanned,
+            bytes_skipped = self.bytes_skipped,
+            cancelled = self.cancelled,
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "scan finished"
+        );
+    }
+}
+
+impl AddAssign for ScanStats {
+    fn add_assign(&mut self, other: Self) {
+        self.regions_scanned += other.regions_scanned;
+        self.regions_skipped += other.regions_skipped;
+        self.bytes_scanned += other.bytes_scanned;
+        self.bytes_skipped += other.bytes_skipped;
+        self.partial_reads += other.partial_reads;
+        self.cancelled |= other.cancelled;
+    }
+}
+
+/// Iterate over the committed memory regions in the given address ranges whose protection matches
+/// the filter, yielding the address to start searching at and the size of the region
+///
+/// Guard and no-access regions are always skipped (see `is_scannable`).
+fn regions_in_ranges<'a>(
+    process: Process,
+    protection: PAGE_PROTECTION_FLAGS,
+    ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+) -> impl Iterator<Item = (*const u8, usize)> {
+    ranges.flat_map(move |&(start, end)| {
+        let mut addr = start;
+        std::iter::from_fn(move || {
+            while addr < end {
+                let memory_info = process.query(addr)?;
+
+                let search_base = addr as *const u8;
+                addr = unsafe { memory_info.BaseAddress.add(memory_info.RegionSize) };
+
+                if !is_scannable(&memory_info, protection) {
+                    continue;
+                }
+
+                return Some((search_base, memory_info.RegionSize));
+            }
+
+            None
+        })
+    })
+}
+
+/// Iterate over the committed memory regions of the current process in the given address ranges
+/// whose protection matches the filter
+pub(crate) fn readable_regions<'a>(
+    protection: PAGE_PROTECTION_FLAGS,
+    ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+) -> impl Iterator<Item = (*const u8, usize)> {
+    regions_in_ranges(Process::current(), protection, ranges)
+}
+
+/// Copy as much of a memory region of the current process as possible into `buffer`, returning
+/// the number of bytes copied
+pub(crate) fn copy_memory(ptr: *const u8, buffer: &mut [u8]) -> usize {
+    Process::current().read(ptr, buffer)
+}
+
+/// Running totals for a single pattern during a diagnostic scan
+#[derive(Debug, Clone, Copy, Default)]
+struct PatternTally {
+    first: Option<*const c_void>,
+    region: Option<(*const c_void, *const c_void)>,
+    match_count: usize,
+    elapsed: Duration,
+}
+
+/// Diagnostic details about the search for a single pattern
+#[derive(Debug, Clone)]
+pub struct PatternReport {
+    /// The address of the first match, if any
+    pub address: Option<*const c_void>,
+    /// The (start, end) addresses of the memory region containing the first match
+    pub region: Option<(*const c_void, *const c_void)>,
+    /// The name of the module containing the first match, if it's in a discovered module
+    pub module: Option<String>,
+    /// The total number of matches found
+    pub match_count: usize,
+    /// The time spent searching for this pattern
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for PatternReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.address, self.region) {
+            (Some(address), Some((start, end))) => {
+                write!(f, "found at {:08X}", address as usize)?;
+                if let Some(ref module) = self.module {
+                    write!(f, " in {}", module)?;
+                }
+                write!(f, " (region {:08X}-{:08X})", start as usize, end as usize)?;
+            }
+            _ => write!(f, "not found")?,
+        }
+
+        write!(
+            f,
+            "; {} match(es); {:.3}ms",
+            self.match_count,
+            self.elapsed.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// The results of a diagnostic scan for multiple patterns
+#[derive(Debug, Clone)]
+pub struct ScanReport<const N: usize> {
+    /// Details for each pattern, in the order the patterns were provided
+    pub patterns: [PatternReport; N],
+    /// Statistics about the memory that was scanned
+    pub stats: ScanStats,
+}
+
+impl<const N: usize> ScanReport<N> {
+    /// The address of the first match of each pattern, as returned by `find_bytes`
+    pub fn addresses(&self) -> [Option<*const c_void>; N] {
+        std::array::from_fn(|i| self.patterns[i].address)
+    }
+}
+
+/// An address expressed as an offset from the base of the module containing it
+///
+/// The module name is owned rather than borrowed from the searcher, so a `ModuleOffset` can be
+/// kept, logged later, or converted to an `Rva` while `enable_auto_discovery` updates the module
+/// map underneath it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleOffset {
+    /// The lowercase name of the module
+    pub module: String,
+    /// The offset of the address from the module base
+    pub offset: usize,
+}
+
+impl fmt::Display for ModuleOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:X}", self.module, self.offset)
+    }
+}
+
+/// The (start, end) addresses of discovered modules, keyed by lowercase module name
+#[derive(Debug, Default)]
+struct ModuleMap(HashMap<String, (*const c_void, *const c_void)>);
+
+// the map only records where modules are; it never dereferences the addresses
+unsafe impl Send for ModuleMap {}
+unsafe impl Sync for ModuleMap {}
+
 /// A utility for searching for byte strings in memory
 ///
 /// The ByteSearcher can search for multiple strings at one time. Searches can be filtered by the
 /// protection level of the memory region and/or the module that the memory region was loaded from.
 /// Filtering by module requires first calling the discover_modules() method to enumerate the
 /// modules loaded in the current process.
-#[derive(Debug)]
+///
+/// Only committed memory is searched, and pages marked `PAGE_GUARD` or `PAGE_NOACCESS` are always
+/// skipped regardless of the protection filter, so a scan never trips a thread's stack guard page
+/// or any other guard page the game relies on.
+///
+/// Clones of a ByteSearcher share the same module list, so a clone can be moved into a callback
+/// (e.g. one registered with `loader::on_load`) and see modules discovered through the original.
+///
+/// A ByteSearcher created with `for_process` searches another process instead, which lets an
+/// external tool locate signatures before injecting anything. The associated functions that
+/// search explicit address ranges (e.g. `find_bytes_in_ranges`) always search the current process.
+#[derive(Debug, Clone)]
 pub struct ByteSearcher {
-    modules: HashMap<String, (*const c_void, *const c_void)>,
+    modules: Arc<RwLock<ModuleMap>>,
+    context: ScanContext,
 }
 
 impl ByteSearcher {
-    /// Create a new ByteSearcher
+    /// Create a new ByteSearcher for the current process
     pub fn new() -> Self {
         Self {
-            modules: HashMap::new(),
+            modules: Arc::new(RwLock::new(ModuleMap::default())),
+            context: ScanContext::current(),
+        }
+    }
+
+    /// Create a new ByteSearcher for another process
+    ///
+    /// The handle needs `PROCESS_QUERY_INFORMATION` and `PROCESS_VM_READ` access, and must stay
+    /// open for as long as the searcher is used; the searcher doesn't close it. Memory is always
+    /// copied out of the other process before it's searched, regardless of the scan mode, and every
+    /// address the searcher returns is an address in the other process.
+    pub fn for_process(process: HANDLE) -> Self {
+        Self {
+            modules: Arc::new(RwLock::new(ModuleMap::default())),
+            context: ScanContext::new(Process(process)),
+        }
+    }
+
+    /// Call a function with the running totals of each scan after every memory region
+    ///
+    /// This is meant for showing progress during long scans of the whole address space. In
+    /// multi-threaded scans, the callback is called from the worker threads.
+    pub fn with_progress(mut self, callback: impl Fn(&ScanStats) + Send + Sync + 'static) -> Self {
+        self.context.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Stop scans early once `cancel` is set
+    ///
+    /// The flag is checked between chunks of memory, so a scan stops shortly after it's set.
+    /// A cancelled scan returns whatever it found before it stopped, and the `cancelled` flag of
+    /// its `ScanStats` is set for the methods that report statistics. The flag isn't cleared
+    /// automatically; clear it before starting another scan.
+    pub fn with_cancel_token(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.context.cancel = Some(cancel);
+        self
+    }
+
+    fn modules(&self) -> RwLockReadGuard<'_, ModuleMap> {
+        self.modules.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn search_in_ranges<'a, T>(
+        context: &ScanContext,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        results: &mut [T],
+        search_func: impl Fn(*const u8, usize, &mut [T], &mut ScanStats) -> bool,
+    ) -> ScanStats {
+        let _phase = timeline::phase("scan");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("scan").entered();
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        // if no specific protection filter was requested, set the filter to be only readable memory
+        let protection = protection.unwrap_or(READABLE_PROTECTION);
+
+        let mut stats = ScanStats::default();
+        for (search_base, region_size) in regions_in_ranges(context.process, protection, ranges) {
+            if context.is_cancelled() {
+                stats.cancelled = true;
+                break;
+            }
+
+            let done = search_func(search_base, region_size, results, &mut stats);
+            context.report(&stats);
+            if done {
+                // if search_func returns true, we've found everything we were looking for (or
+                // the scan was cancelled)
+                break;
+            }
         }
+
+        #[cfg(feature = "tracing")]
+        stats.trace(start);
+        stats
+    }
+
+    /// Search for masked patterns in a range of addresses, splitting the work across threads
+    ///
+    /// The matching regions are enumerated up front and dealt out to `threads` worker threads. Each
+    /// worker finds the first match of every pattern in its share of the regions, and the match
+    /// from the earliest region wins, so the results are the same as a single-threaded search.
+    fn search_patterns_in_ranges_parallel<'a>(
+        context: &ScanContext,
+        patterns: &PatternSet,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        threads: usize,
+        addresses: &mut [Option<*const c_void>],
+    ) -> ScanStats {
+        let _phase = timeline::phase("parallel scan");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parallel scan", threads).entered();
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let protection = protection.unwrap_or(READABLE_PROTECTION);
+        // raw pointers can't be sent between threads, so the workers get plain addresses
+        let regions: Vec<_> = regions_in_ranges(context.process, protection, ranges)
+            .map(|(base, size)| (base as usize, size))
+            .collect();
+        let regions = &regions;
+        let overlap = chunk_overlap(patterns);
+        // running totals across all workers for progress reporting
+        let progress = &Mutex::new(ScanStats::default());
+
+        let results: Vec<_> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    scope.spawn(move || {
+                        // (region index, address) of the first match of each pattern
+                        let mut found: Vec<Option<(usize, usize)>> = vec![None; patterns.len()];
+                        let mut stats = ScanStats::default();
+                        for (index, &(base, size)) in regions.iter().enumerate().skip(worker).step_by(threads) {
+                            let mut region_stats = ScanStats::default();
+                            let done = for_each_chunk(context, base as *const u8, size, overlap, &mut region_stats, |chunk, chunk_base, _| {
+                                for (i, slot) in found.iter_mut().enumerate().filter(|(_, f)| f.is_none()) {
+                                    if let Some(offset) = patterns.find(i, chunk, chunk_base as usize) {
+                                        *slot = Some((index, chunk_base as usize + offset));
+                                    }
+                                }
+
+                                found.iter().all(Option::is_some)
+                            });
+
+                            stats += region_stats;
+                            let total = {
+                                let mut total = progress.lock().unwrap_or_else(|e| e.into_inner());
+                                *total += region_stats;
+                                *total
+                            };
+                            context.report(&total);
+                            if done {
+                                break;
+                            }
+                        }
+
+                        (found, stats)
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+
+        let mut stats = ScanStats::default();
+        let mut earliest: Vec<Option<(usize, usize)>> = vec![None; patterns.len()];
+        for (found, worker_stats) in results {
+            stats += worker_stats;
+            for (best, found) in earliest.iter_mut().zip(found) {
+                if let Some(found) = found
+                    && best.is_none_or(|best| found.0 < best.0)
+                {
+                    *best = Some(found);
+                }
+            }
+        }
+
+        for (address, best) in addresses.iter_mut().zip(earliest) {
+            *address = best.map(|(_, address)| address as *const c_void);
+        }
+
+        #[cfg(feature = "tracing")]
+        stats.trace(start);
+        stats
+    }
+
+    fn search_patterns_in_ranges<'a>(
+        context: &ScanContext,
+        patterns: &PatternSet,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        addresses: &mut [Option<*const c_void>],
+    ) -> ScanStats {
+        let overlap = chunk_overlap(patterns);
+        Self::search_in_ranges(context, protection, ranges, addresses, |search_base, region_size, addresses, stats| {
+            for_each_chunk(context, search_base, region_size, overlap, stats, |chunk, chunk_base, _| {
+                for (i, address) in addresses.iter_mut().enumerate().filter(|(_, a)| a.is_none()) {
+                    if let Some(offset) = patterns.find(i, chunk, chunk_base as usize) {
+                        let found_address = unsafe { chunk_base.add(offset) } as *const c_void;
+                        *address = Some(found_address);
+                    }
+                }
+
+                addresses.iter().all(Option::is_some)
+            })
+        })
+    }
+
+    fn search_all_patterns_in_ranges<'a>(
+        context: &ScanContext,
+        patterns: &PatternSet,
+        limit: Option<usize>,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        matches: &mut [Vec<*const c_void>],
+    ) {
+        let limit = limit.unwrap_or(usize::MAX);
+        let overlap = chunk_overlap(patterns);
+        Self::search_in_ranges(context, protection, ranges, matches, |search_base, region_size, matches, stats| {
+            for_each_chunk(context, search_base, region_size, overlap, stats, |chunk, chunk_base, is_last| {
+                for (i, found) in matches.iter_mut().enumerate() {
+                    // matches starting in the overlap will be found again in the next chunk
+                    let offsets = patterns
+                        .find_iter(i, chunk, chunk_base as usize)
+                        .filter(|&o| is_last || o < SCAN_CHUNK_SIZE);
+                    let remaining = limit - found.len();
+                    found.extend(offsets.take(remaining).map(|o| unsafe { chunk_base.add(o) } as *const c_void));
+                }
+
+                matches.iter().all(|found| found.len() >= limit)
+            })
+        });
+    }
+
+    fn search_addresses_in_ranges<'a>(
+        context: &ScanContext,
+        addresses: &[usize],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        flags: &mut [bool],
+    ) {
+        Self::search_in_ranges(context, protection, ranges, flags, |search_base, region_size, flags, stats| {
+            stats.regions_scanned += 1;
+            for (&address, flag) in addresses
+                .iter()
+                .zip(flags.iter_mut())
+                .filter(|(_, f)| !**f)
+            {
+                let start = search_base as usize;
+                let end = start + region_size;
+                if address >= start && address < end {
+                    *flag = true;
+                }
+            }
+
+            flags.iter().all(|&f| f)
+        });
+    }
+
+    /// Search for byte strings in a range of addresses
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The byte strings to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    ///
+    /// # Return
+    ///
+    /// An array of `Option<*const c_void>` with the same number of elements as the `patterns` argument.
+    /// If the corresponding byte string was found, the value will be `Some(ptr)`, where `ptr` is a
+    /// pointer to the location where the byte string was found. If the byte string was not found,
+    /// the element in the return array will be `None`.
+    pub fn find_bytes_in_ranges<'a, const N: usize>(
+        patterns: &[&[u8]; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> [Option<*const c_void>; N] {
+        Self::find_bytes_in_ranges_with_stats(patterns, protection, ranges).0
+    }
+
+    /// Search for byte strings in a range of addresses, reporting statistics about the scan
+    ///
+    /// Memory can be decommitted or reprotected by other threads while we're scanning it. Each
+    /// region is read in chunks, and each chunk's protection is checked again immediately before
+    /// it's read; if the chunk is no longer readable, the rest of the region is skipped and counted
+    /// in the returned `ScanStats` rather than faulting.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The byte strings to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    ///
+    /// # Return
+    ///
+    /// The same array as `find_bytes_in_ranges`, along with statistics about the regions that
+    /// were scanned and skipped.
+    pub fn find_bytes_in_ranges_with_stats<'a, const N: usize>(
+        patterns: &[&[u8]; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> ([Option<*const c_void>; N], ScanStats) {
+        Self::find_patterns_in_ranges_with_stats(&patterns.map(Pattern::exact), protection, ranges)
+    }
+
+    /// Search for masked patterns in a range of addresses
+    ///
+    /// Works like `find_bytes_in_ranges`, but each pattern can contain wildcard bytes.
+    pub fn find_patterns_in_ranges<'a, const N: usize>(
+        patterns: &[Pattern; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> [Option<*const c_void>; N] {
+        Self::find_patterns_in_ranges_with_stats(patterns, protection, ranges).0
+    }
+
+    /// Search for masked patterns in a range of addresses, reporting statistics about the scan
+    ///
+    /// See `find_bytes_in_ranges_with_stats` for details.
+    pub fn find_patterns_in_ranges_with_stats<'a, const N: usize>(
+        patterns: &[Pattern; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> ([Option<*const c_void>; N], ScanStats) {
+        let patterns = PatternSet::new(patterns.iter().cloned());
+        let mut addresses = [None; N];
+        let stats = Self::search_patterns_in_ranges(&ScanContext::current(), &patterns, protection, ranges, &mut addresses);
+        (addresses, stats)
+    }
+
+    /// Search for every occurrence of byte strings in a range of addresses
+    ///
+    /// Works like `find_all_patterns_in_ranges` with exact patterns.
+    pub fn find_all_bytes_in_ranges<'a, const N: usize>(
+        patterns: &[&[u8]; N],
+        limit: Option<usize>,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> [Vec<*const c_void>; N] {
+        Self::find_all_patterns_in_ranges(&patterns.map(Pattern::exact), limit, protection, ranges)
+    }
+
+    /// Search for every occurrence of masked patterns in a range of addresses
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The patterns to search for
+    /// * `limit` - If provided, stop collecting matches for a pattern once it has this many
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    ///
+    /// # Return
+    ///
+    /// An array with the same number of elements as the `patterns` argument. Each element is a list
+    /// of the addresses where the corresponding pattern was found, in the order they were found.
+    /// Overlapping matches are included.
+    pub fn find_all_patterns_in_ranges<'a, const N: usize>(
+        patterns: &[Pattern; N],
+        limit: Option<usize>,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> [Vec<*const c_void>; N] {
+        let patterns = PatternSet::new(patterns.iter().cloned());
+        let mut matches = std::array::from_fn(|_| Vec::new());
+        Self::search_all_patterns_in_ranges(&ScanContext::current(), &patterns, limit, protection, ranges, &mut matches);
+        matches
+    }
+
+    /// Check if the given addresses are found within the provided memory regions with the specified
+    /// protection flags
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The addresses to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    ///
+    /// # Return
+    ///
+    /// An array of `bool` with the same number of elements as the `addresses` argument. Each element
+    /// in the return will be true if the corresponding address was found or false if it wasn't.
+    pub fn find_addresses_in_ranges<'a, const N: usize>(
+        addresses: &[usize; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> [bool; N] {
+        let mut flags = [false; N];
+        Self::search_addresses_in_ranges(&ScanContext::current(), addresses, protection, ranges, &mut flags);
+        flags
+    }
+
+    /// Enumerate the modules loaded in the process being searched
+    ///
+    /// This method must be called once prior to attempting any searches that filter by module.
+    pub fn discover_modules(&mut self) -> Result<()> {
+        let _phase = timeline::phase("module discovery");
+
+        // build a fresh module list in case we need to discover modules multiple times (e.g.
+        // dynamic DLL load)
+        let mut discovered = HashMap::new();
+        let mut modules = [HMODULE::default(); 1024];
+        let mut bytes_needed = 0;
+        let hproc = self.context.process.0;
+        unsafe {
+            EnumProcessModulesEx(
+                hproc,
+                modules.as_mut_ptr(),
+                size_of_val(&modules) as u32,
+                &mut bytes_needed,
+                LIST_MODULES_ALL,
+            )
+        }?;
+
+        let num_modules =
+            std::cmp::min(bytes_needed as usize / size_of::<HMODULE>(), modules.len());
+        for &module in &modules[..num_modules] {
+            let mut name_utf16 = [0; MAX_PATH as usize];
+            let module_name = unsafe {
+                let num_chars = GetModuleBaseNameW(hproc, Some(module), &mut name_utf16) as usize;
+                if num_chars == 0 || num_chars >= name_utf16.len() {
+                    continue;
+                }
+
+                match PWSTR::from_raw(name_utf16.as_mut_ptr()).to_string() {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                }
+            }
+                .to_lowercase();
+
+            let mut module_info = MODULEINFO::default();
+            unsafe {
+                GetModuleInformation(
+                    hproc,
+                    module,
+                    &mut module_info,
+                    size_of_val(&module_info) as u32,
+                )?;
+                let base = module_info.lpBaseOfDll as *const c_void;
+                discovered.insert(
+                    module_name,
+                    (base, base.add(module_info.SizeOfImage as usize)),
+                );
+            }
+        }
+
+        self.modules.write().unwrap_or_else(|e| e.into_inner()).0 = discovered;
+        Ok(())
+    }
+
+    /// Keep the module list up to date as modules are loaded and unloaded
+    ///
+    /// This registers a loader notification (see `loader::add_listener`) that adds modules to the
+    /// list when they're loaded and removes them when they're unloaded, so `discover_modules`
+    /// doesn't need to be called again when the game loads plugins or renderer DLLs at runtime.
+    /// It should still be called once to pick up the modules that are already loaded. Listeners
+    /// registered after this one will see the updated list.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the loader notification couldn't be registered or if the searcher is
+    /// for another process, since loader notifications are only delivered in-process.
+    pub fn enable_auto_discovery(&self) -> Result<()> {
+        if !self.context.process.is_current() {
+            return Err(Hook86Error::RemoteUnsupported { operation: "module auto-discovery" });
+        }
+
+        let modules = Arc::downgrade(&self.modules);
+        loader::add_listener(move |event| {
+            let Some(modules) = modules.upgrade() else {
+                return;
+            };
+
+            let mut modules = modules.write().unwrap_or_else(|e| e.into_inner());
+            match event.kind {
+                ModuleEventKind::Loaded => {
+                    modules.0.insert(event.name.to_lowercase(), (event.base, event.base.wrapping_byte_add(event.size)));
+                }
+                ModuleEventKind::Unloaded => modules.0.retain(|_, &mut (base, _)| base != event.base),
+            }
+        })
+    }
+
+    fn get_module_ranges(&self, modules: &[&str]) -> Vec<(*const c_void, *const c_void)> {
+        let discovered = self.modules();
+        modules
+            .iter()
+            .filter_map(|&module_name| discovered.0.get(&module_name.to_lowercase()).copied())
+            .collect()
+    }
+
+    fn search_patterns(
+        &self,
+        patterns: &PatternSet,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+        addresses: &mut [Option<*const c_void>],
+    ) -> ScanStats {
+        if !modules.is_empty() {
+            Self::search_patterns_in_ranges(&self.context, patterns, protection, self.get_module_ranges(modules).iter(), addresses)
+        } else {
+            // we'll use the standard page size as the minimum address
+            Self::search_patterns_in_ranges(
+                &self.context,
+                patterns,
+                protection,
+                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+                addresses,
+            )
+        }
+    }
+
+    /// Identify a discovered module for the resolved address cache
+    ///
+    /// The cache reads module headers directly, so it only covers the current process.
+    fn cache_key(&self, module: &str) -> Option<ModuleKey> {
+        if !self.context.process.is_current() {
+            return None;
+        }
+
+        let (base, _) = *self.modules().0.get(&module.to_lowercase())?;
+        unsafe { ModuleKey::of(base) }
+    }
+
+    /// Search for patterns like `search_patterns`, consulting the resolved address cache first
+    ///
+    /// The cache is only used when the search is limited to a single module, since the first
+    /// match across several modules (or all of memory) depends on what the other modules contain.
+    /// Patterns are cached under an id describing their contents and the protection filter. A
+    /// cached address is only used if the pattern still matches there; otherwise it's searched for
+    /// again.
+    fn search_patterns_cached(
+        &self,
+        patterns: &PatternSet,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+        addresses: &mut [Option<*const c_void>],
+    ) {
+        let key = match modules {
+            [module] => self.cache_key(module),
+            _ => None,
+        };
+        let Some(key) = key else {
+            self.search_patterns(patterns, protection, modules, addresses);
+            return;
+        };
+
+        let ids: Vec<_> = patterns.patterns().iter().map(|pattern| pattern_cache_id(pattern, protection)).collect();
+        let mut missing = Vec::new();
+        for (i, (pattern, id)) in patterns.patterns().iter().zip(&ids).enumerate() {
+            // the bytes at a cached address may have been patched since it was found
+            if let Some(addr) = cache::get(&key, id)
+                && self.pattern_matches_at(addr, pattern)
+            {
+                addresses[i] = Some(addr);
+                continue;
+            }
+
+            // an address saved by a previous run is only good if the pattern still matches there
+            if let Some(addr) = cache::get_persisted(&key, id)
+                && self.pattern_matches_at(addr, pattern)
+            {
+                cache::insert(&key, id, addr);
+                addresses[i] = Some(addr);
+                continue;
+            }
+
+            missing.push(i);
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        let remaining: PatternSet = missing.iter().map(|&i| patterns.patterns()[i].clone()).collect();
+        let mut found = vec![None; remaining.len()];
+        self.search_patterns(&remaining, protection, modules, &mut found);
+        for (i, addr) in missing.into_iter().zip(found) {
+            if let Some(addr) = addr {
+                cache::insert(&key, &ids[i], addr);
+            }
+            addresses[i] = addr;
+        }
+    }
+
+    fn search_addresses(
+        &self,
+        addresses: &[usize],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+        flags: &mut [bool],
+    ) {
+        if !modules.is_empty() {
+            Self::search_addresses_in_ranges(&self.context, addresses, protection, self.get_module_ranges(modules).iter(), flags);
+        } else {
+            // we'll use the standard page size as the minimum address
+            Self::search_addresses_in_ranges(
+                &self.context,
+                addresses,
+                protection,
+                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+                flags,
+            );
+        }
+    }
+
+    /// Check whether the bytes at `address` in the process being searched are exactly `expected`
+    fn matches_at(&self, address: *const c_void, expected: &[u8]) -> bool {
+        let mut actual = vec![0u8; expected.len()];
+        self.context.process.read(address as *const u8, &mut actual) == expected.len() && actual == expected
+    }
+
+    /// Check whether `pattern` matches at `address` in the process being searched
+    fn pattern_matches_at(&self, address: *const c_void, pattern: &Pattern) -> bool {
+        let mut actual = vec![0u8; pattern.len()];
+        (address as usize).is_multiple_of(pattern.alignment())
+            && self.context.process.read(address as *const u8, &mut actual) == pattern.len()
+            && pattern.matches(&actual)
+    }
+
+    /// Get the sections of the module loaded at `base` in the process being searched
+    fn sections(&self, base: *const c_void) -> Option<Vec<pe::Section>> {
+        if self.context.process.is_current() {
+            return unsafe { pe::sections(base) }.ok();
+        }
+
+        // copy the headers out of the other process and parse them here. the headers nearly always
+        // fit in the first page; u32 elements keep the copy aligned for the header structs.
+        let mut headers = vec![0u32; PAGE_SIZE / size_of::<u32>()];
+        let header_bytes = unsafe { std::slice::from_raw_parts_mut(headers.as_mut_ptr() as *mut u8, PAGE_SIZE) };
+        if self.context.process.read(base as *const u8, header_bytes) < PAGE_SIZE {
+            return None;
+        }
+
+        let local_base = headers.as_ptr() as *const c_void;
+        let nt_offset = unsafe { (*(local_base as *const IMAGE_DOS_HEADER)).e_lfanew } as usize;
+        if nt_offset > PAGE_SIZE - size_of::<IMAGE_NT_HEADERS32>() {
+            return None;
+        }
+
+        let nt_headers = unsafe { pe::nt_headers(local_base) }.ok()?;
+        let file_header = unsafe { &(*nt_headers).FileHeader };
+        let section_table_end = nt_offset
+            + size_of::<u32>()
+            + size_of_val(file_header)
+            + file_header.SizeOfOptionalHeader as usize
+            + file_header.NumberOfSections as usize * size_of::<IMAGE_SECTION_HEADER>();
+        if section_table_end > PAGE_SIZE {
+            return None;
+        }
+
+        let sections = unsafe { pe::sections(local_base) }.ok()?;
+        let rebase = |address: *const c_void| base.wrapping_byte_add(address as usize - local_base as usize);
+        Some(
+            sections
+                .into_iter()
+                .map(|section| pe::Section {
+                    start: rebase(section.start),
+                    end: rebase(section.end),
+                    ..section
+                })
+                .collect(),
+        )
+    }
+
+    /// Get the address ranges of the named sections of discovered modules
+    ///
+    /// If `modules` is empty, the sections of every discovered module are returned. Modules that
+    /// haven't been discovered and sections that don't exist are skipped.
+    pub fn section_ranges(&self, modules: &[&str], sections: &[&str]) -> Vec<(*const c_void, *const c_void)> {
+        let bases: Vec<_> = if modules.is_empty() {
+            self.modules().0.values().map(|&(base, _)| base).collect()
+        } else {
+            self.get_module_ranges(modules).into_iter().map(|(base, _)| base).collect()
+        };
+
+        bases
+            .into_iter()
+            .filter_map(|base| self.sections(base))
+            .flatten()
+            .filter(|section| sections.contains(&section.name.as_str()))
+            .map(|section| (section.start, section.end))
+            .collect()
+    }
+
+    /// Search for byte strings anywhere in process memory
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The byte strings to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    ///
+    /// # Return
+    ///
+    /// An array of `Option<*const c_void>` with the same number of elements as the `patterns` argument.
+    /// If the corresponding byte string was found, the value will be `Some(ptr)`, where `ptr` is a
+    /// pointer to the location where the byte string was found. If the byte string was not found,
+    /// the element in the return array will be `None`.
+    pub fn find_bytes_anywhere<const N: usize>(
+        patterns: &[&[u8]; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+    ) -> [Option<*const c_void>; N] {
+        // we'll use the standard page size as the minimum address
+        Self::find_bytes_in_ranges(
+            patterns,
+            protection,
+            [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+        )
+    }
+
+    /// Search for byte strings in process memory
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The byte strings to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `modules` - If not empty, only search memory regions belonging to the specified modules
+    ///
+    /// # Return
+    ///
+    /// An array of `Option<*const c_void>` with the same number of elements as the `patterns` argument.
+    /// If the corresponding byte string was found, the value will be `Some(ptr)`, where `ptr` is a
+    /// pointer to the location where the byte string was found. If the byte string was not found,
+    /// the element in the return array will be `None`.
+    pub fn find_bytes<const N: usize, const M: usize>(
+        &self,
+        patterns: &[&[u8]; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+    ) -> [Option<*const c_void>; N] {
+        self.find_patterns(&patterns.map(Pattern::exact), protection, modules)
+    }
+
+    /// Search for masked patterns in process memory
+    ///
+    /// Works like `find_bytes`, but each pattern can contain wildcard bytes. When `modules` names a
+    /// single module of the current process, matches are recorded in the resolved address cache
+    /// (see `resolve`), so searching for the same pattern again doesn't rescan the module.
+    pub fn find_patterns<const N: usize, const M: usize>(
+        &self,
+        patterns: &[Pattern; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+    ) -> [Option<*const c_void>; N] {
+        let mut addresses = [None; N];
+        self.search_patterns_cached(&PatternSet::new(patterns.iter().cloned()), protection, modules, &mut addresses);
+        addresses
+    }
+
+    /// Search for every occurrence of byte strings in process memory
+    ///
+    /// Works like `find_all_patterns` with exact patterns.
+    pub fn find_all_bytes<const N: usize, const M: usize>(
+        &self,
+        patterns: &[&[u8]; N],
+        limit: Option<usize>,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+    ) -> [Vec<*const c_void>; N] {
+        self.find_all_patterns(&patterns.map(Pattern::exact), limit, protection, modules)
+    }
+
+    /// Search for every occurrence of masked patterns in process memory
+    ///
+    /// Unlike `find_patterns`, this keeps scanning after the first match, which is useful for
+    /// signatures that legitimately occur at several call sites that all need patching.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The patterns to search for
+    /// * `limit` - If provided, stop collecting matches for a pattern once it has this many
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `modules` - If not empty, only search memory regions belonging to the specified modules
+    ///
+    /// # Return
+    ///
+    /// An array with the same number of elements as the `patterns` argument. Each element is a list
+    /// of the addresses where the corresponding pattern was found.
+    pub fn find_all_patterns<const N: usize, const M: usize>(
+        &self,
+        patterns: &[Pattern; N],
+        limit: Option<usize>,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+    ) -> [Vec<*const c_void>; N] {
+        let patterns = PatternSet::new(patterns.iter().cloned());
+        let mut matches = std::array::from_fn(|_| Vec::new());
+        if M > 0 {
+            Self::search_all_patterns_in_ranges(&self.context, &patterns, limit, protection, self.get_module_ranges(modules).iter(), &mut matches);
+        } else {
+            // we'll use the standard page size as the minimum address
+            Self::search_all_patterns_in_ranges(
+                &self.context,
+                &patterns,
+                limit,
+                protection,
+                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+                &mut matches,
+            );
+        }
+
+        matches
+    }
+
+    /// Search for signatures in process memory and resolve their matches
+    ///
+    /// Each signature's pattern is searched for like `find_patterns`, including its use of the
+    /// resolved address cache, and the signature's steps are then applied to the match, so a
+    /// signature on a call site can resolve directly to the callee. Only the pattern matches are
+    /// cached; the steps are applied again on each call.
+    ///
+    /// # Arguments
+    ///
+    /// * `signatures` - The signatures to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `modules` - If not empty, only search memory regions belonging to the specified modules
+    ///
+    /// # Return
+    ///
+    /// An array of `Option<*const c_void>` with the same number of elements as the `signatures`
+    /// argument. An element is `None` if the signature's pattern wasn't found or its steps couldn't
+    /// be applied to the match. When searching another process, steps read that process's memory.
+    pub fn find_signatures<const N: usize, const M: usize>(
+        &self,
+        signatures: &[Signature; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+    ) -> [Option<*const c_void>; N] {
+        let patterns = signatures.each_ref().map(|signature| signature.pattern.clone());
+        let addresses = self.find_patterns(&patterns, protection, modules);
+        let process = self.context.process;
+        let mut read = |ptr: *const c_void, buffer: &mut [u8]| process.read(ptr as *const u8, buffer) == buffer.len();
+        std::array::from_fn(|i| {
+            let address = addresses[i]?;
+            if process.is_current() {
+                unsafe { signatures[i].resolve(address) }.ok()
+            } else {
+                signature::resolve_with(address, &signatures[i].steps, &mut read).ok()
+            }
+        })
+    }
+
+    /// Search for byte strings in process memory using multiple threads
+    ///
+    /// Works like `find_patterns_parallel` with exact patterns.
+    pub fn find_bytes_parallel<const N: usize, const M: usize>(
+        &self,
+        patterns: &[&[u8]; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+        threads: Option<usize>,
+    ) -> [Option<*const c_void>; N] {
+        self.find_patterns_parallel(&patterns.map(Pattern::exact), protection, modules, threads)
+    }
+
+    /// Search for masked patterns in process memory using multiple threads
+    ///
+    /// Returns the same results as `find_patterns`, but the memory regions to search are split
+    /// across a pool of threads. This is mainly useful for scans of the entire address space of a
+    /// large process, where a single-threaded scan can noticeably delay startup.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The patterns to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `modules` - If not empty, only search memory regions belonging to the specified modules
+    /// * `threads` - The number of threads to use; defaults to the available parallelism
+    ///
+    /// Worker threads can't start while the calling thread holds the loader lock, so waiting for
+    /// them from `DllMain` or a module listener would deadlock. In that case, the search runs on
+    /// the calling thread instead, like `find_patterns` without the resolved address cache.
+    pub fn find_patterns_parallel<const N: usize, const M: usize>(
+        &self,
+        patterns: &[Pattern; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+        threads: Option<usize>,
+    ) -> [Option<*const c_void>; N] {
+        let threads = threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .max(1);
+        let patterns = PatternSet::new(patterns.iter().cloned());
+        let mut addresses = [None; N];
+        if loader::holds_loader_lock() {
+            self.search_patterns(&patterns, protection, modules, &mut addresses);
+        } else if M > 0 {
+            Self::search_patterns_in_ranges_parallel(&self.context, &patterns, protection, self.get_module_ranges(modules).iter(), threads, &mut addresses);
+        } else {
+            // we'll use the standard page size as the minimum address
+            Self::search_patterns_in_ranges_parallel(
+                &self.context,
+                &patterns,
+                protection,
+                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+                threads,
+                &mut addresses,
+            );
+        }
+
+        addresses
+    }
+
+    /// Find the occurrence of a byte string closest to an address
+    ///
+    /// Works like `find_nearest_pattern` with an exact pattern.
+    pub fn find_nearest_bytes<const M: usize>(
+        &self,
+        pattern: &[u8],
+        near: *const c_void,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+    ) -> Option<*const c_void> {
+        self.find_nearest_pattern(&Pattern::exact(pattern), near, protection, modules)
     }
 
-    fn search_in_ranges<'a, T: Default + Copy, const N: usize>(
+    /// Find the occurrence of a masked pattern closest to an address
+    ///
+    /// When a pattern matches in several places and you know roughly where the one you want is
+    /// (e.g. near a function you've already resolved), this picks the match with the smallest
+    /// distance to `near`, in either direction, rather than the first one in memory order. The
+    /// entire search space is scanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to search for
+    /// * `near` - The address to measure distance from
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `modules` - If not empty, only search memory regions belonging to the specified modules
+    ///
+    /// # Return
+    ///
+    /// The address of the closest match, or `None` if the pattern wasn't found. If two matches are
+    /// equally close, the lower address is returned.
+    pub fn find_nearest_pattern<const M: usize>(
+        &self,
+        pattern: &Pattern,
+        near: *const c_void,
         protection: Option<PAGE_PROTECTION_FLAGS>,
-        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
-        search_func: impl Fn(*const u8, usize, &mut [T]) -> bool,
-    ) -> [T; N] {
-        // if no specific protection filter was requested, set the filter to be only readable memory
-        let protection = protection.unwrap_or(READABLE_PROTECTION);
-
-        let mut results = [Default::default(); N];
-        for &(start, end) in ranges {
-            let mut addr = start;
-            while addr < end {
-                let mut memory_info = MEMORY_BASIC_INFORMATION::default();
-                let result = unsafe {
-                    VirtualQuery(Some(addr), &mut memory_info, size_of_val(&memory_info))
-                };
-                if result == 0 {
-                    break;
-                }
+        modules: &[&str; M],
+    ) -> Option<*const c_void> {
+        let [matches] = self.find_all_patterns(std::array::from_ref(pattern), None, protection, modules);
+        matches
+            .into_iter()
+            .min_by_key(|&address| ((address as usize).abs_diff(near as usize), address as usize))
+    }
 
-                let search_base = addr as *const u8;
-                addr = unsafe { memory_info.BaseAddress.add(memory_info.RegionSize) };
+    /// Find code that refers to an address
+    ///
+    /// Scans executable memory for branches to `target` and 32-bit values equal to `target`. See
+    /// `asm::find_references` for exactly what's recognized and its caveats.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The address to find references to
+    /// * `modules` - If not empty, only search memory regions belonging to the specified modules
+    ///
+    /// # Return
+    ///
+    /// Every reference that was found, in the order it was found.
+    pub fn find_references<const M: usize>(&self, target: *const c_void, modules: &[&str; M]) -> Vec<CodeReference> {
+        let protection = Some(PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY);
+        // the longest reference we recognize is a 6-byte conditional jump
+        let overlap = 5;
+        let context = &self.context;
+        let search_func = |search_base: *const u8, region_size: usize, references: &mut [Vec<CodeReference>], stats: &mut ScanStats| {
+            for_each_chunk(context, search_base, region_size, overlap, stats, |chunk, chunk_base, is_last| {
+                // references starting in the overlap will be found again in the next chunk
+                let found = asm::find_references(chunk, chunk_base as usize, target as usize)
+                    .filter(|r| is_last || r.address - (chunk_base as usize) < SCAN_CHUNK_SIZE);
+                references[0].extend(found);
+                false
+            });
 
-                if memory_info.State != MEM_COMMIT || !protection.contains(memory_info.Protect) {
-                    continue;
-                }
+            false
+        };
 
-                if search_func(search_base, memory_info.RegionSize, &mut results) {
-                    // if search_func returns true, we've found everything we were looking for
-                    return results;
-                }
-            }
+        let mut references = [Vec::new()];
+        if M > 0 {
+            Self::search_in_ranges(context, protection, self.get_module_ranges(modules).iter(), &mut references, search_func);
+        } else {
+            // we'll use the standard page size as the minimum address
+            Self::search_in_ranges(
+                context,
+                protection,
+                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+                &mut references,
+                search_func,
+            );
         }
 
-        results
+        let [references] = references;
+        references
     }
 
-    /// Search for byte strings in a range of addresses
+    /// Search for byte strings in specific sections of modules
+    ///
+    /// Works like `find_patterns_in_sections` with exact patterns.
+    pub fn find_bytes_in_sections<const N: usize, const M: usize, const S: usize>(
+        &self,
+        patterns: &[&[u8]; N],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str; M],
+        sections: &[&str; S],
+    ) -> [Option<*const c_void>; N] {
+        self.find_patterns_in_sections(&patterns.map(Pattern::exact), protection, modules, sections)
+    }
+
+    /// Search for masked patterns in specific sections of modules
+    ///
+    /// Protection filters can only tell code from data coarsely; restricting the search to named
+    /// sections (e.g. `.text` for code signatures, `.rdata` for vtables and strings) keeps
+    /// signatures from matching in the wrong kind of memory.
     ///
     /// # Arguments
     ///
-    /// * `patterns` - The byte strings to search for
+    /// * `patterns` - The patterns to search for
     /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
-    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    /// * `modules` - The modules to search in; if empty, all discovered modules are searched
+    /// * `sections` - The names of the sections to search in
     ///
     /// # Return
     ///
-    /// An array of `Option<*const c_void>` with the same number of elements as the `patterns` argument.
-    /// If the corresponding byte string was found, the value will be `Some(ptr)`, where `ptr` is a
-    /// pointer to the location where the byte string was found. If the byte string was not found,
-    /// the element in the return array will be `None`.
-    pub fn find_bytes_in_ranges<'a, const N: usize>(
-        patterns: &[&[u8]; N],
+    /// The same array as `find_patterns`.
+    pub fn find_patterns_in_sections<const N: usize, const M: usize, const S: usize>(
+        &self,
+        patterns: &[Pattern; N],
         protection: Option<PAGE_PROTECTION_FLAGS>,
-        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        modules: &[&str; M],
+        sections: &[&str; S],
     ) -> [Option<*const c_void>; N] {
-        Self::search_in_ranges(protection, ranges, |search_base, region_size, addresses: &mut [Option<*const c_void>]| {
-            let search_region =
-                unsafe { std::slice::from_raw_parts(search_base, region_size) };
-            for (&pattern, address) in patterns
-                .iter()
-                .zip(addresses.iter_mut())
-                .filter(|(_, a)| a.is_none())
-            {
-                if let Some(offset) = memmem::find(search_region, pattern) {
-                    let found_address = unsafe { search_base.add(offset) } as *const c_void;
-                    *address = Some(found_address);
-                }
-            }
-
-            addresses.iter().all(Option::is_some)
-        })
+        let ranges = self.section_ranges(modules, sections);
+        let patterns = PatternSet::new(patterns.iter().cloned());
+        let mut addresses = [None; N];
+        Self::search_patterns_in_ranges(&self.context, &patterns, protection, ranges.iter(), &mut addresses);
+        addresses
     }
 
-    /// Check if the given addresses are found within the provided memory regions with the specified
-    /// protection flags
+    /// Search for a narrow string in the data sections of modules
+    ///
+    /// The string is searched for as-is (i.e. as UTF-8, which is the same as ASCII for ASCII
+    /// strings) in the `.rdata` and `.data` sections, where compilers put string literals. Finding
+    /// the string is usually the first step toward finding the code that uses it; see
+    /// `find_references`.
     ///
     /// # Arguments
     ///
-    /// * `addresses` - The addresses to search for
-    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
-    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    /// * `string` - The string to search for
+    /// * `null_terminated` - Whether the string must be followed by a null terminator. This keeps
+    ///   the search from matching the start of a longer string.
+    /// * `modules` - The modules to search in; if empty, all discovered modules are searched
     ///
     /// # Return
     ///
-    /// An array of `bool` with the same number of elements as the `addresses` argument. Each element
-    /// in the return will be true if the corresponding address was found or false if it wasn't.
-    pub fn find_addresses_in_ranges<'a, const N: usize>(
-        addresses: &[usize; N],
-        protection: Option<PAGE_PROTECTION_FLAGS>,
-        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
-    ) -> [bool; N] {
-        Self::search_in_ranges(protection, ranges, |search_base, region_size, flags: &mut [bool]| {
-            for (&address, flag) in addresses
-                .iter()
-                .zip(flags.iter_mut())
-                .filter(|(_, f)| !**f)
-            {
-                let start = search_base as usize;
-                let end = start + region_size;
-                if address >= start && address < end {
-                    *flag = true;
-                }
-            }
+    /// The address of the first occurrence of the string, or `None` if it wasn't found.
+    pub fn find_string<const M: usize>(&self, string: &str, null_terminated: bool, modules: &[&str; M]) -> Option<*const c_void> {
+        let mut bytes = string.as_bytes().to_vec();
+        if null_terminated {
+            bytes.push(0);
+        }
 
-            flags.iter().all(|&f| f)
-        })
+        let [address] = self.find_bytes_in_sections(&[&bytes], None, modules, &DATA_SECTIONS);
+        address
     }
 
-    /// Enumerate the modules loaded in the current process
+    /// Search for a wide (UTF-16) string in the data sections of modules
     ///
-    /// This method must be called once prior to attempting any searches that filter by module.
-    pub fn discover_modules(&mut self) -> Result<()> {
-        // reset module list in case we need to discover modules multiple times (e.g. dynamic DLL
-        // load)
-        self.modules.clear();
-
-        let mut modules = [HMODULE::default(); 1024];
-        let mut bytes_needed = 0;
-        let hproc = unsafe { GetCurrentProcess() };
-        unsafe {
-            EnumProcessModules(
-                hproc,
-                modules.as_mut_ptr(),
-                size_of_val(&modules) as u32,
-                &mut bytes_needed,
-            )
-        }?;
+    /// Works like `find_string`, but searches for the string encoded as little-endian UTF-16, which
+    /// is how `wchar_t` strings are stored on Windows. The null terminator, if requested, is two
+    /// bytes.
+    pub fn find_wstring<const M: usize>(&self, string: &str, null_terminated: bool, modules: &[&str; M]) -> Option<*const c_void> {
+        let terminator = null_terminated.then_some(0u16);
+        let bytes: Vec<_> = string
+            .encode_utf16()
+            .chain(terminator)
+            .flat_map(u16::to_le_bytes)
+            .collect();
 
-        let num_modules =
-            std::cmp::min(bytes_needed as usize / size_of::<HMODULE>(), modules.len());
-        for &module in &modules[..num_modules] {
-            let mut name_utf16 = [0; MAX_PATH as usize];
-            let module_name = unsafe {
-                let num_chars = GetModuleBaseNameW(hproc, Some(module), &mut name_utf16) as usize;
-                if num_chars == 0 || num_chars >= name_utf16.len() {
-                    continue;
-                }
+        let [address] = self.find_bytes_in_sections(&[&bytes], None, modules, &DATA_SECTIONS);
+        address
+    }
 
-                match PWSTR::from_raw(name_utf16.as_mut_ptr()).to_string() {
-                    Ok(name) => name,
-                    Err(_) => continue,
-                }
+    /// Find a byte string in a module, consulting the resolved address cache first
+    ///
+    /// Results are cached under `id` for the build of `module` that is loaded, so when several
+    /// subsystems resolve the same signature, only the first one actually scans memory. Call
+    /// `cache::enable_unload_invalidation` to have cached entries dropped when the module unloads.
+    /// The cache only covers the current process. A cached address is only used if the byte string
+    /// still matches there, so patching the bytes at a match doesn't leave a stale result behind.
+    /// If `cache::enable_persistence` has been called, addresses resolved by previous runs are
+    /// reused under the same condition.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - A name uniquely identifying this signature
+    /// * `pattern` - The byte string to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
+    /// * `module` - The module to search in
+    ///
+    /// # Return
+    ///
+    /// A pointer to the location where the byte string was found, or `None` if the module hasn't
+    /// been discovered or the byte string wasn't found.
+    pub fn resolve(
+        &self,
+        id: &str,
+        pattern: &[u8],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        module: &str,
+    ) -> Option<*const c_void> {
+        let range = *self.modules().0.get(&module.to_lowercase())?;
+        let key = self.cache_key(module);
+        if let Some(ref key) = key {
+            // the bytes at a cached address may have been patched since it was found
+            if let Some(addr) = cache::get(key, id)
+                && self.matches_at(addr, pattern)
+            {
+                return Some(addr);
             }
-                .to_lowercase();
 
-            let mut module_info = MODULEINFO::default();
-            unsafe {
-                GetModuleInformation(
-                    hproc,
-                    module,
-                    &mut module_info,
-                    size_of_val(&module_info) as u32,
-                )?;
-                let base = module_info.lpBaseOfDll as *const c_void;
-                self.modules.insert(
-                    module_name,
-                    (base, base.add(module_info.SizeOfImage as usize)),
-                );
+            // an address saved by a previous run is only good if the signature still matches there
+            if let Some(addr) = cache::get_persisted(key, id)
+                && self.matches_at(addr, pattern)
+            {
+                cache::insert(key, id, addr);
+                return Some(addr);
             }
         }
 
-        Ok(())
-    }
+        let patterns = PatternSet::new([Pattern::exact(pattern)]);
+        let mut addresses = [None];
+        Self::search_patterns_in_ranges(&self.context, &patterns, protection, [&range].into_iter(), &mut addresses);
+        let [addr] = addresses;
+        if let (Some(key), Some(addr)) = (key, addr) {
+            cache::insert(&key, id, addr);
+        }
 
-    fn get_module_ranges<'b, 'a: 'b, 'c: 'b>(
-        &'a self,
-        modules: &'b [&'c str],
-    ) -> impl Iterator<Item = &'a (*const c_void, *const c_void)> + 'b {
-        modules
-            .iter()
-            .filter_map(|&module_name| self.modules.get(&module_name.to_lowercase()))
+        addr
     }
 
-    /// Search for byte strings anywhere in process memory
-    ///
-    /// # Arguments
+    /// Search for byte strings in process memory, reporting statistics about the scan
     ///
-    /// * `patterns` - The byte strings to search for
-    /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
-    ///
-    /// # Return
-    ///
-    /// An array of `Option<*const c_void>` with the same number of elements as the `patterns` argument.
-    /// If the corresponding byte string was found, the value will be `Some(ptr)`, where `ptr` is a
-    /// pointer to the location where the byte string was found. If the byte string was not found,
-    /// the element in the return array will be `None`.
-    pub fn find_bytes_anywhere<const N: usize>(
+    /// See `find_bytes` and `find_bytes_in_ranges_with_stats` for details.
+    pub fn find_bytes_with_stats<const N: usize, const M: usize>(
+        &self,
         patterns: &[&[u8]; N],
         protection: Option<PAGE_PROTECTION_FLAGS>,
-    ) -> [Option<*const c_void>; N] {
-        // we'll use the standard page size as the minimum address
-        Self::find_bytes_in_ranges(
-            patterns,
-            protection,
-            [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
-        )
+        modules: &[&str; M],
+    ) -> ([Option<*const c_void>; N], ScanStats) {
+        let mut addresses = [None; N];
+        let stats = self.search_patterns(&PatternSet::new(patterns.map(Pattern::exact)), protection, modules, &mut addresses);
+        (addresses, stats)
     }
 
-    /// Search for byte strings in process memory
+    /// Search for byte strings in process memory, collecting diagnostics for each pattern
+    ///
+    /// Unlike `find_bytes`, this scans the entire search space even after every pattern has been
+    /// found, so it can report how many times each pattern matched. It's meant for debugging
+    /// signatures that resolve to the wrong address, not for routine use.
     ///
     /// # Arguments
     ///
     /// * `patterns` - The byte strings to search for
     /// * `protection` - If provided, only search memory regions matching one of the specified protection flags
     /// * `modules` - If not empty, only search memory regions belonging to the specified modules
-    ///
-    /// # Return
-    ///
-    /// An array of `Option<*const c_void>` with the same number of elements as the `patterns` argument.
-    /// If the corresponding byte string was found, the value will be `Some(ptr)`, where `ptr` is a
-    /// pointer to the location where the byte string was found. If the byte string was not found,
-    /// the element in the return array will be `None`.
-    pub fn find_bytes<const N: usize, const M: usize>(
+    pub fn find_bytes_report<const N: usize, const M: usize>(
         &self,
         patterns: &[&[u8]; N],
         protection: Option<PAGE_PROTECTION_FLAGS>,
         modules: &[&str; M],
-    ) -> [Option<*const c_void>; N] {
-        if M > 0 {
-            Self::find_bytes_in_ranges(patterns, protection, self.get_module_ranges(modules))
+    ) -> ScanReport<N> {
+        let patterns = PatternSet::new(patterns.map(Pattern::exact));
+        let overlap = chunk_overlap(&patterns);
+        let context = &self.context;
+        let search_func = |search_base: *const u8, region_size: usize, tallies: &mut [PatternTally], stats: &mut ScanStats| {
+            let region_end = unsafe { search_base.add(region_size) } as *const c_void;
+            for_each_chunk(context, search_base, region_size, overlap, stats, |chunk, chunk_base, is_last| {
+                for (i, tally) in tallies.iter_mut().enumerate() {
+                    let start_time = Instant::now();
+                    // matches starting in the overlap will be counted again in the next chunk
+                    for offset in patterns.find_iter(i, chunk, chunk_base as usize).filter(|&o| is_last || o < SCAN_CHUNK_SIZE) {
+                        if tally.first.is_none() {
+                            tally.first = Some(unsafe { chunk_base.add(offset) } as *const c_void);
+                            tally.region = Some((search_base as *const c_void, region_end));
+                        }
+                        tally.match_count += 1;
+                    }
+                    tally.elapsed += start_time.elapsed();
+                }
+
+                false
+            });
+
+            false
+        };
+
+        let mut tallies = [PatternTally::default(); N];
+        let stats = if M > 0 {
+            Self::search_in_ranges(context, protection, self.get_module_ranges(modules).iter(), &mut tallies, search_func)
         } else {
-            Self::find_bytes_anywhere(patterns, protection)
+            Self::search_in_ranges(
+                context,
+                protection,
+                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+                &mut tallies,
+                search_func,
+            )
+        };
+
+        ScanReport {
+            patterns: tallies.map(|tally| PatternReport {
+                address: tally.first,
+                region: tally.region,
+                module: tally.first.and_then(|address| self.locate(address)).map(|location| location.module),
+                match_count: tally.match_count,
+                elapsed: tally.elapsed,
+            }),
+            stats,
+        }
+    }
+
+    /// Get the (start, end) address range of a discovered module
+    pub fn module_range(&self, module: &str) -> Option<(*const c_void, *const c_void)> {
+        self.modules().0.get(&module.to_lowercase()).copied()
+    }
+
+    /// Find every discovered module's imports of the named function
+    ///
+    /// This answers questions like "who imports CreateFileW?". The library name isn't checked,
+    /// since the same function can be imported through different libraries (e.g. kernel32.dll and
+    /// kernelbase.dll).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a module's import table is invalid, or if the searcher is for another
+    /// process.
+    pub fn find_imports(&self, function: &str) -> Result<Vec<Import>> {
+        if !self.context.process.is_current() {
+            return Err(Hook86Error::RemoteUnsupported { operation: "import enumeration" });
+        }
+
+        let bases: Vec<_> = self.modules().0.values().map(|&(start, _)| start).collect();
+        let mut imports = Vec::new();
+        for base in bases {
+            let module_imports = unsafe { pe::imports(base) }?;
+            imports.extend(module_imports.into_iter().filter(|import| import.is_named(function)));
         }
+
+        imports.sort_by_key(|import| import.slot);
+        Ok(imports)
+    }
+
+    /// Find the discovered module containing the given address and the address's offset from the
+    /// module base
+    ///
+    /// The result displays as `module+offset`, which is handy for logging, and the offset stays
+    /// the same across runs even if the module is loaded at a different address.
+    pub fn locate(&self, address: *const c_void) -> Option<ModuleOffset> {
+        self.modules()
+            .0
+            .iter()
+            .find(|(_, (start, end))| address >= *start && address < *end)
+            .map(|(name, &(start, _))| ModuleOffset {
+                module: name.clone(),
+                offset: address as usize - start as usize,
+            })
     }
 
     /// Check if the given addresses are found within process memory with the specified protection flags
@@ -309,16 +2025,9 @@ impl ByteSearcher {
         protection: Option<PAGE_PROTECTION_FLAGS>,
         modules: &[&str; M],
     ) -> [bool; N] {
-        if M > 0 {
-            Self::find_addresses_in_ranges(addresses, protection, self.get_module_ranges(modules))
-        } else {
-            // we'll use the standard page size as the minimum address
-            Self::find_addresses_in_ranges(
-                addresses,
-                protection,
-                [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
-            )
-        }
+        let mut flags = [false; N];
+        self.search_addresses(addresses, protection, modules, &mut flags);
+        flags
     }
 
     /// Shorthand for calling `find_addresses` with a protection of `PAGE_READWRITE | PAGE_WRITECOPY`
@@ -338,4 +2047,142 @@ impl ByteSearcher {
     ) -> [bool; N] {
         self.find_addresses(addresses, Some(PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE), modules)
     }
-}
\ No newline at end of file
+
+    /// Search for a runtime-determined set of byte strings in process memory
+    ///
+    /// Works like `find_bytes`, but takes slices instead of arrays, so the number of patterns and
+    /// modules doesn't need to be known at compile time (e.g. when signatures are loaded from a
+    /// config file). If `modules` is empty, all of process memory is searched.
+    pub fn find_bytes_vec(
+        &self,
+        patterns: &[&[u8]],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+    ) -> Vec<Option<*const c_void>> {
+        let patterns: PatternSet = patterns.iter().copied().map(Pattern::exact).collect();
+        self.find_pattern_set(&patterns, protection, modules)
+    }
+
+    /// Search for a runtime-determined set of masked patterns in process memory
+    ///
+    /// Works like `find_patterns`, but takes slices instead of arrays. See `find_bytes_vec`.
+    pub fn find_patterns_vec(
+        &self,
+        patterns: &[Pattern],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+    ) -> Vec<Option<*const c_void>> {
+        self.find_pattern_set(&PatternSet::new(patterns.iter().cloned()), protection, modules)
+    }
+
+    /// Search for a prepared set of patterns in process memory
+    ///
+    /// Every other pattern search prepares its patterns on each call. When the same patterns are
+    /// searched for repeatedly (e.g. in each module as it's loaded), preparing a `PatternSet` once
+    /// and searching with this method avoids that overhead. If `modules` is empty, all of process
+    /// memory is searched. Like `find_patterns`, searches of a single module use the resolved
+    /// address cache.
+    ///
+    /// # Return
+    ///
+    /// A `Vec` with one element per pattern in the set, in the same order. Each element is the
+    /// address of the first match of the corresponding pattern, or `None` if it wasn't found.
+    pub fn find_pattern_set(
+        &self,
+        patterns: &PatternSet,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+    ) -> Vec<Option<*const c_void>> {
+        let mut addresses = vec![None; patterns.len()];
+        self.search_patterns_cached(patterns, protection, modules, &mut addresses);
+        addresses
+    }
+
+    /// Check if a runtime-determined set of addresses are found within process memory with the
+    /// specified protection flags
+    ///
+    /// Works like `find_addresses`, but takes slices instead of arrays. If `modules` is empty, all
+    /// of process memory is searched.
+    pub fn find_addresses_vec(
+        &self,
+        addresses: &[usize],
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        modules: &[&str],
+    ) -> Vec<bool> {
+        let mut flags = vec![false; addresses.len()];
+        self.search_addresses(addresses, protection, modules, &mut flags);
+        flags
+    }
+}
+
+impl Default for ByteSearcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static mut PLAYER_DATA: [u32; 0x12] = [0; 0x12];
+    static mut SIGNATURE: [u8; 16] = [0; 16];
+
+    fn resolve_player() -> Result<*const c_void> {
+        Ok(&raw const PLAYER_DATA as *const c_void)
+    }
+
+    game_struct! {
+        Player @ resolve_player {
+            health: f32 @ 0x1C,
+            gold: u32 @ 0x44,
+            target: *const c_void @ 0x4,
+        }
+    }
+
+    #[test]
+    fn game_struct_layout() {
+        let player = Player::get().unwrap();
+        assert_eq!(player.as_ptr(), resolve_player().unwrap());
+
+        player.set_health(75.5).unwrap();
+        player.set_gold(1234).unwrap();
+        player.set_target(0x401000 as *const c_void).unwrap();
+        let data = unsafe { (&raw const PLAYER_DATA).read() };
+        assert_eq!(data[0x1C / 4], 75.5f32.to_bits());
+        assert_eq!(data[0x44 / 4], 1234);
+        assert_eq!(data[1], 0x401000);
+        assert_eq!(player.health().unwrap(), 75.5);
+        assert_eq!(player.gold().unwrap(), 1234);
+        assert_eq!(player.target().unwrap(), 0x401000 as *const c_void);
+
+        // fields of a structure at a bad address are errors, not crashes
+        let missing = unsafe { Player::at(std::ptr::null()) };
+        assert!(matches!(missing.gold(), Err(Hook86Error::UnreadableMemory { ptr: 0x44, size: 4 })));
+        assert!(missing.set_gold(1).is_err());
+    }
+    #[test]
+    fn patched_cache_hit() {
+        // built at runtime so the only copy in the module is the one that gets patched
+        let pattern: Vec<u8> = (u64::from(std::process::id()) ^ 0x9E3779B97F4A7C15)
+            .to_le_bytes()
+            .into_iter()
+            .chain(*b"hook86!!")
+            .collect();
+        let signature = &raw mut SIGNATURE;
+        unsafe { signature.write(pattern.clone().try_into().unwrap()) };
+        let address = signature as *const c_void;
+
+        let exe = std::env::current_exe().unwrap();
+        let module = exe.file_name().unwrap().to_str().unwrap().to_lowercase();
+        let mut searcher = ByteSearcher::new();
+        searcher.discover_modules().unwrap();
+        assert_eq!(searcher.find_bytes(&[&pattern], None, &[&module]), [Some(address)]);
+        assert_eq!(searcher.resolve("patched signature", &pattern, None, &module), Some(address));
+
+        // the cached address no longer matches, so it isn't returned again
+        unsafe { signature.cast::<u8>().write(!pattern[0]) };
+        assert_eq!(searcher.find_bytes(&[&pattern], None, &[&module]), [None]);
+        assert_eq!(searcher.resolve("patched signature", &pattern, None, &module), None);
+    }
+}
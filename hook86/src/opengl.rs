@@ -0,0 +1,139 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::core::BOOL;
+use windows::Win32::Graphics::Gdi::HDC;
+
+use crate::callbacks::{CallbackHandle, CallbackList};
+use crate::error::Result;
+use crate::hook::{CaveHook, HookManager, Prelude};
+use crate::mem::ByteSearcher;
+use crate::pe;
+
+/// The name the inline `wglSwapBuffers` hook is registered under in the `HookManager`
+pub const INLINE_HOOK_NAME: &str = "hook86 opengl wglSwapBuffers";
+
+type SwapBuffersFn = unsafe extern "system" fn(HDC) -> BOOL;
+
+/// Identifies a callback registered with `on_frame` so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameHandle(CallbackHandle);
+
+static FRAME_CALLBACKS: CallbackList<dyn FnMut(HDC) + Send> = CallbackList::new();
+
+static ORIGINAL_SWAP_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+static ORIGINAL_WGL_SWAP_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Register a callback to run each time the game swaps buffers, i.e. once per frame
+///
+/// Callbacks run in the order they were registered on the game's render thread, with the game's
+/// GL context current and the finished frame still in the back buffer, so they can draw overlays
+/// on top of it. They receive the device context being presented. Nothing runs until
+/// `install_import_hooks` or `install_inline` has been called. A callback that panics is removed,
+/// but the others still run.
+pub fn on_frame(callback: impl FnMut(HDC) + Send + 'static) -> FrameHandle {
+    FrameHandle(FRAME_CALLBACKS.add(Arc::new(Mutex::new(callback))))
+}
+
+/// Remove a callback registered with `on_frame`
+///
+/// If the callbacks for the current frame are already running, the removed callback may still run
+/// one last time. Returns false if the callback was already removed.
+pub fn remove_frame(handle: FrameHandle) -> bool {
+    FRAME_CALLBACKS.remove(handle.0)
+}
+
+extern "system" fn dispatch(hdc: HDC) {
+    FRAME_CALLBACKS.dispatch(|callback| callback(hdc));
+}
+
+unsafe extern "system" fn swap_buffers(hdc: HDC) -> BOOL {
+    dispatch(hdc);
+    let original: SwapBuffersFn = unsafe { std::mem::transmute(ORIGINAL_SWAP_BUFFERS.load(Ordering::Acquire)) };
+    unsafe { original(hdc) }
+}
+
+unsafe extern "system" fn wgl_swap_buffers(hdc: HDC) -> BOOL {
+    dispatch(hdc);
+    let original: SwapBuffersFn = unsafe { std::mem::transmute(ORIGINAL_WGL_SWAP_BUFFERS.load(Ordering::Acquire)) };
+    unsafe { original(hdc) }
+}
+
+/// Hook the `SwapBuffers` and `wglSwapBuffers` imports of every module the searcher has
+/// discovered and start running frame callbacks
+///
+/// Most games call `SwapBuffers` from gdi32, which calls `wglSwapBuffers` internally without
+/// going through an import, so each frame is only seen once. The hooks are registered with the
+/// `HookManager`. Games that look the functions up with `GetProcAddress` aren't covered; use
+/// `install_inline` for those instead. Don't combine the two, or callbacks run twice per frame.
+///
+/// Import slots that point somewhere other than the function the first hooked slot pointed to
+/// (e.g. because another mod already hooked them) are left alone, since only one original can be
+/// called. Returns the number of import slots that were hooked.
+///
+/// # Safety
+///
+/// Every module the searcher has discovered must still be loaded. Their import slots are patched
+/// while the game may be calling through them.
+///
+/// # Errors
+///
+/// Returns an error if a module's import table can't be read or a slot couldn't be patched.
+pub unsafe fn install_import_hooks(searcher: &ByteSearcher) -> Result<usize> {
+    let detours: [(&str, &AtomicUsize, usize); 2] = [
+        ("SwapBuffers", &ORIGINAL_SWAP_BUFFERS, swap_buffers as SwapBuffersFn as usize),
+        ("wglSwapBuffers", &ORIGINAL_WGL_SWAP_BUFFERS, wgl_swap_buffers as SwapBuffersFn as usize),
+    ];
+
+    let mut num_hooked = 0;
+    for (function, original, detour) in detours {
+        for import in searcher.find_imports(function)? {
+            if import.value as usize == detour {
+                continue;
+            }
+
+            // every slot calls the same original, so a slot that resolves to a different function
+            // can't be hooked without breaking it
+            let value = import.value as usize;
+            if let Err(existing) = original.compare_exchange(0, value, Ordering::AcqRel, Ordering::Acquire)
+                && existing != value
+            {
+                continue;
+            }
+            let name = format!("hook86 opengl {} {:p}", function, import.slot);
+            unsafe { HookManager::global().apply(&name, import.slot as *const c_void, &detour.to_le_bytes()) }?;
+            num_hooked += 1;
+        }
+    }
+
+    Ok(num_hooked)
+}
+
+/// Hook `wglSwapBuffers` in opengl32 itself and start running frame callbacks
+///
+/// This catches every buffer swap no matter how the game found the function, including the ones
+/// `SwapBuffers` makes on the game's behalf. opengl32.dll must already be loaded. The hook is
+/// registered with the `HookManager` under `INLINE_HOOK_NAME`.
+///
+/// # Safety
+///
+/// The start of `wglSwapBuffers` is overwritten, so no other thread may be running it at the time.
+/// In practice that means calling this before the game starts rendering or from the render thread.
+///
+/// # Errors
+///
+/// Returns an error if opengl32.dll isn't loaded, the start of `wglSwapBuffers` contains a relative
+/// branch, or the hook has already been installed.
+pub unsafe fn install_inline() -> Result<()> {
+    let target = pe::get_export("opengl32.dll", "wglSwapBuffers")?;
+    // push dword [esp+4]; call dispatch
+    let prelude = || {
+        Prelude::new()
+            .code(&[0xFF, 0x74, 0x24, 0x04])
+            .call(dispatch as extern "system" fn(HDC) as usize)
+    };
+    unsafe { CaveHook::install_with_prelude(INLINE_HOOK_NAME, target, prelude) }?;
+
+    Ok(())
+}
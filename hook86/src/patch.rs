@@ -2,6 +2,40 @@ use crate::mem::{IntPtr, PTR_SIZE};
 
 pub use hook86_macro::patch;
 
+/// A reference to a location within a patch's bytes
+///
+/// Patches generate a `PatchRef` for each label in their body. Pass `PatchRef::addr` to another
+/// patch's `bind` to wire a placeholder in that patch to this location. Like the pointer returned
+/// by `bind`, a `PatchRef` is only valid as long as the patch it refers to doesn't move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchRef {
+    ptr: *const u8,
+}
+
+impl PatchRef {
+    pub const fn new(base: *const u8, offset: usize) -> Self {
+        Self {
+            ptr: base.wrapping_add(offset),
+        }
+    }
+
+    /// A pointer to the referenced location
+    pub const fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    /// The absolute address of the referenced location, suitable for passing to `bind`
+    pub fn addr(&self) -> IntPtr {
+        self.ptr as IntPtr
+    }
+}
+
+impl From<PatchRef> for IntPtr {
+    fn from(patch_ref: PatchRef) -> Self {
+        patch_ref.addr()
+    }
+}
+
 #[derive(Debug)]
 pub struct PatchPlaceholder {
     offset: usize,
@@ -23,13 +57,21 @@ impl PatchPlaceholder {
     /// If `value` is a memory address, it should be an absolute address, even if the placeholder is
     /// relative.
     pub fn set_value(&mut self, buf: &mut [u8], value: IntPtr) {
+        let base = buf.as_ptr() as IntPtr;
+        self.set_value_at(buf, base, value);
+    }
+
+    /// Set the value of the placeholder for a buffer that will be copied to address `base` before
+    /// it runs
+    ///
+    /// Relative placeholders are calculated from `base` rather than the buffer's own address, e.g.
+    /// for a patch that's written into another process with `RemoteProcess::write_code`.
+    pub fn set_value_at(&mut self, buf: &mut [u8], base: IntPtr, value: IntPtr) {
         self.value = Some(value);
 
         let value_bytes = if self.is_relative {
-            let buf_addr = buf.as_mut_ptr() as usize;
-            let from_addr = buf_addr + self.offset + PTR_SIZE;
-            let rel = value.overflowing_sub(from_addr as IntPtr).0;
-            rel.to_le_bytes()
+            let from_addr = base.wrapping_add((self.offset + PTR_SIZE) as IntPtr);
+            value.wrapping_sub(from_addr).to_le_bytes()
         } else {
             value.to_le_bytes()
         };
@@ -38,6 +80,24 @@ impl PatchPlaceholder {
     }
 }
 
+#[cfg(test)]
+mod placeholder_tests {
+    use super::*;
+
+    #[test]
+    fn relative_to_remote_base() {
+        // call rel32 at the start of a buffer that will be written to 0x10000000 in another process
+        let mut buf = [0xE8, 0, 0, 0, 0, 0x68, 0, 0, 0, 0];
+        let mut call = PatchPlaceholder::new(1, true);
+        let mut push = PatchPlaceholder::new(6, false);
+        call.set_value_at(&mut buf, 0x10000000, 0x00401000);
+        push.set_value_at(&mut buf, 0x10000000, 1234);
+
+        assert_eq!(buf[1..5], 0x00401000u32.wrapping_sub(0x10000005).to_le_bytes());
+        assert_eq!(buf[6..], 1234u32.to_le_bytes());
+    }
+}
+
 // commented out for now until I figure out how to have the macro refer to types in the crate::
 // namespace here but the hook86:: namespace for external users
 /*#[cfg(test)]
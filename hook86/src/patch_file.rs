@@ -0,0 +1,243 @@
+use std::ffi::c_void;
+use std::path::Path;
+
+use crate::error::{Hook86Error, Result};
+use crate::mem::{self, ByteSearcher};
+use crate::pe::ImageLayout;
+
+/// How a patch file identifies the location of a byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PatchLocation {
+    /// An offset from the module base, as exported by x64dbg
+    Rva(u32),
+    /// An offset in the module's file, as exported by IDA
+    FileOffset(u32),
+}
+
+/// A single changed byte from a patch file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BytePatch {
+    /// The lowercase name of the patched module, e.g. "game.exe"
+    pub module: String,
+    pub location: PatchLocation,
+    /// The byte before patching
+    pub original: u8,
+    /// The byte after patching
+    pub replacement: u8,
+}
+
+fn parse_error(line: usize, reason: &'static str) -> Hook86Error {
+    Hook86Error::PatchFileParse { line, reason }
+}
+
+/// Parse a line of the form "<offset>:<rest>" with a hex offset
+fn split_offset(line: &str, line_number: usize) -> Result<(u32, &str)> {
+    let (offset, rest) = line.split_once(':').ok_or_else(|| parse_error(line_number, "expected an offset followed by ':'"))?;
+    let offset = u32::from_str_radix(offset.trim(), 16).map_err(|_| parse_error(line_number, "invalid offset"))?;
+    Ok((offset, rest))
+}
+
+fn parse_byte(byte: &str, line_number: usize) -> Result<u8> {
+    u8::from_str_radix(byte.trim(), 16).map_err(|_| parse_error(line_number, "invalid byte"))
+}
+
+/// Parse a patch file exported by x64dbg (.1337)
+///
+/// The file is made of sections, each starting with a line like `>game.exe` naming the module,
+/// followed by lines like `00001234:74->EB` giving an RVA, the original byte, and the new byte.
+pub fn parse_x64dbg(source: &str) -> Result<Vec<BytePatch>> {
+    let mut module = None;
+    let mut patches = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('>') {
+            module = Some(name.trim().to_lowercase());
+            continue;
+        }
+
+        let Some(module) = &module else {
+            return Err(parse_error(line_number, "patch before any module line"));
+        };
+        let (rva, bytes) = split_offset(line, line_number)?;
+        let (original, replacement) = bytes.split_once("->").ok_or_else(|| parse_error(line_number, "expected '->' between bytes"))?;
+        patches.push(BytePatch {
+            module: module.clone(),
+            location: PatchLocation::Rva(rva),
+            original: parse_byte(original, line_number)?,
+            replacement: parse_byte(replacement, line_number)?,
+        });
+    }
+
+    Ok(patches)
+}
+
+/// Parse a difference file exported by IDA (.dif)
+///
+/// After a line of description, the file names the module and then lists lines like
+/// `00001234: 74 EB` giving a file offset, the original byte, and the new byte.
+pub fn parse_ida_dif(source: &str) -> Result<Vec<BytePatch>> {
+    let mut module = None;
+    let mut patches = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        // the first line is always "This difference file was created by IDA"
+        if line.is_empty() || index == 0 {
+            continue;
+        }
+
+        let Some(module) = &module else {
+            module = Some(line.to_lowercase());
+            continue;
+        };
+        let (offset, bytes) = split_offset(line, line_number)?;
+        let mut bytes = bytes.split_whitespace();
+        let (Some(original), Some(replacement), None) = (bytes.next(), bytes.next(), bytes.next()) else {
+            return Err(parse_error(line_number, "expected an original and a new byte"));
+        };
+        patches.push(BytePatch {
+            module: module.clone(),
+            location: PatchLocation::FileOffset(offset),
+            original: parse_byte(original, line_number)?,
+            replacement: parse_byte(replacement, line_number)?,
+        });
+    }
+
+    Ok(patches)
+}
+
+/// Load a patch file, choosing the parser by extension
+///
+/// Files ending in `.dif` are parsed as IDA difference files and everything else as x64dbg patch
+/// files.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<BytePatch>> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("dif")) {
+        parse_ida_dif(&source)
+    } else {
+        parse_x64dbg(&source)
+    }
+}
+
+/// Merge single-byte patches at consecutive addresses into runs
+///
+/// Takes (address, original, replacement) tuples and returns (start address, original bytes,
+/// replacement bytes) for each run, in address order.
+fn merge_runs(mut bytes: Vec<(usize, u8, u8)>) -> Vec<(usize, Vec<u8>, Vec<u8>)> {
+    bytes.sort_by_key(|&(address, _, _)| address);
+    let mut runs: Vec<(usize, Vec<u8>, Vec<u8>)> = Vec::new();
+    for (address, original, replacement) in bytes {
+        match runs.last_mut() {
+            Some((start, run_original, run_replacement)) if *start + run_original.len() == address => {
+                run_original.push(original);
+                run_replacement.push(replacement);
+            }
+            _ => runs.push((address, vec![original], vec![replacement])),
+        }
+    }
+
+    runs
+}
+
+/// Apply patches from a patch file to the live modules
+///
+/// RVAs and file offsets are translated to addresses in the modules the searcher has discovered,
+/// and consecutive bytes are written together with `mem::patch`. Every run's original bytes are
+/// verified before anything is written, so a patch file for a different build of the game is
+/// rejected as a whole instead of being half-applied.
+///
+/// # Safety
+///
+/// The patches are written to live code and data while other threads may be running it, so each
+/// run of bytes must be safe to change in place, e.g. by not rewriting an instruction another
+/// thread could be in the middle of executing. Runs are written one at a time, so the game may
+/// briefly see some runs applied and others not.
+///
+/// # Errors
+///
+/// Returns an error if a module hasn't been discovered, a location isn't in its module, or the
+/// bytes at a location don't match the patch file's original bytes.
+pub unsafe fn apply(patches: &[BytePatch], searcher: &ByteSearcher) -> Result<()> {
+    let mut layouts: Vec<(&str, ImageLayout)> = Vec::new();
+    let mut bytes = Vec::with_capacity(patches.len());
+    for patch in patches {
+        let index = match layouts.iter().position(|(module, _)| *module == patch.module) {
+            Some(index) => index,
+            None => {
+                let (base, _) = searcher
+                    .module_range(&patch.module)
+                    .ok_or_else(|| Hook86Error::ModuleNotFound { module: patch.module.clone() })?;
+                layouts.push((&patch.module, unsafe { ImageLayout::of(base) }?));
+                layouts.len() - 1
+            }
+        };
+
+        let layout = &layouts[index].1;
+        let (address, offset) = match patch.location {
+            PatchLocation::Rva(rva) => (layout.rva_to_va(rva), rva),
+            PatchLocation::FileOffset(offset) => (layout.offset_to_va(offset), offset),
+        };
+        let address = address.ok_or_else(|| Hook86Error::RvaOutOfRange {
+            module: patch.module.clone(),
+            offset,
+        })?;
+        bytes.push((address as usize, patch.original, patch.replacement));
+    }
+
+    let runs = merge_runs(bytes);
+    for (address, original, _) in &runs {
+        unsafe { mem::verify(*address as *const c_void, original) }?;
+    }
+
+    for (address, _, replacement) in &runs {
+        unsafe { mem::patch(*address as *const c_void, replacement) }?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x64dbg() {
+        let patches = parse_x64dbg(">Game.exe\n00001234:74->EB\n00001235:05->10\n\n>other.dll\n0000ABCD:90->CC\n").unwrap();
+        assert_eq!(
+            patches,
+            [
+                BytePatch { module: "game.exe".into(), location: PatchLocation::Rva(0x1234), original: 0x74, replacement: 0xEB },
+                BytePatch { module: "game.exe".into(), location: PatchLocation::Rva(0x1235), original: 0x05, replacement: 0x10 },
+                BytePatch { module: "other.dll".into(), location: PatchLocation::Rva(0xABCD), original: 0x90, replacement: 0xCC },
+            ]
+        );
+        assert!(matches!(parse_x64dbg("00001234:74->EB"), Err(Hook86Error::PatchFileParse { line: 1, .. })));
+        assert!(matches!(parse_x64dbg(">game.exe\n00001234:74-EB"), Err(Hook86Error::PatchFileParse { line: 2, .. })));
+    }
+
+    #[test]
+    fn ida() {
+        let source = "This difference file was created by IDA\n\ngame.exe\n00000634: 74 EB\n00000635: 05 10\n";
+        let patches = parse_ida_dif(source).unwrap();
+        assert_eq!(
+            patches,
+            [
+                BytePatch { module: "game.exe".into(), location: PatchLocation::FileOffset(0x634), original: 0x74, replacement: 0xEB },
+                BytePatch { module: "game.exe".into(), location: PatchLocation::FileOffset(0x635), original: 0x05, replacement: 0x10 },
+            ]
+        );
+        assert!(matches!(parse_ida_dif("header\ngame.exe\n00000634: 74"), Err(Hook86Error::PatchFileParse { line: 3, .. })));
+    }
+
+    #[test]
+    fn runs() {
+        let runs = merge_runs(vec![(0x1001, 2, 0xB), (0x1000, 1, 0xA), (0x1005, 3, 0xC)]);
+        assert_eq!(runs, [(0x1000, vec![1, 2], vec![0xA, 0xB]), (0x1005, vec![3], vec![0xC])]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::ffi::c_void;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+use crate::mem::{self, ByteSearcher};
+use crate::pattern::Pattern;
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A byte patch declared in a patch file
+///
+/// The location is either an `address` relative to the start of `module`, or a `signature` to
+/// search for (in `module` if one is given, otherwise everywhere) plus an `offset` from the
+/// match. Bytes are written as hex strings like `"90 90 EB 05"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatchDefinition {
+    /// The name the patch is registered under in the `HookManager`
+    pub name: String,
+    pub module: Option<String>,
+    /// The offset of the patch from the module base
+    pub address: Option<usize>,
+    /// A signature in the same syntax as `Pattern::parse`
+    pub signature: Option<String>,
+    /// The offset of the patch from the signature match
+    #[serde(default)]
+    pub offset: isize,
+    /// The bytes expected at the location before patching, to catch unsupported game versions
+    pub original: Option<String>,
+    /// The bytes to write
+    pub replacement: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+/// Parse a hex string like "90 90 EB 05" into bytes
+///
+/// This accepts the same syntax as `Pattern::parse`, minus the wildcards, so whitespace between
+/// bytes is optional but every byte must be two digits.
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    if hex.trim().is_empty() {
+        return Some(Vec::new());
+    }
+
+    let pattern = Pattern::parse(hex).ok()?;
+    (!pattern.has_wildcards()).then(|| pattern.bytes().to_vec())
+}
+
+fn invalid(patch: &PatchDefinition, reason: &str) -> Hook86Error {
+    Hook86Error::PatchSet {
+        reason: format!("patch {}: {}", patch.name, reason),
+    }
+}
+
+impl PatchDefinition {
+    /// Find the address this patch applies to
+    fn locate(&self, searcher: &ByteSearcher) -> Result<*const c_void> {
+        match (self.address, &self.signature) {
+            (Some(address), None) => {
+                let Some(module) = &self.module else {
+                    return Err(invalid(self, "an address requires a module"));
+                };
+                let (start, end) = searcher
+                    .module_range(module)
+                    .ok_or_else(|| Hook86Error::ModuleNotFound { module: module.clone() })?;
+                if address >= end as usize - start as usize {
+                    return Err(Hook86Error::RvaOutOfRange {
+                        module: module.clone(),
+                        offset: address as u32,
+                    });
+                }
+                Ok(start.wrapping_byte_add(address))
+            }
+            (None, Some(signature)) => {
+                // without this, a missing module would look like a signature that didn't match
+                if let Some(module) = &self.module
+                    && searcher.module_range(module).is_none()
+                {
+                    return Err(Hook86Error::ModuleNotFound { module: module.clone() });
+                }
+                let pattern = Pattern::parse(signature)?;
+                let modules: Vec<&str> = self.module.iter().map(String::as_str).collect();
+                let found = searcher.find_patterns_vec(&[pattern], None, &modules)[0];
+                found
+                    .map(|address| address.wrapping_byte_offset(self.offset))
+                    .ok_or_else(|| Hook86Error::PatternNotFound { name: self.name.clone() })
+            }
+            _ => Err(invalid(self, "exactly one of address and signature must be given")),
+        }
+    }
+
+    /// Apply the patch through the given `HookManager`
+    ///
+    /// # Return
+    ///
+    /// The address the patch was written to.
+    unsafe fn apply(&self, searcher: &ByteSearcher, manager: &mut HookManager) -> Result<*const c_void> {
+        let replacement = parse_bytes(&self.replacement).ok_or_else(|| invalid(self, "replacement is not valid hex"))?;
+        let original = match &self.original {
+            Some(original) => Some(parse_bytes(original).ok_or_else(|| invalid(self, "original is not valid hex"))?),
+            None => None,
+        };
+
+        let address = self.locate(searcher)?;
+        if let Some(original) = original {
+            unsafe { mem::verify(address, &original) }?;
+        }
+
+        unsafe { manager.apply(&self.name, address, &replacement) }?;
+        Ok(address)
+    }
+}
+
+/// A set of byte patches loaded from a TOML or JSON file
+///
+/// This lets users of a mod tweak or disable byte patches without recompiling it. In TOML, each
+/// patch is a `[[patch]]` table:
+/// ```toml
+/// [[patch]]
+/// name = "skip_intro"
+/// module = "game.exe"
+/// signature = "E8 ?? ?? ?? ?? 84 C0 74 ?? 6A 01"
+/// offset = 7
+/// original = "74"
+/// replacement = "EB"
+///
+/// [[patch]]
+/// name = "max_fps"
+/// module = "game.exe"
+/// address = 0x1A2B3C
+/// replacement = "90 00 00 00"
+/// enabled = false
+/// ```
+/// JSON files have the same structure: an object with a `patch` array.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatchSet {
+    #[serde(rename = "patch", default)]
+    pub patches: Vec<PatchDefinition>,
+}
+
+impl PatchSet {
+    /// Parse a patch set from TOML
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|e| Hook86Error::PatchSet { reason: e.to_string() })
+    }
+
+    /// Parse a patch set from JSON
+    pub fn from_json(source: &str) -> Result<Self> {
+        serde_json::from_str(source).map_err(|e| Hook86Error::PatchSet { reason: e.to_string() })
+    }
+
+    /// Load a patch set from a file
+    ///
+    /// Files with a `.json` extension are parsed as JSON and everything else as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json")) {
+            Self::from_json(&source)
+        } else {
+            Self::from_toml(&source)
+        }
+    }
+
+    /// Apply every enabled patch through the given `HookManager`
+    ///
+    /// A patch that fails doesn't stop the others from being applied, since patches in a set are
+    /// usually independent tweaks. Each patch is registered under its name, so it can be reverted
+    /// individually later.
+    ///
+    /// # Return
+    ///
+    /// The name of each enabled patch and either the address it was written to or the reason it
+    /// couldn't be applied, in file order.
+    ///
+    /// # Safety
+    ///
+    /// The patches come from a file the user can edit, so they're only as safe as its contents.
+    /// Nothing else may be running code or using data that a patch overwrites while it's written.
+    pub unsafe fn apply(&self, searcher: &ByteSearcher, manager: &mut HookManager) -> Vec<(String, Result<*const c_void>)> {
+        self.patches
+            .iter()
+            .filter(|patch| patch.enabled)
+            .map(|patch| (patch.name.clone(), unsafe { patch.apply(searcher, manager) }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        assert_eq!(parse_bytes("90 90 EB 05"), Some(vec![0x90, 0x90, 0xEB, 0x05]));
+        assert_eq!(parse_bytes("9090eb05"), Some(vec![0x90, 0x90, 0xEB, 0x05]));
+        assert_eq!(parse_bytes(""), Some(vec![]));
+        assert_eq!(parse_bytes("9 0"), None);
+        assert_eq!(parse_bytes("909"), None);
+        assert_eq!(parse_bytes("ZZ"), None);
+        assert_eq!(parse_bytes("90 ??"), None);
+    }
+
+    #[test]
+    fn formats() {
+        let toml = PatchSet::from_toml(
+            r#"
+            [[patch]]
+            name = "skip_intro"
+            module = "game.exe"
+            signature = "74 ?? 6A 01"
+            original = "74"
+            replacement = "EB"
+
+            [[patch]]
+            name = "max_fps"
+            module = "game.exe"
+            address = 0x1A2B3C
+            replacement = "90 00 00 00"
+            enabled = false
+            "#,
+        )
+        .unwrap();
+        let json = PatchSet::from_json(
+            r#"{"patch": [
+                {"name": "skip_intro", "module": "game.exe", "signature": "74 ?? 6A 01", "original": "74", "replacement": "EB"},
+                {"name": "max_fps", "module": "game.exe", "address": 1715004, "replacement": "90 00 00 00", "enabled": false}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(toml, json);
+        assert_eq!(toml.patches.len(), 2);
+        assert!(toml.patches[0].enabled);
+        assert_eq!(toml.patches[0].offset, 0);
+        assert_eq!(toml.patches[1].address, Some(0x1A2B3C));
+        assert!(PatchSet::from_toml("[[patch]]\nname = \"x\"\nreplacment = \"90\"").is_err());
+    }
+}
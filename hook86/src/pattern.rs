@@ -0,0 +1,668 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use memchr::memmem;
+use thiserror::Error;
+
+pub use hook86_macro::pattern;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PatternParseError {
+    #[error("Pattern is empty")]
+    Empty,
+    #[error("Invalid pattern byte `{token}` at position {position}")]
+    InvalidByte { position: usize, token: String },
+}
+
+/// Anchors shorter than this match too often to be worth searching for on their own
+const MIN_ANCHOR_LEN: usize = 3;
+
+/// Two bytes of a pattern that are checked together to find candidate matches
+///
+/// When a pattern doesn't have a long enough run of exact bytes to search for with `memmem`, we
+/// compare the (masked) first and last significant bytes of the pattern at every position, which
+/// can be done 16 or 32 positions at a time with SIMD.
+#[derive(Debug, Clone, Copy)]
+struct KeyPair {
+    offsets: (usize, usize),
+    masks: (u8, u8),
+    values: (u8, u8),
+}
+
+impl KeyPair {
+    fn matches_at(&self, haystack: &[u8], pos: usize) -> bool {
+        haystack[pos + self.offsets.0] & self.masks.0 == self.values.0
+            && haystack[pos + self.offsets.1] & self.masks.1 == self.values.1
+    }
+
+    /// Find the first position in `start..=last_start` where both key bytes match
+    fn find(&self, haystack: &[u8], start: usize, last_start: usize) -> Option<usize> {
+        let mut pos = start;
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if let Some(found) = simd::find(self, haystack, &mut pos, last_start) {
+            return Some(found);
+        }
+
+        (pos..=last_start).find(|&p| self.matches_at(haystack, p))
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod simd {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::KeyPair;
+
+    /// Check whole blocks of positions for key matches, advancing `pos` past the blocks that were
+    /// checked
+    ///
+    /// Positions left over at the end that don't fill a block are left to the caller.
+    pub(super) fn find(keys: &KeyPair, haystack: &[u8], pos: &mut usize, last_start: usize) -> Option<usize> {
+        if is_x86_feature_detected!("avx2") {
+            unsafe { find_avx2(keys, haystack, pos, last_start) }
+        } else if is_x86_feature_detected!("sse2") {
+            unsafe { find_sse2(keys, haystack, pos, last_start) }
+        } else {
+            None
+        }
+    }
+
+    // every load reads 16 bytes starting at most `offsets.1` bytes past a position no greater than
+    // `last_start`, and the haystack is at least `last_start + offsets.1 + 1` bytes long, so loads
+    // stay in bounds as long as the whole block of positions is <= `last_start`
+
+    #[target_feature(enable = "sse2")]
+    fn find_sse2(keys: &KeyPair, haystack: &[u8], pos: &mut usize, last_start: usize) -> Option<usize> {
+        let ptr = haystack.as_ptr();
+        let mask0 = _mm_set1_epi8(keys.masks.0 as i8);
+        let value0 = _mm_set1_epi8(keys.values.0 as i8);
+        let mask1 = _mm_set1_epi8(keys.masks.1 as i8);
+        let value1 = _mm_set1_epi8(keys.values.1 as i8);
+        while *pos + 16 <= last_start + 1 {
+            let (block0, block1) = unsafe {
+                (
+                    _mm_loadu_si128(ptr.add(*pos + keys.offsets.0) as *const __m128i),
+                    _mm_loadu_si128(ptr.add(*pos + keys.offsets.1) as *const __m128i),
+                )
+            };
+            let eq0 = _mm_cmpeq_epi8(_mm_and_si128(block0, mask0), value0);
+            let eq1 = _mm_cmpeq_epi8(_mm_and_si128(block1, mask1), value1);
+            let bits = _mm_movemask_epi8(_mm_and_si128(eq0, eq1)) as u32;
+            if bits != 0 {
+                return Some(*pos + bits.trailing_zeros() as usize);
+            }
+
+            *pos += 16;
+        }
+
+        None
+    }
+
+    #[target_feature(enable = "avx2")]
+    fn find_avx2(keys: &KeyPair, haystack: &[u8], pos: &mut usize, last_start: usize) -> Option<usize> {
+        let ptr = haystack.as_ptr();
+        let mask0 = _mm256_set1_epi8(keys.masks.0 as i8);
+        let value0 = _mm256_set1_epi8(keys.values.0 as i8);
+        let mask1 = _mm256_set1_epi8(keys.masks.1 as i8);
+        let value1 = _mm256_set1_epi8(keys.values.1 as i8);
+        while *pos + 32 <= last_start + 1 {
+            let (block0, block1) = unsafe {
+                (
+                    _mm256_loadu_si256(ptr.add(*pos + keys.offsets.0) as *const __m256i),
+                    _mm256_loadu_si256(ptr.add(*pos + keys.offsets.1) as *const __m256i),
+                )
+            };
+            let eq0 = _mm256_cmpeq_epi8(_mm256_and_si256(block0, mask0), value0);
+            let eq1 = _mm256_cmpeq_epi8(_mm256_and_si256(block1, mask1), value1);
+            let bits = _mm256_movemask_epi8(_mm256_and_si256(eq0, eq1)) as u32;
+            if bits != 0 {
+                return Some(*pos + bits.trailing_zeros() as usize);
+            }
+
+            *pos += 32;
+        }
+
+        // finish off with 16-byte blocks
+        find_sse2(keys, haystack, pos, last_start)
+    }
+}
+
+/// Parse one hex digit of a signature, returning the value and mask of the nibble
+fn parse_nibble(c: u8) -> Option<(u8, u8)> {
+    match c {
+        b'?' => Some((0, 0)),
+        _ => Some(((c as char).to_digit(16)? as u8, 0xF)),
+    }
+}
+
+/// A byte signature with a per-byte mask
+///
+/// A byte of memory matches the corresponding byte of the pattern if they're equal in every bit
+/// that's set in the mask. A mask byte of 0xFF requires an exact match, and a mask byte of 0x00
+/// matches anything, which is useful for skipping over relocated addresses and other immediates
+/// that change between builds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern<'a> {
+    bytes: Cow<'a, [u8]>,
+    /// `None` if every byte must match exactly
+    mask: Option<Cow<'a, [u8]>>,
+    /// The longest run of exact bytes, which is searched for first to find candidate matches
+    anchor_offset: usize,
+    anchor_len: usize,
+    /// Matches are only reported at addresses that are a multiple of this
+    alignment: usize,
+}
+
+/// Find the longest run of bytes that must match exactly
+const fn find_anchor(mask: &[u8]) -> (usize, usize) {
+    let mut best = (0, 0);
+    let mut run_start = 0;
+    let mut i = 0;
+    while i < mask.len() {
+        if mask[i] != 0xFF {
+            run_start = i + 1;
+        } else if i + 1 - run_start > best.1 {
+            best = (run_start, i + 1 - run_start);
+        }
+        i += 1;
+    }
+
+    best
+}
+
+impl<'a> Pattern<'a> {
+    /// Create a pattern that matches the given bytes exactly
+    pub const fn exact(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes: Cow::Borrowed(bytes),
+            mask: None,
+            anchor_offset: 0,
+            anchor_len: bytes.len(),
+            alignment: 1,
+        }
+    }
+
+    /// Create a pattern from bytes and a mask of the same length
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` and `mask` have different lengths.
+    pub const fn from_slices(bytes: &'a [u8], mask: &'a [u8]) -> Self {
+        assert!(bytes.len() == mask.len(), "pattern bytes and mask must be the same length");
+        let (anchor_offset, anchor_len) = find_anchor(mask);
+        Self {
+            bytes: Cow::Borrowed(bytes),
+            mask: Some(Cow::Borrowed(mask)),
+            anchor_offset,
+            anchor_len,
+            alignment: 1,
+        }
+    }
+
+    /// Create a pattern from owned bytes and a mask of the same length
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` and `mask` have different lengths.
+    pub fn new(bytes: Vec<u8>, mask: Vec<u8>) -> Pattern<'static> {
+        assert_eq!(bytes.len(), mask.len(), "pattern bytes and mask must be the same length");
+        let (anchor_offset, anchor_len) = find_anchor(&mask);
+        Pattern {
+            bytes: Cow::Owned(bytes),
+            mask: Some(Cow::Owned(mask)),
+            anchor_offset,
+            anchor_len,
+            alignment: 1,
+        }
+    }
+
+    /// Parse a signature in IDA/x64dbg style
+    ///
+    /// The signature is a list of whitespace-separated hex bytes like `"55 8B EC 83 EC ?? E8 ? ? ? ?"`.
+    /// A byte of `?` or `??` matches anything, and a `?` in place of a single hex digit (e.g. `5?`)
+    /// matches any value of that nibble. Bytes may also be written without spaces (`"558BEC??"`),
+    /// in which case every byte must be exactly two characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signature is empty or contains something other than hex digits and
+    /// wildcards.
+    pub fn parse(signature: &str) -> Result<Pattern<'static>, PatternParseError> {
+        let mut bytes = Vec::new();
+        let mut mask = Vec::new();
+        for token in signature.split_whitespace() {
+            let position = bytes.len();
+            let invalid = || PatternParseError::InvalidByte {
+                position,
+                token: token.to_string(),
+            };
+
+            if token == "?" {
+                bytes.push(0);
+                mask.push(0);
+                continue;
+            }
+
+            if token.len() % 2 != 0 {
+                return Err(invalid());
+            }
+
+            for pair in token.as_bytes().chunks_exact(2) {
+                let (Some((high, high_mask)), Some((low, low_mask))) = (parse_nibble(pair[0]), parse_nibble(pair[1])) else {
+                    return Err(invalid());
+                };
+                bytes.push(high << 4 | low);
+                mask.push(high_mask << 4 | low_mask);
+            }
+        }
+
+        if bytes.is_empty() {
+            return Err(PatternParseError::Empty);
+        }
+
+        if mask.iter().all(|&m| m == 0xFF) {
+            let len = bytes.len();
+            Ok(Pattern {
+                bytes: Cow::Owned(bytes),
+                mask: None,
+                anchor_offset: 0,
+                anchor_len: len,
+                alignment: 1,
+            })
+        } else {
+            Ok(Pattern::new(bytes, mask))
+        }
+    }
+
+    /// Only match at addresses that are a multiple of `alignment`
+    ///
+    /// Pointers and vtable entries are almost always aligned, so requiring alignment when searching
+    /// for them in data sections eliminates most false positives. Alignment is checked against the
+    /// actual address of each match, not its offset in the haystack.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alignment` is not a power of two.
+    pub const fn with_alignment(mut self, alignment: usize) -> Self {
+        assert!(alignment.is_power_of_two(), "pattern alignment must be a power of two");
+        self.alignment = alignment;
+        self
+    }
+
+    /// The alignment required of matches
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// The length of the pattern in bytes
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Check whether the pattern is empty
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The bytes to match
+    ///
+    /// Bits that are masked out are meaningless.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The mask for each byte, or `None` if every byte must match exactly
+    pub fn mask(&self) -> Option<&[u8]> {
+        self.mask.as_deref()
+    }
+
+    /// Check whether the pattern contains any wildcard bits
+    pub fn has_wildcards(&self) -> bool {
+        self.mask.as_deref().is_some_and(|mask| mask.iter().any(|&m| m != 0xFF))
+    }
+
+    /// Check whether `data` starts with bytes matching this pattern
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let Some(data) = data.get(..self.len()) else {
+            return false;
+        };
+
+        match self.mask {
+            Some(ref mask) => data
+                .iter()
+                .zip(self.bytes.iter())
+                .zip(mask.iter())
+                .all(|((&d, &b), &m)| d & m == b & m),
+            None => data == &*self.bytes,
+        }
+    }
+
+    /// Find the offset of the first match of this pattern in `haystack`
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        self.find_iter(haystack).next()
+    }
+
+    /// Find the offset of the first match of this pattern in `haystack`, which is a copy of the
+    /// memory at address `base`
+    ///
+    /// Alignment is checked against `base` rather than the address of the copy.
+    pub fn find_at(&self, haystack: &[u8], base: usize) -> Option<usize> {
+        self.find_iter_at(haystack, base).next()
+    }
+
+    /// The first and last bytes of the pattern that aren't entirely wildcards, if the pattern's
+    /// anchor is too short to search for efficiently
+    fn key_pair(&self) -> Option<KeyPair> {
+        let mask = self.mask.as_deref()?;
+        if self.anchor_len >= MIN_ANCHOR_LEN {
+            return None;
+        }
+
+        let first = mask.iter().position(|&m| m != 0)?;
+        let last = mask.iter().rposition(|&m| m != 0)?;
+        Some(KeyPair {
+            offsets: (first, last),
+            masks: (mask[first], mask[last]),
+            values: (self.bytes[first] & mask[first], self.bytes[last] & mask[last]),
+        })
+    }
+
+    /// Build the state needed to search for this pattern
+    fn searcher(&self) -> Searcher {
+        let keys = self.key_pair();
+        let anchor = &self.bytes[self.anchor_offset..self.anchor_offset + self.anchor_len];
+        let finder = (keys.is_none() && !anchor.is_empty()).then(|| memmem::Finder::new(anchor).into_owned());
+        Searcher { finder, keys }
+    }
+
+    /// Find the offsets of every match of this pattern in `haystack`, including overlapping ones
+    pub fn find_iter<'h>(&'h self, haystack: &'h [u8]) -> impl Iterator<Item = usize> + 'h {
+        self.find_iter_at(haystack, haystack.as_ptr() as usize)
+    }
+
+    /// Find the offsets of every match of this pattern in `haystack`, which is a copy of the memory
+    /// at address `base`
+    ///
+    /// Alignment is checked against `base` rather than the address of the copy.
+    pub fn find_iter_at<'h>(&'h self, haystack: &'h [u8], base: usize) -> impl Iterator<Item = usize> + 'h {
+        self.find_iter_with(Cow::Owned(self.searcher()), haystack, base)
+    }
+
+    fn find_iter_with<'h>(
+        &'h self,
+        searcher: Cow<'h, Searcher>,
+        haystack: &'h [u8],
+        base: usize,
+    ) -> impl Iterator<Item = usize> + 'h {
+        let last_start = haystack.len().checked_sub(self.len());
+        let mut next_start = 0;
+        std::iter::from_fn(move || {
+            let last_start = last_start?;
+            while next_start <= last_start {
+                let candidate = if let Some(ref keys) = searcher.keys {
+                    keys.find(haystack, next_start, last_start)?
+                } else if let Some(ref finder) = searcher.finder {
+                    let anchor_search = &haystack[next_start + self.anchor_offset..last_start + self.anchor_offset + self.anchor_len];
+                    next_start + finder.find(anchor_search)?
+                } else {
+                    // nothing to anchor on, so every position is a candidate
+                    next_start
+                };
+
+                next_start = candidate + 1;
+                let is_aligned = base.wrapping_add(candidate) & (self.alignment - 1) == 0;
+                if is_aligned && self.matches(&haystack[candidate..]) {
+                    return Some(candidate);
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Get a copy of this pattern that doesn't borrow anything
+    pub fn into_owned(self) -> Pattern<'static> {
+        Pattern {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+            mask: self.mask.map(|mask| Cow::Owned(mask.into_owned())),
+            anchor_offset: self.anchor_offset,
+            anchor_len: self.anchor_len,
+            alignment: self.alignment,
+        }
+    }
+}
+
+/// Precomputed state for searching for a pattern
+#[derive(Debug, Clone)]
+struct Searcher {
+    /// Searches for the pattern's anchor
+    finder: Option<memmem::Finder<'static>>,
+    /// Used instead of the finder when the anchor is too short
+    keys: Option<KeyPair>,
+}
+
+/// A set of patterns prepared for repeated searching
+///
+/// Searching for a pattern requires some setup, such as building a substring finder for its
+/// anchor. `Pattern::find` does this on every call, which adds up when the same patterns are
+/// searched for in every chunk of a large scan. A `PatternSet` does it once up front.
+#[derive(Debug, Clone)]
+pub struct PatternSet<'a> {
+    patterns: Vec<Pattern<'a>>,
+    searchers: Vec<Searcher>,
+}
+
+impl<'a> PatternSet<'a> {
+    /// Prepare a set of patterns for searching
+    pub fn new(patterns: impl IntoIterator<Item = Pattern<'a>>) -> Self {
+        let patterns: Vec<_> = patterns.into_iter().collect();
+        let searchers = patterns.iter().map(Pattern::searcher).collect();
+        Self { patterns, searchers }
+    }
+
+    /// The number of patterns in the set
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Check whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// The patterns in the set, in the order they were provided
+    pub fn patterns(&self) -> &[Pattern<'a>] {
+        &self.patterns
+    }
+
+    /// The length of the longest pattern in the set
+    pub fn max_len(&self) -> usize {
+        self.patterns.iter().map(Pattern::len).max().unwrap_or(0)
+    }
+
+    /// Find the offset of the first match of the pattern at `index` in `haystack`
+    ///
+    /// `base` is the address `haystack` was read from, which alignment is checked against. For a
+    /// haystack that borrows memory directly rather than copying it, that's `haystack.as_ptr()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn find(&self, index: usize, haystack: &[u8], base: usize) -> Option<usize> {
+        self.find_iter(index, haystack, base).next()
+    }
+
+    /// Find the offsets of every match of the pattern at `index` in `haystack`
+    ///
+    /// `base` is the address `haystack` was read from, as in `find`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn find_iter<'h>(&'h self, index: usize, haystack: &'h [u8], base: usize) -> impl Iterator<Item = usize> + 'h {
+        self.patterns[index].find_iter_with(Cow::Borrowed(&self.searchers[index]), haystack, base)
+    }
+}
+
+impl<'a> FromIterator<Pattern<'a>> for PatternSet<'a> {
+    fn from_iter<T: IntoIterator<Item = Pattern<'a>>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
+
+impl FromStr for Pattern<'static> {
+    type Err = PatternParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Pattern<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self::exact(bytes)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for Pattern<'a> {
+    fn from(bytes: &'a [u8; N]) -> Self {
+        Self::exact(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchor_is_longest_exact_run() {
+        let pattern = Pattern::from_slices(&[0x55, 0x8B, 0, 0xE8, 0x10, 0x20], &[0xFF, 0xFF, 0, 0xFF, 0xFF, 0xFF]);
+        assert_eq!((pattern.anchor_offset, pattern.anchor_len), (3, 3));
+    }
+
+    #[test]
+    fn exact_find() {
+        let pattern = Pattern::exact(&[0x8B, 0xEC]);
+        assert_eq!(pattern.find(&[0x55, 0x8B, 0xEC, 0x83]), Some(1));
+        assert_eq!(pattern.find(&[0x55, 0x8B, 0xED]), None);
+    }
+
+    #[test]
+    fn masked_find() {
+        let pattern = Pattern::from_slices(&[0xE8, 0, 0, 0, 0, 0x8B, 0xF0], &[0xFF, 0, 0, 0, 0, 0xFF, 0xFF]);
+        let haystack = [0x90, 0xE8, 0x12, 0x34, 0x56, 0x78, 0x8B, 0xF0, 0xE8, 1, 2, 3, 4, 0x8B, 0xF1];
+        assert_eq!(pattern.find_iter(&haystack).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn partial_bit_mask() {
+        // match any push reg (50+r)
+        let pattern = Pattern::from_slices(&[0x50], &[0xF8]);
+        assert_eq!(pattern.find_iter(&[0x55, 0x8B, 0x57, 0x90]).collect::<Vec<_>>(), [0, 2]);
+    }
+
+    #[test]
+    fn all_wildcards() {
+        let pattern = Pattern::from_slices(&[0, 0], &[0, 0]);
+        assert_eq!(pattern.find_iter(&[1, 2, 3]).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(pattern.find(&[1]), None);
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        let pattern = Pattern::exact(&[0x90, 0x90]);
+        assert_eq!(pattern.find_iter(&[0x90, 0x90, 0x90]).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn aligned_matches() {
+        let pattern = Pattern::exact(&[0x90, 0x90]).with_alignment(2);
+        let haystack = [0x9090u16; 4];
+        let bytes = unsafe { std::slice::from_raw_parts(haystack.as_ptr() as *const u8, 8) };
+        assert_eq!(pattern.find_iter(bytes).collect::<Vec<_>>(), [0, 2, 4, 6]);
+        assert_eq!(pattern.find_iter(&bytes[1..]).collect::<Vec<_>>(), [1, 3, 5]);
+    }
+
+    #[test]
+    fn aligned_matches_in_copy() {
+        let pattern = Pattern::exact(&[0x90, 0x90]).with_alignment(4);
+        // deliberately start the copy one byte past an aligned address, so alignment has to come
+        // from the address the memory was read from
+        let storage = [0x90909090u32; 3];
+        let bytes = unsafe { std::slice::from_raw_parts((storage.as_ptr() as *const u8).add(1), 10) };
+        assert_eq!(pattern.find_iter(bytes).collect::<Vec<_>>(), [3, 7]);
+        assert_eq!(pattern.find_iter_at(bytes, 0x401000).collect::<Vec<_>>(), [0, 4, 8]);
+        assert_eq!(pattern.find_at(bytes, 0x401002), Some(2));
+
+        let set = PatternSet::new([pattern]);
+        assert_eq!(set.find_iter(0, bytes, 0x401000).collect::<Vec<_>>(), [0, 4, 8]);
+    }
+
+    #[test]
+    fn key_pair_search() {
+        // no exact run long enough to anchor on, so this goes through the key pair matcher
+        let pattern = Pattern::from_slices(&[0xE8, 0, 0, 0, 0, 0x50], &[0xFF, 0, 0, 0, 0, 0xF8]);
+        assert!(pattern.key_pair().is_some());
+
+        let mut haystack = vec![0xE8; 100];
+        haystack[40] = 0x57;
+        haystack[90] = 0x53;
+        assert_eq!(pattern.find_iter(&haystack).collect::<Vec<_>>(), [35, 85]);
+        assert_eq!(pattern.find_iter(&haystack[36..]).collect::<Vec<_>>(), [49]);
+    }
+
+    #[test]
+    fn pattern_set() {
+        let set: PatternSet = [
+            Pattern::exact(&[0x8B, 0xEC]),
+            Pattern::from_slices(&[0x50], &[0xF8]),
+            Pattern::from_slices(&[0xE8, 0, 0, 0, 0, 0x8B, 0xF0], &[0xFF, 0, 0, 0, 0, 0xFF, 0xFF]),
+        ]
+        .into_iter()
+        .collect();
+        let haystack = [0x55, 0x8B, 0xEC, 0xE8, 1, 2, 3, 4, 0x8B, 0xF0, 0x57];
+        assert_eq!(set.max_len(), 7);
+        assert_eq!(set.find(0, &haystack, 0), Some(1));
+        assert_eq!(set.find_iter(1, &haystack, 0).collect::<Vec<_>>(), [0, 10]);
+        assert_eq!(set.find(2, &haystack, 0), Some(3));
+    }
+
+    #[test]
+    fn parse_ida_style() {
+        let pattern = Pattern::parse("55 8B EC 83 EC ?? E8 ? ? ? ?").unwrap();
+        assert_eq!(pattern.bytes(), [0x55, 0x8B, 0xEC, 0x83, 0xEC, 0, 0xE8, 0, 0, 0, 0]);
+        assert_eq!(pattern.mask().unwrap(), [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0xFF, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn parse_compact_and_nibbles() {
+        let pattern = Pattern::parse("558bec 5? ?F").unwrap();
+        assert_eq!(pattern.bytes(), [0x55, 0x8B, 0xEC, 0x50, 0x0F]);
+        assert_eq!(pattern.mask().unwrap(), [0xFF, 0xFF, 0xFF, 0xF0, 0x0F]);
+        assert_eq!(Pattern::parse("90 C3").unwrap(), Pattern::exact(&[0x90, 0xC3]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Pattern::parse("  "), Err(PatternParseError::Empty));
+        assert_eq!(
+            Pattern::parse("55 8G"),
+            Err(PatternParseError::InvalidByte {
+                position: 1,
+                token: "8G".to_string()
+            })
+        );
+        assert!(Pattern::parse("55 8").is_err());
+        assert!(Pattern::parse("E8 ???").is_err());
+    }
+
+    #[test]
+    fn match_at_end() {
+        let pattern = Pattern::from_slices(&[0xC3, 0], &[0xFF, 0]);
+        assert_eq!(pattern.find(&[0x90, 0xC3, 0xCC]), Some(1));
+        assert_eq!(pattern.find(&[0x90, 0x90, 0xC3]), None);
+    }
+}
@@ -0,0 +1,1008 @@
+use std::ffi::{c_void, CStr};
+use std::path::PathBuf;
+
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{HMODULE, MAX_PATH};
+use windows::Win32::System::Diagnostics::Debug::{
+    IMAGE_DIRECTORY_ENTRY, IMAGE_DIRECTORY_ENTRY_BASERELOC, IMAGE_DIRECTORY_ENTRY_EXPORT, IMAGE_DIRECTORY_ENTRY_IAT, IMAGE_DIRECTORY_ENTRY_IMPORT,
+    IMAGE_NT_HEADERS32,
+    IMAGE_SCN_CNT_CODE, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE, IMAGE_SECTION_CHARACTERISTICS, IMAGE_SECTION_HEADER,
+};
+use windows::Win32::System::LibraryLoader::{GetModuleFileNameW, GetModuleHandleW};
+use windows::Win32::System::SystemServices::{
+    IMAGE_DOS_HEADER, IMAGE_DOS_SIGNATURE, IMAGE_EXPORT_DIRECTORY, IMAGE_IMPORT_DESCRIPTOR, IMAGE_NT_SIGNATURE,
+    IMAGE_ORDINAL_FLAG32, IMAGE_REL_BASED_ABSOLUTE, IMAGE_REL_BASED_HIGHLOW,
+};
+
+use crate::error::{Hook86Error, Result};
+use crate::mem::{self, IntPtr};
+use crate::pattern::Pattern;
+use crate::snapshot;
+
+/// A section of a loaded module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The section name, e.g. ".text"
+    pub name: String,
+    /// The address of the start of the section in memory
+    pub start: *const c_void,
+    /// The address immediately after the end of the section in memory
+    pub end: *const c_void,
+    /// The section flags from the section header
+    pub characteristics: IMAGE_SECTION_CHARACTERISTICS,
+}
+
+impl Section {
+    /// Check whether the section contains the given address
+    pub fn contains(&self, address: *const c_void) -> bool {
+        address >= self.start && address < self.end
+    }
+
+    /// Check whether the section is marked as executable
+    pub fn is_executable(&self) -> bool {
+        self.characteristics.contains(IMAGE_SCN_MEM_EXECUTE)
+    }
+
+    /// Check whether the section is marked as writable
+    pub fn is_writable(&self) -> bool {
+        self.characteristics.contains(IMAGE_SCN_MEM_WRITE)
+    }
+
+    /// Check whether the section contains code
+    pub fn is_code(&self) -> bool {
+        self.is_executable() || self.characteristics.contains(IMAGE_SCN_CNT_CODE)
+    }
+}
+
+/// Where a section is in the file and in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SectionMapping {
+    rva: u32,
+    virtual_size: u32,
+    file_offset: u32,
+    raw_size: u32,
+}
+
+/// The layout of a loaded module, for converting between virtual addresses, RVAs, and file offsets
+///
+/// Addresses from static analysis tools come in different forms: disassemblers show virtual
+/// addresses at the image's preferred base, hex editors and patch files use file offsets, and
+/// most PE tools use RVAs. This translates between all of them and live addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageLayout {
+    base: *const c_void,
+    preferred_base: u32,
+    image_size: u32,
+    header_size: u32,
+    sections: Vec<SectionMapping>,
+}
+
+impl ImageLayout {
+    /// Read the layout of the module loaded at `base`
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module's headers are invalid.
+    pub unsafe fn of(base: *const c_void) -> Result<Self> {
+        let optional_header = unsafe { &(*nt_headers(base)?).OptionalHeader };
+        let sections = unsafe { section_headers(base) }?
+            .iter()
+            .map(|header| SectionMapping {
+                rva: header.VirtualAddress,
+                virtual_size: match unsafe { header.Misc.VirtualSize } {
+                    0 => header.SizeOfRawData,
+                    size => size,
+                },
+                file_offset: header.PointerToRawData,
+                raw_size: header.SizeOfRawData,
+            })
+            .collect();
+
+        Ok(Self {
+            base,
+            preferred_base: optional_header.ImageBase,
+            image_size: optional_header.SizeOfImage,
+            header_size: optional_header.SizeOfHeaders,
+            sections,
+        })
+    }
+
+    /// The address the module is loaded at
+    pub fn base(&self) -> *const c_void {
+        self.base
+    }
+
+    /// The address the module would be loaded at if it didn't need to be relocated
+    ///
+    /// This is the base disassemblers use unless told otherwise.
+    pub fn preferred_base(&self) -> u32 {
+        self.preferred_base
+    }
+
+    /// Convert a live address to an RVA, or `None` if it isn't in the module
+    pub fn va_to_rva(&self, address: *const c_void) -> Option<u32> {
+        let rva = (address as usize).checked_sub(self.base as usize)?;
+        (rva < self.image_size as usize).then_some(rva as u32)
+    }
+
+    /// Convert an RVA to a live address, or `None` if it isn't in the module
+    pub fn rva_to_va(&self, rva: u32) -> Option<*const c_void> {
+        (rva < self.image_size).then(|| self.base.wrapping_byte_add(rva as usize))
+    }
+
+    /// Convert an address at the preferred base, as shown by a disassembler, to a live address
+    pub fn rebase(&self, preferred_address: u32) -> Option<*const c_void> {
+        self.rva_to_va(preferred_address.checked_sub(self.preferred_base)?)
+    }
+
+    /// Convert an RVA to an offset in the module's file
+    ///
+    /// Returns `None` if the RVA is outside the module or in a part of a section that isn't backed
+    /// by the file, like uninitialized data.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<u32> {
+        if rva < self.header_size {
+            return Some(rva);
+        }
+
+        self.sections.iter().find_map(|section| {
+            let delta = rva.checked_sub(section.rva)?;
+            (delta < section.virtual_size && delta < section.raw_size).then_some(section.file_offset + delta)
+        })
+    }
+
+    /// Convert an offset in the module's file to an RVA
+    ///
+    /// Returns `None` if the offset isn't part of the headers or any section, e.g. for overlay
+    /// data appended to the end of the file.
+    pub fn offset_to_rva(&self, offset: u32) -> Option<u32> {
+        if offset < self.header_size {
+            return Some(offset);
+        }
+
+        self.sections.iter().find_map(|section| {
+            let delta = offset.checked_sub(section.file_offset)?;
+            (delta < section.raw_size && delta < section.virtual_size).then_some(section.rva + delta)
+        })
+    }
+
+    /// Convert a live address to an offset in the module's file
+    pub fn va_to_offset(&self, address: *const c_void) -> Option<u32> {
+        self.rva_to_offset(self.va_to_rva(address)?)
+    }
+
+    /// Convert an offset in the module's file to a live address
+    pub fn offset_to_va(&self, offset: u32) -> Option<*const c_void> {
+        self.rva_to_va(self.offset_to_rva(offset)?)
+    }
+}
+
+/// A range of a loaded module whose bytes differ from the module's file on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifiedRange {
+    /// The name of the section containing the range
+    pub section: String,
+    /// The address of the first modified byte
+    pub start: *const c_void,
+    /// The address immediately after the last modified byte
+    pub end: *const c_void,
+    /// The bytes the file has in the range
+    pub original: Vec<u8>,
+}
+
+/// The most forwarders `get_export` will follow before giving up
+///
+/// Real forwarder chains are one or two hops long; this only guards against cycles.
+const MAX_FORWARDER_DEPTH: usize = 16;
+
+/// Where an export leads
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExportTarget {
+    /// The export is implemented in the module at this address
+    Address(*const c_void),
+    /// The export is forwarded to another module, e.g. "NTDLL.RtlAllocateHeap" or "NTDLL.#12"
+    Forwarder(String),
+}
+
+/// An entry in a module's export table
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Export {
+    /// The exported name, or `None` for exports that can only be imported by ordinal
+    pub name: Option<String>,
+    /// The export's ordinal
+    pub ordinal: u16,
+    pub target: ExportTarget,
+}
+
+/// A reference to an export, by name or by ordinal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportRef<'a> {
+    Name(&'a str),
+    Ordinal(u16),
+}
+
+impl<'a> ExportRef<'a> {
+    /// Parse a reference the way forwarder strings write them: a name, or `#` and an ordinal
+    pub fn parse(reference: &'a str) -> Self {
+        match reference.strip_prefix('#').and_then(|ordinal| ordinal.parse().ok()) {
+            Some(ordinal) => Self::Ordinal(ordinal),
+            None => Self::Name(reference),
+        }
+    }
+}
+
+/// How an imported function is identified
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ImportedFunction {
+    /// Imported by name
+    Name(String),
+    /// Imported by ordinal
+    Ordinal(u16),
+    /// The importing module has no import name table, so the function can't be identified
+    ///
+    /// Some older linkers only emit the import address table, which the loader overwrites with
+    /// the resolved addresses.
+    Unknown,
+}
+
+/// A function imported by a loaded module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Import {
+    /// The base address of the importing module
+    pub importer: *const c_void,
+    /// The name of the module the function is imported from, as written in the import table
+    pub library: String,
+    pub function: ImportedFunction,
+    /// The import address table slot the loader wrote the function's address to
+    ///
+    /// The importing module calls the function through this slot, so replacing its contents
+    /// redirects the module's calls.
+    pub slot: *const *const c_void,
+    /// The address in the slot when the imports were read
+    pub value: *const c_void,
+}
+
+impl Import {
+    /// Check whether this is an import of the named function
+    pub fn is_named(&self, name: &str) -> bool {
+        matches!(&self.function, ImportedFunction::Name(function) if function == name)
+    }
+}
+
+/// Split a forwarder string into the module name and the export
+///
+/// The module name is everything before the last dot, since module names can contain dots too.
+fn parse_forwarder(forwarder: &str) -> Option<(&str, &str)> {
+    let (module, export) = forwarder.rsplit_once('.')?;
+    (!module.is_empty() && !export.is_empty()).then_some((module, export))
+}
+
+fn invalid(base: *const c_void, reason: &'static str) -> Hook86Error {
+    Hook86Error::InvalidImage { base: base as usize, reason }
+}
+
+/// Get the NT headers of the module loaded at `base`
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+///
+/// # Errors
+///
+/// Returns an error if the DOS or NT headers are missing or the NT headers lie outside the image.
+pub unsafe fn nt_headers(base: *const c_void) -> Result<*const IMAGE_NT_HEADERS32> {
+    let dos_header = unsafe { &*(base as *const IMAGE_DOS_HEADER) };
+    if dos_header.e_magic != IMAGE_DOS_SIGNATURE {
+        return Err(invalid(base, "missing DOS signature"));
+    }
+
+    // a corrupt offset could point anywhere, so check the headers are really there before reading
+    let nt_offset = usize::try_from(dos_header.e_lfanew).map_err(|_| invalid(base, "negative NT header offset"))?;
+    let nt_headers = base.wrapping_byte_add(nt_offset) as *const IMAGE_NT_HEADERS32;
+    if !mem::is_readable(nt_headers as *const c_void, size_of::<IMAGE_NT_HEADERS32>()) {
+        return Err(invalid(base, "NT headers lie outside the mapped headers"));
+    }
+    if unsafe { (*nt_headers).Signature } != IMAGE_NT_SIGNATURE {
+        return Err(invalid(base, "missing NT signature"));
+    }
+
+    let image_size = unsafe { (*nt_headers).OptionalHeader.SizeOfImage } as usize;
+    if nt_offset
+        .checked_add(size_of::<IMAGE_NT_HEADERS32>())
+        .is_none_or(|end| end > image_size)
+    {
+        return Err(invalid(base, "NT headers lie outside the image"));
+    }
+
+    Ok(nt_headers)
+}
+
+/// Read a null-terminated string at `rva` in an image, without reading past the end of the image
+///
+/// # Safety
+///
+/// The whole image must be mapped at `base`.
+unsafe fn read_image_string(base: *const c_void, image_size: usize, rva: usize, what: &'static str) -> Result<String> {
+    let Some(len) = image_size.checked_sub(rva).filter(|&len| len > 0) else {
+        return Err(invalid(base, what));
+    };
+
+    let bytes = unsafe { std::slice::from_raw_parts(base.wrapping_byte_add(rva) as *const u8, len) };
+    let string = CStr::from_bytes_until_nul(bytes).map_err(|_| invalid(base, what))?;
+    Ok(string.to_string_lossy().into_owned())
+}
+
+/// Get the section headers of the module loaded at `base`
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`), and the
+/// module must stay loaded for as long as the returned headers are used.
+pub unsafe fn section_headers<'a>(base: *const c_void) -> Result<&'a [IMAGE_SECTION_HEADER]> {
+    let nt_headers = unsafe { nt_headers(base) }?;
+    let file_header = unsafe { &(*nt_headers).FileHeader };
+    // the section table immediately follows the optional header, whatever its size
+    let optional_header = unsafe { &raw const (*nt_headers).OptionalHeader };
+    let first_section = optional_header.wrapping_byte_add(file_header.SizeOfOptionalHeader as usize) as *const IMAGE_SECTION_HEADER;
+
+    Ok(unsafe { std::slice::from_raw_parts(first_section, file_header.NumberOfSections as usize) })
+}
+
+/// Get the sections of the module loaded at `base`
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+pub unsafe fn sections(base: *const c_void) -> Result<Vec<Section>> {
+    let headers = unsafe { section_headers(base) }?;
+    Ok(headers
+        .iter()
+        .map(|header| {
+            let name_len = header.Name.iter().position(|&c| c == 0).unwrap_or(header.Name.len());
+            // the virtual size is the size of the section in memory, but some linkers leave it zero
+            let size = match unsafe { header.Misc.VirtualSize } {
+                0 => header.SizeOfRawData,
+                size => size,
+            };
+            let start = base.wrapping_byte_add(header.VirtualAddress as usize);
+            Section {
+                name: String::from_utf8_lossy(&header.Name[..name_len]).into_owned(),
+                start,
+                end: start.wrapping_byte_add(size as usize),
+                characteristics: header.Characteristics,
+            }
+        })
+        .collect())
+}
+
+/// Find a section of the module loaded at `base` by name
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+pub unsafe fn find_section(base: *const c_void, name: &str) -> Result<Option<Section>> {
+    Ok(unsafe { sections(base) }?.into_iter().find(|section| section.name == name))
+}
+
+/// Get the path of the file the module loaded at `base` was loaded from
+///
+/// # Errors
+///
+/// Returns an error if `base` isn't the base of a loaded module.
+pub fn module_path(base: *const c_void) -> Result<PathBuf> {
+    let mut path_buf = [0u16; MAX_PATH as usize];
+    let path_len = unsafe { GetModuleFileNameW(Some(HMODULE(base as *mut c_void)), &mut path_buf) } as usize;
+    if path_len == 0 || path_len >= path_buf.len() {
+        return Err(windows::core::Error::from_thread().into());
+    }
+
+    Ok(PathBuf::from(String::from_utf16_lossy(&path_buf[..path_len])))
+}
+
+/// Read the export table of the module loaded at `base`
+///
+/// # Return
+///
+/// Every export in ordinal order, including ordinal-only exports. Modules without an export table
+/// have no exports.
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+///
+/// # Errors
+///
+/// Returns an error if the headers are invalid or the export table lies outside the image.
+pub unsafe fn exports(base: *const c_void) -> Result<Vec<Export>> {
+    let nt_headers = unsafe { &*nt_headers(base)? };
+    let Some((directory_rva, directory_size)) = data_directory(nt_headers, IMAGE_DIRECTORY_ENTRY_EXPORT) else {
+        return Ok(Vec::new());
+    };
+
+    let image_size = nt_headers.OptionalHeader.SizeOfImage as usize;
+    let in_image = |rva: usize, count: usize, size: usize| {
+        count
+            .checked_mul(size)
+            .and_then(|len| rva.checked_add(len))
+            .is_some_and(|end| end <= image_size)
+    };
+    if !in_image(directory_rva, 1, size_of::<IMAGE_EXPORT_DIRECTORY>()) {
+        return Err(invalid(base, "export directory lies outside the image"));
+    }
+
+    let directory = unsafe { &*(base.wrapping_byte_add(directory_rva) as *const IMAGE_EXPORT_DIRECTORY) };
+    let num_functions = directory.NumberOfFunctions as usize;
+    let num_names = directory.NumberOfNames as usize;
+    if !in_image(directory.AddressOfFunctions as usize, num_functions, 4)
+        || !in_image(directory.AddressOfNames as usize, num_names, 4)
+        || !in_image(directory.AddressOfNameOrdinals as usize, num_names, 2)
+    {
+        return Err(invalid(base, "export table lies outside the image"));
+    }
+
+    let functions = unsafe {
+        std::slice::from_raw_parts(base.wrapping_byte_add(directory.AddressOfFunctions as usize) as *const u32, num_functions)
+    };
+    let names = unsafe { std::slice::from_raw_parts(base.wrapping_byte_add(directory.AddressOfNames as usize) as *const u32, num_names) };
+    let name_ordinals = unsafe {
+        std::slice::from_raw_parts(base.wrapping_byte_add(directory.AddressOfNameOrdinals as usize) as *const u16, num_names)
+    };
+
+    let read_string =
+        |rva: u32| unsafe { read_image_string(base, image_size, rva as usize, "export string lies outside the image") };
+
+    let mut function_names = vec![None; num_functions];
+    for (&name_rva, &index) in names.iter().zip(name_ordinals) {
+        if let Some(slot) = function_names.get_mut(index as usize) {
+            *slot = Some(read_string(name_rva)?);
+        }
+    }
+
+    let mut exports = Vec::with_capacity(num_functions);
+    for (index, (&function_rva, name)) in functions.iter().zip(function_names).enumerate() {
+        // unused slots in the address table are zero
+        if function_rva == 0 {
+            continue;
+        }
+
+        let rva = function_rva as usize;
+        // an RVA inside the export directory points to a forwarder string rather than code
+        let target = if rva >= directory_rva && rva - directory_rva < directory_size {
+            ExportTarget::Forwarder(read_string(function_rva)?)
+        } else {
+            ExportTarget::Address(base.wrapping_byte_add(rva))
+        };
+
+        exports.push(Export {
+            name,
+            ordinal: (directory.Base as usize + index) as u16,
+            target,
+        });
+    }
+
+    Ok(exports)
+}
+
+/// Read the import table of the module loaded at `base`
+///
+/// # Return
+///
+/// Every import in the order it appears in the import table. Delay-loaded imports aren't
+/// included.
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+///
+/// # Errors
+///
+/// Returns an error if the headers are invalid or the import table lies outside the image.
+pub unsafe fn imports(base: *const c_void) -> Result<Vec<Import>> {
+    let nt_headers = unsafe { &*nt_headers(base)? };
+    let Some((directory_rva, _)) = data_directory(nt_headers, IMAGE_DIRECTORY_ENTRY_IMPORT) else {
+        return Ok(Vec::new());
+    };
+
+    let image_size = nt_headers.OptionalHeader.SizeOfImage as usize;
+    let in_image = |rva: usize, size: usize| rva.checked_add(size).is_some_and(|end| end <= image_size);
+    let read_string = |rva: usize| unsafe { read_image_string(base, image_size, rva, "import string lies outside the image") };
+
+    let mut imports = Vec::new();
+    let mut descriptor_rva = directory_rva;
+    loop {
+        if !in_image(descriptor_rva, size_of::<IMAGE_IMPORT_DESCRIPTOR>()) {
+            return Err(invalid(base, "import descriptor lies outside the image"));
+        }
+
+        // the descriptor list ends with an all-zero entry
+        let descriptor = unsafe { &*(base.wrapping_byte_add(descriptor_rva) as *const IMAGE_IMPORT_DESCRIPTOR) };
+        if descriptor.Name == 0 && descriptor.FirstThunk == 0 {
+            break;
+        }
+
+        let library = read_string(descriptor.Name as usize)?;
+        let name_table = unsafe { descriptor.Anonymous.OriginalFirstThunk } as usize;
+        let address_table = descriptor.FirstThunk as usize;
+        for index in 0.. {
+            let slot_rva = address_table + index * 4;
+            if !in_image(slot_rva, 4) || (name_table != 0 && !in_image(name_table + index * 4, 4)) {
+                return Err(invalid(base, "import thunk lies outside the image"));
+            }
+
+            let slot = base.wrapping_byte_add(slot_rva) as *const *const c_void;
+            let value = unsafe { *slot };
+            let function = if name_table != 0 {
+                let thunk = unsafe { *(base.wrapping_byte_add(name_table + index * 4) as *const u32) };
+                if thunk == 0 {
+                    break;
+                }
+
+                if thunk & IMAGE_ORDINAL_FLAG32 != 0 {
+                    ImportedFunction::Ordinal(thunk as u16)
+                } else {
+                    // skip the 16-bit hint that precedes the name
+                    ImportedFunction::Name(read_string(thunk as usize + 2)?)
+                }
+            } else if value.is_null() {
+                break;
+            } else {
+                ImportedFunction::Unknown
+            };
+
+            imports.push(Import {
+                importer: base,
+                library: library.clone(),
+                function,
+                slot,
+                value,
+            });
+        }
+
+        descriptor_rva += size_of::<IMAGE_IMPORT_DESCRIPTOR>();
+    }
+
+    Ok(imports)
+}
+
+/// Get the base address of a loaded module by name
+fn module_base(module: &str) -> Result<*const c_void> {
+    unsafe { GetModuleHandleW(&HSTRING::from(module)) }
+        .map(|handle| handle.0 as *const c_void)
+        .map_err(|_| Hook86Error::ModuleNotFound { module: module.to_string() })
+}
+
+/// Find the address of an export of a loaded module
+///
+/// Unlike `GetProcAddress`, this reads the export table directly, so it isn't affected by hooks on
+/// `GetProcAddress` itself. Forwarded exports are followed to the module that implements them,
+/// which must already be loaded; modules are never loaded by this function. `export` is a name,
+/// or `#` followed by an ordinal for ordinal-only exports.
+///
+/// # Errors
+///
+/// Returns an error if a module along the way isn't loaded or doesn't have the export.
+pub fn get_export(module: &str, export: &str) -> Result<*const c_void> {
+    let mut module = module.to_string();
+    let mut export = export.to_string();
+    for _ in 0..MAX_FORWARDER_DEPTH {
+        let base = module_base(&module)?;
+        let export_ref = ExportRef::parse(&export);
+        let found = unsafe { exports(base) }?.into_iter().find(|entry| match export_ref {
+            ExportRef::Name(name) => entry.name.as_deref() == Some(name),
+            ExportRef::Ordinal(ordinal) => entry.ordinal == ordinal,
+        });
+
+        match found.map(|entry| entry.target) {
+            Some(ExportTarget::Address(address)) => return Ok(address),
+            Some(ExportTarget::Forwarder(forwarder)) => {
+                let Some((next_module, next_export)) = parse_forwarder(&forwarder) else {
+                    return Err(invalid(base, "malformed forwarder string"));
+                };
+                module = next_module.to_string();
+                export = next_export.to_string();
+            }
+            None => return Err(Hook86Error::ExportNotFound { module, export }),
+        }
+    }
+
+    Err(Hook86Error::ExportNotFound { module, export })
+}
+
+/// Read a `T` out of a byte buffer, or `None` if it would run past the end of the buffer
+///
+/// Only used for header structures, which are plain integers and valid for any bit pattern.
+fn read_struct<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let bytes = data.get(offset..offset.checked_add(size_of::<T>())?)?;
+    Some(unsafe { (bytes.as_ptr() as *const T).read_unaligned() })
+}
+
+/// Get the (RVA, size) of one of an image's data directories, or `None` if it's absent
+fn data_directory(nt_headers: &IMAGE_NT_HEADERS32, entry: IMAGE_DIRECTORY_ENTRY) -> Option<(usize, usize)> {
+    let index = entry.0 as usize;
+    if index >= nt_headers.OptionalHeader.NumberOfRvaAndSizes as usize {
+        return None;
+    }
+
+    let directory = nt_headers.OptionalHeader.DataDirectory.get(index)?;
+    (directory.VirtualAddress != 0 && directory.Size != 0)
+        .then_some((directory.VirtualAddress as usize, directory.Size as usize))
+}
+
+/// Apply base relocations to an image that was laid out in memory for a different base address
+///
+/// `relocations` is the (offset, size) of the relocation directory within `image`, and `delta` is
+/// the actual base minus the preferred base. Relocations that fall outside the image are ignored.
+fn apply_relocations(image: &mut [u8], relocations: (usize, usize), delta: IntPtr) {
+    let (start, size) = relocations;
+    let end = start.saturating_add(size).min(image.len());
+    let mut block = start;
+    // each block is a page RVA and a block size, followed by 16-bit entries for that page
+    while block + 8 <= end {
+        let page = u32::from_le_bytes(image[block..block + 4].try_into().unwrap()) as usize;
+        let block_size = u32::from_le_bytes(image[block + 4..block + 8].try_into().unwrap()) as usize;
+        if block_size < 8 {
+            break;
+        }
+
+        let block_end = (block + block_size).min(end);
+        for entry in (block + 8..block_end).step_by(2) {
+            let Some(entry) = image.get(entry..entry + 2) else {
+                break;
+            };
+            let entry = u16::from_le_bytes([entry[0], entry[1]]);
+            let kind = (entry >> 12) as u32;
+            let target = page + (entry & 0xFFF) as usize;
+            match kind {
+                IMAGE_REL_BASED_ABSOLUTE => (),
+                IMAGE_REL_BASED_HIGHLOW => {
+                    if let Some(bytes) = image.get_mut(target..target + 4) {
+                        let value = u32::from_le_bytes((&*bytes).try_into().unwrap()).wrapping_add(delta);
+                        bytes.copy_from_slice(&value.to_le_bytes());
+                    }
+                }
+                // other relocation types don't occur in 32-bit x86 images
+                _ => (),
+            }
+        }
+
+        block += block_size;
+    }
+}
+
+/// Lay out a module's file the way the loader maps it at `base`
+///
+/// The result is as large as the image in memory, with each section at its RVA and relocations
+/// applied for `base`. Imports aren't resolved, so the import address table has the values from
+/// the file.
+fn map_file(base: *const c_void, file: &[u8]) -> Result<Vec<u8>> {
+    let dos_header: IMAGE_DOS_HEADER = read_struct(file, 0).ok_or_else(|| invalid(base, "file is truncated"))?;
+    if dos_header.e_magic != IMAGE_DOS_SIGNATURE {
+        return Err(invalid(base, "file is missing DOS signature"));
+    }
+
+    let nt_offset = dos_header.e_lfanew as usize;
+    let nt_headers: IMAGE_NT_HEADERS32 = read_struct(file, nt_offset).ok_or_else(|| invalid(base, "file is truncated"))?;
+    if nt_headers.Signature != IMAGE_NT_SIGNATURE {
+        return Err(invalid(base, "file is missing NT signature"));
+    }
+
+    let optional_header = &nt_headers.OptionalHeader;
+    let mut image = vec![0u8; optional_header.SizeOfImage as usize];
+    let header_size = (optional_header.SizeOfHeaders as usize).min(file.len()).min(image.len());
+    image[..header_size].copy_from_slice(&file[..header_size]);
+
+    let first_section = nt_offset + size_of::<u32>() + size_of_val(&nt_headers.FileHeader) + nt_headers.FileHeader.SizeOfOptionalHeader as usize;
+    for i in 0..nt_headers.FileHeader.NumberOfSections as usize {
+        let header: IMAGE_SECTION_HEADER = read_struct(file, first_section + i * size_of::<IMAGE_SECTION_HEADER>())
+            .ok_or_else(|| invalid(base, "file is truncated"))?;
+        let virtual_size = match unsafe { header.Misc.VirtualSize } {
+            0 => header.SizeOfRawData,
+            size => size,
+        };
+        // the raw data is padded to the file alignment, so only the virtual size of it is mapped
+        let size = header.SizeOfRawData.min(virtual_size) as usize;
+        let raw = header.PointerToRawData as usize;
+        let rva = header.VirtualAddress as usize;
+        let (Some(source), Some(dest)) = (file.get(raw..raw + size), image.get_mut(rva..rva + size)) else {
+            return Err(invalid(base, "section lies outside the file or image"));
+        };
+        dest.copy_from_slice(source);
+    }
+
+    let delta = (base as IntPtr).wrapping_sub(optional_header.ImageBase);
+    if delta != 0
+        && let Some(relocations) = data_directory(&nt_headers, IMAGE_DIRECTORY_ENTRY_BASERELOC)
+    {
+        apply_relocations(&mut image, relocations, delta);
+    }
+
+    Ok(image)
+}
+
+/// An unmodified copy of a loaded module, mapped from the module's file
+///
+/// Other software may have hooked or patched a module before your mod gets to it, which breaks
+/// signatures and means the bytes you back up before patching aren't really the original ones. A
+/// `CleanImage` maps the module's file the same way the loader did, relocated to the live module's
+/// base, so its bytes are what the live module contained when it was loaded. Addresses passed to
+/// and returned from its methods are addresses in the live module, so results can be used directly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanImage {
+    base: *const c_void,
+    image: Vec<u8>,
+}
+
+impl CleanImage {
+    /// Map a clean copy of the module loaded at `base`
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module's file can't be read or isn't a valid image.
+    pub unsafe fn load(base: *const c_void) -> Result<Self> {
+        let file = std::fs::read(module_path(base)?)?;
+        let image = map_file(base, &file)?;
+        Ok(Self { base, image })
+    }
+
+    /// The base address of the live module
+    pub fn base(&self) -> *const c_void {
+        self.base
+    }
+
+    /// The clean bytes of the whole image
+    pub fn bytes(&self) -> &[u8] {
+        &self.image
+    }
+
+    /// Check whether an address in the live module is covered by the image
+    pub fn contains(&self, address: *const c_void) -> bool {
+        address >= self.base && (address as usize - self.base as usize) < self.image.len()
+    }
+
+    /// Get the clean bytes at an address in the live module
+    ///
+    /// Use this to back up the original bytes before patching instead of reading the live module,
+    /// which may already have been modified. Returns `None` if any of the range lies outside the
+    /// image.
+    pub fn read(&self, address: *const c_void, len: usize) -> Option<&[u8]> {
+        let offset = (address as usize).checked_sub(self.base as usize)?;
+        self.image.get(offset..offset.checked_add(len)?)
+    }
+
+    /// Find the first match for a pattern in the clean image
+    ///
+    /// # Return
+    ///
+    /// The address of the match in the live module, or `None` if the pattern wasn't found.
+    pub fn find(&self, pattern: &Pattern) -> Option<*const c_void> {
+        pattern.find_at(&self.image, self.base as usize).map(|offset| self.base.wrapping_byte_add(offset))
+    }
+
+    /// Find every match for a pattern in the clean image
+    ///
+    /// # Return
+    ///
+    /// The addresses of the matches in the live module, in address order.
+    pub fn find_all(&self, pattern: &Pattern) -> Vec<*const c_void> {
+        pattern.find_iter_at(&self.image, self.base as usize).map(|offset| self.base.wrapping_byte_add(offset)).collect()
+    }
+}
+
+/// Compare the code of the module loaded at `base` against the module's file on disk
+///
+/// The file is mapped and relocated the same way the loader does it, so the only differences are
+/// changes made since the module was loaded: hooks and patches from other mods, anti-tamper code,
+/// or a packer unpacking itself. Only code sections are compared. If the import address table
+/// shares a section with code, it's ignored, since the loader fills it in.
+///
+/// # Return
+///
+/// The modified ranges, in address order.
+///
+/// # Safety
+///
+/// `base` must point to the start of a module mapped by the loader (i.e. an `HMODULE`).
+///
+/// # Errors
+///
+/// Returns an error if the module's file can't be read or isn't a valid image.
+pub unsafe fn diff_against_disk(base: *const c_void) -> Result<Vec<ModifiedRange>> {
+    let mut clean = unsafe { CleanImage::load(base) }?.image;
+
+    let nt_headers = unsafe { &*nt_headers(base)? };
+    let iat = data_directory(nt_headers, IMAGE_DIRECTORY_ENTRY_IAT);
+
+    let mut ranges = Vec::new();
+    for section in unsafe { sections(base) }? {
+        if !section.is_code() {
+            continue;
+        }
+
+        let offset = section.start as usize - base as usize;
+        let size = (section.end as usize - section.start as usize).min(clean.len().saturating_sub(offset));
+        let mut live = vec![0u8; size];
+        let copied = mem::copy_memory(section.start as *const u8, &mut live);
+        live.truncate(copied);
+
+        let original = &mut clean[offset..offset + size];
+        if let Some((iat_start, iat_size)) = iat {
+            // copy the live IAT over the file's so it doesn't show up as modified
+            let overlap_start = iat_start.max(offset) - offset;
+            let overlap_end = (iat_start + iat_size).min(offset + live.len()).saturating_sub(offset);
+            if overlap_start < overlap_end {
+                original[overlap_start..overlap_end].copy_from_slice(&live[overlap_start..overlap_end]);
+            }
+        }
+
+        ranges.extend(
+            snapshot::diff_runs(original, &live)
+                .into_iter()
+                .filter(|&(_, _, changed)| changed)
+                .map(|(start, end, _)| ModifiedRange {
+                    section: section.name.clone(),
+                    start: section.start.wrapping_byte_add(start),
+                    end: section.start.wrapping_byte_add(end),
+                    original: original[start..end].to_vec(),
+                }),
+        );
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarders() {
+        assert_eq!(parse_forwarder("NTDLL.RtlAllocateHeap"), Some(("NTDLL", "RtlAllocateHeap")));
+        assert_eq!(parse_forwarder("my.module.#12"), Some(("my.module", "#12")));
+        assert_eq!(parse_forwarder("NoDot"), None);
+        assert_eq!(parse_forwarder("NTDLL."), None);
+
+        assert_eq!(ExportRef::parse("HeapAlloc"), ExportRef::Name("HeapAlloc"));
+        assert_eq!(ExportRef::parse("#12"), ExportRef::Ordinal(12));
+        assert_eq!(ExportRef::parse("#x"), ExportRef::Name("#x"));
+    }
+
+    #[test]
+    fn layout() {
+        let layout = ImageLayout {
+            base: 0x10000000 as *const c_void,
+            preferred_base: 0x400000,
+            image_size: 0x4000,
+            header_size: 0x400,
+            sections: vec![
+                SectionMapping { rva: 0x1000, virtual_size: 0x1800, file_offset: 0x400, raw_size: 0x1800 },
+                // .bss-style tail: only the first 0x200 bytes are in the file
+                SectionMapping { rva: 0x3000, virtual_size: 0x1000, file_offset: 0x1C00, raw_size: 0x200 },
+            ],
+        };
+
+        assert_eq!(layout.rva_to_offset(0x10), Some(0x10));
+        assert_eq!(layout.rva_to_offset(0x1234), Some(0x634));
+        assert_eq!(layout.rva_to_offset(0x3100), Some(0x1D00));
+        assert_eq!(layout.rva_to_offset(0x3200), None);
+        assert_eq!(layout.rva_to_offset(0x2900), None);
+        assert_eq!(layout.offset_to_rva(0x634), Some(0x1234));
+        assert_eq!(layout.offset_to_rva(0x1E00), None);
+        assert_eq!(layout.rebase(0x401234), Some(0x10001234 as *const c_void));
+        assert_eq!(layout.rebase(0x3FFFFF), None);
+        assert_eq!(layout.va_to_offset(0x10001234 as *const c_void), Some(0x634));
+        assert_eq!(layout.offset_to_va(0x1D00), Some(0x10003100 as *const c_void));
+        assert_eq!(layout.va_to_rva(0x10004000 as *const c_void), None);
+    }
+
+    /// Copy a header structure into a file being built
+    fn write_struct<T: Copy>(file: &mut [u8], offset: usize, value: T) {
+        let bytes = unsafe { std::slice::from_raw_parts(&raw const value as *const u8, size_of::<T>()) };
+        file[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Build the file of a one-section image preferring base 0x400000, whose code loads the
+    /// relocated address 0x401010 and whose section data continues 0x10 bytes past that
+    fn test_file() -> Vec<u8> {
+        let mut file = vec![0u8; 0x400];
+        write_struct(&mut file, 0, IMAGE_DOS_HEADER {
+            e_magic: IMAGE_DOS_SIGNATURE,
+            e_lfanew: 0x40,
+            ..Default::default()
+        });
+
+        let mut nt_headers = IMAGE_NT_HEADERS32 {
+            Signature: IMAGE_NT_SIGNATURE,
+            ..Default::default()
+        };
+        nt_headers.FileHeader.NumberOfSections = 1;
+        nt_headers.FileHeader.SizeOfOptionalHeader = size_of_val(&nt_headers.OptionalHeader) as u16;
+        nt_headers.OptionalHeader.ImageBase = 0x400000;
+        nt_headers.OptionalHeader.SizeOfImage = 0x2000;
+        nt_headers.OptionalHeader.SizeOfHeaders = 0x200;
+        nt_headers.OptionalHeader.NumberOfRvaAndSizes = 16;
+        nt_headers.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_BASERELOC.0 as usize].VirtualAddress = 0x1100;
+        nt_headers.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_BASERELOC.0 as usize].Size = 10;
+        write_struct(&mut file, 0x40, nt_headers);
+
+        let mut section = IMAGE_SECTION_HEADER {
+            VirtualAddress: 0x1000,
+            SizeOfRawData: 0x200,
+            PointerToRawData: 0x200,
+            ..Default::default()
+        };
+        // the section is shorter in memory than in the file, so the rest of the raw data is padding
+        section.Misc.VirtualSize = 0x110;
+        write_struct(&mut file, 0x40 + size_of::<IMAGE_NT_HEADERS32>(), section);
+
+        // mov eax, [0x401010]; ret
+        file[0x200..0x206].copy_from_slice(&[0xA1, 0x10, 0x10, 0x40, 0x00, 0xC3]);
+        file[0x210..0x214].copy_from_slice(b"data");
+        // one relocation block for page 0x1000 with a HIGHLOW at 0x1001
+        file[0x300..0x304].copy_from_slice(&0x1000u32.to_le_bytes());
+        file[0x304..0x308].copy_from_slice(&10u32.to_le_bytes());
+        file[0x308..0x30A].copy_from_slice(&0x3001u16.to_le_bytes());
+        file[0x3F0..0x3F4].copy_from_slice(b"tail");
+
+        file
+    }
+
+    #[test]
+    fn clean_image() {
+        let base = 0x10000000 as *const c_void;
+        let file = test_file();
+        let clean = CleanImage {
+            base,
+            image: map_file(base, &file).unwrap(),
+        };
+
+        assert_eq!(clean.bytes().len(), 0x2000);
+        assert_eq!(clean.bytes()[..0x200], file[..0x200]);
+        // the load address was relocated to the live base
+        assert_eq!(clean.read(base.wrapping_byte_add(0x1000), 6), Some(&[0xA1, 0x10, 0x10, 0x00, 0x10, 0xC3][..]));
+        assert_eq!(clean.read(base.wrapping_byte_add(0x1010), 4), Some(&b"data"[..]));
+        // raw data past the section's virtual size isn't mapped
+        assert!(!clean.bytes().windows(4).any(|bytes| bytes == b"tail"));
+        assert_eq!(clean.read(base.wrapping_byte_add(0x1FFE), 4), None);
+        assert!(clean.contains(base.wrapping_byte_add(0x1FFF)));
+        assert!(!clean.contains(base.wrapping_byte_add(0x2000)));
+        assert!(!clean.contains(base.wrapping_byte_sub(1)));
+
+        let pattern = Pattern::exact(b"data");
+        assert_eq!(clean.find(&pattern), Some(base.wrapping_byte_add(0x1010)));
+        assert_eq!(clean.find_all(&pattern), [base.wrapping_byte_add(0x1010)]);
+
+        // mapped at its preferred base, nothing is relocated
+        let image = map_file(0x400000 as *const c_void, &file).unwrap();
+        assert_eq!(image[0x1001..0x1005], 0x401010u32.to_le_bytes());
+
+        assert!(matches!(map_file(base, &file[..0x100]), Err(Hook86Error::InvalidImage { .. })));
+        let mut corrupt = file.clone();
+        corrupt[0] = 0;
+        assert!(matches!(map_file(base, &corrupt), Err(Hook86Error::InvalidImage { .. })));
+    }
+
+    #[test]
+    fn relocations() {
+        let mut image = vec![0u8; 0x30];
+        image[0x10..0x14].copy_from_slice(&0x00401000u32.to_le_bytes());
+        image[0x18..0x1C].copy_from_slice(&0x00402000u32.to_le_bytes());
+        // one block for page 0: a HIGHLOW at 0x10, an ABSOLUTE padding entry, a HIGHLOW at 0x18
+        let block = [0u32.to_le_bytes(), 14u32.to_le_bytes()].concat();
+        image[0x20..0x28].copy_from_slice(&block);
+        image[0x28..0x2A].copy_from_slice(&0x3010u16.to_le_bytes());
+        image[0x2A..0x2C].copy_from_slice(&0x0000u16.to_le_bytes());
+        image[0x2C..0x2E].copy_from_slice(&0x3018u16.to_le_bytes());
+
+        apply_relocations(&mut image, (0x20, 14), 0x00100000);
+        assert_eq!(image[0x10..0x14], 0x00501000u32.to_le_bytes());
+        assert_eq!(image[0x18..0x1C], 0x00502000u32.to_le_bytes());
+        assert_eq!(image[0x28..0x2A], 0x3010u16.to_le_bytes());
+    }
+}
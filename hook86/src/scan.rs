@@ -0,0 +1,443 @@
+use std::ffi::c_void;
+use std::fmt::{self, Debug};
+
+use windows::Win32::System::Memory::PAGE_PROTECTION_FLAGS;
+
+use crate::mem::{self, ByteSearcher, IntPtr, ModuleOffset, PTR_SIZE, READABLE_PROTECTION, SCAN_CHUNK_SIZE, WRITABLE_PROTECTION};
+
+/// A type of value that a `ValueScanner` can search for
+pub trait ScanValue: Copy + PartialOrd + Debug {
+    /// Read a value from exactly `size_of::<Self>()` little-endian bytes
+    fn from_le_slice(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_scan_value {
+    ($($t:ty),*) => {
+        $(
+            impl ScanValue for $t {
+                fn from_le_slice(bytes: &[u8]) -> Self {
+                    <$t>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_scan_value!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+/// A condition for narrowing down the candidates of a `ValueScanner`
+///
+/// The relative conditions compare a candidate's current value to its value as of the previous
+/// scan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanFilter<T> {
+    /// The value is exactly this. Floats are compared exactly; use `Between` to allow for rounding.
+    Exact(T),
+    /// The value is between these two values, inclusive
+    Between(T, T),
+    /// The value is different than it was
+    Changed,
+    /// The value is the same as it was
+    Unchanged,
+    /// The value is greater than it was
+    Increased,
+    /// The value is less than it was
+    Decreased,
+}
+
+impl<T: ScanValue> ScanFilter<T> {
+    /// Check whether a value that was `old` and is now `new` passes the filter
+    pub fn matches(&self, new: T, old: T) -> bool {
+        match *self {
+            Self::Exact(value) => new == value,
+            Self::Between(min, max) => new >= min && new <= max,
+            Self::Changed => new != old,
+            Self::Unchanged => new == old,
+            Self::Increased => new > old,
+            Self::Decreased => new < old,
+        }
+    }
+}
+
+/// Collect the values between `min` and `max` in a chunk of memory that starts at `base`, until
+/// there are `limit` candidates
+///
+/// `base` must be aligned to the size of `T`.
+///
+/// # Return
+///
+/// Whether the limit was reached.
+fn collect_between<T: ScanValue>(
+    chunk: &[u8],
+    base: usize,
+    min: T,
+    max: T,
+    limit: usize,
+    candidates: &mut Vec<(usize, T)>,
+) -> bool {
+    let size = size_of::<T>();
+    debug_assert_eq!(base % size, 0, "chunk base must be aligned");
+    // the base is aligned, so aligning the offset aligns the address
+    for (i, bytes) in chunk.chunks_exact(size).enumerate() {
+        let value = T::from_le_slice(bytes);
+        if value >= min && value <= max {
+            if candidates.len() >= limit {
+                return true;
+            }
+
+            candidates.push((base + i * size, value));
+        }
+    }
+
+    false
+}
+
+/// The default maximum number of candidates a `ValueScanner` keeps from its first scan
+pub const DEFAULT_CANDIDATE_LIMIT: usize = 1 << 20;
+
+/// Finds where the game stores a value by repeatedly narrowing down the addresses that hold it
+///
+/// This is the classic cheat-tool workflow: search memory for the value you can see in game (e.g.
+/// your gold), do something that changes it, then rescan for the new value (or for values that
+/// increased, decreased, etc.) until only a handful of candidates remain. Only values aligned to
+/// their own size are found, which is how compilers lay out nearly all variables.
+///
+/// A first scan for a common value like 0 or 1 can match a large part of memory, so the number of
+/// candidates kept is limited to `DEFAULT_CANDIDATE_LIMIT` unless another limit is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueScanner<T> {
+    candidates: Vec<(usize, T)>,
+    truncated: bool,
+}
+
+impl<T: ScanValue> ValueScanner<T> {
+    /// Search memory for a value
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to search for
+    /// * `protection` - If provided, only search memory regions matching one of the specified
+    ///   protection flags. Defaults to writable memory, since that's where game state lives.
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    /// * `limit` - If provided, stop once this many candidates have been found instead of after
+    ///   `DEFAULT_CANDIDATE_LIMIT`
+    pub fn scan<'a>(
+        value: T,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        limit: Option<usize>,
+    ) -> Self {
+        Self::scan_between(value, value, protection, ranges, limit)
+    }
+
+    /// Search memory for values in a range, inclusive
+    ///
+    /// Works like `scan`. This is mainly useful for floats, whose displayed value is usually
+    /// rounded.
+    pub fn scan_between<'a>(
+        min: T,
+        max: T,
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+        limit: Option<usize>,
+    ) -> Self {
+        let protection = protection.unwrap_or(WRITABLE_PROTECTION);
+        let limit = limit.unwrap_or(DEFAULT_CANDIDATE_LIMIT);
+        let mut candidates = Vec::new();
+        let mut buffer = vec![0u8; SCAN_CHUNK_SIZE];
+        for (start, size) in mem::readable_regions(protection, ranges) {
+            // regions are clipped to the requested ranges, which don't have to start on a value
+            // boundary. chunks are a multiple of the value size, so aligning the first one aligns
+            // them all.
+            let mut offset = (start as usize).next_multiple_of(size_of::<T>()) - start as usize;
+            while offset < size {
+                let chunk_base = start as usize + offset;
+                let chunk_size = SCAN_CHUNK_SIZE.min(size - offset);
+                let copied = mem::copy_memory(chunk_base as *const u8, &mut buffer[..chunk_size]);
+                if collect_between(&buffer[..copied], chunk_base, min, max, limit, &mut candidates) {
+                    return Self { candidates, truncated: true };
+                }
+                if copied < chunk_size {
+                    // the rest of the region is gone
+                    break;
+                }
+
+                offset += SCAN_CHUNK_SIZE;
+            }
+        }
+
+        Self { candidates, truncated: false }
+    }
+
+    /// Narrow the candidates down to those that pass a filter
+    ///
+    /// Each candidate's current value is read and checked against the filter, and the value is
+    /// remembered for the next relative comparison. Candidates that are no longer readable are
+    /// dropped.
+    pub fn rescan(&mut self, filter: ScanFilter<T>) {
+        let size = size_of::<T>();
+        let mut buffer = vec![0u8; SCAN_CHUNK_SIZE];
+        // read candidates a block at a time rather than making a call for each one
+        let mut block_start = 0;
+        let mut block_len = 0;
+        self.candidates.retain_mut(|(address, value)| {
+            let address = *address;
+            if address < block_start || address + size > block_start + block_len {
+                block_start = address;
+                block_len = mem::copy_memory(address as *const u8, &mut buffer);
+                if block_len < size {
+                    return false;
+                }
+            }
+
+            let offset = address - block_start;
+            let new = T::from_le_slice(&buffer[offset..offset + size]);
+            let keep = filter.matches(new, *value);
+            *value = new;
+            keep
+        });
+    }
+
+    /// The number of remaining candidates
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Check whether there are no candidates left
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Check whether the first scan stopped at its limit before searching all of memory
+    ///
+    /// If it did, the address you're looking for may not be among the candidates. Scan again once
+    /// the value is something less common.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Iterate over the remaining candidates and their values as of the last scan, in address order
+    pub fn candidates(&self) -> impl Iterator<Item = (*const c_void, T)> + '_ {
+        self.candidates.iter().map(|&(address, value)| (address as *const c_void, value))
+    }
+}
+
+/// A chain of pointers leading from a static address in a module to some other address
+///
+/// To follow the path, start at `base`, then for each offset, read the pointer at the current
+/// address and add the offset to it. Because the base is relative to a module, a path found in one
+/// run keeps working in later runs even though the dynamic addresses along the way change.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PointerPath {
+    /// The static address the path starts from
+    pub base: ModuleOffset,
+    /// The offset to add after each pointer is read
+    pub offsets: Vec<usize>,
+}
+
+impl PointerPath {
+    /// Follow the path in the current process
+    ///
+    /// # Return
+    ///
+    /// The address at the end of the path, or `None` if the module hasn't been discovered or a
+    /// pointer along the way isn't readable.
+    pub fn resolve(&self, searcher: &ByteSearcher) -> Option<*const c_void> {
+        let (module_base, _) = searcher.module_range(&self.base.module)?;
+        mem::follow(module_base.wrapping_byte_add(self.base.offset), &self.offsets).ok()
+    }
+}
+
+impl fmt::Display for PointerPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.base)?;
+        for offset in &self.offsets {
+            write!(f, " -> +{:X}", offset)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every pointer in writable memory, for finding pointer paths
+///
+/// Building the map scans all of writable memory for aligned values that point into readable
+/// memory, so it takes a while and uses a fair amount of memory; build it once and search it for
+/// as many targets as you need.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PointerMap {
+    /// (value, address) of each pointer, sorted by value
+    pointers: Vec<(usize, usize)>,
+}
+
+impl PointerMap {
+    /// Scan memory for pointers
+    ///
+    /// # Arguments
+    ///
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to search
+    ///   for pointers. The pointers themselves may point anywhere readable.
+    pub fn build<'a>(ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>) -> Self {
+        // we'll use the standard page size as the minimum address
+        let mut readable: Vec<_> = mem::readable_regions(
+            READABLE_PROTECTION,
+            [&(0x1000 as *const c_void, usize::MAX as *const c_void)].into_iter(),
+        )
+        .map(|(start, size)| (start as usize, start as usize + size))
+        .collect();
+        readable.sort_unstable();
+        let is_valid = |value: usize| {
+            let index = readable.partition_point(|&(start, _)| start <= value);
+            index > 0 && value < readable[index - 1].1
+        };
+
+        let mut pointers = Vec::new();
+        let mut buffer = vec![0u8; SCAN_CHUNK_SIZE];
+        for (start, size) in mem::readable_regions(WRITABLE_PROTECTION, ranges) {
+            // see ValueScanner::scan_between
+            let mut offset = (start as usize).next_multiple_of(PTR_SIZE) - start as usize;
+            while offset < size {
+                let chunk_base = start as usize + offset;
+                let chunk_size = SCAN_CHUNK_SIZE.min(size - offset);
+                let copied = mem::copy_memory(chunk_base as *const u8, &mut buffer[..chunk_size]);
+                for (i, bytes) in buffer[..copied].chunks_exact(PTR_SIZE).enumerate() {
+                    let value = IntPtr::from_le_slice(bytes) as usize;
+                    if is_valid(value) {
+                        pointers.push((value, chunk_base + i * PTR_SIZE));
+                    }
+                }
+
+                if copied < chunk_size {
+                    break;
+                }
+
+                offset += SCAN_CHUNK_SIZE;
+            }
+        }
+
+        pointers.sort_unstable();
+        Self { pointers }
+    }
+
+    /// The number of pointers in the map
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+
+    /// Check whether the map has no pointers
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    /// Find pointer paths from static addresses in discovered modules to `target`
+    ///
+    /// A path ends as soon as it reaches a pointer stored inside a module, so the paths returned
+    /// are the shortest ones through each chain of pointers. Run the search again in a later
+    /// session (or after restarting the level, etc.) and keep the paths that still resolve to the
+    /// target to weed out coincidences.
+    ///
+    /// # Arguments
+    ///
+    /// * `searcher` - The searcher whose discovered modules count as static
+    /// * `target` - The address to find paths to
+    /// * `max_depth` - The maximum number of pointers in a path
+    /// * `max_offset` - The maximum offset to allow after each pointer, i.e. the largest field
+    ///   offset you expect in the structs along the path
+    /// * `limit` - If provided, stop once this many paths have been found
+    pub fn find_paths(
+        &self,
+        searcher: &ByteSearcher,
+        target: *const c_void,
+        max_depth: usize,
+        max_offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<PointerPath> {
+        let mut search = PathSearch {
+            pointers: &self.pointers,
+            searcher,
+            max_offset,
+            limit: limit.unwrap_or(usize::MAX),
+            offsets: Vec::new(),
+            paths: Vec::new(),
+        };
+        search.search(target as usize, max_depth);
+        search.paths
+    }
+}
+
+/// The state of a pointer path search
+struct PathSearch<'a> {
+    pointers: &'a [(usize, usize)],
+    searcher: &'a ByteSearcher,
+    max_offset: usize,
+    limit: usize,
+    /// The offsets found so far, last offset first
+    offsets: Vec<usize>,
+    paths: Vec<PointerPath>,
+}
+
+impl PathSearch<'_> {
+    /// Find pointers to `target` (minus up to `max_offset`), working backward from the target
+    fn search(&mut self, target: usize, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+
+        let first = self.pointers.partition_point(|&(value, _)| value < target.saturating_sub(self.max_offset));
+        let candidates = self.pointers[first..].iter().take_while(|&&(value, _)| value <= target);
+        for &(value, address) in candidates {
+            if self.paths.len() >= self.limit {
+                return;
+            }
+
+            self.offsets.push(target - value);
+            match self.searcher.locate(address as *const c_void) {
+                Some(base) => self.paths.push(PointerPath {
+                    base,
+                    offsets: self.offsets.iter().rev().copied().collect(),
+                }),
+                None => self.search(address, depth - 1),
+            }
+            self.offsets.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters() {
+        assert!(ScanFilter::Exact(5).matches(5, 1));
+        assert!(!ScanFilter::Exact(5).matches(4, 5));
+        assert!(ScanFilter::Between(1.0, 2.0).matches(1.5, 0.0));
+        assert!(ScanFilter::Changed.matches(2, 1));
+        assert!(ScanFilter::Unchanged.matches(1, 1));
+        assert!(ScanFilter::Increased.matches(2, 1));
+        assert!(!ScanFilter::Decreased.matches(2, 1));
+    }
+
+    #[test]
+    fn aligned_values() {
+        let mut chunk = Vec::new();
+        for value in [100i32, 7, 100, 250] {
+            chunk.extend_from_slice(&value.to_le_bytes());
+        }
+        // an unaligned 100 at the end shouldn't be found
+        chunk.extend_from_slice(&[0, 0, 100, 0, 0, 0]);
+
+        let mut candidates = Vec::new();
+        assert!(!collect_between(&chunk, 0x1000, 100, 100, usize::MAX, &mut candidates));
+        assert_eq!(candidates, [(0x1000, 100), (0x1008, 100)]);
+
+        candidates.clear();
+        assert!(!collect_between(&chunk, 0x1000, 100, 300, usize::MAX, &mut candidates));
+        assert_eq!(candidates, [(0x1000, 100), (0x1008, 100), (0x100C, 250)]);
+
+        // a third match doesn't fit under the limit
+        candidates.clear();
+        assert!(collect_between(&chunk, 0x1000, 100, 300, 2, &mut candidates));
+        assert_eq!(candidates, [(0x1000, 100), (0x1008, 100)]);
+    }
+}
@@ -0,0 +1,317 @@
+use std::borrow::Cow;
+use std::ffi::c_void;
+
+use crate::asm::{self, UnexpectedOpcodeError};
+use crate::error::{Hook86Error, Result};
+use crate::mem::{self, ByteSearcher, IntPtr, PTR_SIZE};
+use crate::pattern::{Pattern, PatternSet};
+
+pub use hook86_macro::signatures;
+
+/// A step for getting from the address where a pattern matched to the address that's actually
+/// wanted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResolveStep {
+    /// Add a constant offset to the address
+    Offset(isize),
+    /// Read the pointer stored at the address
+    Deref,
+    /// Read the 32-bit relative offset this many bytes past the address and get the absolute
+    /// address it refers to
+    ///
+    /// The offset is assumed to be the last field of its instruction, which is true of calls,
+    /// jumps, and conditional jumps. For a signature on a `call rel32`, `Rel32(1)` resolves to the
+    /// callee.
+    Rel32(usize),
+    /// Follow the branch instruction at the address (see `asm::get_branch_target`)
+    Branch,
+}
+
+impl ResolveStep {
+    /// Apply this step to an address
+    ///
+    /// # Safety
+    ///
+    /// Memory is checked for readability before it's read, but nothing stops another thread from
+    /// freeing it in between, so the memory the step reads must stay mapped until this returns.
+    /// The result is only as valid as the signature: steps applied to the wrong match produce a
+    /// garbage address without failing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the step needs to read memory that isn't readable or, for `Branch`, if
+    /// the address doesn't contain a supported branch instruction.
+    pub unsafe fn apply(self, address: *const c_void) -> Result<*const c_void> {
+        self.apply_with(address, &mut |ptr, buffer| {
+            if !mem::is_readable(ptr, buffer.len()) {
+                return false;
+            }
+
+            unsafe { std::ptr::copy_nonoverlapping(ptr as *const u8, buffer.as_mut_ptr(), buffer.len()) };
+            true
+        })
+    }
+
+    /// Apply this step to an address, reading memory with `read`
+    ///
+    /// `read` fills the buffer with the memory at the given address and returns whether all of it
+    /// could be read. This lets steps be applied in another process.
+    pub(crate) fn apply_with(
+        self,
+        address: *const c_void,
+        read: &mut dyn FnMut(*const c_void, &mut [u8]) -> bool,
+    ) -> Result<*const c_void> {
+        let mut read_exact = |ptr: *const c_void, buffer: &mut [u8]| {
+            if read(ptr, buffer) {
+                Ok(())
+            } else {
+                Err(Hook86Error::UnreadableMemory {
+                    ptr: ptr as usize,
+                    size: buffer.len(),
+                })
+            }
+        };
+
+        Ok(match self {
+            Self::Offset(offset) => address.wrapping_byte_offset(offset),
+            Self::Deref => {
+                let mut pointer = [0u8; PTR_SIZE];
+                read_exact(address, &mut pointer)?;
+                IntPtr::from_le_bytes(pointer) as usize as *const c_void
+            }
+            Self::Rel32(offset) => {
+                let field = address.wrapping_byte_add(offset);
+                let mut rel = [0u8; 4];
+                read_exact(field, &mut rel)?;
+                field.wrapping_byte_add(4).wrapping_byte_offset(i32::from_le_bytes(rel) as isize)
+            }
+            Self::Branch => {
+                // the longest branch we know how to follow is 6 bytes
+                let mut code = [0u8; 6];
+                read_exact(address, &mut code)?;
+                let copy = code.as_ptr() as *const c_void;
+                // report the address the instruction was read from, not the copy
+                let target = unsafe { asm::get_branch_target(copy) }.map_err(|e| match e {
+                    UnexpectedOpcodeError::SingleByteOpcode { opcode, .. } => {
+                        UnexpectedOpcodeError::SingleByteOpcode { ptr: address, opcode }
+                    }
+                    UnexpectedOpcodeError::DoubleByteOpcode { opcode1, opcode2, .. } => {
+                        UnexpectedOpcodeError::DoubleByteOpcode { ptr: address, opcode1, opcode2 }
+                    }
+                })?;
+
+                // far branches hold an absolute address; everything else is relative to the copy
+                if matches!(code[0], 0x9A | 0xEA) {
+                    target
+                } else {
+                    address.wrapping_byte_offset(target as isize - copy as isize)
+                }
+            }
+        })
+    }
+}
+
+/// Apply a sequence of steps to an address
+///
+/// # Safety
+///
+/// See `ResolveStep::apply`.
+///
+/// # Errors
+///
+/// Returns the error from the first step that fails.
+pub unsafe fn resolve(address: *const c_void, steps: &[ResolveStep]) -> Result<*const c_void> {
+    steps.iter().try_fold(address, |address, step| unsafe { step.apply(address) })
+}
+
+/// Apply a sequence of steps to an address, reading memory with `read` (see `ResolveStep::apply_with`)
+pub(crate) fn resolve_with(
+    address: *const c_void,
+    steps: &[ResolveStep],
+    read: &mut dyn FnMut(*const c_void, &mut [u8]) -> bool,
+) -> Result<*const c_void> {
+    steps.iter().try_fold(address, |address, step| step.apply_with(address, read))
+}
+
+/// A pattern along with the steps needed to get from a match to the address of interest
+///
+/// The common idiom of putting a signature on a call site to find the callee looks like this:
+/// ```ignore
+/// const FIND_CALLEE: Signature = Signature::new(pattern!("E8 ?? ?? ?? ?? 8B F0"), &[ResolveStep::Rel32(1)]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature<'a> {
+    pub pattern: Pattern<'a>,
+    pub steps: Cow<'a, [ResolveStep]>,
+}
+
+impl<'a> Signature<'a> {
+    /// Create a signature from a pattern and the steps to apply to its matches
+    pub const fn new(pattern: Pattern<'a>, steps: &'a [ResolveStep]) -> Self {
+        Self {
+            pattern,
+            steps: Cow::Borrowed(steps),
+        }
+    }
+
+    /// Apply this signature's steps to the address where its pattern matched
+    ///
+    /// # Safety
+    ///
+    /// See `ResolveStep::apply`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the first step that fails.
+    pub unsafe fn resolve(&self, address: *const c_void) -> Result<*const c_void> {
+        unsafe { resolve(address, &self.steps) }
+    }
+}
+
+impl<'a> From<Pattern<'a>> for Signature<'a> {
+    fn from(pattern: Pattern<'a>) -> Self {
+        Self::new(pattern, &[])
+    }
+}
+
+/// A signature declared under a name, as part of a table of signatures to resolve together
+///
+/// These are usually generated by the `signatures!` macro rather than built by hand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedSignature<'a> {
+    /// The name to report if the signature can't be resolved
+    pub name: &'a str,
+    /// The module to search in, or `None` to search all of process memory
+    pub module: Option<&'a str>,
+    pub signature: Signature<'a>,
+}
+
+/// Search for a table of signatures and resolve all of them
+///
+/// The signatures are grouped by module, and each module is scanned once for all of its
+/// signatures that aren't already in the resolved address cache.
+///
+/// # Return
+///
+/// The resolved addresses, in the same order as `signatures`.
+///
+/// # Errors
+///
+/// Returns `Hook86Error::SignaturesUnresolved` listing every signature whose module hasn't been
+/// discovered, whose pattern wasn't found, or whose steps couldn't be applied.
+pub fn resolve_all(searcher: &ByteSearcher, signatures: &[NamedSignature]) -> Result<Vec<*const c_void>> {
+    let mut modules: Vec<_> = signatures.iter().map(|named| named.module).collect();
+    modules.sort_unstable();
+    modules.dedup();
+
+    let mut addresses = vec![std::ptr::null(); signatures.len()];
+    let mut failures = Vec::new();
+    for module in modules {
+        let indexes: Vec<_> = (0..signatures.len()).filter(|&i| signatures[i].module == module).collect();
+        if let Some(module) = module
+            && searcher.module_range(module).is_none()
+        {
+            failures.extend(indexes.into_iter().map(|i| (i, format!("{}: module {} has not been discovered", signatures[i].name, module))));
+            continue;
+        }
+
+        let patterns: PatternSet = indexes.iter().map(|&i| signatures[i].signature.pattern.clone()).collect();
+        let found = searcher.find_pattern_set(&patterns, None, module.as_slice());
+        for (i, address) in indexes.into_iter().zip(found) {
+            let named = &signatures[i];
+            let Some(address) = address else {
+                failures.push((i, format!("{}: pattern not found", named.name)));
+                continue;
+            };
+
+            match unsafe { named.signature.resolve(address) } {
+                Ok(address) => addresses[i] = address,
+                Err(e) => failures.push((i, format!("{}: {}", named.name, e))),
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        failures.sort_unstable_by_key(|&(i, _)| i);
+        return Err(Hook86Error::SignaturesUnresolved {
+            failures: failures.into_iter().map(|(_, failure)| failure).collect(),
+        });
+    }
+
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply steps to an address in a buffer that pretends to be mapped at `base`
+    fn resolve_in(memory: &[u8], base: usize, address: usize, steps: &[ResolveStep]) -> Result<usize> {
+        let mut read = |ptr: *const c_void, buffer: &mut [u8]| {
+            let Some(bytes) = (ptr as usize).checked_sub(base).and_then(|start| memory.get(start..start + buffer.len())) else {
+                return false;
+            };
+
+            buffer.copy_from_slice(bytes);
+            true
+        };
+        resolve_with(address as *const c_void, steps, &mut read).map(|address| address as usize)
+    }
+
+    #[test]
+    fn steps() {
+        // call +0x10; jmp far 0x401000 (a far jump's selector isn't needed); pointer to 0x00405060;
+        // short jump back to the call, padded so the 6-byte read for a branch fits
+        let mut memory = vec![0xE8, 0x10, 0, 0, 0, 0xEA, 0x00, 0x10, 0x40, 0x00];
+        memory.extend_from_slice(&0x00405060u32.to_le_bytes());
+        memory.extend_from_slice(&[0xEB, 0xF0, 0xCC, 0xCC, 0xCC, 0xCC]);
+        let base = 0x1000;
+
+        assert_eq!(resolve_in(&memory, base, base, &[ResolveStep::Offset(14)]).unwrap(), 0x100E);
+        assert_eq!(resolve_in(&memory, base, base + 10, &[ResolveStep::Deref]).unwrap(), 0x00405060);
+        assert_eq!(resolve_in(&memory, base, base, &[ResolveStep::Rel32(1)]).unwrap(), 0x1015);
+        assert_eq!(resolve_in(&memory, base, base, &[ResolveStep::Branch]).unwrap(), 0x1015);
+        assert_eq!(resolve_in(&memory, base, base + 5, &[ResolveStep::Branch]).unwrap(), 0x401000);
+        // the branch is followed relative to where it lives, not where it was copied to
+        assert_eq!(
+            resolve_in(&memory, base, base, &[ResolveStep::Offset(14), ResolveStep::Branch]).unwrap(),
+            0x1000
+        );
+    }
+
+    #[test]
+    fn step_errors() {
+        let memory = [0xCC, 0x8B, 0x45, 0x08, 0xC3, 0x90, 0x90];
+        let base = 0x1000;
+        assert!(matches!(
+            resolve_in(&memory, base, base, &[ResolveStep::Branch]),
+            Err(Hook86Error::UnexpectedOpcode(UnexpectedOpcodeError::SingleByteOpcode { ptr, opcode: 0xCC }))
+                if ptr as usize == base
+        ));
+        assert!(matches!(
+            resolve_in(&memory, base, base + 4, &[ResolveStep::Deref]),
+            Err(Hook86Error::UnreadableMemory { ptr: 0x1004, size: PTR_SIZE })
+        ));
+        // a later step fails after an earlier one succeeds
+        assert!(matches!(
+            resolve_in(&memory, base, base, &[ResolveStep::Offset(-0x10), ResolveStep::Rel32(0)]),
+            Err(Hook86Error::UnreadableMemory { ptr: 0xFF0, size: 4 })
+        ));
+    }
+
+    #[test]
+    fn local_memory() {
+        // mov eax, [value]; jmp $+7; padding
+        let value = 0x12345678u32;
+        let mut code = vec![0xA1];
+        code.extend_from_slice(&(&raw const value as usize as u32).to_le_bytes());
+        code.extend_from_slice(&[0xEB, 0x05, 0x90, 0x90, 0x90, 0x90, 0x90]);
+        let address = code.as_ptr() as *const c_void;
+
+        let steps = [ResolveStep::Offset(1), ResolveStep::Deref, ResolveStep::Deref];
+        let resolved = unsafe { resolve(address, &steps) }.unwrap();
+        assert_eq!(resolved as usize, value as usize);
+        let branch = unsafe { ResolveStep::Branch.apply(address.wrapping_byte_add(5)) }.unwrap();
+        assert_eq!(branch, address.wrapping_byte_add(12));
+    }
+}
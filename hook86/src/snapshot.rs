@@ -0,0 +1,167 @@
+use std::ffi::c_void;
+
+use windows::Win32::System::Memory::PAGE_PROTECTION_FLAGS;
+
+use crate::mem::{self, READABLE_PROTECTION};
+
+/// A memory region copied by a `Snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotRegion {
+    /// The address the region was copied from
+    pub start: *const c_void,
+    /// The contents of the region when it was copied
+    pub data: Vec<u8>,
+}
+
+impl SnapshotRegion {
+    /// The address immediately after the end of the region
+    pub fn end(&self) -> *const c_void {
+        self.start.wrapping_byte_add(self.data.len())
+    }
+}
+
+/// A run of bytes that either all changed or all stayed the same since a snapshot was taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiffRange {
+    /// The address of the first byte in the run
+    pub start: *const c_void,
+    /// The address immediately after the last byte in the run
+    pub end: *const c_void,
+    /// Whether the bytes in the run changed
+    pub changed: bool,
+}
+
+/// Split two versions of the same memory into runs of changed and unchanged bytes
+///
+/// Returns (start offset, end offset, changed) for each run. If `new` is shorter than `old`, the
+/// missing bytes count as changed.
+pub(crate) fn diff_runs(old: &[u8], new: &[u8]) -> Vec<(usize, usize, bool)> {
+    let mut runs: Vec<(usize, usize, bool)> = Vec::new();
+    for (offset, &old_byte) in old.iter().enumerate() {
+        let changed = new.get(offset) != Some(&old_byte);
+        match runs.last_mut() {
+            Some((_, end, run_changed)) if *run_changed == changed => *end = offset + 1,
+            _ => runs.push((offset, offset + 1, changed)),
+        }
+    }
+
+    runs
+}
+
+/// A copy of some of the process's memory that can later be compared against live memory
+///
+/// This supports the "what changed since last time" workflow of finding dynamic state: take a
+/// snapshot, change something in the game, and look at the ranges that changed (or didn't). Every
+/// byte in the snapshotted regions is copied, so snapshots of large areas use a lot of memory;
+/// restrict them to writable memory in the modules or heaps you're interested in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    regions: Vec<SnapshotRegion>,
+}
+
+impl Snapshot {
+    /// Copy the memory regions in the given address ranges
+    ///
+    /// # Arguments
+    ///
+    /// * `protection` - If provided, only copy memory regions matching one of the specified protection flags
+    /// * `ranges` - An iterator of (start, end) address tuples defining the address ranges to copy
+    pub fn capture<'a>(
+        protection: Option<PAGE_PROTECTION_FLAGS>,
+        ranges: impl Iterator<Item = &'a (*const c_void, *const c_void)>,
+    ) -> Self {
+        let protection = protection.unwrap_or(READABLE_PROTECTION);
+        let regions = mem::readable_regions(protection, ranges)
+            .filter_map(|(start, size)| {
+                let mut data = vec![0u8; size];
+                // a region that was freed since we enumerated it is only partly copied, if at all
+                let copied = mem::copy_memory(start, &mut data);
+                data.truncate(copied);
+                (!data.is_empty()).then_some(SnapshotRegion {
+                    start: start as *const c_void,
+                    data,
+                })
+            })
+            .collect();
+
+        Self { regions }
+    }
+
+    /// The regions that were copied
+    pub fn regions(&self) -> &[SnapshotRegion] {
+        &self.regions
+    }
+
+    /// The total number of bytes copied
+    pub fn size(&self) -> usize {
+        self.regions.iter().map(|region| region.data.len()).sum()
+    }
+
+    /// Compare the snapshot against the current contents of memory
+    ///
+    /// # Return
+    ///
+    /// The ranges of bytes that changed and stayed the same, in address order. Each snapshotted
+    /// region is covered exactly once. Bytes that are no longer readable count as changed.
+    pub fn diff(&self) -> Vec<DiffRange> {
+        let mut ranges = Vec::new();
+        for region in &self.regions {
+            let mut live = vec![0u8; region.data.len()];
+            let copied = mem::copy_memory(region.start as *const u8, &mut live);
+            live.truncate(copied);
+
+            ranges.extend(diff_runs(&region.data, &live).into_iter().map(|(start, end, changed)| DiffRange {
+                start: region.start.wrapping_byte_add(start),
+                end: region.start.wrapping_byte_add(end),
+                changed,
+            }));
+        }
+
+        ranges
+    }
+
+    /// Get the (start, end) address ranges that changed since the snapshot was taken
+    pub fn changed(&self) -> Vec<(*const c_void, *const c_void)> {
+        self.diff()
+            .into_iter()
+            .filter(|range| range.changed)
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    /// Get the (start, end) address ranges that stayed the same since the snapshot was taken
+    pub fn unchanged(&self) -> Vec<(*const c_void, *const c_void)> {
+        self.diff()
+            .into_iter()
+            .filter(|range| !range.changed)
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    /// Replace the snapshot's copy of each region with the current contents of memory
+    ///
+    /// This makes the next `diff` compare against now rather than against when the snapshot was
+    /// first taken. Bytes that are no longer readable are dropped from the snapshot.
+    pub fn refresh(&mut self) {
+        for region in &mut self.regions {
+            let copied = mem::copy_memory(region.start as *const u8, &mut region.data);
+            region.data.truncate(copied);
+        }
+
+        self.regions.retain(|region| !region.data.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs() {
+        let old = [1, 2, 3, 4, 5, 6];
+        let new = [1, 2, 0, 0, 5];
+        assert_eq!(diff_runs(&old, &new), [(0, 2, false), (2, 4, true), (4, 5, false), (5, 6, true)]);
+        assert_eq!(diff_runs(&old, &old), [(0, 6, false)]);
+        assert!(diff_runs(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use std::ffi::c_void;
+
+use crate::asm::ReferenceKind;
+use crate::error::{Hook86Error, Result};
+use crate::hook::HookManager;
+use crate::mem::ByteSearcher;
+
+/// The null-terminated replacement text, kept at a fixed address for as long as code points to it
+#[derive(Debug)]
+enum Replacement {
+    Narrow(Box<[u8]>),
+    Wide(Box<[u16]>),
+}
+
+impl Replacement {
+    fn as_ptr(&self) -> *const c_void {
+        match self {
+            Self::Narrow(text) => text.as_ptr() as *const c_void,
+            Self::Wide(text) => text.as_ptr() as *const c_void,
+        }
+    }
+}
+
+/// A string literal whose references in code have been redirected to a different string
+///
+/// Replacing a string in place is limited to the length of the original. A `StringPatch` instead
+/// allocates the new string elsewhere and rewrites every instruction that refers to the original
+/// (e.g. `push offset aText` or `mov ecx, offset aText`) to refer to the new one, so the text can
+/// be any length. The patches are registered with the `HookManager` under `"<name> <address>"`.
+///
+/// The game may copy the pointer into its own structures at any time, so there's no telling when
+/// the replacement is safe to free. Dropping a `StringPatch` therefore leaves the references
+/// redirected and leaks the replacement; only `revert` puts the original back.
+#[derive(Debug)]
+#[must_use = "dropping a StringPatch keeps the patch applied but gives up the ability to revert it"]
+pub struct StringPatch {
+    name: String,
+    original: *const c_void,
+    replacement: Replacement,
+    references: Vec<usize>,
+}
+
+impl StringPatch {
+    /// Replace a narrow (`char`) string literal
+    ///
+    /// `replacement` is written as-is, so it should only contain ASCII unless the game expects
+    /// UTF-8. A null terminator is added.
+    ///
+    /// # Arguments
+    ///
+    /// * `searcher` - The searcher to find references with
+    /// * `name` - The name to register the patches under
+    /// * `original` - The address of the string literal, e.g. from `ByteSearcher::find_string`
+    /// * `replacement` - The new text
+    /// * `modules` - If not empty, only redirect references in code belonging to these modules
+    ///
+    /// # Safety
+    ///
+    /// `original` must be the address of a string literal that the referencing code treats as
+    /// read-only. Every code reference to it is rewritten while other threads may be running it,
+    /// so the game must be able to cope with either string until the patch is complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no code refers to the string or a reference couldn't be patched. Any
+    /// references that were already redirected are put back.
+    pub unsafe fn apply<const M: usize>(
+        searcher: &ByteSearcher,
+        name: &str,
+        original: *const c_void,
+        replacement: &str,
+        modules: &[&str; M],
+    ) -> Result<Self> {
+        let text = replacement.bytes().chain(std::iter::once(0)).collect();
+        unsafe { Self::redirect(searcher, name, original, Replacement::Narrow(text), modules) }
+    }
+
+    /// Replace a wide (`wchar_t`) string literal
+    ///
+    /// Works like `apply`, but the replacement is encoded as UTF-16, e.g. for a string found with
+    /// `ByteSearcher::find_wstring`.
+    ///
+    /// # Safety
+    ///
+    /// See `apply`.
+    pub unsafe fn apply_wide<const M: usize>(
+        searcher: &ByteSearcher,
+        name: &str,
+        original: *const c_void,
+        replacement: &str,
+        modules: &[&str; M],
+    ) -> Result<Self> {
+        let text = replacement.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe { Self::redirect(searcher, name, original, Replacement::Wide(text), modules) }
+    }
+
+    unsafe fn redirect<const M: usize>(
+        searcher: &ByteSearcher,
+        name: &str,
+        original: *const c_void,
+        replacement: Replacement,
+        modules: &[&str; M],
+    ) -> Result<Self> {
+        // a branch to a string is never a real reference
+        let addresses: Vec<_> = searcher
+            .find_references(original, modules)
+            .into_iter()
+            .filter(|reference| reference.kind == ReferenceKind::Absolute)
+            .map(|reference| reference.address)
+            .collect();
+        if addresses.is_empty() {
+            return Err(Hook86Error::InvalidHook {
+                name: name.to_string(),
+                reason: "no code refers to the string",
+            });
+        }
+
+        let mut patch = Self {
+            name: name.to_string(),
+            original,
+            replacement,
+            references: Vec::with_capacity(addresses.len()),
+        };
+        let new_address = (patch.replacement.as_ptr() as u32).to_le_bytes();
+        let mut manager = HookManager::global();
+        for address in addresses {
+            if let Err(e) = unsafe { manager.apply(&patch.patch_name(address), address as *const c_void, &new_address) } {
+                drop(manager);
+                // the game may already have picked up the new pointer, so dropping the patch
+                // afterwards leaks the replacement
+                let _ = patch.restore();
+                return Err(e);
+            }
+            patch.references.push(address);
+        }
+
+        Ok(patch)
+    }
+
+    fn patch_name(&self, reference: usize) -> String {
+        format!("{} {:08X}", self.name, reference)
+    }
+
+    /// The address of the original string literal
+    pub const fn original(&self) -> *const c_void {
+        self.original
+    }
+
+    /// The address of the replacement string
+    pub fn replacement(&self) -> *const c_void {
+        self.replacement.as_ptr()
+    }
+
+    /// The addresses of the redirected references
+    ///
+    /// Each address is where the 32-bit pointer to the string sits inside the referencing
+    /// instruction, not the start of the instruction.
+    pub fn references(&self) -> &[usize] {
+        &self.references
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        let mut manager = HookManager::global();
+        // references are removed as they're restored, so a failed revert can be retried
+        while let Some(&reference) = self.references.last() {
+            unsafe { manager.revert(&self.patch_name(reference)) }?;
+            self.references.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Point every redirected reference back at the original string and free the replacement
+    ///
+    /// # Safety
+    ///
+    /// Nothing may use the replacement after this returns. In particular, the game must not have
+    /// stored the pointer anywhere (e.g. in a UI element or a string table), and no thread may be
+    /// executing code that has already loaded it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a reference couldn't be restored. The replacement is leaked in that
+    /// case, since code still points at it.
+    pub unsafe fn revert(mut self) -> Result<()> {
+        let result = self.restore();
+        if result.is_ok() {
+            // every reference points at the original again, so the caller's guarantee covers the
+            // rest
+            let replacement = std::mem::replace(&mut self.replacement, Replacement::Narrow(Box::new([])));
+            drop(replacement);
+        }
+
+        result
+    }
+}
+
+impl Drop for StringPatch {
+    fn drop(&mut self) {
+        // code may still point at the replacement, so it can't be freed
+        let replacement = std::mem::replace(&mut self.replacement, Replacement::Narrow(Box::new([])));
+        std::mem::forget(replacement);
+    }
+}
@@ -0,0 +1,176 @@
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use windows::Win32::System::Threading::{GetCurrentProcessId, GetCurrentThreadId};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ORIGIN: LazyLock<Instant> = LazyLock::new(Instant::now);
+static PHASES: Mutex<Vec<PhaseRecord>> = Mutex::new(Vec::new());
+
+/// A completed phase of initialization
+#[derive(Debug, Clone)]
+pub struct PhaseRecord {
+    /// The name of the phase
+    pub name: Cow<'static, str>,
+    /// When the phase started, relative to when the timeline was enabled
+    pub start: Duration,
+    /// How long the phase took
+    pub duration: Duration,
+    /// The ID of the thread the phase ran on
+    pub thread_id: u32,
+}
+
+/// Records the duration of a phase when dropped
+#[derive(Debug)]
+#[must_use = "the phase ends when the guard is dropped"]
+pub struct PhaseGuard {
+    name: Option<Cow<'static, str>>,
+    start: Instant,
+}
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        if let Some(name) = self.name.take() {
+            record(name, self.start, Instant::now());
+        }
+    }
+}
+
+/// Start recording init phases
+///
+/// Phases are only recorded while the timeline is enabled, so instrumented code costs next to
+/// nothing in builds that don't care about startup timing.
+pub fn enable() {
+    LazyLock::force(&ORIGIN);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stop recording init phases
+///
+/// Phases that have already been recorded are kept until `clear` is called.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Check whether init phases are currently being recorded
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Discard all recorded phases
+pub fn clear() {
+    PHASES.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Begin a phase that lasts until the returned guard is dropped
+pub fn phase(name: impl Into<Cow<'static, str>>) -> PhaseGuard {
+    PhaseGuard {
+        name: is_enabled().then(|| name.into()),
+        start: Instant::now(),
+    }
+}
+
+/// Record a phase that ran between the given start and end times
+pub fn record(name: impl Into<Cow<'static, str>>, start: Instant, end: Instant) {
+    if !is_enabled() {
+        return;
+    }
+
+    let record = PhaseRecord {
+        name: name.into(),
+        start: start.saturating_duration_since(*ORIGIN),
+        duration: end.saturating_duration_since(start),
+        thread_id: unsafe { GetCurrentThreadId() },
+    };
+    PHASES.lock().unwrap_or_else(|e| e.into_inner()).push(record);
+}
+
+/// Get a copy of all phases recorded so far
+pub fn phases() -> Vec<PhaseRecord> {
+    PHASES.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Summarize the recorded phases in a single line
+///
+/// Phases with the same name are combined, and the total time spent in each one is listed in the
+/// order the phases were first seen.
+pub fn summary() -> String {
+    let mut totals: Vec<(Cow<'static, str>, Duration, usize)> = Vec::new();
+    for phase in phases() {
+        match totals.iter_mut().find(|(name, _, _)| *name == phase.name) {
+            Some((_, total, count)) => {
+                *total += phase.duration;
+                *count += 1;
+            }
+            None => totals.push((phase.name, phase.duration, 1)),
+        }
+    }
+
+    let mut line = String::from("Startup timeline:");
+    for (i, (name, total, count)) in totals.iter().enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+        let _ = write!(line, "{}{} {:.2}ms", separator, name, total.as_secs_f64() * 1000.0);
+        if *count > 1 {
+            let _ = write!(line, " ({}x)", count);
+        }
+    }
+    let _ = write!(line, "; {:.2}ms since enabled", ORIGIN.elapsed().as_secs_f64() * 1000.0);
+
+    line
+}
+
+/// Log a summary of the recorded phases
+#[cfg(feature = "log")]
+pub fn log_summary() {
+    log::info!("{}", summary());
+}
+
+fn write_json_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// Write the recorded phases in the Chrome trace event format
+///
+/// The output can be loaded in chrome://tracing or https://ui.perfetto.dev.
+pub fn write_chrome_trace(out: &mut impl Write) -> io::Result<()> {
+    let pid = unsafe { GetCurrentProcessId() };
+    out.write_all(b"[")?;
+    for (i, phase) in phases().iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(b"\n{\"name\":")?;
+        write_json_string(out, &phase.name)?;
+        write!(
+            out,
+            ",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":{},\"tid\":{}}}",
+            phase.start.as_micros(),
+            phase.duration.as_micros(),
+            pid,
+            phase.thread_id
+        )?;
+    }
+    out.write_all(b"\n]\n")
+}
+
+/// Export the recorded phases to a Chrome trace file at the given path
+pub fn export_chrome_trace(path: impl AsRef<Path>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_chrome_trace(&mut out)?;
+    out.flush()
+}
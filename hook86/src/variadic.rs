@@ -0,0 +1,206 @@
+use std::ffi::{c_char, c_void, CStr};
+
+use crate::asm;
+use crate::callbacks;
+use crate::error::Result;
+use crate::hook::{CaveHook, Prelude};
+
+/// The number of 4-byte argument slots `VariadicCall::call_original` passes to the original
+pub const FORWARDED_SLOTS: usize = 16;
+
+/// The original function called with a fixed number of slots
+///
+/// With cdecl, the caller removes the arguments from the stack, so passing more slots than the
+/// function reads is harmless.
+type ForwardFn =
+    unsafe extern "C" fn(u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32) -> u32;
+type VariadicDetour = Box<dyn Fn(&VariadicCall) -> VariadicAction + Send + Sync>;
+
+/// What should happen after a variadic detour returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariadicAction {
+    /// Run the original function with the caller's arguments, untouched
+    CallOriginal,
+    /// Return to the caller with this value in `eax` without running the original function
+    Return(u32),
+}
+
+/// A hooked variadic function
+struct VariadicHook {
+    detour: VariadicDetour,
+}
+
+/// A call to a function hooked with `hook_variadic`
+#[derive(Debug)]
+pub struct VariadicCall {
+    args: *const u32,
+    return_address: *const c_void,
+    original: *const c_void,
+}
+
+impl VariadicCall {
+    /// The address the hooked function will return to
+    pub const fn return_address(&self) -> *const c_void {
+        self.return_address
+    }
+
+    /// A pointer to the first argument on the stack; the rest follow it in 4-byte slots
+    ///
+    /// This is the same layout as a `va_list`, so it can be passed to `vsnprintf` and friends
+    /// (starting from the slot after the format string).
+    pub const fn args(&self) -> *const c_void {
+        self.args as *const c_void
+    }
+
+    /// Read the argument in the given 4-byte slot
+    ///
+    /// Doubles (which floats are promoted to in variadic calls) and 64-bit integers take up two
+    /// slots. Nothing stops you from reading past the arguments the caller actually passed, so
+    /// use the fixed arguments (e.g. a format string) to work out how many there are.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have passed a `T` in `slot` (and the slots after it, if `T` is bigger than 4
+    /// bytes). Reading past the passed arguments reads whatever else is on the caller's stack.
+    pub unsafe fn arg<T: Copy>(&self, slot: usize) -> T {
+        unsafe { (self.args.wrapping_add(slot) as *const T).read_unaligned() }
+    }
+
+    /// Read the C string pointed to by the argument in the given slot, e.g. a printf format string
+    ///
+    /// Returns `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have passed a pointer to a null-terminated string in `slot`, and the string
+    /// must outlive the borrow, which it does for the duration of the detour.
+    pub unsafe fn arg_str(&self, slot: usize) -> Option<&CStr> {
+        let ptr: *const c_char = unsafe { self.arg(slot) };
+        (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) })
+    }
+
+    /// Call the original function with the same arguments and get its return value
+    ///
+    /// The first `FORWARDED_SLOTS` slots are passed along, which covers all but the longest
+    /// argument lists. The detour should usually return `VariadicAction::Return` with the result
+    /// afterwards; returning `CallOriginal` runs the function a second time.
+    ///
+    /// # Safety
+    ///
+    /// The function runs again with the caller's arguments, so it must be safe to call twice if
+    /// the detour goes on to return `CallOriginal`. Arguments beyond `FORWARDED_SLOTS` slots are
+    /// lost.
+    pub unsafe fn call_original(&self) -> u32 {
+        let forward: ForwardFn = unsafe { std::mem::transmute::<*const c_void, ForwardFn>(self.original) };
+        let args: [u32; FORWARDED_SLOTS] = unsafe { self.arg(0) };
+        unsafe {
+            forward(
+                args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7], args[8], args[9], args[10],
+                args[11], args[12], args[13], args[14], args[15],
+            )
+        }
+    }
+}
+
+/// Encode a detour's decision for the stub, which checks `edx` and returns `eax` if it's non-zero
+const fn encode_action(action: VariadicAction) -> u64 {
+    match action {
+        VariadicAction::CallOriginal => 0,
+        VariadicAction::Return(value) => 1 << 32 | value as u64,
+    }
+}
+
+/// Run a detour, called from the stub with the copy of the overwritten instructions as `original`
+extern "C" fn dispatch(
+    hook: *const VariadicHook,
+    original: *const c_void,
+    args: *const u32,
+    return_address: *const c_void,
+) -> u64 {
+    let hook = unsafe { &*hook };
+    let call = VariadicCall {
+        args,
+        return_address,
+        original,
+    };
+
+    // if the detour panics, let the game carry on as if the function wasn't hooked
+    let action = callbacks::catch_panic(|| (hook.detour)(&call)).unwrap_or(VariadicAction::CallOriginal);
+    encode_action(action)
+}
+
+/// Hook a variadic cdecl function, like a game's printf-style logger
+///
+/// The detour runs before the function with access to the caller's arguments, and decides whether
+/// the original function runs with the arguments exactly as the caller passed them or the call
+/// returns a value of the detour's choosing. The hook is registered with the `HookManager` under
+/// `name`. `eax`, `ecx`, and `edx` are clobbered before the original function runs, which is fine
+/// for cdecl functions but not for functions that take arguments in registers.
+///
+/// # Safety
+///
+/// `target` must be the start of a cdecl function, and no thread may be executing its first
+/// instructions while they're overwritten.
+///
+/// # Errors
+///
+/// Returns an error if the first instructions of the function contain a relative branch or call,
+/// or if the hook couldn't be installed.
+pub unsafe fn hook_variadic(
+    name: &str,
+    target: *const c_void,
+    detour: impl Fn(&VariadicCall) -> VariadicAction + Send + Sync + 'static,
+) -> Result<()> {
+    let prelude = || {
+        // the hook state lives for the rest of the process, like the stub
+        let hook: &'static VariadicHook = Box::leak(Box::new(VariadicHook {
+            detour: Box::new(detour),
+        }));
+
+        // lea eax, [esp+4]; push dword [esp]; push eax; push <original instructions>; push <hook>;
+        // call dispatch; add esp, 16; test edx, edx; jz original; ret
+        Prelude::new()
+            .code(&[0x8D, 0x44, 0x24, 0x04, 0xFF, 0x34, 0x24, 0x50])
+            .push_relocated()
+            .code(&asm::push(hook as *const VariadicHook as usize))
+            .call(dispatch as extern "C" fn(_, _, _, _) -> u64 as usize)
+            .code(&[0x83, 0xC4, 0x10])
+            .code(&[0x85, 0xD2, 0x74, 0x01, 0xC3])
+    };
+    unsafe { CaveHook::install_with_prelude(name, target, prelude) }?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments() {
+        let format = c"%d %s %f";
+        let text = c"text";
+        let value = 1.5f64.to_bits();
+        let stack = [
+            format.as_ptr() as usize as u32,
+            42,
+            text.as_ptr() as usize as u32,
+            value as u32,
+            (value >> 32) as u32,
+        ];
+
+        let call = VariadicCall {
+            args: stack.as_ptr(),
+            return_address: std::ptr::null(),
+            original: std::ptr::null(),
+        };
+        unsafe {
+            assert_eq!(call.arg_str(0), Some(format));
+            assert_eq!(call.arg::<i32>(1), 42);
+            assert_eq!(call.arg_str(2), Some(text));
+            assert_eq!(call.arg::<f64>(3), 1.5);
+            assert_eq!(encode_action(VariadicAction::Return(7)), 0x1_0000_0007);
+            assert_eq!(encode_action(VariadicAction::CallOriginal), 0);
+        }
+    }
+}
@@ -1,16 +1,15 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 
+mod mnemonics;
+mod signature;
+
 use quote::quote;
 use syn::parse::{Parse, ParseStream, Result};
-use syn::{bracketed, parse_macro_input, Error, Ident, LitInt, Token, Visibility};
+use syn::punctuated::Punctuated;
+use syn::{braced, bracketed, parse_macro_input, Attribute, Error, Expr, Ident, LitInt, LitStr, Path, Token, Type, Visibility};
 
-macro_rules! byte {
-    ($buf:expr, $byte:expr) => {
-        $buf.push($byte);
-        continue;
-    }
-}
+use mnemonics::{Mnemonic, MnemonicTable};
 
 #[derive(Debug)]
 enum PatchComponent {
@@ -37,10 +36,15 @@ impl PatchComponent {
     }
 }
 
+/// Methods every patch has, which a label can't share a name with
+const RESERVED_LABELS: [&str; 6] = ["new", "buf", "buf_raw", "at", "bind", "bind_at"];
+
 struct Patch {
     visibility: Visibility,
     name: Ident,
     components: Vec<PatchComponent>,
+    labels: Vec<(Ident, usize)>,
+    mnemonics: MnemonicTable,
 }
 
 impl Parse for Patch {
@@ -54,8 +58,11 @@ impl Parse for Patch {
         let content;
         bracketed!(content in input);
 
-        let mut components = vec![];
+        let mnemonics = MnemonicTable::load().map_err(|msg| Error::new(proc_macro2::Span::call_site(), msg))?;
+
+        let mut components: Vec<PatchComponent> = vec![];
         let mut current_buf = vec![];
+        let mut labels = vec![];
 
         while !content.is_empty() {
             if content.peek(LitInt) {
@@ -63,54 +70,44 @@ impl Parse for Patch {
                 current_buf.push(byte.base10_parse::<u8>()?);
             } else {
                 let instruction: Ident = content.parse()?;
-                let inst_string = instruction.to_string();
-                match inst_string.as_str() {
-                    "pushad" => {
-                        byte!(current_buf, 0x60);
+                if content.peek(Token![:]) {
+                    content.parse::<Token![:]>()?;
+                    if RESERVED_LABELS.contains(&instruction.to_string().as_str()) {
+                        return Err(Error::new(
+                            instruction.span(),
+                            format!("Label `{}` has the same name as a method of every patch", instruction),
+                        ));
                     }
-                    "popad" => {
-                        byte!(current_buf, 0x61);
+                    if labels.iter().any(|(label, _)| *label == instruction) {
+                        return Err(Error::new(instruction.span(), format!("Duplicate label `{}`", instruction)));
                     }
-                    "ret" | "retn" => {
-                        byte!(current_buf, 0xC3);
-                    }
-                    _ => (),
+                    let offset = components.iter().map(PatchComponent::size).sum::<usize>() + current_buf.len();
+                    labels.push((instruction, offset));
+                    continue;
                 }
 
-                let target: Ident = content.parse()?;
-                let component = match inst_string.as_str() {
-                    "imm32" => PatchComponent::Imm32(target),
-                    "rel32" => PatchComponent::Rel32(vec![], target),
-                    "call" => PatchComponent::Rel32(vec![0xE8], target),
-                    "jmp" => PatchComponent::Rel32(vec![0xE9], target),
-                    "jz" | "je" => PatchComponent::Rel32(vec![0x0F, 0x84], target),
-                    "jl" | "jnge" => PatchComponent::Rel32(vec![0x0F, 0x8C], target),
-                    "jge" | "jnl" => PatchComponent::Rel32(vec![0x0F, 0x8D], target),
-                    "ja" | "jnbe" => PatchComponent::Rel32(vec![0x0F, 0x87], target),
-                    "jae" | "jnb" | "jnc" => PatchComponent::Rel32(vec![0x0F, 0x83], target),
-                    "jb" | "jc" | "jnae" => PatchComponent::Rel32(vec![0x0F, 0x82], target),
-                    "jbe" | "jna" => PatchComponent::Rel32(vec![0x0F, 0x86], target),
-                    "jg" | "jnle" => PatchComponent::Rel32(vec![0x0F, 0x8F], target),
-                    "jle" | "jng" => PatchComponent::Rel32(vec![0x0F, 0x8E], target),
-                    "jne" | "jnz" => PatchComponent::Rel32(vec![0x0F, 0x85], target),
-                    "jno" => PatchComponent::Rel32(vec![0x0F, 0x81], target),
-                    "jnp" | "jpo" => PatchComponent::Rel32(vec![0x0F, 0x8B], target),
-                    "jns" => PatchComponent::Rel32(vec![0x0F, 0x89], target),
-                    "jo" => PatchComponent::Rel32(vec![0x0F, 0x80], target),
-                    "jp" | "jpe" => PatchComponent::Rel32(vec![0x0F, 0x8A], target),
-                    "js" => PatchComponent::Rel32(vec![0x0F, 0x88], target),
-                    "push" => {
-                        current_buf.push(0x68);
-                        PatchComponent::Imm32(target)
+                let Some(mnemonic) = mnemonics.resolve(&instruction.to_string()) else {
+                    return Err(Error::new(instruction.span(), "Invalid or unsupported instruction"));
+                };
+                let component = match mnemonic {
+                    Mnemonic::Bytes(bytes) => {
+                        current_buf.extend_from_slice(&bytes);
+                        None
+                    }
+                    Mnemonic::Rel32(opcode) => Some(PatchComponent::Rel32(opcode, content.parse()?)),
+                    Mnemonic::Imm32(opcode) => {
+                        current_buf.extend_from_slice(&opcode);
+                        Some(PatchComponent::Imm32(content.parse()?))
                     }
-                    _ => return Err(Error::new(instruction.span(), "Invalid or unsupported instruction")),
                 };
 
-                if !current_buf.is_empty() {
-                    components.push(PatchComponent::Bytes(current_buf));
-                    current_buf = vec![];
+                if let Some(component) = component {
+                    if !current_buf.is_empty() {
+                        components.push(PatchComponent::Bytes(current_buf));
+                        current_buf = vec![];
+                    }
+                    components.push(component);
                 }
-                components.push(component);
             }
 
             // optionally allow commas between values
@@ -129,6 +126,8 @@ impl Parse for Patch {
             visibility,
             name,
             components,
+            labels,
+            mnemonics,
         })
     }
 }
@@ -159,17 +158,42 @@ impl Parse for Patch {
 /// automatically fill in the appropriate opcode bytes and a placeholder of the appropriate type.
 /// Placeholder bytes are initialized to zero. Integers and placeholders can be interspersed freely.
 ///
+/// A label can be placed anywhere in the patch body by writing a name followed by a colon, e.g.
+/// `handler:`. Each label generates a method of the same name returning a `PatchRef` to that
+/// location in the patch bytes, which can be passed (via `PatchRef::addr`) to another patch's
+/// `bind` to have one patch jump or call into another. The `at` method returns a `PatchRef` to an
+/// arbitrary offset. Labels must be unique and can't be named after the methods every patch has
+/// (`new`, `buf`, `buf_raw`, `at`, `bind`, and `bind_at`).
+///
+/// Crates can define their own mnemonics in a file named `hook86_mnemonics.txt` in the crate root
+/// (or at the path in the `HOOK86_MNEMONICS` environment variable). Each line defines one
+/// mnemonic as a name, an optional operand type of `imm32` or `rel32`, an equals sign, and the
+/// opcode bytes; `#` starts a comment:
+/// ```text
+/// cpuid = 0x0F 0xA2
+/// mov_eax imm32 = 0xB8 # mov eax, imm32
+/// ```
+/// Mnemonics with an operand type take a placeholder name just like the built-in instructions.
+/// Built-in mnemonics take precedence over custom ones with the same name.
+///
 /// Once an instance of a patch type has been created with the `new` method and you've identified
 /// the runtime values for the placeholders, you can call the instance's `bind` method, which takes
 /// one argument per placeholder in the order the placeholders were defined. `bind` will fill in
 /// the placeholder bytes with the appropriate values, mark the patch bytes as executable, and
 /// return a pointer to the patch bytes (make sure the patch instance is in static/pinned memory!).
+///
+/// For a patch that will run somewhere else, such as in another process, `bind_at` takes the
+/// address the bytes will be copied to before the placeholder values, calculates relative
+/// placeholders from that address instead, and returns the bound bytes without touching their
+/// memory protection. `RemoteProcess::write_code` allocates the memory and writes the bytes.
 #[proc_macro]
 pub fn patch(input: TokenStream) -> TokenStream {
     let Patch {
         visibility,
         name,
         components,
+        labels,
+        mnemonics,
     } = parse_macro_input!(input as Patch);
 
     let patch_size = components.iter().map(PatchComponent::size).sum::<usize>();
@@ -202,7 +226,23 @@ pub fn patch(input: TokenStream) -> TokenStream {
         PatchComponent::Imm32(_) => Some(false),
     });
 
+    let (label_names, label_offsets): (Vec<_>, Vec<_>) = labels.into_iter().unzip();
+
+    // make sure the patch is recompiled when the mnemonic file changes
+    let mnemonic_file = mnemonics.path.and_then(|path| path.to_str().map(String::from)).map(|path| {
+        quote! { const _: &[u8] = include_bytes!(#path); }
+    });
+    // and when the variable overriding its location does. proc macros can't track environment
+    // variables on stable, but option_env! in the expansion makes the variable a dependency.
+    let mnemonic_var = mnemonics::MNEMONIC_FILE_VAR;
+    let mnemonic_dependency = quote! {
+        #mnemonic_file
+        const _: Option<&str> = option_env!(#mnemonic_var);
+    };
+
     let expanded = quote! {
+        #mnemonic_dependency
+
         #visibility struct #name {
             __buf: [u8; #patch_size],
             #(#field_names: hook86::patch::PatchPlaceholder),*
@@ -224,12 +264,358 @@ pub fn patch(input: TokenStream) -> TokenStream {
                 self.buf().as_ptr()
             }
 
-            pub fn bind(&mut self, #(#field_names: hook86::mem::IntPtr,)*) -> windows::core::Result<*const u8> {
+            pub const fn at(&self, offset: usize) -> hook86::patch::PatchRef {
+                hook86::patch::PatchRef::new(self.buf_raw(), offset)
+            }
+
+            #(
+                pub const fn #label_names(&self) -> hook86::patch::PatchRef {
+                    self.at(#label_offsets)
+                }
+            )*
+
+            pub fn bind(&mut self, #(#field_names: hook86::mem::IntPtr,)*) -> hook86::Result<*const u8> {
                 #(self.#field_names.set_value(&mut self.__buf, #field_names);)*
                 hook86::mem::unprotect(self.buf_raw() as *const std::ffi::c_void, #patch_size).map(|_| self.buf_raw())
             }
+
+            pub fn bind_at(&mut self, __base: hook86::mem::IntPtr, #(#field_names: hook86::mem::IntPtr,)*) -> &[u8] {
+                #(self.#field_names.set_value_at(&mut self.__buf, __base, #field_names);)*
+                self.buf()
+            }
         }
     };
 
     TokenStream::from(expanded)
-}
\ No newline at end of file
+}
+
+/// Define a byte pattern from an IDA/x64dbg-style signature at compile time
+///
+/// The signature uses the same syntax as `Pattern::parse`, but it's parsed during compilation, so
+/// malformed signatures are compile errors and there's no parsing overhead at runtime. The macro
+/// evaluates to a `Pattern<'static>` and can be used to initialize a constant:
+/// ```ignore
+/// const CALL_SITE: Pattern = pattern!("E8 ?? ?? ?? ?? 8B F0");
+/// ```
+#[proc_macro]
+pub fn pattern(input: TokenStream) -> TokenStream {
+    let signature = parse_macro_input!(input as LitStr);
+    match pattern_tokens(&signature) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Parse a signature literal into an expression evaluating to a `Pattern<'static>`
+fn pattern_tokens(signature: &LitStr) -> Result<proc_macro2::TokenStream> {
+    let (bytes, mask) = signature::parse_signature(&signature.value()).map_err(|msg| Error::new(signature.span(), msg))?;
+
+    let constructor = if mask.iter().all(|&m| m == 0xFF) {
+        quote! { hook86::pattern::Pattern::exact(&[#(#bytes),*]) }
+    } else {
+        quote! { hook86::pattern::Pattern::from_slices(&[#(#bytes),*], &[#(#mask),*]) }
+    };
+
+    // evaluate in a const so the anchor is computed at compile time even in a non-const context
+    Ok(quote! {
+        {
+            const PATTERN: hook86::pattern::Pattern<'static> = #constructor;
+            PATTERN
+        }
+    })
+}
+
+struct SignatureField {
+    attrs: Vec<Attribute>,
+    name: Ident,
+    pattern: LitStr,
+    module: Option<LitStr>,
+    steps: Vec<Expr>,
+}
+
+impl Parse for SignatureField {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let name: Ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let pattern: LitStr = input.parse()?;
+
+        let module = if input.peek(Token![in]) {
+            input.parse::<Token![in]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        let steps = if input.peek(Token![=>]) {
+            input.parse::<Token![=>]>()?;
+            let content;
+            bracketed!(content in input);
+            Punctuated::<Expr, Token![,]>::parse_terminated(&content)?.into_iter().collect()
+        } else {
+            vec![]
+        };
+
+        Ok(Self {
+            attrs,
+            name,
+            pattern,
+            module,
+            steps,
+        })
+    }
+}
+
+struct SignatureTable {
+    attrs: Vec<Attribute>,
+    visibility: Visibility,
+    name: Ident,
+    fields: Vec<SignatureField>,
+}
+
+impl Parse for SignatureTable {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let visibility: Visibility = input.parse()?;
+        input.parse::<Token![struct]>()?;
+        let name: Ident = input.parse()?;
+
+        let content;
+        braced!(content in input);
+        let fields = Punctuated::<SignatureField, Token![,]>::parse_terminated(&content)?.into_iter().collect();
+
+        Ok(Self {
+            attrs,
+            visibility,
+            name,
+            fields,
+        })
+    }
+}
+
+/// Declare a struct of named signatures that are all resolved at once
+///
+/// Each field is a signature in the same syntax as `pattern!`, optionally followed by `in` and the
+/// name of the module to search, and optionally followed by `=>` and a list of `ResolveStep`s to
+/// apply to the match (the variants can be named without the `ResolveStep::` prefix):
+/// ```ignore
+/// signatures! {
+///     pub struct GameFunctions {
+///         give_item: "E8 ? ? ? ? 8B F0 85 F6" in "game.exe" => [Rel32(1)],
+///         player_list: "A1 ? ? ? ? 8B 0C 88" in "game.exe" => [Offset(1), Deref],
+///         update: "55 8B EC 83 E4 F8 81 EC",
+///     }
+/// }
+/// ```
+///
+/// Signatures without a module are searched for in all of process memory. The struct gets one
+/// `*const c_void` field per signature and a `resolve` method that takes a `ByteSearcher`, scans
+/// each module once for all of its signatures, and returns either a fully-populated struct or an
+/// error listing every signature that couldn't be resolved.
+#[proc_macro]
+pub fn signatures(input: TokenStream) -> TokenStream {
+    let SignatureTable {
+        attrs,
+        visibility,
+        name,
+        fields,
+    } = parse_macro_input!(input as SignatureTable);
+
+    let mut entries = Vec::with_capacity(fields.len());
+    for field in &fields {
+        let pattern = match pattern_tokens(&field.pattern) {
+            Ok(pattern) => pattern,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let field_name = field.name.to_string();
+        let module = match field.module {
+            Some(ref module) => quote! { Some(#module) },
+            None => quote! { None },
+        };
+        let steps = &field.steps;
+
+        entries.push(quote! {
+            hook86::signature::NamedSignature {
+                name: #field_name,
+                module: #module,
+                signature: hook86::signature::Signature::new(#pattern, {
+                    #[allow(unused_imports)]
+                    use hook86::signature::ResolveStep::*;
+                    const STEPS: &[hook86::signature::ResolveStep] = &[#(#steps),*];
+                    STEPS
+                }),
+            }
+        });
+    }
+
+    let field_attrs = fields.iter().map(|field| &field.attrs);
+    let field_names: Vec<_> = fields.iter().map(|field| &field.name).collect();
+    let field_indexes = 0..fields.len();
+
+    let expanded = quote! {
+        #(#attrs)*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #visibility struct #name {
+            #(#(#field_attrs)* #visibility #field_names: *const std::ffi::c_void,)*
+        }
+
+        impl #name {
+            pub fn resolve(searcher: &hook86::mem::ByteSearcher) -> hook86::Result<Self> {
+                let signatures = [#(#entries),*];
+                let addresses = hook86::signature::resolve_all(searcher, &signatures)?;
+                Ok(Self {
+                    #(#field_names: addresses[#field_indexes],)*
+                })
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+struct OverlayField {
+    attrs: Vec<Attribute>,
+    name: Ident,
+    ty: Type,
+    offset: Expr,
+}
+
+impl Parse for OverlayField {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let name: Ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty: Type = input.parse()?;
+        input.parse::<Token![@]>()?;
+        let offset: Expr = input.parse()?;
+
+        Ok(Self { attrs, name, ty, offset })
+    }
+}
+
+struct Overlay {
+    attrs: Vec<Attribute>,
+    visibility: Visibility,
+    name: Ident,
+    resolver: Path,
+    fields: Vec<OverlayField>,
+}
+
+impl Parse for Overlay {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let visibility: Visibility = input.parse()?;
+        let name: Ident = input.parse()?;
+        input.parse::<Token![@]>()?;
+        let resolver: Path = input.parse()?;
+
+        let content;
+        braced!(content in input);
+        let fields = Punctuated::<OverlayField, Token![,]>::parse_terminated(&content)?.into_iter().collect();
+
+        Ok(Self {
+            attrs,
+            visibility,
+            name,
+            resolver,
+            fields,
+        })
+    }
+}
+
+/// Declare typed accessors for a game structure that lives at a runtime-determined address
+///
+/// The structure is named, followed by `@` and the path of a function that locates it, then
+/// a list of fields, each with a type and an offset from the start of the structure:
+/// ```ignore
+/// game_struct! {
+///     pub Player @ resolve_player_base {
+///         health: f32 @ 0x1C,
+///         gold: u32 @ 0x44,
+///     }
+/// }
+/// ```
+///
+/// The locating function takes no arguments and returns a `hook86::Result<*const c_void>`. The
+/// generated type is a copyable handle holding the structure's address: `Player::get()` calls the
+/// locating function, and `Player::at` takes an address directly. Each field gets a getter
+/// (`health()`) and a setter (`set_health(value)`) that use `mem::read` and `mem::write`, so an
+/// unreadable address is an error rather than a crash. Only declare fields whose types are valid
+/// for any bit pattern, such as integers, floats, and raw pointers.
+#[proc_macro]
+pub fn game_struct(input: TokenStream) -> TokenStream {
+    let Overlay {
+        attrs,
+        visibility,
+        name,
+        resolver,
+        fields,
+    } = parse_macro_input!(input as Overlay);
+
+    let accessors = fields.iter().map(|field| {
+        let OverlayField { attrs, name, ty, offset } = field;
+        let setter = Ident::new(&format!("set_{name}"), name.span());
+        quote! {
+            #(#attrs)*
+            #visibility fn #name(&self) -> hook86::Result<#ty> {
+                unsafe { hook86::mem::read(self.base.wrapping_byte_add(#offset)) }
+            }
+
+            #(#attrs)*
+            #visibility fn #setter(&self, value: #ty) -> hook86::Result<()> {
+                unsafe { hook86::mem::write(self.base.wrapping_byte_add(#offset), value) }
+            }
+        }
+    });
+
+    let expanded = quote! {
+        #(#attrs)*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #visibility struct #name {
+            base: *const std::ffi::c_void,
+        }
+
+        impl #name {
+            /// Locate the structure in game memory
+            #visibility fn get() -> hook86::Result<Self> {
+                let base: *const std::ffi::c_void = #resolver()?;
+                Ok(Self { base })
+            }
+
+            /// Access the structure at the given address
+            ///
+            /// # Safety
+            ///
+            /// The address must point to an instance of the structure for as long as the handle is used.
+            #visibility const unsafe fn at(base: *const std::ffi::c_void) -> Self {
+                Self { base }
+            }
+
+            /// The address of the structure
+            #visibility const fn as_ptr(&self) -> *const std::ffi::c_void {
+                self.base
+            }
+
+            #(#accessors)*
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_names() {
+        let patch: Patch = syn::parse_str("Hook = [ 0x90 start: call target done: ret ];").unwrap();
+        let labels: Vec<_> = patch.labels.iter().map(|(label, offset)| (label.to_string(), *offset)).collect();
+        assert_eq!(labels, [(String::from("start"), 1), (String::from("done"), 6)]);
+
+        let error = syn::parse_str::<Patch>("Hook = [ at: ret ];").err().unwrap();
+        assert_eq!(error.to_string(), "Label `at` has the same name as a method of every patch");
+        let error = syn::parse_str::<Patch>("Hook = [ start: 0x90 start: ret ];").err().unwrap();
+        assert_eq!(error.to_string(), "Duplicate label `start`");
+    }
+}
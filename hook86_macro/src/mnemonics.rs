@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The name of the file in a crate's root directory that defines custom mnemonics for `patch!`
+const MNEMONIC_FILE_NAME: &str = "hook86_mnemonics.txt";
+/// An environment variable that can be set to override the location of the mnemonic file
+pub const MNEMONIC_FILE_VAR: &str = "HOOK86_MNEMONICS";
+
+/// How a mnemonic is encoded in a patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mnemonic {
+    /// A fixed sequence of bytes with no operand
+    Bytes(Vec<u8>),
+    /// Opcode bytes followed by a 32-bit relative offset placeholder
+    Rel32(Vec<u8>),
+    /// Opcode bytes followed by a 32-bit immediate placeholder
+    Imm32(Vec<u8>),
+}
+
+/// Mnemonics defined by the crate invoking `patch!`
+#[derive(Debug, Default)]
+pub struct MnemonicTable {
+    pub path: Option<PathBuf>,
+    entries: HashMap<String, Mnemonic>,
+}
+
+/// The encoding of a mnemonic `patch!` supports without a mnemonic file
+fn builtin(name: &str) -> Option<Mnemonic> {
+    let mnemonic = match name {
+        "pushad" => Mnemonic::Bytes(vec![0x60]),
+        "popad" => Mnemonic::Bytes(vec![0x61]),
+        "ret" | "retn" => Mnemonic::Bytes(vec![0xC3]),
+        "imm32" => Mnemonic::Imm32(vec![]),
+        "rel32" => Mnemonic::Rel32(vec![]),
+        "push" => Mnemonic::Imm32(vec![0x68]),
+        "call" => Mnemonic::Rel32(vec![0xE8]),
+        "jmp" => Mnemonic::Rel32(vec![0xE9]),
+        "jz" | "je" => Mnemonic::Rel32(vec![0x0F, 0x84]),
+        "jl" | "jnge" => Mnemonic::Rel32(vec![0x0F, 0x8C]),
+        "jge" | "jnl" => Mnemonic::Rel32(vec![0x0F, 0x8D]),
+        "ja" | "jnbe" => Mnemonic::Rel32(vec![0x0F, 0x87]),
+        "jae" | "jnb" | "jnc" => Mnemonic::Rel32(vec![0x0F, 0x83]),
+        "jb" | "jc" | "jnae" => Mnemonic::Rel32(vec![0x0F, 0x82]),
+        "jbe" | "jna" => Mnemonic::Rel32(vec![0x0F, 0x86]),
+        "jg" | "jnle" => Mnemonic::Rel32(vec![0x0F, 0x8F]),
+        "jle" | "jng" => Mnemonic::Rel32(vec![0x0F, 0x8E]),
+        "jne" | "jnz" => Mnemonic::Rel32(vec![0x0F, 0x85]),
+        "jno" => Mnemonic::Rel32(vec![0x0F, 0x81]),
+        "jnp" | "jpo" => Mnemonic::Rel32(vec![0x0F, 0x8B]),
+        "jns" => Mnemonic::Rel32(vec![0x0F, 0x89]),
+        "jo" => Mnemonic::Rel32(vec![0x0F, 0x80]),
+        "jp" | "jpe" => Mnemonic::Rel32(vec![0x0F, 0x8A]),
+        "js" => Mnemonic::Rel32(vec![0x0F, 0x88]),
+        _ => return None,
+    };
+
+    Some(mnemonic)
+}
+
+fn parse_byte(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+impl MnemonicTable {
+    fn file_path() -> Option<PathBuf> {
+        if let Ok(path) = std::env::var(MNEMONIC_FILE_VAR) {
+            return Some(PathBuf::from(path));
+        }
+
+        let path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").ok()?).join(MNEMONIC_FILE_NAME);
+        path.is_file().then_some(path)
+    }
+
+    /// Load the mnemonic table for the crate currently being compiled
+    ///
+    /// If the crate doesn't define any custom mnemonics, the table will be empty.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::file_path() else {
+            return Ok(Self::default());
+        };
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read mnemonic file {}: {}", path.display(), e))?;
+        let mut entries = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |msg: &str| format!("{}:{}: {}", path.display(), i + 1, msg);
+            let Some((lhs, rhs)) = line.split_once('=') else {
+                return Err(error("expected `mnemonic [imm32|rel32] = bytes`"));
+            };
+
+            let mut lhs = lhs.split_whitespace();
+            let Some(name) = lhs.next() else {
+                return Err(error("missing mnemonic name"));
+            };
+            let operand = lhs.next();
+            if lhs.next().is_some() {
+                return Err(error("too many operands"));
+            }
+
+            let bytes = rhs
+                .split_whitespace()
+                .map(|b| parse_byte(b.trim_end_matches(',')).ok_or_else(|| error(&format!("invalid byte `{}`", b))))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mnemonic = match operand {
+                None if bytes.is_empty() => return Err(error("mnemonic has no bytes")),
+                None => Mnemonic::Bytes(bytes),
+                Some("rel32") => Mnemonic::Rel32(bytes),
+                Some("imm32") => Mnemonic::Imm32(bytes),
+                Some(other) => return Err(error(&format!("unknown operand type `{}`", other))),
+            };
+
+            if entries.insert(name.to_string(), mnemonic).is_some() {
+                return Err(error(&format!("duplicate mnemonic `{}`", name)));
+            }
+        }
+
+        Ok(Self {
+            path: Some(path),
+            entries,
+        })
+    }
+
+    /// Look up a mnemonic by name
+    ///
+    /// Built-in mnemonics are checked first, so a custom mnemonic with the same name as a built-in
+    /// one is never used.
+    pub fn resolve(&self, name: &str) -> Option<Mnemonic> {
+        builtin(name).or_else(|| self.entries.get(name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_take_precedence() {
+        let table = MnemonicTable {
+            path: None,
+            entries: HashMap::from([
+                (String::from("call"), Mnemonic::Bytes(vec![0x90])),
+                (String::from("cpuid"), Mnemonic::Bytes(vec![0x0F, 0xA2])),
+            ]),
+        };
+
+        assert_eq!(table.resolve("call"), Some(Mnemonic::Rel32(vec![0xE8])));
+        assert_eq!(table.resolve("cpuid"), Some(Mnemonic::Bytes(vec![0x0F, 0xA2])));
+        assert_eq!(table.resolve("nop"), None);
+    }
+}
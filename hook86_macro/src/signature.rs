@@ -0,0 +1,43 @@
+/// Parse one hex digit of a signature, returning the value and mask of the nibble
+fn parse_nibble(c: u8) -> Option<(u8, u8)> {
+    match c {
+        b'?' => Some((0, 0)),
+        _ => Some(((c as char).to_digit(16)? as u8, 0xF)),
+    }
+}
+
+/// Parse an IDA/x64dbg-style signature into its bytes and mask
+///
+/// This accepts the same syntax as `Pattern::parse` in the main crate.
+pub fn parse_signature(signature: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut bytes = Vec::new();
+    let mut mask = Vec::new();
+    for token in signature.split_whitespace() {
+        let position = bytes.len();
+        let invalid = || format!("invalid pattern byte `{}` at position {}", token, position);
+
+        if token == "?" {
+            bytes.push(0);
+            mask.push(0);
+            continue;
+        }
+
+        if token.len() % 2 != 0 {
+            return Err(invalid());
+        }
+
+        for pair in token.as_bytes().chunks_exact(2) {
+            let (Some((high, high_mask)), Some((low, low_mask))) = (parse_nibble(pair[0]), parse_nibble(pair[1])) else {
+                return Err(invalid());
+            };
+            bytes.push(high << 4 | low);
+            mask.push(high_mask << 4 | low_mask);
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(String::from("pattern is empty"));
+    }
+
+    Ok((bytes, mask))
+}